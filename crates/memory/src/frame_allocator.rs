@@ -0,0 +1,848 @@
+//! Buddy Physical Memory Manager (PMM)
+//!
+//! Frames are grouped in blocks of 2^order frames, aligned on their size. To serve an allocation,
+//! a free block of the smallest big enough order is split in two halves ("buddies") until it has
+//! the requested order. When both buddies are free again, they're merged back, so big physically
+//! contiguous allocations stay possible.
+//!
+//! The free frames aren't mapped in virtual memory, so we can't link them together like most buddy
+//! allocators do. Instead, the free blocks of each order are tracked in a bitmap, along with a
+//! count (to find the smallest order with a free block) and a search hint (to skip the empty
+//! start of the bitmap).
+//!
+//! Frames can also be shared (e.g. by the copy-on-write pages of forked processes): each frame
+//! has a reference count, and [FrameAllocator::release_frame] only frees it once the last
+//! reference is dropped.
+
+/// Size of a physical page frame
+pub const FRAME_SIZE: usize = 4096;
+
+/// Number of page frames in the 4GiB physical address space
+pub const TOTAL_FRAMES: usize = 1_048_576; // 4GB / 4096
+
+/// Order of the biggest blocks: 2^10 frames = 4 MiB, the memory covered by a page table
+pub const MAX_ORDER: usize = 10;
+
+/// Maximum number of references to a frame (cf. [FrameAllocator::share_frame])
+pub const MAX_FRAME_REFERENCES: usize = u8::MAX as usize + 1;
+
+const BITMAP_LENGTH: usize = TOTAL_FRAMES / 32; // 32,768 u32 blocks
+
+/// Index of the first word of the `order` blocks bitmap in [FrameAllocator::free_blocks]
+///
+/// There are half as many blocks in each order as in the previous one
+const fn order_offset(order: usize) -> usize {
+	let mut offset = 0;
+	let mut i = 0;
+	while i < order {
+		offset += BITMAP_LENGTH >> i;
+		i += 1;
+	}
+	offset
+}
+
+const FREE_BLOCKS_LENGTH: usize = order_offset(MAX_ORDER + 1); // 65,504 u32 blocks
+
+/// Holds a bitmap representing the 4GiB physical memory divided into 4KiB page frames,
+/// and the free blocks of the buddy allocator
+///
+/// The i-th bit in the bitmap tells us if the i-th page frame is used (1) or not (0).
+/// Every free frame is part of exactly one free block.
+pub struct FrameAllocator {
+	/// 0 = free, 1 = used.
+	bitmap: [u32; BITMAP_LENGTH],
+
+	/// 1 = handed out by [FrameAllocator::alloc_frames] (used frames that aren't allocated are
+	/// reserved, and can't be freed)
+	allocated: [u32; BITMAP_LENGTH],
+
+	/// For each order `n`, the i-th bit tells us if the frames `i * 2^n..(i + 1) * 2^n` are a
+	/// free block of order `n` (cf. [order_offset])
+	free_blocks: [u32; FREE_BLOCKS_LENGTH],
+
+	/// Number of references to each allocated frame, besides the one of its allocation
+	shares: [u8; TOTAL_FRAMES],
+
+	/// Number of free blocks of each order
+	free_counts: [usize; MAX_ORDER + 1],
+
+	/// For each order, the words of its bitmap before this index have no free block
+	search_hints: [usize; MAX_ORDER + 1],
+
+	/// Number of free frames
+	free_frames: usize,
+
+	/// Number of frames given by [FrameAllocator::free_region]
+	usable_frames: usize,
+
+	/// Number of usable frames taken back by [FrameAllocator::reserve_region]
+	reserved_frames: usize,
+
+	/// Number of frames given by [FrameAllocator::add_unusable_region]
+	unusable_frames: usize,
+}
+
+/// Snapshot of the frame counters (cf. [FrameAllocator::stats])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+	/// Frames described by the memory map (usable or not)
+	pub total_frames: usize,
+
+	/// Frames the memory map describes as usable
+	pub usable_frames: usize,
+
+	/// Usable frames reserved for the kernel image, the BIOS, etc...
+	pub reserved_frames: usize,
+
+	/// Frames that can be allocated
+	pub free_frames: usize,
+}
+
+impl FrameAllocator {
+	/// Creates a [FrameAllocator] with every frame marked as used
+	pub const fn new_with_every_frame_reserved() -> Self {
+		// u32::MAX is 0xFFFFFFFF (every bits set to 1)
+		Self {
+			bitmap: [u32::MAX; BITMAP_LENGTH],
+			allocated: [0; BITMAP_LENGTH],
+			free_blocks: [0; FREE_BLOCKS_LENGTH],
+			shares: [0; TOTAL_FRAMES],
+			free_counts: [0; MAX_ORDER + 1],
+			search_hints: [0; MAX_ORDER + 1],
+			free_frames: 0,
+			usable_frames: 0,
+			reserved_frames: 0,
+			unusable_frames: 0,
+		}
+	}
+
+	/// Allocates a 4096 bytes frame
+	///
+	/// Returns None if there is no free memory available
+	pub fn allocate_physical_frame(&mut self) -> Option<u32> {
+		self.alloc_frames(0)
+	}
+
+	/// Deallocates a frame
+	///
+	/// `physical_address` should be the first address of a frame allocated with
+	/// [FrameAllocator::allocate_physical_frame] (cf. [FrameAllocator::free_frames] for invalid
+	/// frees)
+	pub fn deallocate_physical_frame(&mut self, physical_address: u32) {
+		self.free_frames(physical_address, 0);
+	}
+
+	/// Allocates 2^`order` physically contiguous frames, aligned on their total size
+	///
+	/// Returns None if there is no free block big enough
+	pub fn alloc_frames(&mut self, order: usize) -> Option<u32> {
+		assert!(order <= MAX_ORDER, "order {order} is bigger than {MAX_ORDER}");
+
+		let mut block_order = (order..=MAX_ORDER).find(|&o| self.free_counts[o] > 0)?;
+		let mut block = self.take_free_block(block_order);
+
+		// split the block, keeping the lower half and freeing the upper one
+		while block_order > order {
+			block_order -= 1;
+			block *= 2;
+			self.insert_block(block + 1, block_order);
+		}
+
+		let first_frame = block << order;
+		for frame in first_frame..first_frame + (1 << order) {
+			self.set_bit(frame);
+			self.allocated[frame / 32] |= 1 << (frame % 32);
+		}
+
+		Some((first_frame * FRAME_SIZE) as u32)
+	}
+
+	/// Deallocates 2^`order` frames allocated with [FrameAllocator::alloc_frames]
+	///
+	/// `physical_address` should be the first address of the block
+	///
+	/// Freeing frames that aren't allocated (already free, or reserved like the kernel image)
+	/// panics in debug builds. Release builds ignore these frames.
+	pub fn free_frames(&mut self, physical_address: u32, order: usize) {
+		assert!(order <= MAX_ORDER, "order {order} is bigger than {MAX_ORDER}");
+		debug_assert_eq!(
+			physical_address as usize % (FRAME_SIZE << order),
+			0,
+			"free of {physical_address:#x}, which isn't the start of a block of order {order}"
+		);
+
+		let first_frame = physical_address as usize / FRAME_SIZE;
+		let frames = first_frame..first_frame + (1 << order);
+
+		if frames.clone().all(|frame| self.is_frame_allocated(frame)) {
+			for frame in frames {
+				self.shares[frame] = 0;
+				self.clear_bit(frame);
+				self.allocated[frame / 32] &= !(1 << (frame % 32));
+			}
+			self.release_block(first_frame >> order, order);
+			return;
+		}
+
+		if cfg!(debug_assertions) {
+			let frame = frames.clone().find(|&frame| !self.is_frame_allocated(frame)).unwrap();
+			let address = frame * FRAME_SIZE;
+
+			if self.is_frame_used(frame as u32) {
+				panic!("free of reserved frame {address:#x}");
+			}
+			panic!("double free of frame {address:#x}");
+		}
+
+		// free the allocated frames one by one
+		for frame in frames {
+			if self.is_frame_allocated(frame) {
+				self.shares[frame] = 0;
+				self.allocated[frame / 32] &= !(1 << (frame % 32));
+				self.set_frame_free(frame as u32);
+			}
+		}
+	}
+
+	/// Adds a reference to an allocated frame, that [FrameAllocator::release_frame] must drop
+	///
+	/// Returns false if the frame isn't allocated, or already has [MAX_FRAME_REFERENCES]
+	pub fn share_frame(&mut self, physical_address: u32) -> bool {
+		let frame = physical_address as usize / FRAME_SIZE;
+
+		if !self.is_frame_allocated(frame) || self.shares[frame] == u8::MAX {
+			return false;
+		}
+		self.shares[frame] += 1;
+		true
+	}
+
+	/// Drops a reference to an allocated frame, and frees it if it was the last one
+	///
+	/// Returns true if the frame was freed (cf. [FrameAllocator::free_frames] for invalid frees)
+	pub fn release_frame(&mut self, physical_address: u32) -> bool {
+		let frame = physical_address as usize / FRAME_SIZE;
+
+		if self.is_frame_allocated(frame) && self.shares[frame] > 0 {
+			self.shares[frame] -= 1;
+			return false;
+		}
+
+		self.free_frames(physical_address, 0);
+		true
+	}
+
+	/// Returns the number of references to a frame (0 if it isn't allocated)
+	pub fn frame_references(&self, physical_address: u32) -> usize {
+		let frame = physical_address as usize / FRAME_SIZE;
+
+		if !self.is_frame_allocated(frame) {
+			return 0;
+		}
+		self.shares[frame] as usize + 1
+	}
+
+	/// Calls [FrameAllocator::set_frame_used] as many times as needed on a region in memory
+	///
+	/// The frames that were free are counted as reserved
+	pub fn reserve_region(&mut self, start_address: u32, end_address: u32) {
+		// We round DOWN the start, and round UP the end to ensure we only reserve safe frames
+		// e.g. if we receive 3000 and 10000, we reserve 0-12288 instead of 4096-8192
+		let start_frame = start_address / FRAME_SIZE as u32;
+		let end_frame = end_address.div_ceil(FRAME_SIZE as u32);
+
+		for frame in start_frame..end_frame {
+			if !self.is_frame_used(frame) {
+				self.set_frame_used(frame);
+				self.reserved_frames += 1;
+			}
+		}
+	}
+
+	/// Calls [FrameAllocator::set_frame_free] as many times as needed on a region in memory
+	///
+	/// The frames that were used are counted as usable
+	pub fn free_region(&mut self, start_address: u32, end_address: u32) {
+		// We round UP the start, and round DOWN the end to ensure we only free safe frames
+		// e.g. if we receive 3000 and 10000, we only free 4096-8192 instead of 0-12288
+		let start_frame = start_address.div_ceil(FRAME_SIZE as u32);
+		let end_frame = end_address / FRAME_SIZE as u32;
+
+		for frame in start_frame..end_frame {
+			if self.is_frame_used(frame) {
+				self.set_frame_free(frame);
+				self.usable_frames += 1;
+			}
+		}
+	}
+
+	/// Counts a region that the memory map describes as unusable (it stays used)
+	pub fn add_unusable_region(&mut self, start_address: u32, end_address: u32) {
+		let start_frame = start_address / FRAME_SIZE as u32;
+		let end_frame = end_address.div_ceil(FRAME_SIZE as u32);
+
+		self.unusable_frames += (end_frame - start_frame) as usize;
+	}
+
+	/// Returns a snapshot of the frame counters
+	pub fn stats(&self) -> FrameStats {
+		FrameStats {
+			total_frames: self.usable_frames + self.unusable_frames,
+			usable_frames: self.usable_frames,
+			reserved_frames: self.reserved_frames,
+			free_frames: self.free_frames,
+		}
+	}
+
+	/// Marks the `frame_number`-th frame as used, splitting the free block it belongs to
+	pub fn set_frame_used(&mut self, frame_number: u32) {
+		if self.is_frame_used(frame_number) {
+			return;
+		}
+		let frame = frame_number as usize;
+		self.set_bit(frame);
+
+		let order = (0..=MAX_ORDER)
+			.find(|&o| self.is_block_free(frame >> o, o))
+			.expect("free frame outside of any free block");
+		self.remove_block(frame >> order, order);
+
+		// free every half that doesn't contain the frame
+		for o in (0..order).rev() {
+			self.insert_block((frame >> o) ^ 1, o);
+		}
+	}
+
+	/// Marks the `frame_number`-th frame as free, merging it with its free buddies
+	pub fn set_frame_free(&mut self, frame_number: u32) {
+		if !self.is_frame_used(frame_number) {
+			return;
+		}
+		self.clear_bit(frame_number as usize);
+
+		self.release_block(frame_number as usize, 0);
+	}
+
+	/// Returns true if the `frame_number`-th frame is marked as used
+	pub fn is_frame_used(&self, frame_number: u32) -> bool {
+		let index = frame_number / 32;
+		let bit = frame_number % 32;
+
+		self.bitmap[index as usize] & (1 << bit) != 0
+	}
+
+	fn is_frame_allocated(&self, frame: usize) -> bool {
+		self.allocated[frame / 32] & (1 << (frame % 32)) != 0
+	}
+
+	fn set_bit(&mut self, frame: usize) {
+		// Bitwise OR assigns 1 to the exact bit without changing the rest
+		self.bitmap[frame / 32] |= 1 << (frame % 32);
+		self.free_frames -= 1;
+	}
+
+	fn clear_bit(&mut self, frame: usize) {
+		// Bitwise AND NOT assigns 0 to the exact bit without changing the rest
+		self.bitmap[frame / 32] &= !(1 << (frame % 32));
+		self.free_frames += 1;
+	}
+
+	/// Adds a block to the free blocks, merging it with its buddy as long as it's free
+	fn release_block(&mut self, mut block: usize, mut order: usize) {
+		while order < MAX_ORDER && self.is_block_free(block ^ 1, order) {
+			self.remove_block(block ^ 1, order);
+			block /= 2;
+			order += 1;
+		}
+
+		self.insert_block(block, order);
+	}
+
+	/// Removes the lowest free block of `order` from the free blocks, and returns it
+	///
+	/// `order` must have at least one free block
+	fn take_free_block(&mut self, order: usize) -> usize {
+		let words = &self.free_blocks[order_offset(order)..order_offset(order + 1)];
+
+		let (index, word) = words
+			.iter()
+			.enumerate()
+			.skip(self.search_hints[order])
+			.find(|(_, word)| **word != 0)
+			.expect("free block count out of sync with the bitmap");
+
+		let block = index * 32 + word.trailing_zeros() as usize;
+		self.search_hints[order] = index;
+		self.remove_block(block, order);
+
+		block
+	}
+
+	fn is_block_free(&self, block: usize, order: usize) -> bool {
+		let word = order_offset(order) + block / 32;
+		self.free_blocks[word] & (1 << (block % 32)) != 0
+	}
+
+	fn insert_block(&mut self, block: usize, order: usize) {
+		self.free_blocks[order_offset(order) + block / 32] |= 1 << (block % 32);
+		self.free_counts[order] += 1;
+		self.search_hints[order] = self.search_hints[order].min(block / 32);
+	}
+
+	fn remove_block(&mut self, block: usize, order: usize) {
+		self.free_blocks[order_offset(order) + block / 32] &= !(1 << (block % 32));
+		self.free_counts[order] -= 1;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeSet;
+
+	use super::*;
+	use crate::test_utils::XorShift;
+
+	const FRAME: u32 = FRAME_SIZE as u32;
+
+	/// First frame handed to the allocators below (1 MiB, like on real hardware)
+	const FIRST_FREE_FRAME: u32 = 256;
+
+	/// Same as [FrameAllocator::new_with_every_frame_reserved], but built on the heap, as it
+	/// doesn't fit on the stack of the test threads
+	fn reserved_allocator() -> Box<FrameAllocator> {
+		// Safety: every field is an integer array or an integer
+		let mut allocator: Box<FrameAllocator> = unsafe { Box::new_zeroed().assume_init() };
+		allocator.bitmap.fill(u32::MAX);
+		allocator
+	}
+
+	/// Returns an allocator where only the frames `FIRST_FREE_FRAME..FIRST_FREE_FRAME + count`
+	/// are free
+	fn allocator_with_free_frames(count: u32) -> Box<FrameAllocator> {
+		let mut allocator = reserved_allocator();
+		allocator.free_region(FIRST_FREE_FRAME * FRAME, (FIRST_FREE_FRAME + count) * FRAME);
+		allocator
+	}
+
+	#[test]
+	fn shared_frames_are_freed_by_the_last_release() {
+		let mut allocator = allocator_with_free_frames(1);
+		let frame = allocator.allocate_physical_frame().unwrap();
+		assert_eq!(allocator.frame_references(frame), 1);
+
+		assert!(allocator.share_frame(frame));
+		assert!(allocator.share_frame(frame));
+		assert_eq!(allocator.frame_references(frame), 3);
+
+		assert!(!allocator.release_frame(frame));
+		assert!(!allocator.release_frame(frame));
+		assert_eq!(allocator.allocate_physical_frame(), None);
+
+		assert!(allocator.release_frame(frame));
+		assert_eq!(allocator.frame_references(frame), 0);
+		assert_eq!(allocator.allocate_physical_frame(), Some(frame));
+	}
+
+	#[test]
+	fn only_allocated_frames_can_be_shared() {
+		let mut allocator = allocator_with_free_frames(1);
+
+		// free, then reserved
+		assert!(!allocator.share_frame(FIRST_FREE_FRAME * FRAME));
+		assert!(!allocator.share_frame(0));
+		assert_eq!(allocator.frame_references(0), 0);
+
+		let frame = allocator.allocate_physical_frame().unwrap();
+		for _ in 1..MAX_FRAME_REFERENCES {
+			assert!(allocator.share_frame(frame));
+		}
+		assert!(!allocator.share_frame(frame));
+		assert_eq!(allocator.frame_references(frame), MAX_FRAME_REFERENCES);
+	}
+
+	#[test]
+	fn freeing_a_shared_frame_drops_its_references() {
+		let mut allocator = allocator_with_free_frames(1);
+		let frame = allocator.allocate_physical_frame().unwrap();
+		assert!(allocator.share_frame(frame));
+
+		allocator.deallocate_physical_frame(frame);
+
+		let frame = allocator.allocate_physical_frame().unwrap();
+		assert_eq!(allocator.frame_references(frame), 1);
+	}
+
+	#[test]
+	fn every_frame_starts_reserved() {
+		let mut allocator = reserved_allocator();
+
+		assert!(allocator.is_frame_used(0));
+		assert!(allocator.is_frame_used(TOTAL_FRAMES as u32 - 1));
+		assert_eq!(allocator.allocate_physical_frame(), None);
+	}
+
+	#[test]
+	fn free_region_rounds_inwards() {
+		let mut allocator = reserved_allocator();
+
+		// 3000..10000 only fully covers the 4096..8192 frame
+		allocator.free_region(3000, 10000);
+
+		assert!(allocator.is_frame_used(0));
+		assert!(!allocator.is_frame_used(1));
+		assert!(allocator.is_frame_used(2));
+	}
+
+	#[test]
+	fn free_region_with_aligned_bounds() {
+		let mut allocator = reserved_allocator();
+
+		allocator.free_region(FRAME, 3 * FRAME);
+
+		assert!(allocator.is_frame_used(0));
+		assert!(!allocator.is_frame_used(1));
+		assert!(!allocator.is_frame_used(2));
+		assert!(allocator.is_frame_used(3));
+	}
+
+	#[test]
+	fn free_region_smaller_than_a_frame_frees_nothing() {
+		let mut allocator = reserved_allocator();
+
+		allocator.free_region(FRAME + 1, 2 * FRAME + 1);
+
+		assert!(allocator.is_frame_used(1));
+		assert!(allocator.is_frame_used(2));
+		assert_eq!(allocator.allocate_physical_frame(), None);
+	}
+
+	#[test]
+	fn reserve_region_rounds_outwards() {
+		let mut allocator = allocator_with_free_frames(0);
+		allocator.free_region(0, 16 * FRAME);
+
+		// 3000..10000 touches the 0..4096, 4096..8192 and 8192..12288 frames
+		allocator.reserve_region(3000, 10000);
+
+		assert!(allocator.is_frame_used(0));
+		assert!(allocator.is_frame_used(1));
+		assert!(allocator.is_frame_used(2));
+		assert!(!allocator.is_frame_used(3));
+	}
+
+	#[test]
+	fn reserve_region_with_aligned_bounds() {
+		let mut allocator = allocator_with_free_frames(0);
+		allocator.free_region(0, 16 * FRAME);
+
+		allocator.reserve_region(FRAME, 3 * FRAME);
+
+		assert!(!allocator.is_frame_used(0));
+		assert!(allocator.is_frame_used(1));
+		assert!(allocator.is_frame_used(2));
+		assert!(!allocator.is_frame_used(3));
+	}
+
+	#[test]
+	fn reserved_region_is_never_allocated() {
+		let mut allocator = allocator_with_free_frames(8);
+		allocator.reserve_region((FIRST_FREE_FRAME + 2) * FRAME, (FIRST_FREE_FRAME + 4) * FRAME);
+
+		while let Some(addr) = allocator.allocate_physical_frame() {
+			let frame = addr / FRAME;
+			assert!(frame != FIRST_FREE_FRAME + 2 && frame != FIRST_FREE_FRAME + 3);
+		}
+	}
+
+	#[test]
+	fn allocate_until_exhausted() {
+		const COUNT: u32 = 100;
+		let mut allocator = allocator_with_free_frames(COUNT);
+		let mut allocated = BTreeSet::new();
+
+		while let Some(addr) = allocator.allocate_physical_frame() {
+			assert_eq!(addr % FRAME, 0, "{addr:#x} is not page-aligned");
+			assert!(allocated.insert(addr), "{addr:#x} allocated twice");
+		}
+
+		assert_eq!(allocated.len(), COUNT as usize);
+		let expected = (FIRST_FREE_FRAME..FIRST_FREE_FRAME + COUNT).map(|frame| frame * FRAME);
+		assert!(allocated.iter().copied().eq(expected));
+
+		// exhausted allocators stay exhausted
+		assert_eq!(allocator.allocate_physical_frame(), None);
+	}
+
+	#[test]
+	fn deallocated_frame_is_reused() {
+		let mut allocator = allocator_with_free_frames(4);
+		let frames: Vec<u32> =
+			core::iter::from_fn(|| allocator.allocate_physical_frame()).collect();
+
+		allocator.deallocate_physical_frame(frames[2]);
+
+		assert_eq!(allocator.allocate_physical_frame(), Some(frames[2]));
+		assert_eq!(allocator.allocate_physical_frame(), None);
+	}
+
+	#[test]
+	#[cfg(not(debug_assertions))]
+	fn double_free_does_not_duplicate_a_frame() {
+		let mut allocator = allocator_with_free_frames(2);
+		let first = allocator.allocate_physical_frame().unwrap();
+		let second = allocator.allocate_physical_frame().unwrap();
+
+		allocator.deallocate_physical_frame(first);
+		allocator.deallocate_physical_frame(first);
+
+		assert_eq!(allocator.allocate_physical_frame(), Some(first));
+		assert_eq!(allocator.allocate_physical_frame(), None);
+		assert!(allocator.is_frame_used(second / FRAME));
+	}
+
+	#[test]
+	#[cfg(not(debug_assertions))]
+	fn partially_freed_block_drops_the_references() {
+		let mut allocator = allocator_with_free_frames(4);
+		let block = allocator.alloc_frames(2).unwrap();
+		assert!(allocator.share_frame(block + FRAME));
+
+		// frees the 3rd frame of the block on its own, then the others one by one
+		allocator.deallocate_physical_frame(block + 2 * FRAME);
+		allocator.free_frames(block, 2);
+
+		assert_eq!(allocator.alloc_frames(2), Some(block));
+		assert_eq!(allocator.frame_references(block + FRAME), 1);
+	}
+
+	#[test]
+	#[cfg(debug_assertions)]
+	#[should_panic(expected = "double free of frame 0x100000")]
+	fn double_free_panics() {
+		let mut allocator = allocator_with_free_frames(2);
+		let frame = allocator.allocate_physical_frame().unwrap();
+
+		allocator.deallocate_physical_frame(frame);
+		allocator.deallocate_physical_frame(frame);
+	}
+
+	#[test]
+	#[cfg(debug_assertions)]
+	#[should_panic(expected = "free of reserved frame 0x101000")]
+	fn free_of_reserved_frame_panics() {
+		let mut allocator = allocator_with_free_frames(4);
+		allocator.reserve_region((FIRST_FREE_FRAME + 1) * FRAME, (FIRST_FREE_FRAME + 2) * FRAME);
+
+		allocator.deallocate_physical_frame((FIRST_FREE_FRAME + 1) * FRAME);
+	}
+
+	#[test]
+	#[cfg(debug_assertions)]
+	#[should_panic(expected = "double free of frame 0x102000")]
+	fn partially_freed_block_panics() {
+		let mut allocator = allocator_with_free_frames(4);
+		let block = allocator.alloc_frames(2).unwrap();
+
+		// frees the 3rd frame of the block on its own
+		allocator.deallocate_physical_frame(block + 2 * FRAME);
+		allocator.free_frames(block, 2);
+	}
+
+	#[test]
+	fn random_sequences_match_model() {
+		const COUNT: u32 = 200;
+
+		for seed in 1..=20 {
+			let mut rng = XorShift::new(seed);
+			let mut allocator = allocator_with_free_frames(COUNT);
+
+			// model: the set of free frames
+			let mut free: BTreeSet<u32> = (FIRST_FREE_FRAME..FIRST_FREE_FRAME + COUNT).collect();
+			let mut allocated: Vec<u32> = Vec::new();
+
+			for _ in 0..2000 {
+				if allocated.is_empty() || rng.chance(60) {
+					match allocator.allocate_physical_frame() {
+						Some(addr) => {
+							let frame = addr / FRAME;
+							assert!(free.remove(&frame), "seed {seed}: frame {frame} wasn't free");
+							allocated.push(frame);
+						}
+						None => assert!(free.is_empty(), "seed {seed}: free frames left"),
+					}
+				} else {
+					let frame = allocated.swap_remove(rng.next_in(0..allocated.len()));
+					allocator.deallocate_physical_frame(frame * FRAME);
+					free.insert(frame);
+				}
+			}
+
+			for frame in FIRST_FREE_FRAME - 1..=FIRST_FREE_FRAME + COUNT {
+				assert_eq!(allocator.is_frame_used(frame), !free.contains(&frame), "seed {seed}");
+			}
+		}
+	}
+
+	#[test]
+	fn counters_follow_the_memory_map() {
+		let mut allocator = reserved_allocator();
+
+		// 0..640 KiB and 1..2 MiB usable, with a hole for the BIOS
+		allocator.free_region(0, 160 * FRAME);
+		allocator.add_unusable_region(160 * FRAME, 256 * FRAME);
+		allocator.free_region(256 * FRAME, 512 * FRAME);
+
+		// low memory and a "kernel image" reserved
+		allocator.reserve_region(0, 256 * FRAME);
+		allocator.reserve_region(256 * FRAME, 300 * FRAME);
+
+		assert_eq!(
+			allocator.stats(),
+			FrameStats {
+				total_frames: 512,
+				usable_frames: 416,
+				reserved_frames: 160 + 44,
+				free_frames: 212,
+			}
+		);
+	}
+
+	#[test]
+	fn free_counter_follows_allocations() {
+		let mut allocator = allocator_with_free_frames(64);
+
+		let block = allocator.alloc_frames(3).unwrap();
+		let frame = allocator.allocate_physical_frame().unwrap();
+		assert_eq!(allocator.stats().free_frames, 64 - 8 - 1);
+
+		allocator.deallocate_physical_frame(frame);
+		allocator.free_frames(block, 3);
+		assert_eq!(allocator.stats().free_frames, 64);
+		assert_eq!(allocator.stats().usable_frames, 64);
+	}
+
+	#[test]
+	fn blocks_are_aligned_on_their_size() {
+		let mut allocator = allocator_with_free_frames(4096);
+
+		for order in 0..=MAX_ORDER {
+			let addr = allocator.alloc_frames(order).expect("Out of memory");
+			let frame = addr / FRAME;
+
+			assert_eq!(frame % (1 << order), 0, "order {order}: frame {frame} is misaligned");
+			for f in frame..frame + (1 << order) {
+				assert!(allocator.is_frame_used(f));
+			}
+		}
+	}
+
+	#[test]
+	fn buddies_are_merged_back() {
+		// exactly one block of the biggest order
+		const BLOCK: u32 = 1 << MAX_ORDER;
+		let mut allocator = reserved_allocator();
+		allocator.free_region(BLOCK * FRAME, 2 * BLOCK * FRAME);
+
+		let frames: Vec<u32> =
+			core::iter::from_fn(|| allocator.allocate_physical_frame()).collect();
+		assert_eq!(frames.len(), 1 << MAX_ORDER);
+		assert_eq!(allocator.alloc_frames(1), None);
+
+		for frame in frames.into_iter().rev() {
+			allocator.deallocate_physical_frame(frame);
+		}
+
+		assert_eq!(allocator.alloc_frames(MAX_ORDER), Some(BLOCK * FRAME));
+		assert_eq!(allocator.allocate_physical_frame(), None);
+	}
+
+	#[test]
+	fn fragmented_memory_has_no_big_block() {
+		let mut allocator = allocator_with_free_frames(16);
+
+		let frames: Vec<u32> =
+			core::iter::from_fn(|| allocator.allocate_physical_frame()).collect();
+
+		// free every other frame: 8 free frames, but no 2 contiguous ones
+		for frame in frames.iter().step_by(2) {
+			allocator.deallocate_physical_frame(*frame);
+		}
+		assert_eq!(allocator.alloc_frames(1), None);
+
+		allocator.deallocate_physical_frame(frames[1]);
+		assert_eq!(allocator.alloc_frames(1), Some(frames[0]));
+	}
+
+	#[test]
+	fn reserving_a_frame_splits_its_block() {
+		let mut allocator = allocator_with_free_frames(1 << MAX_ORDER);
+		let reserved = FIRST_FREE_FRAME + 5;
+		allocator.set_frame_used(reserved);
+
+		let frames: Vec<u32> =
+			core::iter::from_fn(|| allocator.allocate_physical_frame()).collect();
+
+		assert_eq!(frames.len(), (1 << MAX_ORDER) - 1);
+		assert!(!frames.contains(&(reserved * FRAME)));
+	}
+
+	#[test]
+	fn unaligned_region_is_split_in_smaller_blocks() {
+		// frames 3..13: blocks 3, 4..8, 8..12, 12
+		let mut allocator = reserved_allocator();
+		allocator.free_region(3 * FRAME, 13 * FRAME);
+
+		assert_eq!(allocator.alloc_frames(3), None);
+		assert_eq!(allocator.alloc_frames(2), Some(4 * FRAME));
+		assert_eq!(allocator.alloc_frames(2), Some(8 * FRAME));
+		assert_eq!(allocator.alloc_frames(1), None);
+		assert_eq!(allocator.alloc_frames(0), Some(3 * FRAME));
+		assert_eq!(allocator.alloc_frames(0), Some(12 * FRAME));
+	}
+
+	#[test]
+	fn random_orders_match_model() {
+		const COUNT: u32 = 512;
+
+		for seed in 1..=20 {
+			let mut rng = XorShift::new(seed);
+			let mut allocator = allocator_with_free_frames(COUNT);
+
+			// model: the set of free frames
+			let mut free: BTreeSet<u32> = (FIRST_FREE_FRAME..FIRST_FREE_FRAME + COUNT).collect();
+			let mut allocated: Vec<(u32, usize)> = Vec::new();
+
+			for _ in 0..2000 {
+				if allocated.is_empty() || rng.chance(60) {
+					let order = rng.next_in(0..6);
+					let Some(addr) = allocator.alloc_frames(order) else {
+						continue;
+					};
+
+					let first = addr / FRAME;
+					assert_eq!(first % (1 << order), 0, "seed {seed}: misaligned block");
+					for frame in first..first + (1 << order) {
+						assert!(free.remove(&frame), "seed {seed}: frame {frame} wasn't free");
+					}
+					allocated.push((first, order));
+				} else {
+					let (first, order) = allocated.swap_remove(rng.next_in(0..allocated.len()));
+					allocator.free_frames(first * FRAME, order);
+					free.extend(first..first + (1 << order));
+				}
+			}
+
+			for frame in FIRST_FREE_FRAME - 1..=FIRST_FREE_FRAME + COUNT {
+				assert_eq!(allocator.is_frame_used(frame), !free.contains(&frame), "seed {seed}");
+			}
+
+			// once everything is freed, the buddies are merged back
+			for (first, order) in allocated {
+				allocator.free_frames(first * FRAME, order);
+			}
+			for _ in 0..COUNT / (1 << 8) {
+				assert!(allocator.alloc_frames(8).is_some(), "seed {seed}: memory is fragmented");
+			}
+		}
+	}
+}
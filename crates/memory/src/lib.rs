@@ -0,0 +1,24 @@
+//! Memory management data structures of the kernel
+//!
+//! These don't touch any hardware (no paging, no port I/O), so they also build on the host
+//! target, where they're unit tested with `make test-host`:
+//!  - [FrameAllocator]: the buddy Physical Memory Manager (PMM)
+//!  - [LinkedListAllocator]: the free list behind the kernel heap
+//!  - [SlabCache]: the fixed-size object caches in front of it
+//!  - [redzone]: the debug checks wrapped around the heap allocations
+//!
+//! [FrameAllocator]: frame_allocator::FrameAllocator
+//! [LinkedListAllocator]: linked_list::LinkedListAllocator
+//! [SlabCache]: slab::SlabCache
+
+#![cfg_attr(not(test), no_std)]
+#![warn(missing_docs)]
+#![allow(clippy::tabs_in_doc_comments)]
+
+pub mod frame_allocator;
+pub mod linked_list;
+pub mod redzone;
+pub mod slab;
+
+#[cfg(test)]
+mod test_utils;
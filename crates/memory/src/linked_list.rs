@@ -0,0 +1,673 @@
+//! Free list allocator behind the kernel heap
+//!
+//! The free regions are kept sorted by address, so a freed region can be merged with its free
+//! neighbours. Without this, the heap would end up fragmented into small regions, and big
+//! allocations would fail even with plenty of free bytes.
+
+use core::alloc::Layout;
+
+/// Size of the smallest free region (it must be able to hold its [ListNode])
+pub const MIN_REGION_SIZE: usize = size_of::<ListNode>();
+
+/// Keeps the free memory regions of the heap in a singly linked list, sorted by address
+///
+/// Each free region stores its own [ListNode] in its first bytes.
+/// Two free regions are never adjacent: they're merged when the second one is freed.
+pub struct LinkedListAllocator {
+	head: Option<*mut ListNode>,
+}
+
+unsafe impl Send for LinkedListAllocator {}
+
+impl Default for LinkedListAllocator {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl LinkedListAllocator {
+	/// Creates an empty [LinkedListAllocator]
+	pub const fn new() -> Self {
+		Self {
+			head: None,
+		}
+	}
+
+	/// Initialize the allocator
+	///
+	/// # Safety
+	///  - `heap_start` and `heap_address` must design a valid and unused memory region
+	pub unsafe fn init(&mut self, heap_start_vaddr: usize, heap_size: usize) {
+		// the nodes must be aligned, so we drop the misaligned bytes at both ends
+		let start = align_up(heap_start_vaddr, align_of::<ListNode>());
+		let end = align_down(heap_start_vaddr + heap_size, align_of::<ListNode>());
+
+		self.add_free_region(start, end - start);
+	}
+
+	/// Add a memory region to the list, merging it with its adjacent free regions
+	///
+	/// `vaddr` and `size` must design a valid and unused memory region, aligned on a [ListNode]
+	/// (cf. [LinkedListAllocator::size_align])
+	pub fn add_free_region(&mut self, vaddr: usize, size: usize) {
+		// make sure the region can hold a ListNode
+		assert!(size >= MIN_REGION_SIZE);
+		assert_eq!(vaddr % align_of::<ListNode>(), 0, "misaligned free region");
+		assert_eq!(size % align_of::<ListNode>(), 0, "misaligned free region size");
+
+		let (prev_option, next_option) = self.find_neighbours(vaddr);
+
+		let mut node = ListNode::new(size);
+		node.next = next_option;
+
+		// merge with the next region if they touch
+		if let Some(next_ptr) = next_option {
+			let next = unsafe { &*next_ptr };
+			if vaddr + size == next.start_vaddr() {
+				node.size += next.size;
+				node.next = next.next;
+			}
+		}
+
+		// merge with the previous region if they touch
+		if let Some(prev_ptr) = prev_option {
+			let prev = unsafe { &mut *prev_ptr };
+			if prev.end_vaddr() == vaddr {
+				prev.size += node.size;
+				prev.next = node.next;
+				return;
+			}
+		}
+
+		// store `node` at address `vaddr`
+		let node_ptr = vaddr as *mut ListNode;
+		unsafe { core::ptr::write(node_ptr, node) };
+
+		self.link_after(prev_option, Some(node_ptr));
+	}
+
+	/// Finds a free region that fits `layout` and removes it from the list
+	///
+	/// The unused bytes before and after the allocation stay in the list.
+	///
+	/// Returns None if no region is big enough
+	pub fn take_free_region(&mut self, layout: core::alloc::Layout) -> Option<*mut u8> {
+		let adjusted_layout = Self::size_align(layout);
+
+		let mut current_option = self.head;
+		let mut prev_option: Option<*mut ListNode> = None;
+
+		while let Some(current_ptr) = current_option {
+			let current = unsafe { &mut *current_ptr };
+
+			if let Some(alloc_start) = current.find_fit(adjusted_layout) {
+				self.carve(prev_option, current_ptr, alloc_start, adjusted_layout.size());
+				return Some(alloc_start as *mut u8);
+			}
+
+			prev_option = Some(current_ptr);
+			current_option = current.next;
+		}
+
+		// No space left
+		None
+	}
+
+	/// Tries to resize the allocated region at `vaddr` from `old_size` to `new_size` bytes without
+	/// moving it, by giving back its tail or taking the start of the free region right after it.
+	///
+	/// Both sizes must come from [LinkedListAllocator::size_align].
+	///
+	/// Returns false if the allocation must be moved
+	pub fn resize_in_place(&mut self, vaddr: usize, old_size: usize, new_size: usize) -> bool {
+		if new_size == old_size {
+			return true;
+		}
+
+		let old_end = vaddr + old_size;
+		let new_end = vaddr + new_size;
+
+		let (prev_option, next_option) = self.find_neighbours(vaddr);
+		let next_free =
+			next_option.filter(|&next_ptr| unsafe { (*next_ptr).start_vaddr() } == old_end);
+
+		if new_size < old_size {
+			let released = old_size - new_size;
+
+			if released >= MIN_REGION_SIZE {
+				self.add_free_region(new_end, released);
+				return true;
+			}
+
+			// too small to hold a node: only possible if it's merged with the next free region
+			let Some(next_ptr) = next_free else {
+				return false;
+			};
+			let next = unsafe { core::ptr::read(next_ptr) };
+			self.link_after(prev_option, next.next);
+			self.add_free_region(new_end, released + next.size);
+			return true;
+		}
+
+		let Some(next_ptr) = next_free else {
+			return false;
+		};
+		let next = unsafe { core::ptr::read(next_ptr) };
+		let needed = new_size - old_size;
+
+		if next.size == needed {
+			self.link_after(prev_option, next.next);
+			return true;
+		}
+
+		if next.size < needed + MIN_REGION_SIZE {
+			return false;
+		}
+
+		// move the next region's node right after the grown allocation
+		let moved_ptr = new_end as *mut ListNode;
+		unsafe {
+			core::ptr::write(
+				moved_ptr,
+				ListNode {
+					size: next.size - needed,
+					next: next.next,
+				},
+			)
+		};
+		self.link_after(prev_option, Some(moved_ptr));
+
+		true
+	}
+
+	/// Returns the `(start address, size)` of every free region, sorted by address
+	pub fn free_regions(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+		let mut current_option = self.head;
+
+		core::iter::from_fn(move || {
+			let current = unsafe { &*current_option? };
+			current_option = current.next;
+			Some((current.start_vaddr(), current.size))
+		})
+	}
+
+	/// Adjusts `layout` so the allocated region can hold a [ListNode] once it is freed
+	///
+	/// The size is at least `size_of::<ListNode>()`, and both the size and the alignment are
+	/// multiples of `align_of::<ListNode>()`, so the free regions never hold misaligned nodes.
+	///
+	/// Deallocations must add back a region of `size_align(layout).size()` bytes
+	pub fn size_align(layout: Layout) -> Layout {
+		let layout = layout
+			.align_to(align_of::<ListNode>())
+			.expect("adjusting alignment failed")
+			.pad_to_align();
+		let size = layout.size().max(size_of::<ListNode>());
+
+		Layout::from_size_align(size, layout.align()).unwrap()
+	}
+
+	/// Returns the last free region before `vaddr` and the first one after it
+	fn find_neighbours(&self, vaddr: usize) -> (Option<*mut ListNode>, Option<*mut ListNode>) {
+		let mut prev_option: Option<*mut ListNode> = None;
+		let mut current_option = self.head;
+
+		while let Some(current_ptr) = current_option {
+			let current = unsafe { &*current_ptr };
+			if current.start_vaddr() > vaddr {
+				break;
+			}
+
+			prev_option = Some(current_ptr);
+			current_option = current.next;
+		}
+
+		(prev_option, current_option)
+	}
+
+	/// Removes `[alloc_start, alloc_start + size)` from the `current_ptr` region
+	///
+	/// The bytes before `alloc_start` stay in the `current_ptr` node, and the bytes after the
+	/// allocation get their own node.
+	fn carve(
+		&mut self,
+		prev_option: Option<*mut ListNode>,
+		current_ptr: *mut ListNode,
+		alloc_start: usize,
+		size: usize,
+	) {
+		let current = unsafe { &mut *current_ptr };
+		let alloc_end = alloc_start + size;
+
+		let mut next_option = current.next;
+
+		// the unused tail becomes a new free region
+		if alloc_end < current.end_vaddr() {
+			let tail_ptr = alloc_end as *mut ListNode;
+			let tail = ListNode {
+				size: current.end_vaddr() - alloc_end,
+				next: next_option,
+			};
+			unsafe { core::ptr::write(tail_ptr, tail) };
+			next_option = Some(tail_ptr);
+		}
+
+		if alloc_start > current.start_vaddr() {
+			// the unused head stays in the current node
+			current.size = alloc_start - current.start_vaddr();
+			current.next = next_option;
+		} else {
+			self.link_after(prev_option, next_option);
+		}
+	}
+
+	/// Makes `node_option` follow `prev_option` (or become the head if `prev_option` is None)
+	fn link_after(
+		&mut self,
+		prev_option: Option<*mut ListNode>,
+		node_option: Option<*mut ListNode>,
+	) {
+		if let Some(prev_ptr) = prev_option {
+			unsafe { (*prev_ptr).next = node_option };
+		} else {
+			self.head = node_option;
+		}
+	}
+}
+
+/// Header of a free region, written at its start address
+#[derive(Debug, Clone, Copy)]
+pub struct ListNode {
+	/// Size of the free region, including this header
+	pub size: usize,
+
+	/// Next free region (at a higher address)
+	pub next: Option<*mut ListNode>,
+}
+
+impl ListNode {
+	/// Creates a [ListNode] for a free region of `size` bytes
+	pub const fn new(size: usize) -> Self {
+		Self {
+			size,
+			next: None,
+		}
+	}
+
+	/// First address of the free region
+	pub fn start_vaddr(&self) -> usize {
+		self as *const Self as usize
+	}
+
+	/// Address right after the end of the free region
+	pub fn end_vaddr(&self) -> usize {
+		self.start_vaddr() + self.size
+	}
+
+	/// Returns the address where `layout` (adjusted by [LinkedListAllocator::size_align]) fits
+	/// in this region, or None if it doesn't
+	///
+	/// The unused bytes before and after the allocation must either be empty or able to hold
+	/// a [ListNode], so they can stay in the list.
+	fn find_fit(&self, layout: Layout) -> Option<usize> {
+		let (start, end) = (self.start_vaddr(), self.end_vaddr());
+		let (size, align) = (layout.size(), layout.align());
+
+		let is_valid_slack = |slack: usize| slack == 0 || slack >= MIN_REGION_SIZE;
+		let fits = |alloc_start: usize| {
+			alloc_start >= start
+				&& is_valid_slack(alloc_start - start)
+				&& alloc_start
+					.checked_add(size)
+					.is_some_and(|alloc_end| alloc_end <= end && is_valid_slack(end - alloc_end))
+		};
+
+		// first, at the lowest address (using the leading slack if the start is misaligned)
+		let mut alloc_start = align_up(start, align);
+		if !is_valid_slack(alloc_start - start) {
+			alloc_start = align_up(start + MIN_REGION_SIZE, align);
+		}
+		if fits(alloc_start) {
+			return Some(alloc_start);
+		}
+
+		// then, at the highest address, if the tail slack was too small to hold a node
+		let alloc_start = align_down(end.checked_sub(size)?, align);
+		fits(alloc_start).then_some(alloc_start)
+	}
+}
+
+/// Align `addr` downwards to `align`
+///
+/// `align` must be a power of 2
+pub const fn align_down(addr: usize, align: usize) -> usize {
+	addr & !(align - 1)
+}
+
+/// Align `addr` upwards to `align`
+///
+/// `align` must be a power of 2
+pub const fn align_up(addr: usize, align: usize) -> usize {
+	align_down(addr + align - 1, align)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_utils::{
+		HostRegion,
+		XorShift,
+	};
+
+	const HEAP_SIZE: usize = 64 * 1024;
+
+	fn heap() -> (HostRegion, LinkedListAllocator) {
+		let region = HostRegion::new(HEAP_SIZE);
+		let mut allocator = LinkedListAllocator::new();
+		unsafe { allocator.init(region.start(), region.size()) };
+
+		(region, allocator)
+	}
+
+	/// Frees `ptr` the same way the kernel's `GlobalAlloc::dealloc` does
+	fn free(allocator: &mut LinkedListAllocator, ptr: *mut u8, layout: Layout) {
+		allocator.add_free_region(ptr as usize, LinkedListAllocator::size_align(layout).size());
+	}
+
+	/// Returns the `(start, end)` of every free region, in list order
+	fn regions(allocator: &LinkedListAllocator) -> Vec<(usize, usize)> {
+		allocator.free_regions().map(|(start, size)| (start, start + size)).collect()
+	}
+
+	fn free_bytes(allocator: &LinkedListAllocator) -> usize {
+		regions(allocator).iter().map(|(start, end)| end - start).sum()
+	}
+
+	/// Checks that the free regions are sorted, big enough to hold a node, and never adjacent
+	fn assert_sorted_and_merged(allocator: &LinkedListAllocator) {
+		let regions = regions(allocator);
+
+		for &(start, end) in &regions {
+			assert!(end - start >= MIN_REGION_SIZE, "region {start:#x}..{end:#x} is too small");
+			assert_eq!(start % align_of::<ListNode>(), 0, "region {start:#x} is misaligned");
+		}
+		for pair in regions.windows(2) {
+			let ((_, prev_end), (next_start, _)) = (pair[0], pair[1]);
+			assert!(prev_end < next_start, "regions are unsorted or unmerged: {pair:x?}");
+		}
+	}
+
+	#[test]
+	fn size_align_can_hold_a_list_node() {
+		for size in 1..64 {
+			for align in [1, 2, 4, 8, 16, 64] {
+				let layout = Layout::from_size_align(size, align).unwrap();
+				let adjusted = LinkedListAllocator::size_align(layout);
+
+				assert!(adjusted.size() >= size.max(size_of::<ListNode>()));
+				assert!(adjusted.align() >= align.max(align_of::<ListNode>()));
+				assert_eq!(adjusted.size() % align_of::<ListNode>(), 0);
+			}
+		}
+	}
+
+	#[test]
+	fn take_free_region_respects_alignment() {
+		let (region, mut allocator) = heap();
+
+		for shift in 0..=12 {
+			let align = 1 << shift;
+			for size in [1, 3, 8, 24, 100] {
+				let layout = Layout::from_size_align(size, align).unwrap();
+				let ptr = allocator.take_free_region(layout).expect("out of memory") as usize;
+
+				assert_eq!(ptr % align, 0, "{ptr:#x} is not aligned to {align}");
+				assert!(ptr >= region.start() && ptr + size <= region.end());
+			}
+		}
+	}
+
+	#[test]
+	fn allocations_do_not_overlap() {
+		let (_region, mut allocator) = heap();
+		let layout = Layout::from_size_align(48, 8).unwrap();
+
+		let ptrs: Vec<*mut u8> =
+			(0..100).map(|_| allocator.take_free_region(layout).unwrap()).collect();
+
+		for (i, ptr) in ptrs.iter().enumerate() {
+			unsafe { ptr.write_bytes(i as u8, layout.size()) };
+		}
+		for (i, ptr) in ptrs.iter().enumerate() {
+			let bytes = unsafe { core::slice::from_raw_parts(*ptr, layout.size()) };
+			assert!(bytes.iter().all(|&b| b == i as u8), "allocation {i} was overwritten");
+		}
+	}
+
+	#[test]
+	fn allocate_until_exhausted() {
+		let (_region, mut allocator) = heap();
+		let layout = Layout::from_size_align(256, 8).unwrap();
+
+		let mut ptrs = Vec::new();
+		while let Some(ptr) = allocator.take_free_region(layout) {
+			ptrs.push(ptr);
+		}
+
+		assert!(!ptrs.is_empty());
+		assert!(ptrs.len() * layout.size() <= HEAP_SIZE);
+
+		// no byte is lost once everything is freed
+		for ptr in ptrs.drain(..) {
+			free(&mut allocator, ptr, layout);
+		}
+		assert_eq!(free_bytes(&allocator), HEAP_SIZE);
+
+		// and every block can be handed out again
+		while let Some(ptr) = allocator.take_free_region(layout) {
+			ptrs.push(ptr);
+		}
+		assert_eq!(ptrs.len(), HEAP_SIZE / layout.size());
+	}
+
+	#[test]
+	fn exact_fit_takes_the_whole_region() {
+		let (region, mut allocator) = heap();
+		let layout = Layout::from_size_align(HEAP_SIZE, 8).unwrap();
+
+		assert_eq!(allocator.take_free_region(layout), Some(region.start() as *mut u8));
+		assert!(regions(&allocator).is_empty());
+	}
+
+	#[test]
+	fn leading_slack_stays_free() {
+		let (region, mut allocator) = heap();
+		let small = Layout::from_size_align(16, 8).unwrap();
+		let aligned = Layout::from_size_align(64, 1024).unwrap();
+
+		let small_size = LinkedListAllocator::size_align(small).size();
+
+		allocator.take_free_region(small).unwrap();
+		let ptr = allocator.take_free_region(aligned).unwrap() as usize;
+
+		assert_eq!(ptr, region.start() + 1024);
+		assert_eq!(regions(&allocator)[0], (region.start() + small_size, region.start() + 1024));
+
+		// the slack is used by the next small allocations
+		let next = allocator.take_free_region(small).unwrap() as usize;
+		assert_eq!(next, region.start() + small_size);
+	}
+
+	#[test]
+	fn misaligned_region_start_is_usable() {
+		let region = HostRegion::new(HEAP_SIZE);
+		let mut allocator = LinkedListAllocator::new();
+		let start = region.start() + 8;
+		unsafe { allocator.init(start, 256) };
+
+		let layout = Layout::from_size_align(64, 64).unwrap();
+		let ptr = allocator.take_free_region(layout).unwrap() as usize;
+
+		assert_eq!(ptr % 64, 0);
+		assert!(ptr >= start && ptr + 64 <= start + 256);
+		assert_sorted_and_merged(&allocator);
+	}
+
+	#[test]
+	fn adjacent_frees_are_merged() {
+		let (region, mut allocator) = heap();
+		let layout = Layout::from_size_align(128, 8).unwrap();
+		let ptrs: Vec<*mut u8> =
+			(0..4).map(|_| allocator.take_free_region(layout).unwrap()).collect();
+
+		// free in an order that needs merging with the previous, the next, and both regions
+		for index in [1, 3, 2, 0] {
+			free(&mut allocator, ptrs[index], layout);
+			assert_sorted_and_merged(&allocator);
+		}
+
+		assert_eq!(regions(&allocator), vec![(region.start(), region.end())]);
+	}
+
+	#[test]
+	fn big_allocation_succeeds_after_churn() {
+		let (_region, mut allocator) = heap();
+		let mut rng = XorShift::new(42);
+		let mut live = Vec::new();
+
+		for _ in 0..500 {
+			let layout = Layout::from_size_align(rng.next_in(1..200), 8).unwrap();
+			if let Some(ptr) = allocator.take_free_region(layout) {
+				live.push((ptr, layout));
+			}
+		}
+		while !live.is_empty() {
+			let (ptr, layout) = live.swap_remove(rng.next_in(0..live.len()));
+			free(&mut allocator, ptr, layout);
+		}
+
+		let big = Layout::from_size_align(HEAP_SIZE, 8).unwrap();
+		assert!(allocator.take_free_region(big).is_some());
+	}
+
+	#[test]
+	fn grow_in_place_into_the_next_free_region() {
+		let (_region, mut allocator) = heap();
+		let layout = Layout::from_size_align(64, 8).unwrap();
+		let first = allocator.take_free_region(layout).unwrap();
+		let second = allocator.take_free_region(layout).unwrap();
+		free(&mut allocator, second, layout);
+
+		assert!(allocator.resize_in_place(first as usize, 64, 1024));
+		assert_eq!(free_bytes(&allocator), HEAP_SIZE - 1024);
+		assert_sorted_and_merged(&allocator);
+
+		// the grown region isn't handed out again
+		let next = allocator.take_free_region(layout).unwrap() as usize;
+		assert!(next >= first as usize + 1024);
+	}
+
+	#[test]
+	fn grow_in_place_fails_when_next_region_is_used() {
+		let (_region, mut allocator) = heap();
+		let layout = Layout::from_size_align(64, 8).unwrap();
+		let first = allocator.take_free_region(layout).unwrap();
+		allocator.take_free_region(layout).unwrap();
+
+		assert!(!allocator.resize_in_place(first as usize, 64, 128));
+		assert_eq!(free_bytes(&allocator), HEAP_SIZE - 128);
+	}
+
+	#[test]
+	fn grow_in_place_takes_the_whole_next_region() {
+		let (region, mut allocator) = heap();
+		let layout = Layout::from_size_align(HEAP_SIZE / 2, 8).unwrap();
+		let first = allocator.take_free_region(layout).unwrap();
+
+		assert!(allocator.resize_in_place(first as usize, HEAP_SIZE / 2, HEAP_SIZE));
+		assert!(regions(&allocator).is_empty());
+
+		// shrinking gives the tail back
+		assert!(allocator.resize_in_place(first as usize, HEAP_SIZE, 64));
+		assert_eq!(regions(&allocator), vec![(region.start() + 64, region.end())]);
+	}
+
+	#[test]
+	fn too_big_allocation_fails() {
+		let (_region, mut allocator) = heap();
+		let layout = Layout::from_size_align(HEAP_SIZE * 2, 8).unwrap();
+
+		assert_eq!(allocator.take_free_region(layout), None);
+		assert_eq!(free_bytes(&allocator), HEAP_SIZE);
+	}
+
+	#[test]
+	fn random_sequences_match_model() {
+		struct Allocation {
+			ptr: *mut u8,
+			layout: Layout,
+			fill: u8,
+		}
+
+		for seed in 1..=20 {
+			let mut rng = XorShift::new(seed);
+			let (region, mut allocator) = heap();
+
+			// model: the live allocations
+			let mut live: Vec<Allocation> = Vec::new();
+
+			for step in 0..2000 {
+				if live.is_empty() || rng.chance(55) {
+					let size = rng.next_in(1..512);
+					let align = 1 << rng.next_in(0..7);
+					let layout = Layout::from_size_align(size, align).unwrap();
+
+					// the heap may be too fragmented, which isn't an error
+					let Some(ptr) = allocator.take_free_region(layout) else {
+						continue;
+					};
+
+					let (start, end) = (ptr as usize, ptr as usize + size);
+					assert_eq!(start % align, 0, "seed {seed}, step {step}: misaligned");
+					assert!(start >= region.start() && end <= region.end());
+					for other in &live {
+						let other_start = other.ptr as usize;
+						let other_end = other_start + other.layout.size();
+						assert!(
+							end <= other_start || start >= other_end,
+							"seed {seed}, step {step}: overlapping allocations"
+						);
+					}
+
+					let fill = rng.next() as u8;
+					unsafe { ptr.write_bytes(fill, size) };
+					live.push(Allocation {
+						ptr,
+						layout,
+						fill,
+					});
+				} else {
+					let allocation = live.swap_remove(rng.next_in(0..live.len()));
+
+					let bytes = unsafe {
+						core::slice::from_raw_parts(allocation.ptr, allocation.layout.size())
+					};
+					assert!(
+						bytes.iter().all(|&b| b == allocation.fill),
+						"seed {seed}, step {step}: allocation was overwritten"
+					);
+
+					free(&mut allocator, allocation.ptr, allocation.layout);
+				}
+
+				assert_sorted_and_merged(&allocator);
+			}
+
+			// once everything is freed, the heap is a single region again
+			for allocation in live.drain(..) {
+				free(&mut allocator, allocation.ptr, allocation.layout);
+			}
+			assert_eq!(regions(&allocator), vec![(region.start(), region.end())]);
+		}
+	}
+}
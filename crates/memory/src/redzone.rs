@@ -0,0 +1,350 @@
+//! Redzones around heap allocations, to catch invalid frees and buffer overflows
+//!
+//! In debug builds, the kernel heap wraps every allocation in a bigger block:
+//!
+//! ```text
+//! | front redzone | header | allocation | back redzone |
+//!                          ^ pointer returned to the caller
+//! ```
+//!
+//! The redzones are filled with [REDZONE_BYTE], and the header holds a magic value and the size
+//! of the allocation. Everything is checked when the allocation is freed, and the allocation is
+//! then filled with [POISON_BYTE], so use-after-free bugs read obviously wrong values.
+
+use core::alloc::Layout;
+use core::fmt;
+
+use crate::linked_list::align_up;
+
+/// Size of the back redzone, and minimum size of the front one
+pub const REDZONE_SIZE: usize = 16;
+
+/// Fills the redzones
+pub const REDZONE_BYTE: u8 = 0xfd;
+
+/// Fills freed allocations
+pub const POISON_BYTE: u8 = 0x6b;
+
+/// [AllocationHeader::magic] of a live allocation
+const ALLOCATED_MAGIC: usize = 0xa110_c8ed;
+
+/// [AllocationHeader::magic] of a freed allocation
+const FREED_MAGIC: usize = 0xf4ee_d00d;
+
+/// Stored right before every allocation
+#[repr(C)]
+struct AllocationHeader {
+	magic: usize,
+	size: usize,
+}
+
+/// Why [check] refused a pointer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedzoneError {
+	/// The allocation was already freed
+	DoubleFree {
+		/// Pointer given to [check]
+		ptr: usize,
+	},
+
+	/// The header doesn't hold a magic value: the pointer doesn't come from the allocator, or
+	/// the memory right before it was overwritten
+	NotAnAllocation {
+		/// Pointer given to [check]
+		ptr: usize,
+	},
+
+	/// The size given to [check] isn't the size of the allocation
+	SizeMismatch {
+		/// Pointer given to [check]
+		ptr: usize,
+		/// Size given to [check]
+		size: usize,
+		/// Size of the allocation
+		allocated_size: usize,
+	},
+
+	/// A byte of the front redzone was overwritten
+	Underflow {
+		/// Pointer given to [check]
+		ptr: usize,
+		/// Number of bytes between the overwritten byte and the start of the allocation
+		offset: usize,
+	},
+
+	/// A byte of the back redzone was overwritten
+	Overflow {
+		/// Pointer given to [check]
+		ptr: usize,
+		/// Size of the allocation
+		size: usize,
+		/// Number of bytes between the end of the allocation and the overwritten byte
+		offset: usize,
+	},
+}
+
+impl fmt::Display for RedzoneError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Self::DoubleFree {
+				ptr,
+			} => write!(f, "double free of {ptr:#x}"),
+			Self::NotAnAllocation {
+				ptr,
+			} => write!(f, "free of {ptr:#x}, which isn't an allocation (or its header was overwritten)"),
+			Self::SizeMismatch {
+				ptr,
+				size,
+				allocated_size,
+			} => write!(f, "free of {ptr:#x} with a size of {size} bytes, but it was allocated with {allocated_size}"),
+			Self::Underflow {
+				ptr,
+				offset,
+			} => write!(f, "buffer underflow: the byte {offset} bytes before {ptr:#x} was overwritten"),
+			Self::Overflow {
+				ptr,
+				size,
+				offset,
+			} => write!(f, "buffer overflow: the byte {offset} bytes after the end of {ptr:#x} ({size} bytes) was overwritten"),
+		}
+	}
+}
+
+/// Returns the layout of the block holding an allocation of `layout` and its redzones,
+/// and the offset of the allocation in the block
+pub fn block_layout(layout: Layout) -> (Layout, usize) {
+	let align = layout.align().max(align_of::<AllocationHeader>());
+	let offset = align_up(REDZONE_SIZE + size_of::<AllocationHeader>(), align);
+	let size = offset + layout.size() + REDZONE_SIZE;
+
+	let block_layout = Layout::from_size_align(size, align).expect("allocation too big");
+	(block_layout, offset)
+}
+
+/// Writes the redzones and the header of an allocation of `layout` in `block`,
+/// and returns the pointer to the allocation
+///
+/// # Safety
+///  - `block` must be valid for writes of [block_layout]\(`layout`\)
+pub unsafe fn arm(block: *mut u8, layout: Layout) -> *mut u8 {
+	let (_, offset) = block_layout(layout);
+
+	unsafe {
+		let ptr = block.add(offset);
+		let header = header(ptr);
+
+		block.write_bytes(REDZONE_BYTE, header as usize - block as usize);
+		header.write(AllocationHeader {
+			magic: ALLOCATED_MAGIC,
+			size: layout.size(),
+		});
+		ptr.add(layout.size()).write_bytes(REDZONE_BYTE, REDZONE_SIZE);
+
+		ptr
+	}
+}
+
+/// Checks the header and the redzones of the allocation at `ptr`, then poisons it
+///
+/// Returns the block to free (cf. [block_layout])
+///
+/// # Safety
+///  - `ptr` must point inside the heap, after at least [block_layout]\(`layout`\).1 bytes of it
+pub unsafe fn check(ptr: *mut u8, layout: Layout) -> Result<*mut u8, RedzoneError> {
+	let (_, offset) = block_layout(layout);
+	let address = ptr as usize;
+
+	unsafe {
+		let block = ptr.sub(offset);
+		let header = header(ptr);
+
+		match (*header).magic {
+			ALLOCATED_MAGIC => {}
+			FREED_MAGIC => {
+				return Err(RedzoneError::DoubleFree {
+					ptr: address,
+				});
+			}
+			_ => {
+				return Err(RedzoneError::NotAnAllocation {
+					ptr: address,
+				});
+			}
+		}
+
+		if (*header).size != layout.size() {
+			return Err(RedzoneError::SizeMismatch {
+				ptr: address,
+				size: layout.size(),
+				allocated_size: (*header).size,
+			});
+		}
+
+		let front = core::slice::from_raw_parts(block, header as usize - block as usize);
+		if let Some(index) = front.iter().rposition(|&byte| byte != REDZONE_BYTE) {
+			return Err(RedzoneError::Underflow {
+				ptr: address,
+				offset: offset - index,
+			});
+		}
+
+		let back = core::slice::from_raw_parts(ptr.add(layout.size()), REDZONE_SIZE);
+		if let Some(index) = back.iter().position(|&byte| byte != REDZONE_BYTE) {
+			return Err(RedzoneError::Overflow {
+				ptr: address,
+				size: layout.size(),
+				offset: index,
+			});
+		}
+
+		(*header).magic = FREED_MAGIC;
+		ptr.write_bytes(POISON_BYTE, layout.size());
+
+		Ok(block)
+	}
+}
+
+/// Returns the header of the allocation at `ptr`
+fn header(ptr: *mut u8) -> *mut AllocationHeader {
+	ptr.wrapping_sub(size_of::<AllocationHeader>()) as *mut AllocationHeader
+}
+
+#[cfg(test)]
+mod tests {
+	use std::alloc::{
+		alloc,
+		dealloc,
+	};
+
+	use super::*;
+
+	/// An armed allocation in a block from the host allocator
+	struct Armed {
+		block: *mut u8,
+		block_layout: Layout,
+		layout: Layout,
+		ptr: *mut u8,
+	}
+
+	impl Armed {
+		fn new(size: usize, align: usize) -> Self {
+			let layout = Layout::from_size_align(size, align).unwrap();
+			let (block_layout, _) = block_layout(layout);
+			let block = unsafe { alloc(block_layout) };
+			let ptr = unsafe { arm(block, layout) };
+
+			Self {
+				block,
+				block_layout,
+				layout,
+				ptr,
+			}
+		}
+
+		fn check(&self) -> Result<*mut u8, RedzoneError> {
+			unsafe { check(self.ptr, self.layout) }
+		}
+	}
+
+	impl Drop for Armed {
+		fn drop(&mut self) {
+			unsafe { dealloc(self.block, self.block_layout) };
+		}
+	}
+
+	#[test]
+	fn allocation_is_aligned_inside_its_block() {
+		for align in [1, 8, 64, 4096] {
+			let armed = Armed::new(100, align);
+			let (block_layout, offset) = block_layout(armed.layout);
+
+			assert_eq!(armed.ptr as usize % align, 0);
+			assert!(offset >= REDZONE_SIZE + size_of::<AllocationHeader>());
+			assert_eq!(block_layout.size(), offset + 100 + REDZONE_SIZE);
+		}
+	}
+
+	#[test]
+	fn untouched_allocation_passes_and_is_poisoned() {
+		let armed = Armed::new(32, 8);
+		unsafe { armed.ptr.write_bytes(0x42, 32) };
+
+		assert_eq!(armed.check(), Ok(armed.block));
+
+		let bytes = unsafe { core::slice::from_raw_parts(armed.ptr, 32) };
+		assert!(bytes.iter().all(|&byte| byte == POISON_BYTE));
+	}
+
+	#[test]
+	fn double_free_is_detected() {
+		let armed = Armed::new(32, 8);
+
+		assert!(armed.check().is_ok());
+		assert_eq!(
+			armed.check(),
+			Err(RedzoneError::DoubleFree {
+				ptr: armed.ptr as usize
+			})
+		);
+	}
+
+	#[test]
+	fn foreign_pointer_is_detected() {
+		let armed = Armed::new(64, 8);
+
+		// a pointer in the middle of the allocation has no header
+		let inner = unsafe { armed.ptr.add(32) };
+		unsafe { armed.ptr.write_bytes(0, 64) };
+
+		assert_eq!(
+			unsafe { check(inner, Layout::from_size_align(16, 8).unwrap()) },
+			Err(RedzoneError::NotAnAllocation {
+				ptr: inner as usize
+			})
+		);
+	}
+
+	#[test]
+	fn wrong_size_is_detected() {
+		let armed = Armed::new(32, 8);
+
+		assert_eq!(
+			unsafe { check(armed.ptr, Layout::from_size_align(24, 8).unwrap()) },
+			Err(RedzoneError::SizeMismatch {
+				ptr: armed.ptr as usize,
+				size: 24,
+				allocated_size: 32,
+			})
+		);
+	}
+
+	#[test]
+	fn overflow_is_detected() {
+		let armed = Armed::new(30, 2);
+		unsafe { armed.ptr.add(30 + 3).write(0) };
+
+		assert_eq!(
+			armed.check(),
+			Err(RedzoneError::Overflow {
+				ptr: armed.ptr as usize,
+				size: 30,
+				offset: 3,
+			})
+		);
+	}
+
+	#[test]
+	fn underflow_is_detected() {
+		let armed = Armed::new(32, 8);
+		let header_size = size_of::<AllocationHeader>();
+		unsafe { armed.ptr.sub(header_size + 1).write(0) };
+
+		assert_eq!(
+			armed.check(),
+			Err(RedzoneError::Underflow {
+				ptr: armed.ptr as usize,
+				offset: header_size + 1,
+			})
+		);
+	}
+}
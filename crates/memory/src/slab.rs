@@ -0,0 +1,461 @@
+//! Slab allocator for small fixed-size objects
+//!
+//! A [SlabCache] hands out objects of a single size. It carves them out of slabs: naturally
+//! aligned blocks of one or more pages, that start with a [SlabHeader] followed by the objects.
+//! Since the slabs are aligned on their size, the slab of an object is found by aligning its
+//! address down, and allocating/freeing an object never scans a list.
+//!
+//! The free objects of a slab are linked together: each one stores the address of the next one
+//! in its first bytes.
+
+use core::alloc::Layout;
+
+use crate::frame_allocator::FRAME_SIZE;
+use crate::linked_list::align_up;
+
+/// Minimum number of objects in a slab (bigger objects get multi-page slabs)
+const MIN_OBJECTS_PER_SLAB: usize = 8;
+
+/// Object sizes of the general purpose caches in front of the kernel heap
+pub const SIZE_CLASSES: [usize; 9] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+/// Returns the index in [SIZE_CLASSES] of the smallest class that fits `layout`,
+/// or None if `layout` is too big for the slab caches
+///
+/// The classes are powers of 2, and their objects are aligned on their size, so an object of a
+/// class fits any layout with an alignment up to that size.
+pub fn size_class_index(layout: Layout) -> Option<usize> {
+	let size = layout.size().max(layout.align());
+	SIZE_CLASSES.iter().position(|&class_size| size <= class_size)
+}
+
+/// Stored at the start of every slab
+struct SlabHeader {
+	/// First free object of the slab
+	free_list: Option<*mut FreeObject>,
+
+	/// Number of free objects in the slab
+	free_count: usize,
+
+	/// Previous slab with free objects (slabs without free objects aren't linked)
+	prev: Option<*mut SlabHeader>,
+
+	/// Next slab with free objects
+	next: Option<*mut SlabHeader>,
+}
+
+/// Written in the first bytes of every free object
+struct FreeObject {
+	next: Option<*mut FreeObject>,
+}
+
+/// Allocates objects of a single size out of slabs
+///
+/// The slabs are given by the caller ([SlabCache::add_slab]), and given back when they become
+/// empty ([SlabCache::free]).
+pub struct SlabCache {
+	object_size: usize,
+	object_align: usize,
+
+	/// Size (and alignment) of the slabs, a power of 2
+	slab_size: usize,
+
+	/// Slabs with at least one free object
+	partial_slabs: Option<*mut SlabHeader>,
+}
+
+unsafe impl Send for SlabCache {}
+
+impl SlabCache {
+	/// Creates a [SlabCache] for objects of `object_size` bytes aligned on `object_align`
+	///
+	/// `object_align` must be a power of 2
+	pub const fn new(object_size: usize, object_align: usize) -> Self {
+		// free objects must be able to hold a (well aligned) FreeObject
+		let object_align = max(object_align, align_of::<FreeObject>());
+		let object_size = align_up(max(object_size, size_of::<FreeObject>()), object_align);
+
+		let min_slab_size = first_object_offset(object_align) + MIN_OBJECTS_PER_SLAB * object_size;
+		let slab_size = max(min_slab_size, FRAME_SIZE).next_power_of_two();
+
+		Self {
+			object_size,
+			object_align,
+			slab_size,
+			partial_slabs: None,
+		}
+	}
+
+	/// Size of the objects (rounded up to hold a free list link)
+	pub const fn object_size(&self) -> usize {
+		self.object_size
+	}
+
+	/// Size and alignment of the slabs expected by [SlabCache::add_slab]
+	pub const fn slab_layout(&self) -> Layout {
+		// Safety: `slab_size` is a power of 2
+		unsafe { Layout::from_size_align_unchecked(self.slab_size, self.slab_size) }
+	}
+
+	/// Number of objects in a slab
+	pub const fn objects_per_slab(&self) -> usize {
+		(self.slab_size - first_object_offset(self.object_align)) / self.object_size
+	}
+
+	/// Takes a free object
+	///
+	/// Returns None if every slab is full, in which case the caller should give a new slab with
+	/// [SlabCache::add_slab]
+	pub fn allocate(&mut self) -> Option<*mut u8> {
+		let slab_ptr = self.partial_slabs?;
+		let slab = unsafe { &mut *slab_ptr };
+
+		let object_ptr = slab.free_list.expect("partial slab without free objects");
+		slab.free_list = unsafe { (*object_ptr).next };
+		slab.free_count -= 1;
+
+		// full slabs leave the list, they come back when one of their objects is freed
+		if slab.free_count == 0 {
+			self.unlink(slab_ptr);
+		}
+
+		Some(object_ptr as *mut u8)
+	}
+
+	/// Gives a new slab to the cache, and carves it into free objects
+	///
+	/// # Safety
+	///  - `slab_vaddr` must be the start of an unused memory region of [SlabCache::slab_layout]
+	pub unsafe fn add_slab(&mut self, slab_vaddr: usize) {
+		debug_assert_eq!(slab_vaddr % self.slab_size, 0, "misaligned slab");
+
+		let first_object = slab_vaddr + first_object_offset(self.object_align);
+		let count = self.objects_per_slab();
+
+		// link every object to the next one
+		let mut free_list = None;
+		for index in (0..count).rev() {
+			let object_ptr = (first_object + index * self.object_size) as *mut FreeObject;
+			unsafe {
+				core::ptr::write(
+					object_ptr,
+					FreeObject {
+						next: free_list,
+					},
+				)
+			};
+			free_list = Some(object_ptr);
+		}
+
+		let slab_ptr = slab_vaddr as *mut SlabHeader;
+		let header = SlabHeader {
+			free_list,
+			free_count: count,
+			prev: None,
+			next: None,
+		};
+		unsafe { core::ptr::write(slab_ptr, header) };
+
+		self.push(slab_ptr);
+	}
+
+	/// Gives back an object taken with [SlabCache::allocate]
+	///
+	/// Returns the address of its slab if the slab became empty and can be released (the cache
+	/// keeps its last partial slab, so alternating allocations/frees don't release it every time)
+	///
+	/// # Safety
+	///  - `ptr` must have been returned by [SlabCache::allocate] on this cache, and not freed
+	pub unsafe fn free(&mut self, ptr: *mut u8) -> Option<usize> {
+		let slab_vaddr = ptr as usize & !(self.slab_size - 1);
+		let slab_ptr = slab_vaddr as *mut SlabHeader;
+		let slab = unsafe { &mut *slab_ptr };
+
+		let object_ptr = ptr as *mut FreeObject;
+		unsafe {
+			core::ptr::write(
+				object_ptr,
+				FreeObject {
+					next: slab.free_list,
+				},
+			)
+		};
+		slab.free_list = Some(object_ptr);
+		slab.free_count += 1;
+
+		// the slab was full, so it wasn't linked
+		if slab.free_count == 1 {
+			self.push(slab_ptr);
+		}
+
+		let is_last_slab = slab.prev.is_none() && slab.next.is_none();
+		if slab.free_count == self.objects_per_slab() && !is_last_slab {
+			self.unlink(slab_ptr);
+			return Some(slab_vaddr);
+		}
+
+		None
+	}
+
+	/// Adds `slab_ptr` at the head of the partial slabs list
+	fn push(&mut self, slab_ptr: *mut SlabHeader) {
+		let slab = unsafe { &mut *slab_ptr };
+
+		slab.prev = None;
+		slab.next = self.partial_slabs;
+		if let Some(next_ptr) = slab.next {
+			unsafe { (*next_ptr).prev = Some(slab_ptr) };
+		}
+
+		self.partial_slabs = Some(slab_ptr);
+	}
+
+	/// Removes `slab_ptr` from the partial slabs list
+	fn unlink(&mut self, slab_ptr: *mut SlabHeader) {
+		let slab = unsafe { &mut *slab_ptr };
+
+		match slab.prev {
+			Some(prev_ptr) => unsafe { (*prev_ptr).next = slab.next },
+			None => self.partial_slabs = slab.next,
+		}
+		if let Some(next_ptr) = slab.next {
+			unsafe { (*next_ptr).prev = slab.prev };
+		}
+
+		slab.prev = None;
+		slab.next = None;
+	}
+}
+
+/// Offset of the first object in a slab (right after the [SlabHeader], aligned)
+const fn first_object_offset(object_align: usize) -> usize {
+	align_up(size_of::<SlabHeader>(), object_align)
+}
+
+const fn max(a: usize, b: usize) -> usize {
+	if a > b { a } else { b }
+}
+
+#[cfg(test)]
+mod tests {
+	use std::alloc::{
+		alloc,
+		dealloc,
+	};
+	use std::collections::BTreeMap;
+
+	use super::*;
+	use crate::test_utils::XorShift;
+
+	/// Gives slabs from the host allocator, and checks they're all given back
+	struct SlabSource {
+		layout: Layout,
+		slabs: Vec<usize>,
+	}
+
+	impl SlabSource {
+		fn new(cache: &SlabCache) -> Self {
+			Self {
+				layout: cache.slab_layout(),
+				slabs: Vec::new(),
+			}
+		}
+
+		fn add_slab(&mut self, cache: &mut SlabCache) {
+			let slab = unsafe { alloc(self.layout) } as usize;
+			assert_ne!(slab, 0);
+			self.slabs.push(slab);
+			unsafe { cache.add_slab(slab) };
+		}
+
+		fn allocate(&mut self, cache: &mut SlabCache) -> *mut u8 {
+			if let Some(ptr) = cache.allocate() {
+				return ptr;
+			}
+			self.add_slab(cache);
+			cache.allocate().expect("a new slab has free objects")
+		}
+
+		fn release(&mut self, slab: usize) {
+			let index = self.slabs.iter().position(|&s| s == slab).expect("unknown slab released");
+			self.slabs.swap_remove(index);
+			unsafe { dealloc(slab as *mut u8, self.layout) };
+		}
+	}
+
+	impl Drop for SlabSource {
+		fn drop(&mut self) {
+			for &slab in &self.slabs {
+				unsafe { dealloc(slab as *mut u8, self.layout) };
+			}
+		}
+	}
+
+	#[test]
+	fn size_classes() {
+		let class_of = |size, align| {
+			size_class_index(Layout::from_size_align(size, align).unwrap()).map(|i| SIZE_CLASSES[i])
+		};
+
+		assert_eq!(class_of(1, 1), Some(8));
+		assert_eq!(class_of(8, 8), Some(8));
+		assert_eq!(class_of(9, 1), Some(16));
+		assert_eq!(class_of(4, 64), Some(64));
+		assert_eq!(class_of(2048, 8), Some(2048));
+		assert_eq!(class_of(2049, 8), None);
+		assert_eq!(class_of(8, 4096), None);
+	}
+
+	#[test]
+	fn slabs_hold_enough_objects() {
+		for &size in &SIZE_CLASSES {
+			let cache = SlabCache::new(size, size);
+
+			assert!(cache.objects_per_slab() >= MIN_OBJECTS_PER_SLAB, "class {size}");
+			assert!(cache.slab_layout().size().is_power_of_two());
+			assert!(cache.slab_layout().size() >= FRAME_SIZE);
+		}
+	}
+
+	#[test]
+	fn empty_cache_needs_a_slab() {
+		let mut cache = SlabCache::new(32, 32);
+		assert_eq!(cache.allocate(), None);
+	}
+
+	#[test]
+	fn objects_are_aligned_distinct_and_inside_their_slab() {
+		for &size in &SIZE_CLASSES {
+			let mut cache = SlabCache::new(size, size);
+			let mut source = SlabSource::new(&cache);
+			source.add_slab(&mut cache);
+			let slab = source.slabs[0];
+
+			let mut objects: Vec<usize> =
+				(0..cache.objects_per_slab()).map(|_| cache.allocate().unwrap() as usize).collect();
+
+			// the slab is full
+			assert_eq!(cache.allocate(), None);
+
+			for &object in &objects {
+				assert_eq!(object % size, 0, "class {size}: {object:#x} is misaligned");
+				assert!(object > slab && object + size <= slab + cache.slab_layout().size());
+			}
+			objects.sort_unstable();
+			for pair in objects.windows(2) {
+				assert!(pair[0] + size <= pair[1], "class {size}: overlapping objects");
+			}
+		}
+	}
+
+	#[test]
+	fn freed_object_is_reused() {
+		let mut cache = SlabCache::new(64, 8);
+		let mut source = SlabSource::new(&cache);
+
+		let first = source.allocate(&mut cache);
+		let second = source.allocate(&mut cache);
+		assert_eq!(unsafe { cache.free(first) }, None);
+
+		assert_eq!(cache.allocate(), Some(first));
+		assert_ne!(cache.allocate(), Some(second));
+	}
+
+	#[test]
+	fn full_slab_becomes_partial_when_freed() {
+		let mut cache = SlabCache::new(512, 512);
+		let mut source = SlabSource::new(&cache);
+
+		let objects: Vec<*mut u8> =
+			(0..cache.objects_per_slab()).map(|_| source.allocate(&mut cache)).collect();
+		assert_eq!(cache.allocate(), None);
+
+		unsafe { cache.free(objects[3]) };
+		assert_eq!(cache.allocate(), Some(objects[3]));
+	}
+
+	#[test]
+	fn empty_slabs_are_released_except_the_last_one() {
+		let mut cache = SlabCache::new(256, 256);
+		let mut source = SlabSource::new(&cache);
+		let per_slab = cache.objects_per_slab();
+
+		// fill two slabs
+		let objects: Vec<*mut u8> =
+			(0..2 * per_slab).map(|_| source.allocate(&mut cache)).collect();
+		assert_eq!(source.slabs.len(), 2);
+
+		let mut released = Vec::new();
+		for &object in &objects {
+			if let Some(slab) = unsafe { cache.free(object) } {
+				released.push(slab);
+			}
+		}
+
+		// one slab is released, the other one stays in the cache
+		assert_eq!(released.len(), 1);
+		source.release(released[0]);
+		assert_eq!(source.slabs.len(), 1);
+		assert!(cache.allocate().is_some());
+	}
+
+	#[test]
+	fn odd_sized_objects() {
+		let mut cache = SlabCache::new(24, 8);
+		let mut source = SlabSource::new(&cache);
+
+		let objects: Vec<*mut u8> = (0..100).map(|_| source.allocate(&mut cache)).collect();
+		for (i, &object) in objects.iter().enumerate() {
+			assert_eq!(object as usize % 8, 0);
+			unsafe { object.write_bytes(i as u8, 24) };
+		}
+		for (i, &object) in objects.iter().enumerate() {
+			let bytes = unsafe { core::slice::from_raw_parts(object, 24) };
+			assert!(bytes.iter().all(|&b| b == i as u8));
+		}
+	}
+
+	#[test]
+	fn random_sequences_match_model() {
+		for seed in 1..=10 {
+			let mut rng = XorShift::new(seed);
+			let size = SIZE_CLASSES[rng.next_in(0..SIZE_CLASSES.len())];
+			let mut cache = SlabCache::new(size, size);
+			let mut source = SlabSource::new(&cache);
+
+			// model: live objects and their fill byte
+			let mut live: BTreeMap<usize, u8> = BTreeMap::new();
+
+			for step in 0..3000 {
+				if live.is_empty() || rng.chance(55) {
+					let object = source.allocate(&mut cache);
+					let fill = rng.next() as u8;
+					unsafe { object.write_bytes(fill, size) };
+
+					assert!(
+						live.insert(object as usize, fill).is_none(),
+						"seed {seed}, step {step}: object handed out twice"
+					);
+				} else {
+					let index = rng.next_in(0..live.len());
+					let (&object, &fill) = live.iter().nth(index).unwrap();
+					live.remove(&object);
+
+					let bytes = unsafe { core::slice::from_raw_parts(object as *const u8, size) };
+					assert!(
+						bytes.iter().all(|&b| b == fill),
+						"seed {seed}, step {step}: object was overwritten"
+					);
+
+					if let Some(slab) = unsafe { cache.free(object as *mut u8) } {
+						assert!(
+							live.keys().all(|&o| o & !(cache.slab_layout().size() - 1) != slab),
+							"seed {seed}, step {step}: released a slab with live objects"
+						);
+						source.release(slab);
+					}
+				}
+			}
+		}
+	}
+}
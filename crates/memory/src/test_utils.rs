@@ -0,0 +1,73 @@
+//! Helpers shared by the unit tests
+
+use std::alloc::{
+	Layout,
+	alloc_zeroed,
+	dealloc,
+};
+
+/// Deterministic xorshift pseudo-random generator, so failing sequences can be replayed
+pub struct XorShift(u32);
+
+impl XorShift {
+	pub fn new(seed: u32) -> Self {
+		assert_ne!(seed, 0, "xorshift seed must not be 0");
+		Self(seed)
+	}
+
+	pub fn next(&mut self) -> u32 {
+		let mut x = self.0;
+		x ^= x << 13;
+		x ^= x >> 17;
+		x ^= x << 5;
+		self.0 = x;
+		x
+	}
+
+	/// Returns a number in `range`
+	pub fn next_in(&mut self, range: core::ops::Range<usize>) -> usize {
+		range.start + self.next() as usize % (range.end - range.start)
+	}
+
+	/// Returns true with a probability of `percent`%
+	pub fn chance(&mut self, percent: u32) -> bool {
+		self.next() % 100 < percent
+	}
+}
+
+/// Page-aligned host memory region, used as a fake kernel heap
+pub struct HostRegion {
+	ptr: *mut u8,
+	layout: Layout,
+}
+
+impl HostRegion {
+	pub fn new(size: usize) -> Self {
+		let layout = Layout::from_size_align(size, 4096).unwrap();
+		let ptr = unsafe { alloc_zeroed(layout) };
+		assert!(!ptr.is_null());
+
+		Self {
+			ptr,
+			layout,
+		}
+	}
+
+	pub fn start(&self) -> usize {
+		self.ptr as usize
+	}
+
+	pub fn end(&self) -> usize {
+		self.start() + self.layout.size()
+	}
+
+	pub fn size(&self) -> usize {
+		self.layout.size()
+	}
+}
+
+impl Drop for HostRegion {
+	fn drop(&mut self) {
+		unsafe { dealloc(self.ptr, self.layout) };
+	}
+}
@@ -0,0 +1,101 @@
+//! Fixed ACPI Description Table (signature `FACP`)
+//!
+//! Describes the power management registers, and points to the DSDT. The fields after the
+//! ACPI 1.0 ones (e.g. the reset register) are only present if the table is long enough.
+
+use super::{
+	read_u8,
+	read_u32,
+	read_u64,
+};
+
+/// Bit of [Fadt::flags] set if [Fadt::reset_register] is supported
+const RESET_REG_SUP: u32 = 1 << 10;
+
+/// Address spaces of a [GenericAddress]
+pub const SYSTEM_MEMORY: u8 = 0;
+/// cf. [SYSTEM_MEMORY]
+pub const SYSTEM_IO: u8 = 1;
+
+/// Generic Address Structure: a register in memory, I/O or PCI configuration space
+#[derive(Debug, Clone, Copy)]
+pub struct GenericAddress {
+	/// [SYSTEM_MEMORY], [SYSTEM_IO], 2 for PCI configuration space, ...
+	pub address_space: u8,
+
+	/// Size of the register, in bits
+	pub bit_width: u8,
+
+	/// Offset of the register in the address, in bits
+	pub bit_offset: u8,
+
+	/// 0 (undefined), 1 (byte), 2 (word), 3 (dword) or 4 (qword)
+	pub access_size: u8,
+
+	/// Address of the register, in its address space
+	pub address: u64,
+}
+
+impl GenericAddress {
+	/// Parses the 12 bytes Generic Address Structure at `offset` in `table`
+	pub(super) fn parse(table: &[u8], offset: usize) -> Option<Self> {
+		Some(Self {
+			address_space: read_u8(table, offset)?,
+			bit_width: read_u8(table, offset + 1)?,
+			bit_offset: read_u8(table, offset + 2)?,
+			access_size: read_u8(table, offset + 3)?,
+			address: read_u64(table, offset + 4)?,
+		})
+	}
+}
+
+/// The parts of the FADT we use
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+	/// Physical address of the Differentiated System Description Table (AML code)
+	pub dsdt: u32,
+
+	/// I/O port of the System Management Interrupt command, 0 if ACPI is always enabled
+	pub smi_command_port: u32,
+
+	/// Value to write to [Fadt::smi_command_port] to enable ACPI
+	pub acpi_enable: u8,
+
+	/// I/O port of the PM1a control register
+	pub pm1a_control_block: u32,
+
+	/// I/O port of the PM1b control register, 0 if there is none
+	pub pm1b_control_block: u32,
+
+	/// CMOS register of the RTC century, 0 if there is none
+	pub century: u8,
+
+	/// Fixed feature flags
+	pub flags: u32,
+
+	/// Writing [Fadt::reset_value] to this register resets the machine (ACPI 2.0+)
+	pub reset_register: Option<GenericAddress>,
+
+	/// cf. [Fadt::reset_register]
+	pub reset_value: u8,
+}
+
+impl Fadt {
+	/// Parses a FADT, whose checksum was already checked
+	pub(super) fn parse(table: &[u8]) -> Option<Self> {
+		let flags = read_u32(table, 112).unwrap_or(0);
+
+		Some(Self {
+			dsdt: read_u32(table, 40)?,
+			smi_command_port: read_u32(table, 48)?,
+			acpi_enable: read_u8(table, 52)?,
+			pm1a_control_block: read_u32(table, 64)?,
+			pm1b_control_block: read_u32(table, 68)?,
+			century: read_u8(table, 108).unwrap_or(0),
+			flags,
+			reset_register: GenericAddress::parse(table, 116)
+				.filter(|_| flags & RESET_REG_SUP != 0),
+			reset_value: read_u8(table, 128).unwrap_or(0),
+		})
+	}
+}
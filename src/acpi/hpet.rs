@@ -0,0 +1,41 @@
+//! High Precision Event Timer description table (signature `HPET`)
+
+use super::{
+	read_u8,
+	read_u16,
+	read_u32,
+};
+use crate::acpi::GenericAddress;
+
+/// The HPET description
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+	/// Hardware revision, number of comparators, counter size and vendor id of the timer block
+	pub event_timer_block_id: u32,
+
+	/// Where the HPET registers are (always in memory)
+	pub base_address: GenericAddress,
+
+	/// Sequence number of this HPET
+	pub number: u8,
+
+	/// Minimum tick period, in counter ticks, for the periodic mode
+	pub minimum_tick: u16,
+}
+
+impl Hpet {
+	/// Parses a HPET table, whose checksum was already checked
+	pub(super) fn parse(table: &[u8]) -> Option<Self> {
+		Some(Self {
+			event_timer_block_id: read_u32(table, 36)?,
+			base_address: GenericAddress::parse(table, 40)?,
+			number: read_u8(table, 52)?,
+			minimum_tick: read_u16(table, 53)?,
+		})
+	}
+
+	/// Number of comparators of the timer block
+	pub fn comparator_count(&self) -> u8 {
+		((self.event_timer_block_id >> 8) & 0x1f) as u8 + 1
+	}
+}
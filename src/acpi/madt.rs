@@ -0,0 +1,150 @@
+//! Multiple APIC Description Table (signature `APIC`)
+//!
+//! After the local APIC address and flags, the table is a list of variable-length entries, each
+//! starting with its type and length.
+
+use super::{
+	SdtHeader,
+	read_u8,
+	read_u16,
+	read_u32,
+};
+use crate::apic::{
+	ApicConfig,
+	IsaIrqRoute,
+};
+use crate::pic::IRQ_LINES;
+
+const PROCESSOR_LOCAL_APIC: u8 = 0;
+const IO_APIC: u8 = 1;
+const INTERRUPT_SOURCE_OVERRIDE: u8 = 2;
+
+/// Most machines have a single I/O APIC
+pub const MAX_IO_APICS: usize = 4;
+
+/// Maximum number of interrupt source overrides kept (one per ISA IRQ)
+pub const MAX_OVERRIDES: usize = IRQ_LINES;
+
+/// An I/O APIC entry (type 1)
+#[derive(Debug, Clone, Copy)]
+pub struct MadtIoApic {
+	/// APIC id of the I/O APIC
+	pub id: u8,
+
+	/// Physical address of its registers
+	pub address: u32,
+
+	/// First GSI it handles
+	pub gsi_base: u32,
+}
+
+/// An interrupt source override entry (type 2): an ISA IRQ that isn't wired to the GSI with the
+/// same number, or with non-ISA polarity or trigger mode
+#[derive(Debug, Clone, Copy)]
+pub struct MadtOverride {
+	/// The ISA IRQ
+	pub source: u8,
+
+	/// The GSI it arrives on
+	pub gsi: u32,
+
+	/// MPS INTI flags (cf. [IsaIrqRoute::from_mps_flags])
+	pub flags: u16,
+}
+
+/// The parts of the MADT we use
+#[derive(Debug, Clone, Copy)]
+pub struct Madt {
+	/// Physical address of the local APIC registers
+	pub local_apic_address: u32,
+
+	/// Bit 0 set if the machine also has 8259 PICs
+	pub flags: u32,
+
+	/// Number of enabled processors
+	pub processor_count: usize,
+
+	/// The first [Madt::io_apic_count] entries are valid
+	pub io_apics: [Option<MadtIoApic>; MAX_IO_APICS],
+
+	/// Number of I/O APICs
+	pub io_apic_count: usize,
+
+	/// The first [Madt::override_count] entries are valid
+	pub overrides: [Option<MadtOverride>; MAX_OVERRIDES],
+
+	/// Number of interrupt source overrides
+	pub override_count: usize,
+}
+
+impl Madt {
+	/// Parses a MADT, whose checksum was already checked
+	pub(super) fn parse(table: &[u8]) -> Option<Self> {
+		let mut madt = Self {
+			local_apic_address: read_u32(table, 36)?,
+			flags: read_u32(table, 40)?,
+			processor_count: 0,
+			io_apics: [None; MAX_IO_APICS],
+			io_apic_count: 0,
+			overrides: [None; MAX_OVERRIDES],
+			override_count: 0,
+		};
+
+		let mut offset = size_of::<SdtHeader>() + 8;
+		while let (Some(kind), Some(length)) = (read_u8(table, offset), read_u8(table, offset + 1))
+		{
+			// a broken entry would loop forever
+			if length < 2 {
+				break;
+			}
+
+			let entry = table.get(offset..offset + length as usize)?;
+			match kind {
+				// bit 0 of the flags: enabled
+				PROCESSOR_LOCAL_APIC if read_u32(entry, 4)? & 1 != 0 => madt.processor_count += 1,
+
+				IO_APIC if madt.io_apic_count < MAX_IO_APICS => {
+					madt.io_apics[madt.io_apic_count] = Some(MadtIoApic {
+						id: read_u8(entry, 2)?,
+						address: read_u32(entry, 4)?,
+						gsi_base: read_u32(entry, 8)?,
+					});
+					madt.io_apic_count += 1;
+				}
+
+				// bus 0 is ISA
+				INTERRUPT_SOURCE_OVERRIDE
+					if read_u8(entry, 2)? == 0 && madt.override_count < MAX_OVERRIDES =>
+				{
+					madt.overrides[madt.override_count] = Some(MadtOverride {
+						source: read_u8(entry, 3)?,
+						gsi: read_u32(entry, 4)?,
+						flags: read_u16(entry, 8)?,
+					});
+					madt.override_count += 1;
+				}
+
+				_ => {}
+			}
+
+			offset += length as usize;
+		}
+
+		Some(madt)
+	}
+
+	/// The configuration of the first I/O APIC, with the ISA overrides applied
+	pub fn apic_config(&self) -> Option<ApicConfig> {
+		let io_apic = self.io_apics[0]?;
+		let mut config = ApicConfig::new(io_apic.address, io_apic.gsi_base);
+
+		for interrupt_override in self.overrides.iter().flatten() {
+			if let Some(route) = config.isa_routes.get_mut(interrupt_override.source as usize) {
+				*route =
+					IsaIrqRoute::from_mps_flags(interrupt_override.gsi, interrupt_override.flags);
+			}
+		}
+
+		Some(config)
+	}
+}
@@ -0,0 +1,201 @@
+//! ACPI tables
+//!
+//! The firmware leaves a Root System Description Pointer (RSDP) in the BIOS area. It points to
+//! the Root System Description Table (RSDT), which lists the physical addresses of the other
+//! tables. Each table starts with a [SdtHeader], and its bytes add up to 0.
+//!
+//! [init_acpi] parses the tables we use: the MADT (interrupt controllers, cf. [crate::apic]), the
+//! FADT (power management, cf. [power]) and the HPET.
+
+mod fadt;
+mod hpet;
+mod madt;
+pub mod power;
+
+use spin::Mutex;
+
+pub use self::fadt::{
+	Fadt,
+	GenericAddress,
+};
+pub use self::hpet::Hpet;
+pub use self::madt::Madt;
+use self::power::find_s5_sleep_types;
+use crate::paging::mmio::map_physical;
+use crate::paging::phys_to_virt;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+
+/// Size of the ACPI 1.0 RSDP (the checksum covers these bytes)
+const RSDP_V1_SIZE: usize = 20;
+
+/// The tables parsed by [init_acpi] (None if there is no ACPI)
+static ACPI_TABLES: Mutex<Option<AcpiTables>> = Mutex::new(None);
+
+/// Header of every System Description Table
+#[repr(C, packed)]
+pub struct SdtHeader {
+	/// e.g. `APIC` for the MADT
+	pub signature: [u8; 4],
+
+	/// Size of the table, header included
+	pub length: u32,
+	pub revision: u8,
+	pub checksum: u8,
+	pub oem_id: [u8; 6],
+	pub oem_table_id: [u8; 8],
+	pub oem_revision: u32,
+	pub creator_id: u32,
+	pub creator_revision: u32,
+}
+
+#[repr(C, packed)]
+struct Rsdp {
+	signature: [u8; 8],
+	checksum: u8,
+	oem_id: [u8; 6],
+
+	/// 0 for ACPI 1.0, 2 for later versions
+	revision: u8,
+	rsdt_address: u32,
+}
+
+/// The tables we use
+pub struct AcpiTables {
+	/// ACPI revision of the RSDP
+	pub revision: u8,
+
+	/// Name of the firmware vendor
+	pub oem_id: [u8; 6],
+
+	/// Multiple APIC Description Table
+	pub madt: Option<Madt>,
+
+	/// Fixed ACPI Description Table
+	pub fadt: Option<Fadt>,
+
+	/// High Precision Event Timer description
+	pub hpet: Option<Hpet>,
+
+	/// SLP_TYPa and SLP_TYPb values of the S5 (soft off) sleep state, from the DSDT
+	pub s5_sleep_types: Option<(u8, u8)>,
+}
+
+/// Finds the RSDP, then parses the tables listed by the RSDT
+///
+/// Returns false if there is no (valid) ACPI
+///
+/// Must be called once paging is initialized, as tables are often far above the kernel mapping
+pub fn init_acpi() -> bool {
+	let Some(rsdp) = find_rsdp() else {
+		return false;
+	};
+
+	let Some(rsdt) = (unsafe { map_table(rsdp.rsdt_address) }) else {
+		return false;
+	};
+
+	let mut tables = AcpiTables {
+		revision: rsdp.revision,
+		oem_id: rsdp.oem_id,
+		madt: None,
+		fadt: None,
+		hpet: None,
+		s5_sleep_types: None,
+	};
+
+	// the RSDT entries are 32-bit physical addresses
+	for entry in rsdt[size_of::<SdtHeader>()..].as_chunks::<4>().0 {
+		let address = u32::from_le_bytes(*entry);
+		let Some(table) = (unsafe { map_table(address) }) else {
+			continue;
+		};
+
+		match &table[..4] {
+			b"APIC" => tables.madt = Madt::parse(table),
+			b"FACP" => tables.fadt = Fadt::parse(table),
+			b"HPET" => tables.hpet = Hpet::parse(table),
+			_ => {}
+		}
+	}
+
+	// the DSDT isn't in the RSDT, but pointed to by the FADT
+	if let Some(fadt) = &tables.fadt
+		&& let Some(dsdt) = unsafe { map_table(fadt.dsdt) }
+	{
+		tables.s5_sleep_types = find_s5_sleep_types(&dsdt[size_of::<SdtHeader>()..]);
+	}
+
+	*ACPI_TABLES.lock() = Some(tables);
+	true
+}
+
+/// Calls `f` with the ACPI tables, or returns None if [init_acpi] didn't find them
+pub fn acpi_tables<R>(f: impl FnOnce(&AcpiTables) -> R) -> Option<R> {
+	ACPI_TABLES.lock().as_ref().map(f)
+}
+
+/// Looks for the RSDP in the first KiB of the Extended BIOS Data Area, then in the BIOS area
+/// (0xE0000 to 0xFFFFF), on 16 bytes boundaries
+fn find_rsdp() -> Option<&'static Rsdp> {
+	// real mode segment of the EBDA, from the BIOS Data Area
+	let ebda = unsafe { *(phys_to_virt(0x40e) as *const u16) } as u32 * 16;
+
+	[(ebda, 1024), (0xe0000, 0x20000)]
+		.into_iter()
+		.filter(|&(start, _)| start != 0)
+		.flat_map(|(start, length)| (start..start + length).step_by(16))
+		.find_map(|address| {
+			let bytes = unsafe {
+				core::slice::from_raw_parts(phys_to_virt(address) as *const u8, RSDP_V1_SIZE)
+			};
+
+			(&bytes[..8] == RSDP_SIGNATURE && has_valid_checksum(bytes))
+				.then(|| unsafe { &*(bytes.as_ptr() as *const Rsdp) })
+		})
+}
+
+/// Maps the table at `physical_address`, and returns its bytes if its checksum is valid
+///
+/// # Safety
+///  - paging must be initialized
+///  - `physical_address` must point to an ACPI table
+unsafe fn map_table(physical_address: u32) -> Option<&'static [u8]> {
+	let header =
+		unsafe { &*(map_physical(physical_address, size_of::<SdtHeader>()) as *const SdtHeader) };
+	let length = header.length as usize;
+	if length < size_of::<SdtHeader>() {
+		return None;
+	}
+
+	let bytes = unsafe {
+		core::slice::from_raw_parts(map_physical(physical_address, length) as *const u8, length)
+	};
+	has_valid_checksum(bytes).then_some(bytes)
+}
+
+/// The bytes of the ACPI structures add up to 0
+fn has_valid_checksum(bytes: &[u8]) -> bool {
+	bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+/// Reads the `N` bytes at `offset` in a table, if it is long enough
+fn read_bytes<const N: usize>(table: &[u8], offset: usize) -> Option<[u8; N]> {
+	table.get(offset..offset + N)?.try_into().ok()
+}
+
+fn read_u8(table: &[u8], offset: usize) -> Option<u8> {
+	table.get(offset).copied()
+}
+
+fn read_u16(table: &[u8], offset: usize) -> Option<u16> {
+	read_bytes(table, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(table: &[u8], offset: usize) -> Option<u32> {
+	read_bytes(table, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(table: &[u8], offset: usize) -> Option<u64> {
+	read_bytes(table, offset).map(u64::from_le_bytes)
+}
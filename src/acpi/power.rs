@@ -0,0 +1,223 @@
+//! Power-off and reset
+//!
+//! To power off, we put the machine in the S5 (soft off) sleep state: its SLP_TYP values are in
+//! the `\_S5_` package of the DSDT AML, and are written to the PM1 control registers with SLP_EN.
+//! Without ACPI, we try the ports of the common emulators.
+//!
+//! To reset, we write to the FADT reset register, or pulse the CPU reset line of the 8042.
+
+use core::arch::asm;
+
+use super::fadt::{
+	SYSTEM_IO,
+	SYSTEM_MEMORY,
+};
+use super::{
+	Fadt,
+	acpi_tables,
+};
+use crate::idt::interrupts::disable_hardware_interrupts;
+use crate::paging::mmio::map_physical;
+use crate::pit::busy_sleep_ms;
+use crate::shared::{
+	inw,
+	outb,
+	outw,
+};
+
+/// Bit of the PM1 control registers set once ACPI is enabled
+const SCI_EN: u16 = 1 << 0;
+
+/// Bit of the PM1 control registers that enters the sleep state of SLP_TYP (bits 10-12)
+const SLP_EN: u16 = 1 << 13;
+
+/// AML opcodes used by the `\_S5_` package
+const NAME_OP: u8 = 0x08;
+const PACKAGE_OP: u8 = 0x12;
+const ROOT_PREFIX: u8 = b'\\';
+const ZERO_OP: u8 = 0x00;
+const ONE_OP: u8 = 0x01;
+const BYTE_PREFIX: u8 = 0x0a;
+
+/// Powers the machine off
+pub fn shutdown() -> ! {
+	disable_hardware_interrupts();
+
+	if let Some(Some((fadt, (slp_typ_a, slp_typ_b)))) =
+		acpi_tables(|tables| tables.fadt.zip(tables.s5_sleep_types))
+	{
+		unsafe {
+			enable_acpi(&fadt);
+
+			outw(fadt.pm1a_control_block as u16, (slp_typ_a as u16) << 10 | SLP_EN);
+			if fadt.pm1b_control_block != 0 {
+				outw(fadt.pm1b_control_block as u16, (slp_typ_b as u16) << 10 | SLP_EN);
+			}
+		}
+	}
+
+	// QEMU, then Bochs and older QEMU, then VirtualBox
+	unsafe {
+		outw(0x604, 0x2000);
+		outw(0xb004, 0x2000);
+		outw(0x4004, 0x3400);
+	}
+
+	halt_forever()
+}
+
+/// Resets the machine
+pub fn reboot() -> ! {
+	disable_hardware_interrupts();
+
+	if let Some(Some(fadt)) = acpi_tables(|tables| tables.fadt)
+		&& let Some(reset_register) = fadt.reset_register
+	{
+		unsafe {
+			match reset_register.address_space {
+				SYSTEM_IO => outb(reset_register.address as u16, fadt.reset_value),
+				SYSTEM_MEMORY => {
+					let register = map_physical(reset_register.address as u32, 1) as *mut u8;
+					register.write_volatile(fadt.reset_value);
+				}
+				// PCI configuration space isn't supported
+				_ => {}
+			}
+		}
+
+		busy_sleep_ms(10);
+	}
+
+	// pulses the CPU reset line through the 8042 keyboard controller
+	unsafe { outb(0x64, 0xfe) };
+
+	halt_forever()
+}
+
+/// Switches from legacy to ACPI mode, if the firmware didn't
+///
+/// # Safety
+///  - `fadt` must describe this machine
+unsafe fn enable_acpi(fadt: &Fadt) {
+	let pm1a_control = fadt.pm1a_control_block as u16;
+	if unsafe { inw(pm1a_control) } & SCI_EN != 0 || fadt.smi_command_port == 0 {
+		return;
+	}
+
+	unsafe { outb(fadt.smi_command_port as u16, fadt.acpi_enable) };
+
+	// the firmware has up to a few seconds to hand over
+	for _ in 0..300 {
+		if unsafe { inw(pm1a_control) } & SCI_EN != 0 {
+			return;
+		}
+		busy_sleep_ms(10);
+	}
+}
+
+/// Finds the `\_S5_` package in the DSDT AML, and returns its SLP_TYPa and SLP_TYPb values
+///
+/// Rather than interpreting the AML, we look for the usual encoding of the package:
+/// `NameOp ['\'] "_S5_" PackageOp PkgLength NumElements SLP_TYPa SLP_TYPb ...`
+pub(super) fn find_s5_sleep_types(aml: &[u8]) -> Option<(u8, u8)> {
+	let position = (0..aml.len().saturating_sub(3)).find(|&position| {
+		let before = |distance: usize| position.checked_sub(distance).map(|index| aml[index]);
+
+		&aml[position..position + 4] == b"_S5_"
+			&& (before(1) == Some(NAME_OP)
+				|| (before(1) == Some(ROOT_PREFIX) && before(2) == Some(NAME_OP)))
+	})?;
+
+	let mut bytes = aml[position + 4..].iter().copied();
+	if bytes.next()? != PACKAGE_OP {
+		return None;
+	}
+
+	// bits 6-7 of the first PkgLength byte are the number of bytes that follow
+	let pkg_length_lead = bytes.next()?;
+	for _ in 0..pkg_length_lead >> 6 {
+		bytes.next()?;
+	}
+
+	let _element_count = bytes.next()?;
+
+	Some((read_integer(&mut bytes)?, read_integer(&mut bytes)?))
+}
+
+/// Reads a small AML integer: ZeroOp, OneOp, or a BytePrefix followed by the value (some
+/// firmwares also put the bare value)
+fn read_integer(bytes: &mut impl Iterator<Item = u8>) -> Option<u8> {
+	match bytes.next()? {
+		ZERO_OP => Some(0),
+		ONE_OP => Some(1),
+		BYTE_PREFIX => bytes.next(),
+		value => Some(value),
+	}
+}
+
+fn halt_forever() -> ! {
+	loop {
+		unsafe { asm!("cli", "hlt") };
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test_case]
+	fn finds_s5_sleep_types() {
+		// QEMU: Name (\_S5, Package (0x04) { Zero, Zero, Zero, Zero })
+		let aml = [
+			0x10,
+			0x08,
+			NAME_OP,
+			ROOT_PREFIX,
+			b'_',
+			b'S',
+			b'5',
+			b'_',
+			PACKAGE_OP,
+			0x06,
+			0x04,
+			0x00,
+			0x00,
+			0x00,
+			0x00,
+		];
+		assert_eq!(find_s5_sleep_types(&aml), Some((0, 0)));
+
+		// Bochs: Name (_S5, Package (0x04) { 0x07, 0x07, Zero, Zero })
+		let aml = [
+			NAME_OP,
+			b'_',
+			b'S',
+			b'5',
+			b'_',
+			PACKAGE_OP,
+			0x0a,
+			0x04,
+			BYTE_PREFIX,
+			0x07,
+			BYTE_PREFIX,
+			0x07,
+			0x00,
+			0x00,
+		];
+		assert_eq!(find_s5_sleep_types(&aml), Some((7, 7)));
+
+		// two bytes PkgLength
+		let aml = [NAME_OP, b'_', b'S', b'5', b'_', PACKAGE_OP, 0x40, 0x00, 0x02, ONE_OP, 0x05];
+		assert_eq!(find_s5_sleep_types(&aml), Some((1, 5)));
+	}
+
+	#[test_case]
+	fn ignores_s5_outside_of_a_name() {
+		// e.g. a method call
+		let aml = [0x14, b'_', b'S', b'5', b'_', PACKAGE_OP, 0x06, 0x04, 0x00, 0x00];
+		assert_eq!(find_s5_sleep_types(&aml), None);
+
+		let aml = [NAME_OP, b'_', b'S', b'5', b'_', 0x0a, 0x05];
+		assert_eq!(find_s5_sleep_types(&aml), None);
+	}
+}
@@ -0,0 +1,77 @@
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+use memory::slab::SlabCache;
+use spin::Mutex;
+
+/// A cache of objects of type `T`, for kernel structures that are allocated and freed often
+/// (e.g. tasks, page tables bookkeeping)
+///
+/// Its slabs are taken from the kernel heap, and given back when they become empty.
+pub struct KmemCache<T> {
+	name: &'static str,
+	cache: Mutex<SlabCache>,
+	_marker: PhantomData<T>,
+}
+
+// Safety: the cache only hands out raw pointers, the objects are moved in and out by value
+unsafe impl<T: Send> Sync for KmemCache<T> {}
+
+impl<T> KmemCache<T> {
+	/// Creates an empty cache, `name` is only used for diagnostics
+	pub const fn new(name: &'static str) -> Self {
+		Self {
+			name,
+			cache: Mutex::new(SlabCache::new(size_of::<T>(), align_of::<T>())),
+			_marker: PhantomData,
+		}
+	}
+
+	/// Name given to [KmemCache::new]
+	pub fn name(&self) -> &'static str {
+		self.name
+	}
+
+	/// Moves `value` into a new object of the cache
+	///
+	/// Returns None if the kernel heap is out of memory
+	pub fn alloc(&self, value: T) -> Option<NonNull<T>> {
+		let mut cache = self.cache.lock();
+
+		let ptr = match cache.allocate() {
+			Some(ptr) => ptr,
+			None => {
+				let layout = cache.slab_layout();
+				// Safety: slab layouts are never zero-sized
+				let slab = unsafe { alloc::alloc::alloc(layout) };
+				if slab.is_null() {
+					return None;
+				}
+
+				// Safety: the slab was just allocated with the right layout
+				unsafe { cache.add_slab(slab as usize) };
+				cache.allocate()?
+			}
+		};
+
+		let object = ptr as *mut T;
+		// Safety: `object` is a free object, big and aligned enough for a `T`
+		unsafe { object.write(value) };
+
+		NonNull::new(object)
+	}
+
+	/// Drops the object pointed by `ptr`, and gives it back to the cache
+	///
+	/// # Safety
+	///  - `ptr` must have been returned by [KmemCache::alloc] on this cache, and not freed
+	pub unsafe fn free(&self, ptr: NonNull<T>) {
+		unsafe { ptr.drop_in_place() };
+
+		let mut cache = self.cache.lock();
+		if let Some(slab) = unsafe { cache.free(ptr.as_ptr() as *mut u8) } {
+			let layout = cache.slab_layout();
+			unsafe { alloc::alloc::dealloc(slab as *mut u8, layout) };
+		}
+	}
+}
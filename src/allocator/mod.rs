@@ -1,71 +1,373 @@
-mod linked_list;
+mod kmem_cache;
 
-use core::alloc::GlobalAlloc;
-
-use spin::Mutex;
+use core::alloc::{
+	GlobalAlloc,
+	Layout,
+};
 
-use crate::allocator::linked_list::{
+use memory::linked_list::{
 	LinkedListAllocator,
-	ListNode,
+	MIN_REGION_SIZE,
+	align_up,
+};
+use memory::redzone;
+use memory::slab::{
+	SIZE_CLASSES,
+	SlabCache,
+	size_class_index,
 };
+use spin::Mutex;
+
+pub use self::kmem_cache::KmemCache;
 use crate::paging::page_directory::PageDirectory;
 use crate::paging::pmm::{
 	FRAME_SIZE,
 	kmalloc,
 };
+use crate::println;
 
 #[global_allocator]
 pub static VIRTUAL_ALLOCATOR: LockedHeap = LockedHeap::empty();
 
 /// We choose this address to be:
-///  - far from the kernel code (0x00100000, cf. [crate::paging::kernel_start])
-///  - far from the VGA buffer (0xB8000, cf. [crate::vga::VGA_BUFFER_ADDRESS])
+///  - in the higher half, with the kernel, so user space keeps the lower half
+///  - far from the kernel code (0xC0100000, cf. [crate::paging::kernel_start])
+///  - far from the VGA buffer (0xC00B8000, cf. [crate::vga::VGA_BUFFER_ADDRESS])
 ///  - far below the recursive page tables (0xFFC00000, cf.
 ///    [crate::paging::page_directory::PAGE_TABLES_ADDRESS])
 ///
 /// By doing this, we can grow upward without colliding with used memory spaces
 const HEAP_START_ADDRESS: usize = 0xd000_0000;
-const HEAP_SIZE: usize = 128 * 1024; // 100 MB
+
+/// Size mapped by [init_virtual_allocator]
+const HEAP_INITIAL_SIZE: usize = 128 * 1024; // 128 KiB
+
+/// Default ceiling of the heap growth (cf. [LockedHeap::set_max_size])
+const HEAP_DEFAULT_MAX_SIZE: usize = 64 * 1024 * 1024; // 64 MiB
+
+/// Minimum size mapped when the heap grows, so small allocations don't map one frame at a time
+const HEAP_GROWTH_MIN_SIZE: usize = 16 * FRAME_SIZE; // 64 KiB
 
 #[repr(transparent)]
-pub struct LockedHeap(pub Mutex<LinkedListAllocator>);
+pub struct LockedHeap(pub Mutex<Heap>);
 
 impl LockedHeap {
 	pub const fn empty() -> Self {
-		Self(Mutex::new(LinkedListAllocator::new()))
+		Self(Mutex::new(Heap::empty()))
+	}
+
+	/// Sets the ceiling of the heap growth (it never shrinks below its current size)
+	pub fn set_max_size(&self, max_size: usize) {
+		let mut heap = self.0.lock();
+		heap.max_size = max_size.max(heap.size());
+	}
+
+	/// Returns a snapshot of the heap usage
+	pub fn stats(&self) -> HeapStats {
+		self.0.lock().stats()
 	}
 }
 
+/// In debug builds, every allocation is wrapped in redzones (cf. [memory::redzone]), and freeing
+/// panics if the pointer is outside the heap, was already freed, or if the redzones were
+/// overwritten
 unsafe impl GlobalAlloc for LockedHeap {
-	unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
-		let mut allocator = self.0.lock();
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		let mut heap = self.0.lock();
 
-		let ptr = match allocator.take_free_region(layout) {
+		let ptr = if cfg!(debug_assertions) {
+			let (block_layout, _) = redzone::block_layout(layout);
+			// Safety: the block was allocated with `block_layout`
+			heap.allocate(block_layout).map(|block| unsafe { redzone::arm(block, layout) })
+		} else {
+			heap.allocate(layout)
+		};
+
+		match ptr {
 			Some(ptr) => {
-				// println!("allocated {ptr:p}: {:#?}", core::ptr::metadata(ptr));
+				heap.add_used_bytes(layout.size());
 				ptr
 			}
 			None => core::ptr::null_mut(),
+		}
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		if !cfg!(debug_assertions) {
+			let mut heap = self.0.lock();
+			heap.used_bytes -= layout.size();
+			// Safety: the caller guarantees that `ptr` was allocated with `layout`
+			unsafe { heap.deallocate(ptr, layout) };
+			return;
+		}
+
+		// the checks panic without holding the lock
+		let heap_end = self.0.lock().end;
+		if !(HEAP_START_ADDRESS..heap_end).contains(&(ptr as usize)) {
+			panic!(
+				"heap: free of {ptr:p}, outside of the heap ({HEAP_START_ADDRESS:#x}..{heap_end:#x})"
+			);
+		}
+
+		// Safety: `ptr` is in the heap, and its redzones are checked before being freed
+		let block = match unsafe { redzone::check(ptr, layout) } {
+			Ok(block) => block,
+			Err(error) => panic!("heap: {error}"),
 		};
-		ptr
+
+		let mut heap = self.0.lock();
+		heap.used_bytes -= layout.size();
+		unsafe { heap.deallocate(block, redzone::block_layout(layout).0) };
 	}
 
-	unsafe fn dealloc(&self, ptr: *mut u8, layout: core::alloc::Layout) {
-		let size = layout.size().max(size_of::<ListNode>());
+	/// Keeps the allocation in place when possible (same size class, or free region right after
+	/// it), otherwise moves it
+	///
+	/// Debug builds always move it, so the redzones are checked and moved too
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		// Safety: the caller guarantees that `new_size` is valid for `layout.align()`
+		let new_layout = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+
+		let in_place = !cfg!(debug_assertions)
+			&& match (size_class_index(layout), size_class_index(new_layout)) {
+				(Some(old_class), Some(new_class)) => old_class == new_class,
+				(None, None) => {
+					let old_size = LinkedListAllocator::size_align(layout).size();
+					let adjusted_new_size = LinkedListAllocator::size_align(new_layout).size();
+
+					self.0.lock().allocator.resize_in_place(
+						ptr as usize,
+						old_size,
+						adjusted_new_size,
+					)
+				}
+				_ => false,
+			};
+
+		if in_place {
+			let mut heap = self.0.lock();
+			heap.used_bytes -= layout.size();
+			heap.add_used_bytes(new_size);
+			return ptr;
+		}
 
-		let mut allocator = self.0.lock();
-		allocator.add_free_region(ptr as usize, size);
+		let new_ptr = unsafe { self.alloc(new_layout) };
+		if !new_ptr.is_null() {
+			unsafe {
+				core::ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+				self.dealloc(ptr, layout);
+			}
+		}
+		new_ptr
 	}
 }
 
-pub fn init_virtual_allocator() {
-	debug_assert_eq!(HEAP_START_ADDRESS % FRAME_SIZE, 0, "Heap start must be page-aligned");
+/// The kernel heap: a [LinkedListAllocator] over a virtual memory range starting at
+/// [HEAP_START_ADDRESS], which is mapped on demand
+///
+/// Small allocations (up to the biggest of [SIZE_CLASSES]) are served by a [SlabCache] per size
+/// class, whose slabs are carved out of the linked list.
+pub struct Heap {
+	allocator: LinkedListAllocator,
+
+	/// One cache per size class
+	size_classes: [SlabCache; SIZE_CLASSES.len()],
+
+	/// Address right after the last mapped heap page
+	end: usize,
+
+	/// The heap never grows past `HEAP_START_ADDRESS + max_size`
+	max_size: usize,
+
+	/// Sum of the sizes requested by the live allocations
+	used_bytes: usize,
+
+	/// Highest value of `used_bytes` so far
+	peak_used_bytes: usize,
+}
+
+impl Heap {
+	const fn empty() -> Self {
+		Self {
+			allocator: LinkedListAllocator::new(),
+			size_classes: size_class_caches(),
+			end: HEAP_START_ADDRESS,
+			max_size: HEAP_DEFAULT_MAX_SIZE,
+			used_bytes: 0,
+			peak_used_bytes: 0,
+		}
+	}
 
-	let num_frames = HEAP_SIZE / FRAME_SIZE;
+	/// Allocates `layout` from its size class cache, or from the linked list if it's too big
+	fn allocate(&mut self, layout: Layout) -> Option<*mut u8> {
+		match size_class_index(layout) {
+			Some(class) => self.allocate_object(class),
+			None => self.allocate_region(layout),
+		}
+	}
 
-	for i in 0..num_frames {
-		let physical_frame_addr = kmalloc().expect("Out of memory");
-		let current_vaddr = HEAP_START_ADDRESS + i * FRAME_SIZE;
+	/// Frees a pointer returned by [Heap::allocate]
+	///
+	/// # Safety
+	/// `ptr` must have been allocated by [Heap::allocate] with the same `layout`
+	unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+		match size_class_index(layout) {
+			// Safety: `ptr` was allocated from this cache
+			Some(class) => unsafe { self.free_object(class, ptr) },
+			None => {
+				let size = LinkedListAllocator::size_align(layout).size();
+				self.allocator.add_free_region(ptr as usize, size);
+			}
+		}
+	}
+
+	fn add_used_bytes(&mut self, size: usize) {
+		self.used_bytes += size;
+		self.peak_used_bytes = self.peak_used_bytes.max(self.used_bytes);
+	}
+
+	/// Takes a region for `layout` from the linked list, growing the heap if needed
+	fn allocate_region(&mut self, layout: Layout) -> Option<*mut u8> {
+		loop {
+			if let Some(ptr) = self.allocator.take_free_region(layout) {
+				return Some(ptr);
+			}
+
+			// no free region is big enough: map more frames at the end of the heap, then retry
+			if !self.grow(layout) {
+				return None;
+			}
+		}
+	}
+
+	/// Allocates an object from the cache of the size class `class`,
+	/// giving it a new slab if all of its slabs are full
+	fn allocate_object(&mut self, class: usize) -> Option<*mut u8> {
+		if let Some(ptr) = self.size_classes[class].allocate() {
+			return Some(ptr);
+		}
+
+		let slab = self.allocate_region(self.size_classes[class].slab_layout())?;
+
+		let cache = &mut self.size_classes[class];
+		// Safety: the slab was just taken from the free list, so it's unused
+		unsafe { cache.add_slab(slab as usize) };
+		cache.allocate()
+	}
+
+	/// Gives back an object to the cache of the size class `class`,
+	/// and its slab to the linked list if it became empty
+	///
+	/// # Safety
+	/// `ptr` must have been allocated by [Heap::allocate_object] with the same `class`
+	unsafe fn free_object(&mut self, class: usize, ptr: *mut u8) {
+		let cache = &mut self.size_classes[class];
+
+		if let Some(slab) = unsafe { cache.free(ptr) } {
+			let slab_size = LinkedListAllocator::size_align(cache.slab_layout()).size();
+			self.allocator.add_free_region(slab, slab_size);
+		}
+	}
+
+	/// Mapped size of the heap
+	fn size(&self) -> usize {
+		self.end - HEAP_START_ADDRESS
+	}
+
+	/// Maps enough frames at the end of the heap for `layout` to fit,
+	/// and adds them to the free list (where they merge with the last free region)
+	///
+	/// Returns false if the heap can't grow (ceiling reached, or out of physical memory)
+	fn grow(&mut self, layout: Layout) -> bool {
+		// worst case: the new region isn't merged, and `layout` needs some alignment slack
+		let needed =
+			LinkedListAllocator::size_align(layout).size() + layout.align() + MIN_REGION_SIZE;
+		let wanted = align_up(needed.max(HEAP_GROWTH_MIN_SIZE), FRAME_SIZE);
+		let available = self.max_size - self.size();
+
+		if needed > available {
+			return false;
+		}
+
+		let grown = map_heap_frames(self.end, wanted.min(available) / FRAME_SIZE) * FRAME_SIZE;
+		if grown == 0 {
+			return false;
+		}
+
+		self.allocator.add_free_region(self.end, grown);
+		self.end += grown;
+
+		true
+	}
+
+	fn stats(&self) -> HeapStats {
+		let (free_bytes, free_regions, largest_free_region) =
+			self.allocator.free_regions().fold((0, 0, 0), |(bytes, count, largest), (_, size)| {
+				(bytes + size, count + 1, largest.max(size))
+			});
+
+		HeapStats {
+			size: self.size(),
+			max_size: self.max_size,
+			used_bytes: self.used_bytes,
+			peak_used_bytes: self.peak_used_bytes,
+			free_bytes,
+			free_regions,
+			largest_free_region,
+		}
+	}
+}
+
+/// Builds the caches of the [SIZE_CLASSES] (each object is aligned on its size)
+const fn size_class_caches() -> [SlabCache; SIZE_CLASSES.len()] {
+	let mut caches = [const { SlabCache::new(0, 1) }; SIZE_CLASSES.len()];
+
+	let mut i = 0;
+	while i < SIZE_CLASSES.len() {
+		caches[i] = SlabCache::new(SIZE_CLASSES[i], SIZE_CLASSES[i]);
+		i += 1;
+	}
+
+	caches
+}
+
+/// Snapshot of the heap usage (cf. [LockedHeap::stats])
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+	/// Mapped size of the heap
+	pub size: usize,
+
+	/// Ceiling of the heap growth
+	pub max_size: usize,
+
+	/// Sum of the sizes requested by the live allocations
+	pub used_bytes: usize,
+
+	/// Highest value of `used_bytes` since boot
+	pub peak_used_bytes: usize,
+
+	/// Sum of the free regions sizes
+	pub free_bytes: usize,
+
+	/// Number of regions in the free list
+	pub free_regions: usize,
+
+	/// Size of the biggest allocation that can succeed without growing the heap
+	/// (minus alignment slack)
+	pub largest_free_region: usize,
+}
+
+/// Maps `count` newly allocated frames starting at `start_vaddr`
+///
+/// Returns the number of frames mapped (less than `count` if we ran out of physical memory)
+fn map_heap_frames(start_vaddr: usize, count: usize) -> usize {
+	debug_assert_eq!(start_vaddr % FRAME_SIZE, 0, "Heap pages must be page-aligned");
+
+	for i in 0..count {
+		let Some(physical_frame_addr) = kmalloc() else {
+			return i;
+		};
+		let current_vaddr = start_vaddr + i * FRAME_SIZE;
 
 		// Safety:
 		// 	 `physical_frame_addr` is valid
@@ -74,8 +376,37 @@ pub fn init_virtual_allocator() {
 		}
 	}
 
+	count
+}
+
+pub fn init_virtual_allocator() {
+	let num_frames = HEAP_INITIAL_SIZE / FRAME_SIZE;
+	assert_eq!(map_heap_frames(HEAP_START_ADDRESS, num_frames), num_frames, "Out of memory");
+
+	let mut heap = VIRTUAL_ALLOCATOR.0.lock();
+
 	unsafe {
 		// Safety: both args are valid
-		VIRTUAL_ALLOCATOR.0.lock().init(HEAP_START_ADDRESS, num_frames * FRAME_SIZE);
+		heap.allocator.init(HEAP_START_ADDRESS, HEAP_INITIAL_SIZE);
 	}
+	heap.end = HEAP_START_ADDRESS + HEAP_INITIAL_SIZE;
+}
+
+/// Prints the heap statistics, then panics
+#[alloc_error_handler]
+fn alloc_error_handler(layout: Layout) -> ! {
+	let stats = VIRTUAL_ALLOCATOR.stats();
+
+	println!(
+		"Heap: {} bytes mapped (max {}), {} bytes in use, {} bytes free in {} regions, largest \
+		 free region: {}",
+		stats.size,
+		stats.max_size,
+		stats.used_bytes,
+		stats.free_bytes,
+		stats.free_regions,
+		stats.largest_free_region
+	);
+
+	panic!("allocation error: {layout:?}");
 }
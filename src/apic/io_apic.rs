@@ -0,0 +1,151 @@
+//! The I/O APIC, which routes the device interrupts to the local APIC
+
+use spin::Mutex;
+
+use super::{
+	ApicConfig,
+	IsaIrqRoute,
+	local_apic,
+};
+use crate::paging::mmio::map_mmio;
+use crate::pic::{
+	IRQ_LINES,
+	Irq,
+};
+
+/// Register selector, then data window, relative to the I/O APIC base
+const IOREGSEL: usize = 0x00;
+const IOWIN: usize = 0x10;
+
+const REGISTER_VERSION: u32 = 0x01;
+
+/// Low then high 32 bits of the `n`-th redirection entry are at `0x10 + 2n` and `0x10 + 2n + 1`
+const REGISTER_REDIRECTION_TABLE: u32 = 0x10;
+
+// redirection entry bits (fixed delivery, physical destination)
+const ACTIVE_LOW: u32 = 1 << 13;
+const LEVEL_TRIGGERED: u32 = 1 << 15;
+const MASKED: u32 = 1 << 16;
+
+struct IoApic {
+	/// Virtual address of the registers
+	base: usize,
+
+	gsi_base: u32,
+
+	/// Number of inputs (redirection entries)
+	inputs: u32,
+
+	isa_routes: [IsaIrqRoute; IRQ_LINES],
+}
+
+impl IoApic {
+	fn read(&self, register: u32) -> u32 {
+		unsafe {
+			core::ptr::write_volatile((self.base + IOREGSEL) as *mut u32, register);
+			core::ptr::read_volatile((self.base + IOWIN) as *const u32)
+		}
+	}
+
+	fn write(&self, register: u32, value: u32) {
+		unsafe {
+			core::ptr::write_volatile((self.base + IOREGSEL) as *mut u32, register);
+			core::ptr::write_volatile((self.base + IOWIN) as *mut u32, value);
+		}
+	}
+
+	/// Index of the redirection entry of an ISA IRQ
+	fn input_of(&self, irq: Irq) -> Option<u32> {
+		// only used between the two PICs
+		if irq == Irq::Cascade {
+			return None;
+		}
+
+		// the override of another line to the same GSI wins (e.g. the PIT, IRQ0, is often on GSI 2)
+		let gsi = self.isa_routes[irq as usize].gsi;
+		let is_taken = Irq::ALL.into_iter().any(|other| {
+			let other_gsi = self.isa_routes[other as usize].gsi;
+			other != irq && other_gsi == gsi && other_gsi != other as u32
+		});
+		if is_taken {
+			return None;
+		}
+
+		let input = gsi.checked_sub(self.gsi_base)?;
+		(input < self.inputs).then_some(input)
+	}
+
+	fn set_masked(&self, irq: Irq, is_masked: bool) {
+		let Some(input) = self.input_of(irq) else {
+			return;
+		};
+
+		let register = REGISTER_REDIRECTION_TABLE + 2 * input;
+		let low = self.read(register);
+		self.write(register, if is_masked { low | MASKED } else { low & !MASKED });
+	}
+}
+
+/// The I/O APIC (None until [init_io_apic])
+///
+/// Always locked with interrupts disabled, as the IRQ dispatcher locks it too
+static IO_APIC: Mutex<Option<IoApic>> = Mutex::new(None);
+
+/// Maps the I/O APIC, then routes the ISA IRQs to the local APIC, on the vectors the PICs used,
+/// masked
+///
+/// # Safety
+///  - `config` must describe a real I/O APIC
+///  - the local APIC must be initialized (cf. [local_apic::init_local_apic])
+pub(super) unsafe fn init_io_apic(config: &ApicConfig) {
+	let base = unsafe { map_mmio(config.io_apic_address, 4096) };
+
+	let mut io_apic = IoApic {
+		base,
+		gsi_base: config.io_apic_gsi_base,
+		inputs: 0,
+		isa_routes: config.isa_routes,
+	};
+	io_apic.inputs = ((io_apic.read(REGISTER_VERSION) >> 16) & 0xff) + 1;
+
+	// mask everything, including the inputs we don't know about
+	for input in 0..io_apic.inputs {
+		io_apic.write(REGISTER_REDIRECTION_TABLE + 2 * input, MASKED);
+	}
+
+	let destination = (local_apic::id() as u32) << 24;
+
+	for irq in Irq::ALL {
+		let Some(input) = io_apic.input_of(irq) else {
+			continue;
+		};
+		let route = io_apic.isa_routes[irq as usize];
+
+		let mut low = MASKED | irq.vector() as u32;
+		if route.is_active_low {
+			low |= ACTIVE_LOW;
+		}
+		if route.is_level_triggered {
+			low |= LEVEL_TRIGGERED;
+		}
+
+		io_apic.write(REGISTER_REDIRECTION_TABLE + 2 * input + 1, destination);
+		io_apic.write(REGISTER_REDIRECTION_TABLE + 2 * input, low);
+	}
+
+	*IO_APIC.lock() = Some(io_apic);
+}
+
+/// Stops the I/O APIC from delivering `irq`
+pub fn mask_irq(irq: Irq) {
+	if let Some(io_apic) = &*IO_APIC.lock() {
+		io_apic.set_masked(irq, true);
+	}
+}
+
+/// Lets the I/O APIC deliver `irq`
+pub fn unmask_irq(irq: Irq) {
+	if let Some(io_apic) = &*IO_APIC.lock() {
+		io_apic.set_masked(irq, false);
+	}
+}
@@ -0,0 +1,167 @@
+//! The local APIC of the CPU: interrupt acknowledgement and timer
+
+use core::sync::atomic::{
+	AtomicU32,
+	AtomicUsize,
+	Ordering,
+};
+
+use crate::idt::trap::{
+	TrapFrame,
+	register_trap_handler,
+};
+use crate::paging::mmio::map_mmio;
+use crate::pit::busy_sleep_ms;
+use crate::shared::{
+	cpuid,
+	rdmsr,
+	wrmsr,
+};
+
+/// MSR holding the physical address of the local APIC, and its global enable bit
+const IA32_APIC_BASE: u32 = 0x1b;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDRESS_MASK: u64 = 0xffff_f000;
+
+/// CPUID.01h:EDX bit telling if the CPU has a local APIC
+const CPUID_APIC: u32 = 1 << 9;
+
+// register offsets
+const REGISTER_ID: usize = 0x20;
+const REGISTER_TASK_PRIORITY: usize = 0x80;
+const REGISTER_EOI: usize = 0xb0;
+const REGISTER_SPURIOUS: usize = 0xf0;
+const REGISTER_LVT_TIMER: usize = 0x320;
+const REGISTER_LVT_LINT0: usize = 0x350;
+const REGISTER_LVT_LINT1: usize = 0x360;
+const REGISTER_LVT_ERROR: usize = 0x370;
+const REGISTER_TIMER_INITIAL_COUNT: usize = 0x380;
+const REGISTER_TIMER_CURRENT_COUNT: usize = 0x390;
+const REGISTER_TIMER_DIVIDE: usize = 0x3e0;
+
+/// Spurious register: software enable bit
+const SOFTWARE_ENABLE: u32 = 1 << 8;
+
+/// Local vector table entries
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_NMI: u32 = 0b100 << 8;
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+
+/// Timer divide configuration: divide the bus clock by 16
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+
+/// Vector of the local APIC timer interrupt (right after the PIC vectors)
+pub const APIC_TIMER_VECTOR: u8 = 48;
+
+/// Vector of the local APIC internal errors
+pub const APIC_ERROR_VECTOR: u8 = 49;
+
+/// Vector of the spurious interrupts (its low 4 bits must be set on old APICs)
+pub const APIC_SPURIOUS_VECTOR: u8 = 63;
+
+/// Frequency of the local APIC timer, in Hz
+pub const APIC_TIMER_FREQUENCY: u32 = 100;
+
+/// How long the timer counts against the PIT, to know its frequency
+const CALIBRATION_MS: u64 = 10;
+
+/// Virtual address of the local APIC registers (0 until [init_local_apic])
+static LOCAL_APIC_BASE: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of timer interrupts (cf. [start_timer])
+static TIMER_TICKS: AtomicU32 = AtomicU32::new(0);
+
+/// Returns true if the CPU has a local APIC
+pub fn is_supported() -> bool {
+	cpuid(1).edx & CPUID_APIC != 0
+}
+
+/// Maps and enables the local APIC, with its local interrupts masked (the legacy PIC line, LINT0,
+/// included) except NMIs
+///
+/// # Safety
+///  - the CPU must have a local APIC (cf. [is_supported])
+///  - paging must be initialized
+pub unsafe fn init_local_apic() {
+	let apic_base = unsafe { rdmsr(IA32_APIC_BASE) };
+	unsafe { wrmsr(IA32_APIC_BASE, apic_base | APIC_BASE_ENABLE) };
+
+	let physical_address = (apic_base & APIC_BASE_ADDRESS_MASK) as u32;
+	let base = unsafe { map_mmio(physical_address, 4096) };
+	LOCAL_APIC_BASE.store(base, Ordering::Relaxed);
+
+	unsafe {
+		register_trap_handler(APIC_TIMER_VECTOR as usize, timer_interrupt_handler);
+		register_trap_handler(APIC_ERROR_VECTOR as usize, error_interrupt_handler);
+
+		// spurious interrupts must not be acknowledged
+		register_trap_handler(APIC_SPURIOUS_VECTOR as usize, |_| {});
+	}
+
+	write(REGISTER_LVT_TIMER, LVT_MASKED);
+	write(REGISTER_LVT_LINT0, LVT_MASKED);
+	write(REGISTER_LVT_LINT1, LVT_NMI);
+	write(REGISTER_LVT_ERROR, APIC_ERROR_VECTOR as u32);
+
+	// accept every interrupt priority
+	write(REGISTER_TASK_PRIORITY, 0);
+	write(REGISTER_SPURIOUS, SOFTWARE_ENABLE | APIC_SPURIOUS_VECTOR as u32);
+}
+
+/// ID of the local APIC (the destination of the I/O APIC interrupts)
+pub fn id() -> u8 {
+	(read(REGISTER_ID) >> 24) as u8
+}
+
+/// Tells the local APIC that we finished to handle an interrupt
+pub fn end_of_interrupt() {
+	write(REGISTER_EOI, 0);
+}
+
+/// Calibrates the timer against the PIT, then starts it at `frequency` Hz
+///
+/// Returns the number of timer counts per millisecond
+pub fn start_timer(frequency: u32) -> u32 {
+	write(REGISTER_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+	write(REGISTER_LVT_TIMER, LVT_MASKED);
+
+	write(REGISTER_TIMER_INITIAL_COUNT, u32::MAX);
+	busy_sleep_ms(CALIBRATION_MS);
+	let elapsed = u32::MAX - read(REGISTER_TIMER_CURRENT_COUNT);
+
+	let counts_per_ms = elapsed / CALIBRATION_MS as u32;
+
+	write(REGISTER_LVT_TIMER, APIC_TIMER_VECTOR as u32 | LVT_TIMER_PERIODIC);
+	write(REGISTER_TIMER_INITIAL_COUNT, (counts_per_ms * 1000 / frequency).max(1));
+
+	counts_per_ms
+}
+
+/// Number of local APIC timer interrupts since [start_timer]
+pub fn apic_timer_ticks() -> u32 {
+	TIMER_TICKS.load(Ordering::Relaxed)
+}
+
+fn timer_interrupt_handler(_frame: &mut TrapFrame) {
+	TIMER_TICKS.fetch_add(1, Ordering::Relaxed);
+	end_of_interrupt();
+}
+
+fn error_interrupt_handler(_frame: &mut TrapFrame) {
+	crate::println!("APIC: internal error");
+	end_of_interrupt();
+}
+
+fn read(register: usize) -> u32 {
+	let base = LOCAL_APIC_BASE.load(Ordering::Relaxed);
+	debug_assert_ne!(base, 0, "the local APIC isn't mapped");
+
+	unsafe { core::ptr::read_volatile((base + register) as *const u32) }
+}
+
+fn write(register: usize, value: u32) {
+	let base = LOCAL_APIC_BASE.load(Ordering::Relaxed);
+	debug_assert_ne!(base, 0, "the local APIC isn't mapped");
+
+	unsafe { core::ptr::write_volatile((base + register) as *mut u32, value) };
+}
@@ -0,0 +1,127 @@
+//! Local APIC and I/O APIC
+//!
+//! When the CPU has a local APIC and the firmware describes an I/O APIC (cf. [find_apic_config]),
+//! they replace the 8259 PICs: the I/O APIC sends the ISA IRQs on the same vectors as the PICs
+//! did, so the [crate::irq] handlers don't change, and the 8259s are masked. The local APIC timer
+//! is calibrated against the PIT, then runs periodically.
+//!
+//! Otherwise, we keep using the PICs.
+
+mod io_apic;
+pub mod local_apic;
+mod mp;
+
+pub use self::io_apic::{
+	mask_irq,
+	unmask_irq,
+};
+pub use self::local_apic::{
+	apic_timer_ticks,
+	end_of_interrupt,
+};
+use crate::acpi::{
+	Madt,
+	acpi_tables,
+};
+use crate::irq::switch_to_apic;
+use crate::pic::IRQ_LINES;
+
+/// Where an ISA IRQ arrives on the I/O APIC, and how its signal looks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsaIrqRoute {
+	/// Global System Interrupt (the I/O APIC input, for a single I/O APIC starting at 0)
+	pub gsi: u32,
+
+	/// ISA lines are active high, unless overridden (e.g. by some PCI devices)
+	pub is_active_low: bool,
+
+	/// ISA lines are edge-triggered, unless overridden
+	pub is_level_triggered: bool,
+}
+
+impl IsaIrqRoute {
+	/// The route of an ISA line that isn't overridden
+	const fn identity(irq: u8) -> Self {
+		Self {
+			gsi: irq as u32,
+			is_active_low: false,
+			is_level_triggered: false,
+		}
+	}
+
+	/// Parses the MPS INTI flags, used by both the MP tables and the ACPI MADT
+	///
+	/// Bits 0-1 are the polarity and bits 2-3 the trigger mode: 0b00 means "as the bus says"
+	/// (active high and edge-triggered for ISA), 0b01 active high or edge, 0b11 active low or
+	/// level
+	pub const fn from_mps_flags(gsi: u32, flags: u16) -> Self {
+		Self {
+			gsi,
+			is_active_low: flags & 0b11 == 0b11,
+			is_level_triggered: (flags >> 2) & 0b11 == 0b11,
+		}
+	}
+}
+
+/// What the firmware tells us about the APICs
+#[derive(Debug, Clone, Copy)]
+pub struct ApicConfig {
+	/// Physical address of the I/O APIC registers
+	pub io_apic_address: u32,
+
+	/// First GSI handled by the I/O APIC
+	pub io_apic_gsi_base: u32,
+
+	/// Routes of the 16 ISA IRQs, with the firmware overrides applied
+	pub isa_routes: [IsaIrqRoute; IRQ_LINES],
+}
+
+impl ApicConfig {
+	/// A configuration with the I/O APIC at `io_apic_address`, and no override
+	pub fn new(io_apic_address: u32, io_apic_gsi_base: u32) -> Self {
+		let mut isa_routes = [IsaIrqRoute::identity(0); IRQ_LINES];
+		for (irq, route) in isa_routes.iter_mut().enumerate() {
+			*route = IsaIrqRoute::identity(irq as u8);
+		}
+
+		Self {
+			io_apic_address,
+			io_apic_gsi_base,
+			isa_routes,
+		}
+	}
+}
+
+/// Looks for the APIC configuration in the ACPI MADT, then in the older MP tables
+pub fn find_apic_config() -> Option<ApicConfig> {
+	acpi_tables(|tables| tables.madt.as_ref().and_then(Madt::apic_config))
+		.flatten()
+		.or_else(mp::find_mp_config)
+}
+
+/// Switches the IRQs from the PICs to the I/O APIC, and starts the local APIC timer
+///
+/// Returns false if there is no APIC, in which case the PICs keep delivering the IRQs
+///
+/// Must be called once, after [crate::paging::init_virtual_memory] (the APICs are mapped with
+/// [crate::paging::mmio::map_mmio]), [crate::acpi::init_acpi] (for the MADT) and
+/// [crate::pit::init_pit] (for the calibration)
+pub fn init_apic() -> bool {
+	if !local_apic::is_supported() {
+		return false;
+	}
+
+	let Some(config) = find_apic_config() else {
+		return false;
+	};
+
+	unsafe {
+		local_apic::init_local_apic();
+		io_apic::init_io_apic(&config);
+	}
+
+	switch_to_apic();
+
+	local_apic::start_timer(local_apic::APIC_TIMER_FREQUENCY);
+	true
+}
@@ -0,0 +1,188 @@
+//! Intel MultiProcessor Specification tables
+//!
+//! The BIOS leaves a floating pointer structure (signature `_MP_`) in low memory, pointing to a
+//! configuration table that lists the processors, the buses, the I/O APICs, and where each bus
+//! IRQ arrives on them.
+
+use super::{
+	ApicConfig,
+	IsaIrqRoute,
+};
+use crate::paging::mmio::map_physical;
+use crate::paging::phys_to_virt;
+use crate::pic::IRQ_LINES;
+
+const FLOATING_POINTER_SIGNATURE: &[u8; 4] = b"_MP_";
+const CONFIG_TABLE_SIGNATURE: &[u8; 4] = b"PCMP";
+
+/// Address of the I/O APIC in the default configurations (when there's no configuration table)
+const DEFAULT_IO_APIC_ADDRESS: u32 = 0xfec0_0000;
+
+// configuration table entry types
+const ENTRY_PROCESSOR: u8 = 0;
+const ENTRY_BUS: u8 = 1;
+const ENTRY_IO_APIC: u8 = 2;
+const ENTRY_IO_INTERRUPT: u8 = 3;
+
+/// Size of a processor entry (the other entries are 8 bytes long)
+const PROCESSOR_ENTRY_SIZE: usize = 20;
+
+/// I/O interrupt entry type of the vectored interrupts (the others are NMI, SMI and ExtINT)
+const INTERRUPT_TYPE_INT: u8 = 0;
+
+#[repr(C, packed)]
+struct FloatingPointer {
+	signature: [u8; 4],
+
+	/// Physical address of the [ConfigTableHeader], 0 for a default configuration
+	config_table: u32,
+
+	/// In 16 bytes units
+	length: u8,
+	spec_revision: u8,
+	checksum: u8,
+
+	/// Non zero if the system uses a default configuration
+	default_config: u8,
+	features: [u8; 4],
+}
+
+#[repr(C, packed)]
+struct ConfigTableHeader {
+	signature: [u8; 4],
+
+	/// Size of the header and the entries
+	base_table_length: u16,
+	spec_revision: u8,
+	checksum: u8,
+	oem_id: [u8; 8],
+	product_id: [u8; 12],
+	oem_table: u32,
+	oem_table_size: u16,
+	entry_count: u16,
+	local_apic_address: u32,
+	extended_table_length: u16,
+	extended_table_checksum: u8,
+	reserved: u8,
+}
+
+/// Finds the MP floating pointer, and reads the APIC configuration from its configuration table
+pub(super) fn find_mp_config() -> Option<ApicConfig> {
+	let pointer = find_floating_pointer()?;
+
+	if pointer.config_table == 0 {
+		return (pointer.default_config != 0).then(|| ApicConfig::new(DEFAULT_IO_APIC_ADDRESS, 0));
+	}
+
+	let header_size = size_of::<ConfigTableHeader>();
+	let header =
+		unsafe { &*(map_physical(pointer.config_table, header_size) as *const ConfigTableHeader) };
+	if &header.signature != CONFIG_TABLE_SIGNATURE {
+		return None;
+	}
+
+	let table_length = header.base_table_length as usize;
+	let table = unsafe {
+		core::slice::from_raw_parts(
+			map_physical(pointer.config_table, table_length) as *const u8,
+			table_length,
+		)
+	};
+	if !has_valid_checksum(table) {
+		return None;
+	}
+
+	parse_entries(&table[header_size..], header.entry_count as usize)
+}
+
+/// Builds the [ApicConfig] from the entries of the configuration table
+///
+/// Only the first I/O APIC is used
+fn parse_entries(mut entries: &[u8], entry_count: usize) -> Option<ApicConfig> {
+	let mut isa_bus_id = None;
+	let mut io_apic = None;
+
+	// (source bus, source IRQ, destination I/O APIC ID, destination input, flags)
+	let mut interrupts = [None; IRQ_LINES * 2];
+	let mut interrupt_count = 0;
+
+	for _ in 0..entry_count {
+		let size = match *entries.first()? {
+			ENTRY_PROCESSOR => PROCESSOR_ENTRY_SIZE,
+			_ => 8,
+		};
+		let entry = entries.get(..size)?;
+
+		match entry[0] {
+			ENTRY_BUS if &entry[2..8] == b"ISA   " => isa_bus_id = Some(entry[1]),
+
+			// only the enabled ones
+			ENTRY_IO_APIC if io_apic.is_none() && entry[3] & 1 != 0 => {
+				let address = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+				io_apic = Some((entry[1], address));
+			}
+
+			ENTRY_IO_INTERRUPT if entry[1] == INTERRUPT_TYPE_INT => {
+				if let Some(slot) = interrupts.get_mut(interrupt_count) {
+					let flags = u16::from_le_bytes([entry[2], entry[3]]);
+					*slot = Some((entry[4], entry[5], entry[6], entry[7], flags));
+					interrupt_count += 1;
+				}
+			}
+
+			_ => {}
+		}
+
+		entries = &entries[size..];
+	}
+
+	let (io_apic_id, io_apic_address) = io_apic?;
+	let mut config = ApicConfig::new(io_apic_address, 0);
+
+	for (bus, irq, destination, input, flags) in interrupts.into_iter().flatten() {
+		let is_isa = Some(bus) == isa_bus_id;
+
+		// 0xff means every I/O APIC
+		if is_isa
+			&& (irq as usize) < IRQ_LINES
+			&& (destination == io_apic_id || destination == 0xff)
+		{
+			config.isa_routes[irq as usize] = IsaIrqRoute::from_mps_flags(input as u32, flags);
+		}
+	}
+
+	Some(config)
+}
+
+/// Looks for the floating pointer in the first KiB of the Extended BIOS Data Area, the last KiB
+/// of the base memory, then the BIOS ROM
+fn find_floating_pointer() -> Option<&'static FloatingPointer> {
+	// real mode segment of the EBDA, and size of the base memory in KiB, from the BIOS Data Area
+	let ebda = unsafe { *(phys_to_virt(0x40e) as *const u16) } as u32 * 16;
+	let base_memory_end = unsafe { *(phys_to_virt(0x413) as *const u16) } as u32 * 1024;
+
+	[(ebda, 1024), (base_memory_end.saturating_sub(1024), 1024), (0xf0000, 0x10000)]
+		.into_iter()
+		.filter(|&(start, _)| start != 0)
+		.find_map(|(start, length)| search_floating_pointer(start, length))
+}
+
+/// Searches the floating pointer in `length` bytes of low memory, on 16 bytes boundaries
+fn search_floating_pointer(start: u32, length: u32) -> Option<&'static FloatingPointer> {
+	(start..start + length).step_by(16).find_map(|address| {
+		let pointer = unsafe { &*(phys_to_virt(address) as *const FloatingPointer) };
+		if &pointer.signature != FLOATING_POINTER_SIGNATURE || pointer.length == 0 {
+			return None;
+		}
+
+		let length = pointer.length as usize * 16;
+		let bytes =
+			unsafe { core::slice::from_raw_parts(phys_to_virt(address) as *const u8, length) };
+		has_valid_checksum(bytes).then_some(pointer)
+	})
+}
+
+/// The bytes of the MP structures add up to 0
+fn has_valid_checksum(bytes: &[u8]) -> bool {
+	bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
@@ -0,0 +1,496 @@
+//! ELF32 executables
+//!
+//! [Elf::parse] checks that a file is an i386 executable whose loadable (PT_LOAD) segments fit
+//! in user space without overlapping. [load] then creates an [AddressSpace], adds a memory area
+//! for each segment, that is only writable if the segment is, and copies the file content. The
+//! rest (the .bss) is mapped on the first access, as are the heap (empty until `brk`) and the
+//! stack, that grows down on faults. Only the top of the stack, with the arguments, is mapped at
+//! once.
+//!
+//! The address space belongs to the returned [LoadedProgram], and is released when it is dropped.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::paging::KERNEL_VIRTUAL_BASE;
+use crate::paging::address_space::{
+	AddressSpace,
+	MapError,
+};
+use crate::paging::memory_area::{
+	AreaKind,
+	MemoryArea,
+	page_end,
+	page_start,
+};
+use crate::paging::pmm::FRAME_SIZE;
+
+/// `\x7fELF`
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const EV_CURRENT: u32 = 1;
+const ET_EXEC: u16 = 2;
+const EM_386: u16 = 3;
+
+const ELF_HEADER_SIZE: usize = 52;
+const PROGRAM_HEADER_SIZE: usize = 32;
+
+/// Type of the program headers to load
+const PT_LOAD: u32 = 1;
+
+/// Segment permission flags
+const PF_X: u32 = 1 << 0;
+const PF_W: u32 = 1 << 1;
+
+/// The first page stays unmapped, so null pointers fault
+pub const USER_SPACE_START: u32 = FRAME_SIZE as u32;
+
+/// The user stack ends where the kernel half starts
+pub const USER_STACK_TOP: u32 = KERNEL_VIRTUAL_BASE as u32;
+
+/// Initial size of the user stack area
+pub const USER_STACK_SIZE: u32 = 16 * FRAME_SIZE as u32;
+
+/// Size the user stack can grow to
+pub const MAX_USER_STACK_SIZE: u32 = 8 * 1024 * 1024;
+
+/// Segments must end below the range the user stack grows into
+pub const USER_SPACE_END: u32 = USER_STACK_TOP - MAX_USER_STACK_SIZE;
+
+/// Maximum size of the arguments and environment strings, and their pointers
+pub const MAX_ARGUMENTS_SIZE: usize = FRAME_SIZE;
+
+/// Errors returned by [Elf::parse] and [load]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+	/// The file ends before the headers
+	Truncated,
+
+	/// The file doesn't start with `\x7fELF`
+	BadMagic,
+
+	/// Not a 32-bit little-endian ELF, version 1
+	UnsupportedFormat,
+
+	/// Not an executable (e.g. a shared or relocatable object)
+	NotExecutable,
+
+	/// Not built for i386
+	WrongMachine,
+
+	/// The program header entries don't have the ELF32 size
+	BadProgramHeaderSize,
+
+	/// The content of the segment (by program header index) isn't in the file
+	SegmentOutOfFile(usize),
+
+	/// The segment is smaller in memory than in the file
+	SegmentFileSizeTooBig(usize),
+
+	/// The segment is empty in memory
+	EmptySegment(usize),
+
+	/// The segment isn't between [USER_SPACE_START] and [USER_SPACE_END]
+	SegmentOutsideUserSpace(usize),
+
+	/// The two segments share addresses
+	OverlappingSegments(usize, usize),
+
+	/// There is no PT_LOAD segment
+	NoLoadableSegment,
+
+	/// The entry point isn't in an executable segment
+	EntryOutsideSegments,
+
+	/// A page couldn't be mapped
+	Map(MapError),
+
+	/// There is no free page frame left
+	OutOfMemory,
+
+	/// The arguments and environment don't fit in [MAX_ARGUMENTS_SIZE]
+	ArgumentsTooLong,
+}
+
+impl fmt::Display for ElfError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Truncated => write!(f, "the file is truncated"),
+			Self::BadMagic => write!(f, "not an ELF file"),
+			Self::UnsupportedFormat => write!(f, "not a 32-bit little-endian ELF"),
+			Self::NotExecutable => write!(f, "not an executable"),
+			Self::WrongMachine => write!(f, "not an i386 executable"),
+			Self::BadProgramHeaderSize => write!(f, "invalid program header size"),
+			Self::SegmentOutOfFile(index) => write!(f, "segment {index} is outside of the file"),
+			Self::SegmentFileSizeTooBig(index) => {
+				write!(f, "segment {index} is bigger in the file than in memory")
+			}
+			Self::EmptySegment(index) => write!(f, "segment {index} is empty"),
+			Self::SegmentOutsideUserSpace(index) => {
+				write!(f, "segment {index} is outside of user space")
+			}
+			Self::OverlappingSegments(first, second) => {
+				write!(f, "segments {first} and {second} overlap")
+			}
+			Self::NoLoadableSegment => write!(f, "no loadable segment"),
+			Self::EntryOutsideSegments => {
+				write!(f, "the entry point isn't in an executable segment")
+			}
+			Self::Map(error) => write!(f, "a page couldn't be mapped: {error}"),
+			Self::OutOfMemory => write!(f, "out of memory"),
+			Self::ArgumentsTooLong => write!(f, "the arguments are too long"),
+		}
+	}
+}
+
+impl From<MapError> for ElfError {
+	fn from(error: MapError) -> Self {
+		match error {
+			MapError::OutOfMemory => Self::OutOfMemory,
+			error => Self::Map(error),
+		}
+	}
+}
+
+/// A PT_LOAD program header
+#[derive(Debug, Clone, Copy)]
+pub struct Segment {
+	/// Index of the program header
+	pub index: usize,
+
+	/// Offset of the content in the file
+	pub offset: u32,
+
+	/// Where the segment is mapped
+	pub virtual_address: u32,
+
+	/// Size of the content in the file
+	pub file_size: u32,
+
+	/// Size in memory (the bytes after [Segment::file_size] are zeroed)
+	pub memory_size: u32,
+
+	/// PF_X, PF_W and PF_R
+	pub flags: u32,
+}
+
+impl Segment {
+	/// The address after the segment (checked by [Elf::parse])
+	pub fn end(&self) -> u32 {
+		self.virtual_address + self.memory_size
+	}
+
+	/// Returns true if the program can write to the segment
+	pub fn is_writable(&self) -> bool {
+		self.flags & PF_W != 0
+	}
+
+	/// Returns true if the program can run the segment
+	pub fn is_executable(&self) -> bool {
+		self.flags & PF_X != 0
+	}
+
+	fn contains(&self, address: u32) -> bool {
+		(self.virtual_address..self.end()).contains(&address)
+	}
+}
+
+/// A validated ELF32 i386 executable
+pub struct Elf<'a> {
+	bytes: &'a [u8],
+
+	/// Address of the first instruction
+	pub entry: u32,
+
+	program_header_offset: usize,
+	program_header_count: usize,
+}
+
+impl<'a> Elf<'a> {
+	/// Checks the headers of the executable in `bytes`
+	pub fn parse(bytes: &'a [u8]) -> Result<Self, ElfError> {
+		if bytes.len() < ELF_HEADER_SIZE {
+			return Err(ElfError::Truncated);
+		}
+
+		if bytes[..4] != ELF_MAGIC {
+			return Err(ElfError::BadMagic);
+		}
+
+		if bytes[4] != ELFCLASS32
+			|| bytes[5] != ELFDATA2LSB
+			|| bytes[6] != EV_CURRENT as u8
+			|| read_u32(bytes, 20) != EV_CURRENT
+		{
+			return Err(ElfError::UnsupportedFormat);
+		}
+
+		if read_u16(bytes, 16) != ET_EXEC {
+			return Err(ElfError::NotExecutable);
+		}
+
+		if read_u16(bytes, 18) != EM_386 {
+			return Err(ElfError::WrongMachine);
+		}
+
+		let program_header_count = read_u16(bytes, 44) as usize;
+		if program_header_count > 0 && read_u16(bytes, 42) as usize != PROGRAM_HEADER_SIZE {
+			return Err(ElfError::BadProgramHeaderSize);
+		}
+
+		let program_header_offset = read_u32(bytes, 28) as usize;
+		let program_headers_end =
+			program_header_offset.checked_add(program_header_count * PROGRAM_HEADER_SIZE);
+		if program_headers_end.is_none_or(|end| end > bytes.len()) {
+			return Err(ElfError::Truncated);
+		}
+
+		let elf = Self {
+			bytes,
+			entry: read_u32(bytes, 24),
+			program_header_offset,
+			program_header_count,
+		};
+		elf.check_segments()?;
+
+		Ok(elf)
+	}
+
+	/// The PT_LOAD segments
+	pub fn segments(&self) -> impl Iterator<Item = Segment> + '_ {
+		(0..self.program_header_count).filter_map(|index| {
+			let header = self.program_header_offset + index * PROGRAM_HEADER_SIZE;
+
+			(read_u32(self.bytes, header) == PT_LOAD).then(|| Segment {
+				index,
+				offset: read_u32(self.bytes, header + 4),
+				virtual_address: read_u32(self.bytes, header + 8),
+				file_size: read_u32(self.bytes, header + 16),
+				memory_size: read_u32(self.bytes, header + 20),
+				flags: read_u32(self.bytes, header + 24),
+			})
+		})
+	}
+
+	/// The file content of `segment`
+	fn content(&self, segment: &Segment) -> &[u8] {
+		let offset = segment.offset as usize;
+		&self.bytes[offset..offset + segment.file_size as usize]
+	}
+
+	fn check_segments(&self) -> Result<(), ElfError> {
+		for segment in self.segments() {
+			let index = segment.index;
+
+			if segment.file_size > segment.memory_size {
+				return Err(ElfError::SegmentFileSizeTooBig(index));
+			}
+
+			// it would have no page of its own, and share the one before it (cf. load_segment)
+			if segment.memory_size == 0 {
+				return Err(ElfError::EmptySegment(index));
+			}
+
+			let file_end = segment.offset.checked_add(segment.file_size);
+			if file_end.is_none_or(|end| end as usize > self.bytes.len()) {
+				return Err(ElfError::SegmentOutOfFile(index));
+			}
+
+			let end = segment.virtual_address.checked_add(segment.memory_size);
+			if segment.virtual_address < USER_SPACE_START
+				|| end.is_none_or(|end| end > USER_SPACE_END)
+			{
+				return Err(ElfError::SegmentOutsideUserSpace(index));
+			}
+		}
+
+		for first in self.segments() {
+			if let Some(second) =
+				self.segments().skip_while(|other| other.index <= first.index).find(|second| {
+					first.virtual_address < second.end() && second.virtual_address < first.end()
+				}) {
+				return Err(ElfError::OverlappingSegments(first.index, second.index));
+			}
+		}
+
+		if self.segments().next().is_none() {
+			return Err(ElfError::NoLoadableSegment);
+		}
+
+		if !self.segments().any(|segment| segment.is_executable() && segment.contains(self.entry)) {
+			return Err(ElfError::EntryOutsideSegments);
+		}
+
+		Ok(())
+	}
+}
+
+/// A program mapped in its own address space by [load]
+///
+/// Dropping it frees its pages
+pub struct LoadedProgram {
+	/// Address of the first instruction
+	pub entry: u32,
+
+	/// Initial user stack pointer, on `argc`
+	pub stack_pointer: u32,
+
+	/// The segments, the heap and the stack
+	pub address_space: AddressSpace,
+}
+
+/// Maps the segments of `elf` and a user stack holding `args` and `env` in a new address space
+pub fn load(elf: &Elf, args: &[&str], env: &[&str]) -> Result<LoadedProgram, ElfError> {
+	let mut address_space = AddressSpace::new().ok_or(ElfError::OutOfMemory)?;
+
+	for segment in elf.segments() {
+		load_segment(&mut address_space, elf, &segment)?;
+	}
+
+	// the program break starts after the last segment
+	let heap_start =
+		elf.segments().map(|segment| page_end(segment.end())).max().unwrap_or(0) as u32;
+	address_space.add_area(MemoryArea::new(heap_start, heap_start, AreaKind::Heap, true))?;
+
+	let stack_limit = USER_STACK_TOP - MAX_USER_STACK_SIZE;
+	address_space.add_area(MemoryArea::new(
+		USER_STACK_TOP - USER_STACK_SIZE,
+		USER_STACK_TOP,
+		AreaKind::Stack {
+			limit: stack_limit,
+		},
+		true,
+	))?;
+
+	let stack_pointer = build_stack(&mut address_space, args, env)?;
+
+	Ok(LoadedProgram {
+		entry: elf.entry,
+		stack_pointer,
+		address_space,
+	})
+}
+
+/// Adds the memory area of `segment`, and maps the pages that hold its file content: the others
+/// are mapped on the first access
+fn load_segment(
+	address_space: &mut AddressSpace,
+	elf: &Elf,
+	segment: &Segment,
+) -> Result<(), ElfError> {
+	let is_writable = segment.is_writable();
+	let mut area_start = page_start(segment.virtual_address);
+	let mut area_end = page_end(segment.end()) as u32;
+
+	// a page shared with another segment is in its area already: it is mapped now, with the
+	// permissions of both
+	for page in [area_start, area_end - FRAME_SIZE as u32] {
+		// a single page, shared on both ends
+		if area_start >= area_end {
+			break;
+		}
+
+		if let Some(other) = address_space.area(page) {
+			map_user_page(address_space, page, is_writable || other.is_writable)?;
+
+			if page == area_start {
+				area_start += FRAME_SIZE as u32;
+			} else {
+				area_end -= FRAME_SIZE as u32;
+			}
+		}
+	}
+
+	if area_start < area_end {
+		address_space.add_area(MemoryArea::new(
+			area_start,
+			area_end,
+			AreaKind::Anonymous,
+			is_writable,
+		))?;
+	}
+
+	let content_end = segment.virtual_address + segment.file_size;
+	for page in (page_start(segment.virtual_address)..content_end).step_by(FRAME_SIZE) {
+		map_user_page(address_space, page, is_writable)?;
+	}
+
+	address_space.write(segment.virtual_address, elf.content(segment))?;
+	Ok(())
+}
+
+/// Maps a zeroed user page at `virtual_address`, or makes it writable if `is_writable` and
+/// another segment already mapped it
+fn map_user_page(
+	address_space: &mut AddressSpace,
+	virtual_address: u32,
+	is_writable: bool,
+) -> Result<(), ElfError> {
+	match address_space.translate(virtual_address) {
+		Some((physical_address, flags)) if is_writable && !flags.is_writable() => {
+			address_space.map_page(virtual_address, physical_address, true, true)?;
+		}
+		Some(_) => {}
+		None => {
+			address_space.map_zeroed_page(virtual_address, true, is_writable)?;
+		}
+	}
+	Ok(())
+}
+
+/// Maps the top of the user stack, and pushes the arguments and environment as the i386 System V
+/// ABI wants them at the entry point: `argc`, `argv[0..argc]`, NULL, `envp[..]`, NULL, with the
+/// strings above
+///
+/// Returns the stack pointer
+fn build_stack(
+	address_space: &mut AddressSpace,
+	args: &[&str],
+	env: &[&str],
+) -> Result<u32, ElfError> {
+	let strings_size: usize = args.iter().chain(env).map(|string| string.len() + 1).sum();
+	let pointer_count = 1 + args.len() + 1 + env.len() + 1;
+	if strings_size + pointer_count * 4 + 16 > MAX_ARGUMENTS_SIZE {
+		return Err(ElfError::ArgumentsTooLong);
+	}
+
+	let strings_address = USER_STACK_TOP as usize - strings_size;
+	let stack_pointer = (strings_address - pointer_count * 4) & !0xf;
+
+	// the rest of the stack is mapped on the first access
+	for page in (page_start(stack_pointer as u32)..USER_STACK_TOP).step_by(FRAME_SIZE) {
+		map_user_page(address_space, page, true)?;
+	}
+
+	// the top of the stack, built here then copied at once
+	let mut stack = Vec::with_capacity(USER_STACK_TOP as usize - stack_pointer);
+	let mut strings = Vec::with_capacity(strings_size);
+
+	stack.extend((args.len() as u32).to_le_bytes());
+	for list in [args, env] {
+		for string in list {
+			let address = (strings_address + strings.len()) as u32;
+			stack.extend(address.to_le_bytes());
+
+			strings.extend(string.as_bytes());
+			strings.push(0);
+		}
+		stack.extend(0u32.to_le_bytes());
+	}
+
+	// alignment padding
+	stack.resize(strings_address - stack_pointer, 0);
+	stack.extend(strings);
+
+	address_space.write(stack_pointer as u32, &stack)?;
+	Ok(stack_pointer as u32)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+	u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
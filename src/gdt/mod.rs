@@ -1,5 +1,6 @@
 pub mod dump;
 pub mod entry;
+pub mod tss;
 
 use core::arch::asm;
 
@@ -8,16 +9,38 @@ use self::entry::{
 	GdtEntryFlags,
 	GtdEntryAccessFlags,
 };
+use self::tss::{
+	DOUBLE_FAULT_TSS,
+	MAIN_TSS,
+	TaskStateSegment,
+	init_tss,
+	load_task_register,
+};
+use crate::idt::exceptions::double_fault_task;
+use crate::paging::KERNEL_VIRTUAL_BASE;
 use crate::shared::PrivilegeRing;
 
-const GDT_BASE: usize = 0x00000800;
-const GDT_LEN: usize = 7;
+/// The GDT lives at the physical address 0x800, in the reserved low memory
+const GDT_BASE: usize = KERNEL_VIRTUAL_BASE + 0x00000800;
+const GDT_LEN: usize = 9;
 const GDT_SIZE: usize = core::mem::size_of::<[GdtEntry; GDT_LEN]>();
 
 const GDT_ADDRESS: *mut GdtEntry = GDT_BASE as *mut GdtEntry;
 // we place the pointer at the end of the gdt array
 const GDT_PTR_ADDRESS: *mut GdtPointer = (GDT_BASE + GDT_SIZE) as *mut GdtPointer;
 
+// offsets of the GDT entries, relative to GDT_BASE
+pub const KERNEL_CODE_SELECTOR: u16 = 0x08;
+pub const KERNEL_DATA_SELECTOR: u16 = 0x10;
+pub const KERNEL_STACK_SELECTOR: u16 = 0x18;
+pub const TSS_SELECTOR: u16 = 0x38;
+pub const DOUBLE_FAULT_TSS_SELECTOR: u16 = 0x40;
+
+// user segments, with their requested privilege level (the low bits) set to ring 3
+pub const USER_CODE_SELECTOR: u16 = 0x20 | 3;
+pub const USER_DATA_SELECTOR: u16 = 0x28 | 3;
+pub const USER_STACK_SELECTOR: u16 = 0x30 | 3;
+
 /// Pointer descriptor to load GDT into the CPU
 #[repr(C, packed)]
 pub struct GdtPointer {
@@ -37,16 +60,25 @@ pub fn init_gdt() {
 	gdt[5] = user_gdt(MemoryType::Data); // User Data
 	gdt[6] = user_gdt(MemoryType::Data); // User Stack
 
+	init_tss(double_fault_task);
+	gdt[7] = tss_gdt(&raw const MAIN_TSS); // Main TSS
+	gdt[8] = tss_gdt(&raw const DOUBLE_FAULT_TSS); // Double fault TSS
+
 	let gdt_ptr = unsafe { &mut *GDT_PTR_ADDRESS };
 	gdt_ptr.base = GDT_BASE as u32;
 	gdt_ptr.limit = GDT_SIZE as u16 - 1;
 
-	// offset of kernel code/data GDT entries, relative to ptr.base (GDT_BASE)
-	let kcode_offset = core::mem::size_of::<GdtEntry>() as u32;
-	let kdata_offset = 2 * core::mem::size_of::<GdtEntry>() as u16;
-	let kstack_offset = 3 * core::mem::size_of::<GdtEntry>() as u16;
+	unsafe {
+		load_gdt(
+			GDT_PTR_ADDRESS,
+			KERNEL_CODE_SELECTOR as u32,
+			KERNEL_DATA_SELECTOR,
+			KERNEL_STACK_SELECTOR,
+		);
 
-	unsafe { load_gdt(GDT_PTR_ADDRESS, kcode_offset, kdata_offset, kstack_offset) };
+		// Safety: the GDT is loaded, with the main TSS at TSS_SELECTOR
+		load_task_register(TSS_SELECTOR);
+	}
 }
 
 /// Tells the CPU to load a GDT pointer
@@ -129,3 +161,21 @@ fn user_gdt(mem_type: MemoryType) -> GdtEntry {
 
 	GdtEntry::new(0, 0xffffffff, access_flags, flags)
 }
+
+/// Creates a GDT entry for a TSS (a system segment, only usable by the kernel)
+fn tss_gdt(tss: *const TaskStateSegment) -> GdtEntry {
+	// type 0x9: available 32-bit TSS
+	let access_flags = GtdEntryAccessFlags::new()
+		.with_is_present(true)
+		.with_privilege_level(PrivilegeRing::Kernel)
+		.with_is_code_or_data(false)
+		.with_is_executable(true)
+		.with_accessed(true);
+
+	GdtEntry::new(
+		tss as u32,
+		size_of::<TaskStateSegment>() as u32 - 1,
+		access_flags,
+		GdtEntryFlags::new(),
+	)
+}
@@ -0,0 +1,142 @@
+//! Task State Segments (TSS)
+//!
+//! We don't use hardware task switching, except for double faults, so we have two TSS:
+//!  - the main one, loaded in the task register with `ltr`: the CPU reads `esp0`/`ss0` from it
+//!    when an interrupt happens in ring 3, to switch to the kernel stack
+//!  - the double fault one: the double fault IDT entry is a task gate pointing to it, so the CPU
+//!    switches to its stack even if the kernel stack overflowed, and saves the faulting context in
+//!    the main TSS (cf. [crate::idt::exceptions::double_fault_task])
+
+use core::arch::asm;
+
+use super::{
+	KERNEL_CODE_SELECTOR,
+	KERNEL_DATA_SELECTOR,
+	KERNEL_STACK_SELECTOR,
+};
+
+/// Size of the stack used by the double fault task
+const DOUBLE_FAULT_STACK_SIZE: usize = 16 * 1024; // 16 KiB
+
+/// The hardware TSS layout
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct TaskStateSegment {
+	/// Selector of the previous task (set by the CPU when switching through a task gate)
+	pub link: u32,
+
+	/// Stack loaded when an interrupt switches to ring 0
+	pub esp0: u32,
+	pub ss0: u32,
+
+	pub esp1: u32,
+	pub ss1: u32,
+	pub esp2: u32,
+	pub ss2: u32,
+
+	pub cr3: u32,
+	pub eip: u32,
+	pub eflags: u32,
+	pub eax: u32,
+	pub ecx: u32,
+	pub edx: u32,
+	pub ebx: u32,
+	pub esp: u32,
+	pub ebp: u32,
+	pub esi: u32,
+	pub edi: u32,
+	pub es: u32,
+	pub cs: u32,
+	pub ss: u32,
+	pub ds: u32,
+	pub fs: u32,
+	pub gs: u32,
+	pub ldt: u32,
+	pub trap: u16,
+
+	/// Offset of the I/O permission bitmap (we have none, so it points past the TSS)
+	pub iomap_base: u16,
+}
+
+impl TaskStateSegment {
+	const fn zeroed() -> Self {
+		Self {
+			link: 0,
+			esp0: 0,
+			ss0: 0,
+			esp1: 0,
+			ss1: 0,
+			esp2: 0,
+			ss2: 0,
+			cr3: 0,
+			eip: 0,
+			eflags: 0,
+			eax: 0,
+			ecx: 0,
+			edx: 0,
+			ebx: 0,
+			esp: 0,
+			ebp: 0,
+			esi: 0,
+			edi: 0,
+			es: 0,
+			cs: 0,
+			ss: 0,
+			ds: 0,
+			fs: 0,
+			gs: 0,
+			ldt: 0,
+			trap: 0,
+			iomap_base: size_of::<Self>() as u16,
+		}
+	}
+}
+
+/// The TSS loaded in the task register
+pub static mut MAIN_TSS: TaskStateSegment = TaskStateSegment::zeroed();
+
+/// The TSS of the double fault task
+pub static mut DOUBLE_FAULT_TSS: TaskStateSegment = TaskStateSegment::zeroed();
+
+#[repr(C, align(16))]
+struct Stack([u8; DOUBLE_FAULT_STACK_SIZE]);
+
+static mut DOUBLE_FAULT_STACK: Stack = Stack([0; DOUBLE_FAULT_STACK_SIZE]);
+
+/// Fills both TSS
+///
+/// `double_fault_handler` is the entry point of the double fault task
+pub(super) fn init_tss(double_fault_handler: extern "C" fn() -> !) {
+	let cr3: u32;
+	unsafe { asm!("mov {}, cr3", out(reg) cr3) };
+
+	let stack_top = &raw const DOUBLE_FAULT_STACK as u32 + DOUBLE_FAULT_STACK_SIZE as u32;
+
+	unsafe {
+		MAIN_TSS.ss0 = KERNEL_STACK_SELECTOR as u32;
+
+		DOUBLE_FAULT_TSS.cr3 = cr3;
+		DOUBLE_FAULT_TSS.eip = double_fault_handler as *const () as u32;
+		DOUBLE_FAULT_TSS.eflags = 0x2; // reserved bit, interrupts disabled
+		DOUBLE_FAULT_TSS.esp = stack_top;
+		DOUBLE_FAULT_TSS.cs = KERNEL_CODE_SELECTOR as u32;
+		DOUBLE_FAULT_TSS.ss = KERNEL_STACK_SELECTOR as u32;
+		DOUBLE_FAULT_TSS.ds = KERNEL_DATA_SELECTOR as u32;
+		DOUBLE_FAULT_TSS.es = KERNEL_DATA_SELECTOR as u32;
+		DOUBLE_FAULT_TSS.fs = KERNEL_DATA_SELECTOR as u32;
+		DOUBLE_FAULT_TSS.gs = KERNEL_DATA_SELECTOR as u32;
+	}
+}
+
+/// Sets the stack the CPU switches to when an interrupt happens in ring 3
+pub fn set_kernel_stack(esp0: u32) {
+	unsafe { MAIN_TSS.esp0 = esp0 };
+}
+
+/// Loads the main TSS in the task register
+///
+/// # Safety
+///  - the GDT must be loaded, with the main TSS descriptor at `selector`
+pub(super) unsafe fn load_task_register(selector: u16) {
+	unsafe { asm!("ltr {0:x}", in(reg) selector) };
+}
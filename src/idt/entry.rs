@@ -14,10 +14,19 @@ impl IdtEntry {
 	}
 
 	/// Sets the handler function and options for the entry
-	pub fn set_handler_fn(&mut self, handler_address: u32, code_segment_offset: u16) {
+	///
+	/// `privilege_level` is the lowest privilege allowed to trigger the interrupt with `int`
+	/// (e.g. [PrivilegeRing::UserSpace] for system calls). Hardware interrupts and exceptions
+	/// ignore it
+	pub fn set_handler_fn(
+		&mut self,
+		handler_address: u32,
+		code_segment_offset: u16,
+		privilege_level: PrivilegeRing,
+	) {
 		let options = IdtEntryOptions::new()
 			.with_is_present(true)
-			.with_privilege_level(PrivilegeRing::Kernel)
+			.with_privilege_level(privilege_level)
 			.with_is_storage_segment(false) // Must be false for Interrupt/Trap gates
 			.with_gate_type(IdtGateType::InterruptGate32); // 0xE
 
@@ -27,6 +36,22 @@ impl IdtEntry {
 		self.0.set_zero(0);
 		self.0.set_options(options);
 	}
+
+	/// Makes the entry a task gate, switching to the task of the `tss_selector` GDT entry
+	pub fn set_task_gate(&mut self, tss_selector: u16) {
+		let options = IdtEntryOptions::new()
+			.with_is_present(true)
+			.with_privilege_level(PrivilegeRing::Kernel)
+			.with_is_storage_segment(false)
+			.with_gate_type(IdtGateType::TaskGate); // 0x5
+
+		// the CPU takes eip from the TSS, so the offset is unused
+		self.0.set_offset_low_bits(0);
+		self.0.set_offset_high_bits(0);
+		self.0.set_offset(tss_selector);
+		self.0.set_zero(0);
+		self.0.set_options(options);
+	}
 }
 
 /// Raw bit representation of an IDT entry
@@ -36,7 +61,7 @@ pub struct RawIdtEntry {
 	/// Lower 16 bits of handler function address
 	pub offset_low_bits: u16,
 
-	/// Kernel code segment offset (usually 0x08)
+	/// Kernel code segment offset (usually 0x08), or TSS selector for task gates
 	pub offset: u16,
 
 	/// Must always be 0
@@ -69,11 +94,11 @@ pub struct IdtEntryOptions {
 #[derive(Specifier, Clone, Copy, PartialEq, Eq)]
 #[bits = 4]
 pub enum IdtGateType {
-	// TaskGate = 0x5,
+	TaskGate = 0x5, // only for double faults
 	// InterruptGate16 = 0x6,
 	// TrapGate16 = 0x7,
-	InterruptGate32 = 0xe, // We only use this
-	TrapGate32 = 0xF,
+	InterruptGate32 = 0xe,
+	TrapGate32 = 0xf,
 }
 
 /// A pointer descriptor to load IDT into the CPU
@@ -0,0 +1,189 @@
+//! Handlers of the 32 architectural exceptions (vectors 0 to 31)
+//!
+//! Every exception without a dedicated handler (cf. [init_interrupt_handlers]) goes to
+//! [fault_handler] (cf. [crate::idt::trap]), which prints a diagnostic screen and halts.
+//!
+//! Double faults are the exception: they switch to a separate task (cf. [double_fault_task]).
+//!
+//! [init_interrupt_handlers]: crate::idt::interrupts::init_interrupt_handlers
+
+use core::fmt;
+
+use modular_bitfield::bitfield;
+use modular_bitfield::specifiers::{
+	B13,
+	B16,
+};
+
+use crate::gdt::tss::MAIN_TSS;
+use crate::idt::interrupts::disable_hardware_interrupts;
+use crate::idt::trap::{
+	Registers,
+	TrapFrame,
+};
+use crate::{
+	println,
+	testing,
+};
+
+/// Names of the exceptions, indexed by vector
+pub const EXCEPTION_NAMES: [&str; 32] = [
+	"Divide Error",
+	"Debug",
+	"Non-Maskable Interrupt",
+	"Breakpoint",
+	"Overflow",
+	"Bound Range Exceeded",
+	"Invalid Opcode",
+	"Device Not Available",
+	"Double Fault",
+	"Coprocessor Segment Overrun",
+	"Invalid TSS",
+	"Segment Not Present",
+	"Stack-Segment Fault",
+	"General Protection Fault",
+	"Page Fault",
+	"Reserved",
+	"x87 Floating-Point Exception",
+	"Alignment Check",
+	"Machine Check",
+	"SIMD Floating-Point Exception",
+	"Virtualization Exception",
+	"Control Protection Exception",
+	"Reserved",
+	"Reserved",
+	"Reserved",
+	"Reserved",
+	"Reserved",
+	"Reserved",
+	"Hypervisor Injection Exception",
+	"VMM Communication Exception",
+	"Security Exception",
+	"Reserved",
+];
+
+/// Returns true if the CPU pushes an error code for the `vector` exception
+pub const fn has_error_code(vector: u32) -> bool {
+	matches!(vector, 8 | 10..=14 | 17 | 21 | 29 | 30)
+}
+
+/// Returns true if the error code of the `vector` exception is a [SelectorErrorCode]
+const fn has_selector_error_code(vector: u32) -> bool {
+	matches!(vector, 10..=13)
+}
+
+/// Error code of the #TS, #NP, #SS and #GP exceptions, when they're caused by a segment selector
+#[bitfield(bits = 32)]
+#[derive(Debug, Clone, Copy)]
+pub struct SelectorErrorCode {
+	/// The exception happened while delivering an external event (e.g. a hardware interrupt)
+	pub is_external: bool,
+
+	/// The index refers to a gate in the IDT
+	pub is_idt: bool,
+
+	/// The index refers to the LDT (or the GDT if false), only if `is_idt` is false
+	pub is_ldt: bool,
+
+	/// Index of the descriptor in its table
+	pub index: B13,
+
+	#[skip]
+	reserved: B16,
+}
+
+impl fmt::Display for SelectorErrorCode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let table = match (self.is_idt(), self.is_ldt()) {
+			(true, _) => "IDT",
+			(false, true) => "LDT",
+			(false, false) => "GDT",
+		};
+		write!(f, "{table} index {} (selector {:#06x})", self.index(), self.index() << 3)?;
+
+		if self.is_external() {
+			write!(f, ", external event")?;
+		}
+		Ok(())
+	}
+}
+
+/// Prints the exception, its error code, the stack frame and the registers, then halts
+pub fn fault_handler(frame: &TrapFrame) -> ! {
+	disable_hardware_interrupts();
+
+	let vector = frame.vector;
+	let name = EXCEPTION_NAMES[vector as usize];
+
+	println!("EXCEPTION: {name} (vector {vector})");
+
+	if has_selector_error_code(vector) && frame.error_code != 0 {
+		let selector = SelectorErrorCode::from_bytes(frame.error_code.to_le_bytes());
+		println!("Error Code: {:#010x}: {selector}", frame.error_code);
+	} else if has_error_code(vector) {
+		println!("Error Code: {:#010x}", frame.error_code);
+	}
+
+	println!("eip={:#010x} cs={:#06x} eflags={:#010x}", frame.eip, frame.cs, frame.eflags);
+
+	print_registers(&Registers {
+		esp: frame.stack_pointer(),
+		..frame.registers
+	});
+
+	halt(name)
+}
+
+/// Entry point of the double fault task (cf. [crate::gdt::tss])
+///
+/// The CPU jumps here through a task gate, on the double fault stack, after saving the faulting
+/// context in the main TSS: this works even if the kernel stack overflowed. The error code
+/// (always 0) is pushed on the new stack, and there's nothing to return to.
+pub extern "C" fn double_fault_task() -> ! {
+	disable_hardware_interrupts();
+
+	// Safety: the faulting task is stopped, so nothing else writes to its TSS
+	let context = unsafe { MAIN_TSS };
+
+	println!("EXCEPTION: Double Fault (vector 8)");
+	println!(
+		"Faulting context: eip={:#010x} cs={:#06x} eflags={:#010x}",
+		context.eip, context.cs, context.eflags
+	);
+
+	print_registers(&Registers {
+		edi: context.edi,
+		esi: context.esi,
+		ebp: context.ebp,
+		esp: context.esp,
+		ebx: context.ebx,
+		edx: context.edx,
+		ecx: context.ecx,
+		eax: context.eax,
+	});
+
+	halt("Double Fault")
+}
+
+fn print_registers(registers: &Registers) {
+	println!(
+		"eax={:#010x} ebx={:#010x} ecx={:#010x} edx={:#010x}",
+		registers.eax, registers.ebx, registers.ecx, registers.edx
+	);
+	println!(
+		"esi={:#010x} edi={:#010x} ebp={:#010x} esp={:#010x}",
+		registers.esi, registers.edi, registers.ebp, registers.esp
+	);
+}
+
+/// When running tests, the exception is turned into a panic, so the test fails instead of
+/// hanging QEMU
+fn halt(name: &str) -> ! {
+	if testing::is_running_tests() {
+		panic!("unhandled exception: {name}");
+	}
+
+	loop {
+		unsafe { core::arch::asm!("cli", "hlt") };
+	}
+}
@@ -1,68 +1,99 @@
+use crate::gdt::DOUBLE_FAULT_TSS_SELECTOR;
+use crate::idt::IDT;
 use crate::idt::entry::IdtEntry;
-use crate::idt::{
-	IDT,
-	InterruptStackFrame,
+use crate::idt::trap::{
+	SYSCALL_STUB,
+	SYSCALL_VECTOR,
+	TRAP_STUBS,
+	TrapFrame,
+	TrapHandler,
+	register_trap_handler,
 };
-use crate::keyboard::keyboard_interrupt_handler;
+use crate::irq::init_irqs;
 use crate::paging::page_fault::page_fault_interrupt_handler;
 use crate::println;
+use crate::shared::PrivilegeRing;
+use crate::user::syscall::init_syscalls;
 
 /// Initialize our interrupt handlers
+///
+/// Every exception and PIC line goes through the common entry point (cf. [crate::idt::trap]).
+/// Exceptions without a dedicated handler print a diagnostic screen and halt
+/// (cf. [crate::idt::exceptions]).
+///
+/// User mode can only trigger the system call gate (cf. [crate::user::syscall])
+///
+/// Double faults switch to their own task, so they are handled even if the kernel stack overflowed
+/// (cf. [crate::gdt::tss])
 pub fn init_interrupt_handlers() {
-	register_standard_interrupt(Interrupt::Breakpoint, breakpoint_interrupt_handler);
-	register_standard_interrupt(Interrupt::Keyboard, keyboard_interrupt_handler);
+	for (vector, stub) in TRAP_STUBS.iter().enumerate() {
+		set_idt_entry(vector, *stub as *const () as u32, PrivilegeRing::Kernel);
+	}
 
-	register_error_code_interrupt(Interrupt::PageFault, page_fault_interrupt_handler);
-}
+	// the only gate user mode can go through with `int`
+	set_idt_entry(SYSCALL_VECTOR, SYSCALL_STUB as *const () as u32, PrivilegeRing::UserSpace);
 
-/// register the `handler` [InterruptHandler::Standard] for the `interrupt` [Interrupt]
-pub fn register_standard_interrupt(
-	interrupt: Interrupt,
-	handler: extern "x86-interrupt" fn(&mut InterruptStackFrame),
-) {
-	register_interrupt_handler(interrupt, InterruptHandler::Standard(handler));
+	unsafe {
+		IDT[Interrupt::DoubleFault as usize].set_task_gate(DOUBLE_FAULT_TSS_SELECTOR);
+	}
+
+	register_interrupt_handler(Interrupt::Breakpoint, breakpoint_interrupt_handler);
+	register_interrupt_handler(Interrupt::PageFault, page_fault_interrupt_handler);
+
+	// the PIC lines (cf. crate::irq::request_irq)
+	init_irqs();
+
+	init_syscalls();
 }
 
-/// register the `handler` [InterruptHandler::WithErrorCode] for the `interrupt` [Interrupt]
-pub fn register_error_code_interrupt(
-	interrupt: Interrupt,
-	handler: extern "x86-interrupt" fn(&mut InterruptStackFrame, u32),
-) {
-	register_interrupt_handler(interrupt, InterruptHandler::WithErrorCode(handler));
+/// register the `handler` for the `interrupt` [Interrupt] (cf. [crate::idt::trap])
+pub fn register_interrupt_handler(interrupt: Interrupt, handler: TrapHandler) {
+	// Safety: only called during init, with interrupts disabled
+	unsafe { register_trap_handler(interrupt as usize, handler) };
 }
 
-/// register the `handler` [InterruptHandler] for the `interrupt` [Interrupt] into the [IDT]
-pub fn register_interrupt_handler(interrupt: Interrupt, handler: InterruptHandler) {
+/// Points the `vector`-th entry of the [IDT] to the code at `handler_address`
+fn set_idt_entry(vector: usize, handler_address: u32, privilege_level: PrivilegeRing) {
 	const CODE_SEGMENT_OFFSET: u16 = core::mem::size_of::<IdtEntry>() as u16;
 
 	unsafe {
-		IDT[interrupt as usize].set_handler_fn(handler.address(), CODE_SEGMENT_OFFSET);
+		IDT[vector].set_handler_fn(handler_address, CODE_SEGMENT_OFFSET, privilege_level);
 	}
 }
 
-extern "x86-interrupt" fn breakpoint_interrupt_handler(stack_frame: &mut InterruptStackFrame) {
-	println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
-}
-
-pub enum InterruptHandler {
-	Standard(extern "x86-interrupt" fn(&mut InterruptStackFrame)),
-	WithErrorCode(extern "x86-interrupt" fn(&mut InterruptStackFrame, u32)),
-}
-
-impl InterruptHandler {
-	pub fn address(self) -> u32 {
-		match self {
-			Self::Standard(f) => f as *const () as u32,
-			Self::WithErrorCode(f) => f as *const () as u32,
-		}
-	}
+fn breakpoint_interrupt_handler(frame: &mut TrapFrame) {
+	println!("EXCEPTION: BREAKPOINT\n{:#x?}", frame);
 }
 
 /// Interrupt IDs
+///
+/// The CPU exceptions (cf. [crate::idt::exceptions::EXCEPTION_NAMES]), the PIC lines are in
+/// [crate::pic::Irq]
 pub enum Interrupt {
+	DivideError = 0,
+	Debug = 1,
+	NonMaskable = 2,
 	Breakpoint = 3,
+	Overflow = 4,
+	BoundRangeExceeded = 5,
+	InvalidOpcode = 6,
+	DeviceNotAvailable = 7,
+	DoubleFault = 8,
+	CoprocessorSegmentOverrun = 9,
+	InvalidTss = 10,
+	SegmentNotPresent = 11,
+	StackSegmentFault = 12,
+	GeneralProtectionFault = 13,
 	PageFault = 14,
-	Keyboard = 33,
+	X87FloatingPoint = 16,
+	AlignmentCheck = 17,
+	MachineCheck = 18,
+	SimdFloatingPoint = 19,
+	Virtualization = 20,
+	ControlProtection = 21,
+	HypervisorInjection = 28,
+	VmmCommunication = 29,
+	Security = 30,
 }
 
 /// Enables CPU hardware interrupts (e.g. keyboard keys)
@@ -80,3 +111,21 @@ pub fn disable_hardware_interrupts() {
 		core::arch::asm!("cli");
 	}
 }
+
+/// Runs `f` with hardware interrupts disabled, then enables them again if they were enabled
+///
+/// Needed to take a lock that interrupt handlers also take, or the handler would spin forever on
+/// the lock held by the code it interrupted
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+	let eflags: u32;
+	unsafe { core::arch::asm!("pushfd", "pop {}", out(reg) eflags) };
+
+	disable_hardware_interrupts();
+	let result = f();
+
+	// interrupt flag
+	if eflags & (1 << 9) != 0 {
+		unsafe { enable_hardware_interrupts() };
+	}
+	result
+}
@@ -1,13 +1,15 @@
 pub mod entry;
+pub mod exceptions;
 pub mod interrupts;
+pub mod trap;
 
 use core::arch::asm;
 
-use self::interrupts::init_interrupt_handlers;
 use self::entry::{
 	IdtEntry,
 	IdtPointer,
 };
+use self::interrupts::init_interrupt_handlers;
 
 static mut IDT: [IdtEntry; 256] = [IdtEntry::zeroed(); 256];
 static mut IDT_PTR: IdtPointer = IdtPointer {
@@ -15,16 +17,6 @@ static mut IDT_PTR: IdtPointer = IdtPointer {
 	base: 0,
 };
 
-/// The stack frame that interrupt handlers receive as arguments when they are
-/// called
-#[derive(Debug)]
-#[repr(C)]
-pub struct InterruptStackFrame {
-	pub instruction_pointer: u32,
-	pub code_segment: u32,
-	pub cpu_flags: u32,
-}
-
 /// Initialize the IDT table
 ///
 /// SAFETY: GDT must be initialized
@@ -0,0 +1,299 @@
+//! Common entry point of the interrupts
+//!
+//! Every IDT entry points to a small assembly stub that pushes its vector (and a fake error code
+//! if the CPU doesn't push one), then jumps to [trap_entry]. It saves the rest of the interrupted
+//! context, so the stack holds a complete [TrapFrame], and calls [trap_dispatcher] with it.
+//!
+//! Handlers get the [TrapFrame] by mutable reference: everything they change in it is restored
+//! when the interrupt returns (e.g. a system call return value in eax, or another task's context).
+//!
+//! Before an interrupted user program resumes, it gets its pending signals (cf.
+//! [crate::process::signal]).
+
+use core::arch::naked_asm;
+
+use crate::gdt::KERNEL_DATA_SELECTOR;
+use crate::idt::exceptions::fault_handler;
+use crate::println;
+use crate::process::signal::{
+	deliver_signals,
+	exception_signal,
+	force_signal,
+};
+use crate::task::preempt_if_needed;
+
+/// Number of stubs: the 32 exceptions, the 16 PIC lines, then the local APIC vectors
+/// (cf. [crate::apic::local_apic])
+pub const TRAP_STUB_COUNT: usize = 64;
+
+/// The `int 0x80` vector, the only one user mode can trigger (cf. [crate::user::syscall])
+pub const SYSCALL_VECTOR: usize = 0x80;
+
+/// A function called by [trap_dispatcher] for a given vector
+pub type TrapHandler = fn(&mut TrapFrame);
+
+/// The handlers, indexed by vector (cf. [register_trap_handler])
+static mut TRAP_HANDLERS: [Option<TrapHandler>; 256] = [None; 256];
+
+/// The general-purpose registers, in the order `pushad` saves them
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Registers {
+	pub edi: u32,
+	pub esi: u32,
+	pub ebp: u32,
+
+	/// Value of esp before `pushad` (ignored by `popad`, cf. [TrapFrame::stack_pointer])
+	pub esp: u32,
+
+	pub ebx: u32,
+	pub edx: u32,
+	pub ecx: u32,
+	pub eax: u32,
+}
+
+/// The interrupted context, as saved on the stack by the CPU and [trap_entry], in memory order
+#[derive(Debug)]
+#[repr(C)]
+pub struct TrapFrame {
+	/// Segment registers of the interrupted code (replaced by the kernel data segment while the
+	/// handlers run)
+	pub gs: u32,
+	/// cf. [TrapFrame::gs]
+	pub fs: u32,
+	/// cf. [TrapFrame::gs]
+	pub es: u32,
+	/// cf. [TrapFrame::gs]
+	pub ds: u32,
+
+	/// General-purpose registers of the interrupted code
+	pub registers: Registers,
+
+	/// The IDT entry of the interrupt
+	pub vector: u32,
+
+	/// Pushed by the CPU for some exceptions (cf. [has_error_code]), 0 otherwise
+	///
+	/// [has_error_code]: crate::idt::exceptions::has_error_code
+	pub error_code: u32,
+
+	/// Pushed by the CPU: where the interrupted code resumes
+	pub eip: u32,
+	/// Code segment of the interrupted code (its low bits are the privilege level)
+	pub cs: u32,
+	/// Flags of the interrupted code, restored by `iretd`
+	pub eflags: u32,
+
+	/// Only pushed by the CPU when the interrupt came from user mode
+	/// (cf. [TrapFrame::is_from_user_mode])
+	pub user_esp: u32,
+	/// cf. [TrapFrame::user_esp]
+	pub user_ss: u32,
+}
+
+impl TrapFrame {
+	/// Returns true if the interrupt happened in ring 3
+	pub fn is_from_user_mode(&self) -> bool {
+		self.cs & 0b11 == 3
+	}
+
+	/// Value of esp when the interrupt happened
+	///
+	/// Without privilege change, the CPU pushed the frame right below the interrupted stack, so
+	/// the interrupted esp is where [TrapFrame::user_esp] would be
+	pub fn stack_pointer(&self) -> u32 {
+		if self.is_from_user_mode() { self.user_esp } else { &raw const self.user_esp as u32 }
+	}
+}
+
+/// Sets the function called by [trap_dispatcher] for the `vector` interrupt
+///
+/// # Safety
+///  - `vector` must not be triggered while its handler is being replaced
+pub unsafe fn register_trap_handler(vector: usize, handler: TrapHandler) {
+	unsafe { TRAP_HANDLERS[vector] = Some(handler) };
+}
+
+/// Calls the handler registered for the interrupt, or prints a diagnostic screen for exceptions
+/// without one, then delivers the signals of an interrupted user program
+extern "C" fn trap_dispatcher(frame: &mut TrapFrame) {
+	let handler = unsafe { TRAP_HANDLERS[frame.vector as usize] };
+
+	match handler {
+		Some(handler) => {
+			handler(frame);
+
+			// e.g. the time slice of the interrupted task is over
+			preempt_if_needed();
+		}
+		// the kernel survives the exceptions of user programs
+		None if frame.vector < 32 && frame.is_from_user_mode() => {
+			force_signal(exception_signal(frame.vector))
+		}
+		None if frame.vector < 32 => fault_handler(frame),
+		None => println!("unexpected interrupt (vector {})", frame.vector),
+	}
+
+	if frame.is_from_user_mode() {
+		deliver_signals(frame);
+	}
+}
+
+/// Saves the segment and general-purpose registers under the vector pushed by a stub, calls
+/// [trap_dispatcher], then restores everything and returns from the interrupt
+#[unsafe(naked)]
+unsafe extern "C" fn trap_entry() {
+	naked_asm!(
+		"pushad",
+
+		// through eax (saved above), as `push ds` would only push 16 bits
+		"mov eax, ds",
+		"push eax",
+		"mov eax, es",
+		"push eax",
+		"mov eax, fs",
+		"push eax",
+		"mov eax, gs",
+		"push eax",
+
+		// the interrupted code may have had user segments loaded
+		"mov ax, {data}",
+		"mov ds, ax",
+		"mov es, ax",
+		"mov fs, ax",
+		"mov gs, ax",
+		"cld",
+
+		// pointer to the TrapFrame
+		"push esp",
+		"call {dispatcher}",
+		"add esp, 4",
+
+		"pop eax",
+		"mov gs, eax",
+		"pop eax",
+		"mov fs, eax",
+		"pop eax",
+		"mov es, eax",
+		"pop eax",
+		"mov ds, eax",
+		"popad",
+
+		// vector and error code
+		"add esp, 8",
+		"iretd",
+		data = const KERNEL_DATA_SELECTOR,
+		dispatcher = sym trap_dispatcher,
+	)
+}
+
+/// Defines the assembly stub of an interrupt vector, that pushes the beginning of a [TrapFrame]
+/// and jumps to [trap_entry]
+///
+/// The interrupts without error code get a fake one, so every [TrapFrame] has the same layout
+macro_rules! trap_stub {
+	($name:ident, $vector:literal) => {
+		#[unsafe(naked)]
+		unsafe extern "C" fn $name() {
+			naked_asm!(
+				"push 0",
+				"push {vector}",
+				"jmp {entry}",
+				vector = const $vector,
+				entry = sym trap_entry,
+			)
+		}
+	};
+	($name:ident, $vector:literal, error_code) => {
+		#[unsafe(naked)]
+		unsafe extern "C" fn $name() {
+			naked_asm!(
+				"push {vector}",
+				"jmp {entry}",
+				vector = const $vector,
+				entry = sym trap_entry,
+			)
+		}
+	};
+}
+
+trap_stub!(trap_0, 0);
+trap_stub!(trap_1, 1);
+trap_stub!(trap_2, 2);
+trap_stub!(trap_3, 3);
+trap_stub!(trap_4, 4);
+trap_stub!(trap_5, 5);
+trap_stub!(trap_6, 6);
+trap_stub!(trap_7, 7);
+trap_stub!(trap_8, 8, error_code);
+trap_stub!(trap_9, 9);
+trap_stub!(trap_10, 10, error_code);
+trap_stub!(trap_11, 11, error_code);
+trap_stub!(trap_12, 12, error_code);
+trap_stub!(trap_13, 13, error_code);
+trap_stub!(trap_14, 14, error_code);
+trap_stub!(trap_15, 15);
+trap_stub!(trap_16, 16);
+trap_stub!(trap_17, 17, error_code);
+trap_stub!(trap_18, 18);
+trap_stub!(trap_19, 19);
+trap_stub!(trap_20, 20);
+trap_stub!(trap_21, 21, error_code);
+trap_stub!(trap_22, 22);
+trap_stub!(trap_23, 23);
+trap_stub!(trap_24, 24);
+trap_stub!(trap_25, 25);
+trap_stub!(trap_26, 26);
+trap_stub!(trap_27, 27);
+trap_stub!(trap_28, 28);
+trap_stub!(trap_29, 29, error_code);
+trap_stub!(trap_30, 30, error_code);
+trap_stub!(trap_31, 31);
+trap_stub!(trap_32, 32);
+trap_stub!(trap_33, 33);
+trap_stub!(trap_34, 34);
+trap_stub!(trap_35, 35);
+trap_stub!(trap_36, 36);
+trap_stub!(trap_37, 37);
+trap_stub!(trap_38, 38);
+trap_stub!(trap_39, 39);
+trap_stub!(trap_40, 40);
+trap_stub!(trap_41, 41);
+trap_stub!(trap_42, 42);
+trap_stub!(trap_43, 43);
+trap_stub!(trap_44, 44);
+trap_stub!(trap_45, 45);
+trap_stub!(trap_46, 46);
+trap_stub!(trap_47, 47);
+trap_stub!(trap_48, 48);
+trap_stub!(trap_49, 49);
+trap_stub!(trap_50, 50);
+trap_stub!(trap_51, 51);
+trap_stub!(trap_52, 52);
+trap_stub!(trap_53, 53);
+trap_stub!(trap_54, 54);
+trap_stub!(trap_55, 55);
+trap_stub!(trap_56, 56);
+trap_stub!(trap_57, 57);
+trap_stub!(trap_58, 58);
+trap_stub!(trap_59, 59);
+trap_stub!(trap_60, 60);
+trap_stub!(trap_61, 61);
+trap_stub!(trap_62, 62);
+trap_stub!(trap_63, 63);
+
+trap_stub!(trap_128, 128);
+
+/// The assembly stub of [SYSCALL_VECTOR]
+pub static SYSCALL_STUB: unsafe extern "C" fn() = trap_128;
+
+/// The assembly stubs, indexed by vector
+pub static TRAP_STUBS: [unsafe extern "C" fn(); TRAP_STUB_COUNT] = [
+	trap_0, trap_1, trap_2, trap_3, trap_4, trap_5, trap_6, trap_7, trap_8, trap_9, trap_10,
+	trap_11, trap_12, trap_13, trap_14, trap_15, trap_16, trap_17, trap_18, trap_19, trap_20,
+	trap_21, trap_22, trap_23, trap_24, trap_25, trap_26, trap_27, trap_28, trap_29, trap_30,
+	trap_31, trap_32, trap_33, trap_34, trap_35, trap_36, trap_37, trap_38, trap_39, trap_40,
+	trap_41, trap_42, trap_43, trap_44, trap_45, trap_46, trap_47, trap_48, trap_49, trap_50,
+	trap_51, trap_52, trap_53, trap_54, trap_55, trap_56, trap_57, trap_58, trap_59, trap_60,
+	trap_61, trap_62, trap_63,
+];
@@ -0,0 +1,277 @@
+//! Generic IRQ layer on top of the PICs
+//!
+//! Drivers register handlers for a line with [request_irq]. Several handlers can share a line:
+//! they are all called, in registration order, by [irq_dispatcher]. It masks the line while the
+//! handlers run and sends the end of interrupt itself, so handlers only deal with their device.
+//!
+//! The dispatcher also counts the interrupts of each line, and the spurious IRQ7/IRQ15 raised by
+//! the PICs when an interrupt disappears before being acknowledged (cf. [irq_stats]).
+//!
+//! The lines are delivered by the 8259 PICs, or by the I/O APIC once [crate::apic] switched to it
+//! (cf. [switch_to_apic]). Both use the same vectors.
+
+use core::fmt;
+use core::sync::atomic::{
+	AtomicBool,
+	Ordering,
+};
+
+use spin::Mutex;
+
+use crate::apic;
+use crate::idt::interrupts::without_interrupts;
+use crate::idt::trap::{
+	TrapFrame,
+	register_trap_handler,
+};
+use crate::pic::{
+	IRQ_LINES,
+	Irq,
+	disable_irq,
+	enable_irq,
+	is_in_service,
+	mask_all_irqs,
+	send_end_of_interrupt,
+	send_master_end_of_interrupt,
+};
+
+/// Maximum number of handlers sharing a line
+pub const MAX_HANDLERS_PER_LINE: usize = 4;
+
+/// A function called by [irq_dispatcher] when its line raises an interrupt
+pub type IrqHandler = fn(&mut TrapFrame);
+
+#[derive(Clone, Copy)]
+struct IrqAction {
+	handler: IrqHandler,
+	name: &'static str,
+}
+
+#[derive(Clone, Copy)]
+struct IrqLine {
+	actions: [Option<IrqAction>; MAX_HANDLERS_PER_LINE],
+	count: u64,
+	spurious: u64,
+}
+
+impl IrqLine {
+	const fn new() -> Self {
+		Self {
+			actions: [None; MAX_HANDLERS_PER_LINE],
+			count: 0,
+			spurious: 0,
+		}
+	}
+
+	fn has_handlers(&self) -> bool {
+		self.actions.iter().any(Option::is_some)
+	}
+}
+
+/// The handlers and counters of every line
+///
+/// Always locked with interrupts disabled, as [irq_dispatcher] locks it too
+static LINES: Mutex<[IrqLine; IRQ_LINES]> = Mutex::new([IrqLine::new(); IRQ_LINES]);
+
+/// Set once the I/O APIC delivers the IRQs instead of the PICs
+static USE_APIC: AtomicBool = AtomicBool::new(false);
+
+/// Errors returned by [request_irq] and [free_irq]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+	/// The line already has [MAX_HANDLERS_PER_LINE] handlers
+	LineFull(Irq),
+
+	/// A handler with the same name is already registered on the line
+	NameTaken(Irq, &'static str),
+
+	/// No handler with this name is registered on the line
+	NotRegistered(Irq, &'static str),
+}
+
+impl fmt::Display for IrqError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::LineFull(irq) => {
+				write!(f, "IRQ {} ({irq:?}) has no free handler slot", *irq as u8)
+			}
+			Self::NameTaken(irq, name) => {
+				write!(f, "IRQ {} ({irq:?}) already has a handler named {name:?}", *irq as u8)
+			}
+			Self::NotRegistered(irq, name) => {
+				write!(f, "IRQ {} ({irq:?}) has no handler named {name:?}", *irq as u8)
+			}
+		}
+	}
+}
+
+/// Counters and handlers of a line (cf. [irq_stats])
+pub struct IrqLineStats {
+	/// The line
+	pub irq: Irq,
+
+	/// Number of interrupts handled
+	pub count: u64,
+
+	/// Number of spurious interrupts ignored (only IRQ7 and IRQ15 can have some)
+	pub spurious: u64,
+
+	/// Names of the handlers, in call order
+	pub handlers: [Option<&'static str>; MAX_HANDLERS_PER_LINE],
+}
+
+/// Points the PIC vectors to [irq_dispatcher]
+///
+/// Every line stays masked until a handler is registered on it
+pub fn init_irqs() {
+	for irq in Irq::ALL {
+		// Safety: the lines are masked
+		unsafe { register_trap_handler(irq.vector() as usize, irq_dispatcher) };
+	}
+}
+
+/// Adds `handler` to the handlers of `irq`, and unmasks the line
+///
+/// `name` identifies the handler for [free_irq] and [irq_stats]
+pub fn request_irq(irq: Irq, handler: IrqHandler, name: &'static str) -> Result<(), IrqError> {
+	without_interrupts(|| {
+		let mut lines = LINES.lock();
+		let line = &mut lines[irq as usize];
+
+		if line.actions.iter().flatten().any(|action| action.name == name) {
+			return Err(IrqError::NameTaken(irq, name));
+		}
+
+		let slot = line.actions.iter_mut().find(|action| action.is_none());
+		let slot = slot.ok_or(IrqError::LineFull(irq))?;
+		*slot = Some(IrqAction {
+			handler,
+			name,
+		});
+
+		unmask_line(irq);
+		Ok(())
+	})
+}
+
+/// Removes the `name` handler of `irq`, and masks the line if it was the last one
+pub fn free_irq(irq: Irq, name: &'static str) -> Result<(), IrqError> {
+	without_interrupts(|| {
+		let mut lines = LINES.lock();
+		let line = &mut lines[irq as usize];
+
+		let index = line
+			.actions
+			.iter()
+			.position(|action| action.is_some_and(|action| action.name == name))
+			.ok_or(IrqError::NotRegistered(irq, name))?;
+
+		// keep the registration order of the other handlers
+		line.actions.copy_within(index + 1.., index);
+		line.actions[MAX_HANDLERS_PER_LINE - 1] = None;
+
+		if !line.has_handlers() {
+			mask_line(irq);
+		}
+		Ok(())
+	})
+}
+
+/// Calls `f` with the counters and handlers of every line, in line order
+pub fn irq_stats(mut f: impl FnMut(IrqLineStats)) {
+	// copied, so `f` runs with interrupts enabled
+	let lines = without_interrupts(|| *LINES.lock());
+
+	for (irq, line) in Irq::ALL.into_iter().zip(lines) {
+		f(IrqLineStats {
+			irq,
+			count: line.count,
+			spurious: line.spurious,
+			handlers: line.actions.map(|action| action.map(|action| action.name)),
+		});
+	}
+}
+
+/// Handles the interrupt of a PIC line: masks it, acknowledges the PIC, calls every handler of the
+/// line, then unmasks it (unless its handlers were freed)
+fn irq_dispatcher(frame: &mut TrapFrame) {
+	let Some(irq) = Irq::from_vector(frame.vector) else {
+		return;
+	};
+
+	let use_apic = USE_APIC.load(Ordering::Relaxed);
+
+	// the lowest priority line of each PIC is raised when an interrupt disappears too soon
+	if !use_apic && matches!(irq, Irq::Lpt1 | Irq::SecondaryAta) && !is_in_service(irq) {
+		LINES.lock()[irq as usize].spurious += 1;
+
+		// the master PIC still saw a real interrupt on the cascade line
+		if irq == Irq::SecondaryAta {
+			send_master_end_of_interrupt();
+		}
+		return;
+	}
+
+	mask_line(irq);
+	if use_apic {
+		apic::end_of_interrupt();
+	} else {
+		send_end_of_interrupt(irq);
+	}
+
+	// copied, so handlers can (un)register handlers
+	let actions = {
+		let mut lines = LINES.lock();
+		lines[irq as usize].count += 1;
+		lines[irq as usize].actions
+	};
+
+	for action in actions.iter().flatten() {
+		(action.handler)(frame);
+	}
+
+	if LINES.lock()[irq as usize].has_handlers() {
+		unmask_line(irq);
+	}
+}
+
+/// Masks every PIC line, and lets the I/O APIC deliver the lines that have handlers
+///
+/// The I/O APIC must route the ISA IRQs on the PIC vectors (cf. [crate::apic::init_apic])
+pub fn switch_to_apic() {
+	without_interrupts(|| {
+		let lines = LINES.lock();
+
+		mask_all_irqs();
+		USE_APIC.store(true, Ordering::Relaxed);
+
+		for irq in Irq::ALL {
+			if lines[irq as usize].has_handlers() {
+				apic::unmask_irq(irq);
+			}
+		}
+	});
+}
+
+/// Name of the chip delivering the IRQs
+pub fn irq_controller_name() -> &'static str {
+	if USE_APIC.load(Ordering::Relaxed) { "I/O APIC" } else { "8259 PIC" }
+}
+
+fn mask_line(irq: Irq) {
+	if USE_APIC.load(Ordering::Relaxed) {
+		apic::mask_irq(irq);
+	} else {
+		// Safety: the PICs are initialized before the IDT
+		unsafe { disable_irq(irq) };
+	}
+}
+
+fn unmask_line(irq: Irq) {
+	if USE_APIC.load(Ordering::Relaxed) {
+		apic::unmask_irq(irq);
+	} else {
+		// Safety: the PICs are initialized before the IDT
+		unsafe { enable_irq(irq) };
+	}
+}
@@ -1,8 +1,4 @@
-use crate::idt::InterruptStackFrame;
-use crate::pic::{
-	Irq,
-	send_end_of_interrupt,
-};
+use crate::idt::trap::TrapFrame;
 use crate::print;
 use crate::shared::inb;
 use crate::shell::add_char_to_command_buffer;
@@ -12,11 +8,11 @@ static mut RSHIFT_PRESSED: bool = false;
 static mut CAPS_LOCK_ON: bool = false;
 static mut IS_EXTENDED: bool = false;
 
-/// Handles keyboard interrupts
+/// Handles keyboard interrupts (registered on [crate::pic::Irq::Keyboard])
 ///
 /// Note: this reads the scancode from port and appends printable characters to
 /// the command buffer
-pub extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: &mut InterruptStackFrame) {
+pub fn keyboard_interrupt_handler(_frame: &mut TrapFrame) {
 	// read the last pressed/released key
 	let scancode = read_scancode();
 
@@ -60,9 +56,6 @@ pub extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: &mut Inte
 
 	// 0xE0 means that the next byte is an extended key (e.g. arrow keys, etc...)
 	set_next_scancode_extended(scancode == 0xe0);
-
-	// telling the PIC we finished to handle this keyboard interrupt
-	send_end_of_interrupt(Irq::Keyboard);
 }
 
 /// Reads the last scancode from the keyboard data port
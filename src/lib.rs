@@ -2,39 +2,251 @@
 
 #![no_std]
 #![no_main]
-#![feature(abi_x86_interrupt)]
+#![feature(alloc_error_handler)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 #![warn(missing_docs)]
 #![allow(clippy::tabs_in_doc_comments)]
 #![allow(dead_code)]
 
+mod acpi;
 mod allocator;
+mod apic;
+mod elf;
 mod gdt;
 mod idt;
+mod irq;
 mod keyboard;
 mod macros;
 mod paging;
 mod pic;
+mod pit;
+mod process;
+mod rtc;
+mod serial;
 mod shared;
 mod shell;
+mod task;
+mod testing;
+mod user;
 mod vga;
 
 use core::arch::asm;
 use core::panic::PanicInfo;
 
+pub use crate::acpi::power::{
+	reboot,
+	shutdown,
+};
+pub use crate::acpi::{
+	AcpiTables,
+	acpi_tables,
+};
+pub use crate::allocator::KmemCache;
 use crate::allocator::init_virtual_allocator;
+pub use crate::apic::apic_timer_ticks;
+pub use crate::elf::{
+	ElfError,
+	MAX_ARGUMENTS_SIZE,
+	MAX_USER_STACK_SIZE,
+	USER_SPACE_END,
+	USER_SPACE_START,
+	USER_STACK_SIZE,
+	USER_STACK_TOP,
+};
 pub use crate::gdt::dump::dump_kernel_stack;
+pub use crate::idt::interrupts::without_interrupts;
 use crate::idt::interrupts::{
 	disable_hardware_interrupts,
 	enable_hardware_interrupts,
 };
-use crate::paging::page_directory::enable_paging;
+pub use crate::idt::trap::TrapFrame;
+pub use crate::irq::{
+	IrqError,
+	IrqLineStats,
+	free_irq,
+	irq_controller_name,
+	irq_stats,
+	request_irq,
+};
+use crate::keyboard::keyboard_interrupt_handler;
+pub use crate::paging::address_space::{
+	AddressSpace,
+	MapError,
+	active_area,
+	resolve_demand_fault,
+};
+pub use crate::paging::memory_area::{
+	AreaKind,
+	MemoryArea,
+	MemoryAreas,
+	STACK_ACCESS_SLACK,
+};
+pub use crate::paging::page_directory::{
+	PageDirectory,
+	active_page_directory,
+};
+pub use crate::paging::pmm::{
+	FrameStats,
+	MAX_FRAME_REFERENCES,
+	MAX_ORDER,
+	alloc_frames,
+	frame_references,
+	frame_stats,
+	free_frames,
+	kfree,
+	kmalloc,
+	release_frame,
+	share_frame,
+};
+pub use crate::paging::{
+	BootModule,
+	boot_module,
+	boot_modules,
+	kernel_page_directory,
+	kernel_parameter,
+};
 use crate::paging::{
 	GRUB_MULTIBOOT_MAGIC,
 	MultibootInfo,
 	init_physical_memory,
 	init_virtual_memory,
+	map_boot_modules,
+	phys_to_virt,
+};
+pub use crate::pic::Irq;
+pub use crate::pit::timer::{
+	MAX_TIMERS,
+	TimerId,
+	add_oneshot_timer,
+	add_periodic_timer,
+	cancel_timer,
+};
+pub use crate::pit::{
+	busy_sleep_ms,
+	frequency,
+	sleep_ms,
+	ticks,
+	uptime,
+};
+pub use crate::process::signal::{
+	DefaultAction,
+	MaskChange,
+	SA_NODEFER,
+	SA_RESETHAND,
+	SA_RESTORER,
+	SIG_BLOCK,
+	SIG_DFL,
+	SIG_IGN,
+	SIG_SETMASK,
+	SIG_UNBLOCK,
+	SIGABRT,
+	SIGALRM,
+	SIGBUS,
+	SIGCHLD,
+	SIGCONT,
+	SIGFPE,
+	SIGHUP,
+	SIGILL,
+	SIGINT,
+	SIGIO,
+	SIGKILL,
+	SIGNAL_COUNT,
+	SIGNAL_NAMES,
+	SIGPIPE,
+	SIGPROF,
+	SIGPWR,
+	SIGQUIT,
+	SIGSEGV,
+	SIGSTKFLT,
+	SIGSTOP,
+	SIGSYS,
+	SIGTERM,
+	SIGTRAP,
+	SIGTSTP,
+	SIGTTIN,
+	SIGTTOU,
+	SIGURG,
+	SIGUSR1,
+	SIGUSR2,
+	SIGVTALRM,
+	SIGWINCH,
+	SIGXCPU,
+	SIGXFSZ,
+	SigAction,
+	Signal,
+	SignalError,
+	change_blocked_signals,
+	default_action,
+	exception_signal,
+	send_signal,
+	set_signal_action,
+	signal_bit,
+};
+pub use crate::process::{
+	DEFAULT_INIT,
+	ExitStatus,
+	INIT_PID,
+	Pid,
+	ProcessInfo,
+	ProcessState,
+	WaitError,
+	current_pid,
+	processes,
+	run_init,
+	wait_child,
+};
+pub use crate::rtc::{
+	DateTime,
+	now,
+};
+pub use crate::serial::{
+	_print as _serial_print,
+	set_console_mirroring,
 };
 pub use crate::shell::shell_loop;
+pub use crate::task::{
+	KERNEL_STACK_SIZE,
+	MAX_TASKS,
+	SpawnError,
+	TaskId,
+	TaskInfo,
+	TaskState,
+	block_current,
+	current_task_id,
+	current_task_name,
+	exit_current,
+	spawn_kernel_thread,
+	tasks,
+	wake,
+	yield_now,
+};
+pub use crate::testing::{
+	QemuExitCode,
+	Testable,
+	exit_qemu,
+	test_runner,
+};
+pub use crate::user::syscall::{
+	SYS_BRK,
+	SYS_EXIT,
+	SYS_FORK,
+	SYS_GETPID,
+	SYS_GETPPID,
+	SYS_KILL,
+	SYS_SIGACTION,
+	SYS_SIGPROCMASK,
+	SYS_SIGRETURN,
+	SYS_WAITPID,
+	SYS_WRITE,
+	SYS_YIELD,
+	WNOHANG,
+};
+pub use crate::user::{
+	EXIT_CODE_KILLED,
+	run_user_program,
+};
 pub use crate::vga::_print;
 
 pub extern crate alloc;
@@ -52,26 +264,42 @@ pub fn init(magic_number: u32, multiboot_info_ptr: u32) {
 	// Safety: GDT is initialized
 	unsafe { idt::init_idt() };
 
+	request_irq(Irq::Keyboard, keyboard_interrupt_handler, "keyboard")
+		.expect("the keyboard line is free");
+	pit::init_pit(pit::DEFAULT_FREQUENCY);
+
 	// enables CPU hardware interrupts (e.g. keyboard keys)
 	// Safety: IDT is initialized
 	unsafe { enable_hardware_interrupts() };
 
-	let multiboot_info_ptr = unsafe { &*(multiboot_info_ptr as *const MultibootInfo) };
+	// the bootloader gives a physical address, that boot.s mapped in the higher half
+	let multiboot_info_ptr =
+		unsafe { &*(phys_to_virt(multiboot_info_ptr) as *const MultibootInfo) };
 	init_physical_memory(multiboot_info_ptr);
 
-	// Safety: physical memory is initialized
-	let directory_phys_addr = unsafe { init_virtual_memory() };
+	// Safety: nothing uses the identity map, and the heap isn't mapped yet
+	unsafe { init_virtual_memory() };
 
-	unsafe {
-		enable_paging(directory_phys_addr);
-	}
+	map_boot_modules();
 
 	init_virtual_allocator();
+
+	acpi::init_acpi();
+
+	// falls back to the PICs if there is no APIC
+	apic::init_apic();
+
+	// the boot flow becomes the `main` task
+	task::init_scheduler();
 }
 
 /// Prints the panic info and enters an infinite loop
 #[panic_handler]
 pub fn panic(info: &PanicInfo) -> ! {
+	if testing::is_running_tests() {
+		testing::test_panic_handler(info);
+	}
+
 	println!("{info}");
 
 	loop {
@@ -81,3 +309,15 @@ pub fn panic(info: &PanicInfo) -> ! {
 		}
 	}
 }
+
+/// Entry point of the lib unit tests kernel (cf. [testing])
+#[cfg(test)]
+#[unsafe(no_mangle)]
+pub extern "C" fn _entrypoint(magic_number: u32, multiboot_info_ptr: u32) -> ! {
+	init(magic_number, multiboot_info_ptr);
+
+	test_main();
+
+	// test_main() exits QEMU once every test ran
+	unreachable!()
+}
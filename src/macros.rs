@@ -13,3 +13,19 @@ macro_rules! println {
     () => ($crate::print!("\n"));
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
+
+/// Prints to the serial port (COM1).
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => {{
+        #[allow(clippy::used_underscore_items)]
+        $crate::_serial_print(format_args!($($arg)*))
+    }};
+}
+
+/// Prints to the serial port (COM1), with a newline.
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
+}
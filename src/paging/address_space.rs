@@ -0,0 +1,656 @@
+//! Page directories of the user programs
+//!
+//! An [AddressSpace] owns a [PageDirectory] whose lower half is its own, and whose kernel half
+//! points to the page tables of the kernel directory. Those tables are all allocated by
+//! [crate::paging::init_virtual_memory], so a kernel mapping made later (e.g. the heap growing)
+//! is seen by every address space.
+//!
+//! [PageDirectory::map_page] only edits the active directory, through the recursive backdoor at
+//! `0xFFFFF000`. An [AddressSpace] can be edited while it isn't active: its directory and tables
+//! are reached by mapping their frames, one at a time, at [SCRATCH_ADDRESS] (cf. [with_frame]).
+//!
+//! [AddressSpace::fork] copies an address space without copying its pages: both share the frames
+//! (counted by the frame allocator, cf. [share_frame]), and the writable pages become read-only
+//! and copy-on-write in both. The first write to such a page faults, and
+//! [resolve_copy_on_write] gives the writer a copy of its own.
+//!
+//! The [MemoryArea]s of an address space say which user pages its program may use. They are
+//! kept by directory address (cf. [MEMORY_AREAS]), so the page fault handler finds those of the
+//! active directory, and [resolve_demand_fault] maps their pages on the first access.
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::fmt;
+use core::mem::ManuallyDrop;
+
+use spin::Mutex;
+
+use super::memory_area::{
+	MemoryArea,
+	MemoryAreas,
+	page_end,
+	page_start,
+};
+use super::mmio::MMIO_VIRTUAL_BASE;
+use super::page_directory::{
+	PageDirectory,
+	PageEntryFlags,
+	PagePointer,
+	PageTable,
+	active_page_directory,
+	flush_tlb,
+	load_page_directory,
+};
+use super::pmm::{
+	FRAME_SIZE,
+	frame_references,
+	kfree,
+	kmalloc,
+	release_frame,
+	share_frame,
+};
+use super::{
+	KERNEL_VIRTUAL_BASE,
+	kernel_page_directory,
+	phys_to_virt,
+};
+use crate::idt::interrupts::without_interrupts;
+
+/// Number of [PageDirectory] entries below [KERNEL_VIRTUAL_BASE]
+const USER_TABLE_COUNT: usize = KERNEL_VIRTUAL_BASE >> 22;
+
+/// Virtual page where [with_frame] maps the frame it gives access to (just below the MMIO window)
+const SCRATCH_ADDRESS: u32 = (MMIO_VIRTUAL_BASE - FRAME_SIZE) as u32;
+
+/// Held while [SCRATCH_ADDRESS] is in use
+static SCRATCH_LOCK: Mutex<()> = Mutex::new(());
+
+/// The memory areas of each [AddressSpace], by physical address of its directory
+static MEMORY_AREAS: Mutex<BTreeMap<u32, MemoryAreas>> = Mutex::new(BTreeMap::new());
+
+/// Errors returned when editing an [AddressSpace]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+	/// There was no free frame for a page table
+	OutOfMemory,
+
+	/// The address is in the kernel half, which is shared by every address space
+	KernelAddress(u32),
+
+	/// No page is mapped at the address
+	NotMapped(u32),
+
+	/// The memory area starting at the address overlaps another one
+	AreaInUse(u32),
+}
+
+impl fmt::Display for MapError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::OutOfMemory => write!(f, "out of memory"),
+			Self::KernelAddress(address) => write!(f, "{address:#010x} is in the kernel half"),
+			Self::NotMapped(address) => write!(f, "nothing is mapped at {address:#010x}"),
+			Self::AreaInUse(address) => {
+				write!(f, "the area at {address:#010x} overlaps another one")
+			}
+		}
+	}
+}
+
+/// A page directory with its own user half, and the kernel half of every other one
+///
+/// It holds a reference to each frame mapped in its user half: dropping it releases them, and
+/// frees the page tables and the directory (cf. [AddressSpace::map_page])
+pub struct AddressSpace {
+	/// Physical address of the [PageDirectory] (what CR3 holds while it is active)
+	directory: u32,
+}
+
+impl AddressSpace {
+	/// Allocates a page directory with an empty user half, and no memory area
+	///
+	/// Returns None if there is no free frame
+	pub fn new() -> Option<Self> {
+		let directory = kmalloc()?;
+		MEMORY_AREAS.lock().insert(directory, MemoryAreas::new());
+
+		let kernel_directory =
+			unsafe { &*(phys_to_virt(kernel_page_directory()) as *const PageDirectory) };
+
+		unsafe {
+			with_frame(directory, |new_directory: &mut PageDirectory| {
+				new_directory.table_pointers[..USER_TABLE_COUNT].fill(PagePointer::zeroed());
+				new_directory.table_pointers[USER_TABLE_COUNT..]
+					.copy_from_slice(&kernel_directory.table_pointers[USER_TABLE_COUNT..]);
+
+				// cf. PageDirectory::setup_directory_backdoor
+				new_directory.setup_directory_backdoor(directory);
+			})
+		};
+
+		Some(Self {
+			directory,
+		})
+	}
+
+	/// Returns the address space of the active page directory, without taking ownership: it isn't
+	/// freed when the returned value is dropped
+	///
+	/// # Safety
+	///  - the active directory must belong to an [AddressSpace] (e.g. while a user program runs)
+	///  - its owner must not use it meanwhile
+	pub unsafe fn active() -> ManuallyDrop<AddressSpace> {
+		let directory = active_page_directory();
+		debug_assert_ne!(directory, kernel_page_directory(), "the kernel directory is active");
+
+		ManuallyDrop::new(Self {
+			directory,
+		})
+	}
+
+	/// Physical address of the page directory
+	pub fn directory_address(&self) -> u32 {
+		self.directory
+	}
+
+	/// Returns true if this is the page directory used by the CPU
+	pub fn is_active(&self) -> bool {
+		active_page_directory() == self.directory
+	}
+
+	/// Makes this the page directory used by the CPU
+	///
+	/// The kernel keeps running, as its half is the same in every address space. The scheduler
+	/// keeps the active directory of each task (cf. [crate::task]).
+	pub fn activate(&self) {
+		unsafe { load_page_directory(self.directory) };
+	}
+
+	/// Connects the user virtual address `virtual_addr` to the frame at `physical_addr`
+	///
+	/// The address space then holds the reference to the frame given by [kmalloc] (or
+	/// [share_frame]): it is released when the address space is dropped, unless
+	/// [AddressSpace::unmap_page] gives it back first
+	pub fn map_page(
+		&mut self,
+		virtual_addr: u32,
+		physical_addr: u32,
+		is_user_space: bool,
+		is_writable: bool,
+	) -> Result<(), MapError> {
+		let flags = PageEntryFlags::new()
+			.with_is_present(true)
+			.with_is_user_space(is_user_space)
+			.with_is_writable(is_writable);
+
+		self.map_page_with_flags(virtual_addr, physical_addr, flags)
+	}
+
+	/// Connects the user virtual address `virtual_addr` to the frame at `physical_addr`, with
+	/// custom `flags` (cf. [AddressSpace::map_page])
+	pub fn map_page_with_flags(
+		&mut self,
+		virtual_addr: u32,
+		physical_addr: u32,
+		flags: PageEntryFlags,
+	) -> Result<(), MapError> {
+		let (dir_offset, table_offset) = split_user_address(virtual_addr)?;
+
+		let table = match self.table_address(dir_offset) {
+			Some(table) => table,
+			None => self.allocate_table(dir_offset)?,
+		};
+
+		unsafe {
+			with_frame(table, |table: &mut PageTable| {
+				table[table_offset].set(physical_addr, flags);
+			})
+		};
+
+		self.invalidate(virtual_addr);
+		Ok(())
+	}
+
+	/// Maps a zeroed frame at the user virtual address `virtual_addr`, and returns its physical
+	/// address
+	pub fn map_zeroed_page(
+		&mut self,
+		virtual_addr: u32,
+		is_user_space: bool,
+		is_writable: bool,
+	) -> Result<u32, MapError> {
+		let physical_addr = kmalloc().ok_or(MapError::OutOfMemory)?;
+		unsafe { with_frame(physical_addr, |page: &mut [u8; FRAME_SIZE]| page.fill(0)) };
+
+		self.map_page(virtual_addr, physical_addr, is_user_space, is_writable)
+			.inspect_err(|_| kfree(physical_addr))?;
+
+		Ok(physical_addr)
+	}
+
+	/// Disconnects the user virtual address `virtual_addr` from its frame
+	///
+	/// Returns the physical address of the frame, whose reference belongs to the caller again, or
+	/// None if nothing was mapped
+	pub fn unmap_page(&mut self, virtual_addr: u32) -> Option<u32> {
+		let (dir_offset, table_offset) = split_user_address(virtual_addr).ok()?;
+		let table = self.table_address(dir_offset)?;
+
+		let physical_addr = unsafe {
+			with_frame(table, |table: &mut PageTable| {
+				let page = table[table_offset];
+				table[table_offset].clear();
+
+				page.flags().is_present().then(|| page.physical_addr())
+			})
+		}?;
+
+		self.invalidate(virtual_addr);
+		Some(physical_addr)
+	}
+
+	/// Returns the physical address and flags of the page mapped at `virtual_addr`, or None if it
+	/// isn't mapped
+	pub fn translate(&self, virtual_addr: u32) -> Option<(u32, PageEntryFlags)> {
+		let dir_offset = (virtual_addr >> 22) as usize;
+		let table_offset = ((virtual_addr >> 12) & 0x3ff) as usize;
+
+		let table = self.table_address(dir_offset)?;
+		let page = unsafe { with_frame(table, |table: &mut PageTable| table[table_offset]) };
+
+		page.flags().is_present().then(|| (page.physical_addr(), page.flags()))
+	}
+
+	/// Copies `bytes` to the mapped pages at `virtual_addr`, whether they are writable or not
+	pub fn write(&mut self, virtual_addr: u32, bytes: &[u8]) -> Result<(), MapError> {
+		let mut address = virtual_addr;
+		let mut bytes = bytes;
+
+		while !bytes.is_empty() {
+			let (physical_addr, _) = self.translate(address).ok_or(MapError::NotMapped(address))?;
+
+			let offset = address as usize % FRAME_SIZE;
+			let (chunk, rest) = bytes.split_at(bytes.len().min(FRAME_SIZE - offset));
+
+			unsafe {
+				with_frame(physical_addr, |page: &mut [u8; FRAME_SIZE]| {
+					page[offset..offset + chunk.len()].copy_from_slice(chunk);
+				})
+			};
+
+			address += chunk.len() as u32;
+			bytes = rest;
+		}
+		Ok(())
+	}
+
+	/// Adds a memory area, whose pages are mapped on the first access (cf.
+	/// [resolve_demand_fault])
+	pub fn add_area(&mut self, area: MemoryArea) -> Result<(), MapError> {
+		if page_end(area.end) > KERNEL_VIRTUAL_BASE as u64 {
+			return Err(MapError::KernelAddress(area.end));
+		}
+
+		let mut memory_areas = MEMORY_AREAS.lock();
+		let areas = memory_areas.get_mut(&self.directory).expect("the address space has no areas");
+
+		if !areas.insert(area) {
+			return Err(MapError::AreaInUse(area.start));
+		}
+		Ok(())
+	}
+
+	/// Returns the memory area that holds `virtual_addr`
+	pub fn area(&self, virtual_addr: u32) -> Option<MemoryArea> {
+		MEMORY_AREAS.lock().get(&self.directory)?.find(virtual_addr).copied()
+	}
+
+	/// The memory areas, sorted by address
+	pub fn areas(&self) -> Vec<MemoryArea> {
+		MEMORY_AREAS
+			.lock()
+			.get(&self.directory)
+			.map(|areas| areas.iter().copied().collect())
+			.unwrap_or_default()
+	}
+
+	/// Moves the program break (the end of the heap area) to `address`, and returns the new
+	/// break, as `brk` does
+	///
+	/// The break doesn't move if `address` is below the start of the heap, or if the heap would
+	/// reach another area: the current break is returned then (0 without a heap area). The pages
+	/// above a lower break are released.
+	pub fn set_break(&mut self, address: u32) -> u32 {
+		let previous_end = {
+			let mut memory_areas = MEMORY_AREAS.lock();
+			let Some(areas) = memory_areas.get_mut(&self.directory) else {
+				return 0;
+			};
+			let Some(heap) = areas.heap().copied() else {
+				return 0;
+			};
+
+			if page_end(address) > KERNEL_VIRTUAL_BASE as u64 {
+				return heap.end;
+			}
+			match areas.set_heap_end(address) {
+				Some(previous_end) => previous_end,
+				None => return heap.end,
+			}
+		};
+
+		for page in (page_end(address)..page_end(previous_end)).step_by(FRAME_SIZE) {
+			if let Some(frame) = self.unmap_page(page as u32) {
+				release_frame(frame);
+			}
+		}
+		address
+	}
+
+	/// Creates a copy of the user half and of the memory areas, that shares its frames: the
+	/// writable pages become read-only and copy-on-write in both address spaces, until a write
+	/// gives the writer a copy of its own (cf. [resolve_copy_on_write])
+	///
+	/// The frames that already have [MAX_FRAME_REFERENCES](super::pmm::MAX_FRAME_REFERENCES) are
+	/// copied at once
+	pub fn fork(&mut self) -> Result<AddressSpace, MapError> {
+		let mut child = AddressSpace::new().ok_or(MapError::OutOfMemory)?;
+
+		{
+			let mut memory_areas = MEMORY_AREAS.lock();
+			let areas = memory_areas.get(&self.directory).cloned().unwrap_or_default();
+			memory_areas.insert(child.directory, areas);
+		}
+
+		// the tables are edited outside of with_frame (cf. its documentation)
+		let mut parent_entries = vec![PagePointer::zeroed(); 1024];
+		let mut child_entries = vec![PagePointer::zeroed(); 1024];
+		let mut buffer = vec![0; FRAME_SIZE];
+		let mut result = Ok(());
+
+		for dir_offset in 0..USER_TABLE_COUNT {
+			let Some(table) = self.table_address(dir_offset) else {
+				continue;
+			};
+			let child_table = child.allocate_table(dir_offset)?;
+
+			unsafe {
+				with_frame(table, |table: &mut PageTable| {
+					parent_entries.copy_from_slice(&table.physical_page_pointers);
+				})
+			};
+			child_entries.fill(PagePointer::zeroed());
+
+			for (parent_entry, child_entry) in parent_entries.iter_mut().zip(&mut child_entries) {
+				if parent_entry.flags().is_present() {
+					match fork_entry(parent_entry, &mut buffer) {
+						Ok(entry) => *child_entry = entry,
+						Err(error) => {
+							result = Err(error);
+							break;
+						}
+					}
+				}
+			}
+
+			// the entries forked before an error are kept, so their references are released
+			unsafe {
+				with_frame(table, |table: &mut PageTable| {
+					table.physical_page_pointers.copy_from_slice(&parent_entries);
+				});
+				with_frame(child_table, |table: &mut PageTable| {
+					table.physical_page_pointers.copy_from_slice(&child_entries);
+				});
+			}
+
+			if result.is_err() {
+				break;
+			}
+		}
+
+		// the writable pages became read-only
+		if self.is_active() {
+			unsafe { flush_tlb() };
+		}
+
+		result.map(|()| child)
+	}
+
+	/// Returns the physical address of the [PageTable] at `dir_offset`, if there is one
+	fn table_address(&self, dir_offset: usize) -> Option<u32> {
+		let pointer = unsafe {
+			with_frame(self.directory, |directory: &mut PageDirectory| directory[dir_offset])
+		};
+
+		pointer.flags().is_present().then(|| pointer.physical_addr())
+	}
+
+	/// Allocates an empty [PageTable] at `dir_offset` of the user half, and returns its physical
+	/// address
+	fn allocate_table(&mut self, dir_offset: usize) -> Result<u32, MapError> {
+		let table = kmalloc().ok_or(MapError::OutOfMemory)?;
+
+		// the pages themselves say whether user space can access them (cf.
+		// PageDirectory::allocate_table_at)
+		let flags = PageEntryFlags::new()
+			.with_is_present(true)
+			.with_is_writable(true)
+			.with_is_user_space(true);
+
+		unsafe {
+			with_frame(table, |table: &mut PageTable| *table = PageTable::zeroed());
+			with_frame(self.directory, |directory: &mut PageDirectory| {
+				directory[dir_offset].set(table, flags);
+			});
+		}
+		Ok(table)
+	}
+
+	/// Removes the stale TLB entry of `virtual_addr`, if the CPU uses this directory
+	fn invalidate(&self, virtual_addr: u32) {
+		if self.is_active() {
+			unsafe { asm!("invlpg [{}]", in(reg) virtual_addr) };
+		}
+	}
+}
+
+impl Drop for AddressSpace {
+	fn drop(&mut self) {
+		if self.is_active() {
+			unsafe { load_page_directory(kernel_page_directory()) };
+		}
+
+		// the frames are released outside of with_frame (cf. its documentation)
+		let mut frames = Vec::with_capacity(1024);
+
+		for dir_offset in 0..USER_TABLE_COUNT {
+			let Some(table) = self.table_address(dir_offset) else {
+				continue;
+			};
+
+			unsafe {
+				with_frame(table, |table: &mut PageTable| {
+					frames.extend(
+						table
+							.physical_page_pointers
+							.iter()
+							.filter(|page| page.flags().is_present())
+							.map(|page| page.physical_addr()),
+					);
+				})
+			};
+
+			for frame in frames.drain(..) {
+				release_frame(frame);
+			}
+			kfree(table);
+		}
+
+		// before the frame can be a new directory
+		MEMORY_AREAS.lock().remove(&self.directory);
+		kfree(self.directory);
+	}
+}
+
+/// Shares the frame of the present `parent_entry` with a child address space, and returns the
+/// entry of the child (cf. [AddressSpace::fork])
+///
+/// The frame is copied (through `buffer`) instead if it can't have another reference
+fn fork_entry(parent_entry: &mut PagePointer, buffer: &mut [u8]) -> Result<PagePointer, MapError> {
+	let physical_addr = parent_entry.physical_addr();
+	let flags = parent_entry.flags();
+	let is_writable = flags.is_writable() || flags.is_copy_on_write();
+
+	let mut child_entry = PagePointer::zeroed();
+
+	if share_frame(physical_addr) {
+		let flags = if is_writable {
+			flags.with_is_writable(false).with_is_copy_on_write(true)
+		} else {
+			flags
+		};
+
+		parent_entry.set(physical_addr, flags);
+		child_entry.set(physical_addr, flags);
+		return Ok(child_entry);
+	}
+
+	let copy = kmalloc().ok_or(MapError::OutOfMemory)?;
+	unsafe {
+		with_frame(physical_addr, |page: &mut [u8; FRAME_SIZE]| buffer.copy_from_slice(page));
+		with_frame(copy, |page: &mut [u8; FRAME_SIZE]| page.copy_from_slice(buffer));
+	}
+
+	child_entry.set(copy, flags.with_is_writable(is_writable).with_is_copy_on_write(false));
+	Ok(child_entry)
+}
+
+/// Gives the active address space a writable page of its own at the copy-on-write page of
+/// `virtual_addr` (cf. [AddressSpace::fork]): a copy of the frame, or the frame itself if no
+/// other address space uses it anymore
+///
+/// Returns false if the page isn't copy-on-write, or if there is no free frame for the copy
+pub fn resolve_copy_on_write(virtual_addr: u32) -> bool {
+	let page = virtual_addr & !(FRAME_SIZE as u32 - 1);
+	if page as usize >= KERNEL_VIRTUAL_BASE {
+		return false;
+	}
+
+	let Some((physical_addr, flags)) = (unsafe { PageDirectory::translate(page) }) else {
+		return false;
+	};
+	if !flags.is_copy_on_write() {
+		return false;
+	}
+
+	let flags = flags.with_is_writable(true).with_is_copy_on_write(false);
+
+	// the other address spaces dropped it, or wrote to it already
+	if frame_references(physical_addr) == 1 {
+		unsafe { PageDirectory::map_page_with_flags(page, physical_addr, flags) };
+		return true;
+	}
+
+	let Some(copy) = kmalloc() else {
+		return false;
+	};
+
+	unsafe {
+		let content = &*(page as *const [u8; FRAME_SIZE]);
+		with_frame(copy, |destination: &mut [u8; FRAME_SIZE]| destination.copy_from_slice(content));
+
+		PageDirectory::map_page_with_flags(page, copy, flags);
+	}
+
+	release_frame(physical_addr);
+	true
+}
+
+/// Maps a zeroed page at `virtual_addr` in the active address space, if it is in one of its
+/// memory areas that allows the access (cf. [MemoryAreas::find_for_fault]): a stack grows down to
+/// it if needed
+///
+/// `stack_pointer` is the one of the program, for faults from user mode. Returns false if the
+/// access isn't allowed (a segmentation fault), or if there is no free frame.
+pub fn resolve_demand_fault(virtual_addr: u32, is_write: bool, stack_pointer: Option<u32>) -> bool {
+	if virtual_addr as usize >= KERNEL_VIRTUAL_BASE {
+		return false;
+	}
+
+	let directory = active_page_directory();
+	let is_writable = {
+		let mut memory_areas = MEMORY_AREAS.lock();
+		let Some(areas) = memory_areas.get_mut(&directory) else {
+			return false;
+		};
+
+		match areas.find_for_fault(virtual_addr, stack_pointer) {
+			Some(area) if area.is_writable || !is_write => area.is_writable,
+			_ => return false,
+		}
+	};
+
+	// Safety: the directory has memory areas, so it belongs to an AddressSpace
+	let mut address_space = unsafe { AddressSpace::active() };
+	address_space.map_zeroed_page(page_start(virtual_addr), true, is_writable).is_ok()
+}
+
+/// Returns the memory area of the active address space that holds `virtual_addr`
+pub fn active_area(virtual_addr: u32) -> Option<MemoryArea> {
+	MEMORY_AREAS.lock().get(&active_page_directory())?.find(virtual_addr).copied()
+}
+
+/// Makes the `length` bytes at `virtual_addr` writable user memory in the active address space,
+/// before the kernel writes there on behalf of the program: the copy-on-write pages are copied,
+/// and the pages of its memory areas are mapped (a stack grows down if `stack_pointer` allows it)
+///
+/// Returns false if the program couldn't write there itself, or if there is no free frame
+pub fn prepare_user_write(virtual_addr: u32, length: u32, stack_pointer: u32) -> bool {
+	let Some(end) = virtual_addr.checked_add(length) else {
+		return false;
+	};
+
+	(page_start(virtual_addr)..end).step_by(FRAME_SIZE).all(|page| {
+		match unsafe { PageDirectory::translate(page) } {
+			Some((_, flags)) if !flags.is_user_space() => false,
+			Some((_, flags)) if flags.is_writable() => true,
+			Some(_) => resolve_copy_on_write(page),
+			None => resolve_demand_fault(page, true, Some(stack_pointer)),
+		}
+	})
+}
+
+/// Returns the directory and table offsets of `virtual_addr`, if it is in the user half
+fn split_user_address(virtual_addr: u32) -> Result<(usize, usize), MapError> {
+	let dir_offset = (virtual_addr >> 22) as usize; // Top 10 bits
+	let table_offset = ((virtual_addr >> 12) & 0x3ff) as usize; // Middle 10 bits
+
+	if dir_offset >= USER_TABLE_COUNT {
+		return Err(MapError::KernelAddress(virtual_addr));
+	}
+	Ok((dir_offset, table_offset))
+}
+
+/// Maps the frame at `physical_addr` at [SCRATCH_ADDRESS], and calls `f` on its content
+///
+/// Interrupts are disabled meanwhile, so the task holding [SCRATCH_LOCK] can't be preempted.
+/// `f` must then not take locks that a preempted task could hold (e.g. the frame allocator).
+///
+/// # Safety
+///  - `T` must fit in a frame, and any bytes must be a valid `T`
+///  - the frame must not be used as something else meanwhile
+unsafe fn with_frame<T, R>(physical_addr: u32, f: impl FnOnce(&mut T) -> R) -> R {
+	const { assert!(size_of::<T>() <= FRAME_SIZE) };
+
+	without_interrupts(|| {
+		let _scratch = SCRATCH_LOCK.lock();
+
+		unsafe { PageDirectory::map_page(SCRATCH_ADDRESS, physical_addr, false, true) };
+		let result = f(unsafe { &mut *(SCRATCH_ADDRESS as *mut T) });
+		unsafe { PageDirectory::unmap_page(SCRATCH_ADDRESS) };
+
+		result
+	})
+}
@@ -0,0 +1,201 @@
+//! Virtual memory areas: the ranges of an address space that its program may use
+//!
+//! Each [AddressSpace](super::address_space::AddressSpace) keeps a list of [MemoryArea]s. Their
+//! pages are only mapped on the first access: the page fault handler looks up the area of the
+//! faulting address, and maps a zeroed frame if the access is allowed (cf.
+//! [resolve_demand_fault](super::address_space::resolve_demand_fault)). An access outside of
+//! every area is a segmentation fault.
+//!
+//! A stack area grows down on faults below it, up to its limit. A heap area grows and shrinks
+//! with `brk` (cf. [AddressSpace::set_break](super::address_space::AddressSpace::set_break)).
+
+use alloc::vec::Vec;
+
+use super::pmm::FRAME_SIZE;
+
+/// A fault below the stack pointer is still a stack access if it is this close (`enter` and
+/// `pusha` write below it before moving it)
+pub const STACK_ACCESS_SLACK: u32 = 65536 + 32 * 4;
+
+/// What a [MemoryArea] holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AreaKind {
+	/// Zero-filled memory (e.g. the segments of a program, past their file content)
+	Anonymous,
+
+	/// A stack, that grows down to `limit` on faults below it
+	Stack {
+		/// Lowest address of the stack once fully grown
+		limit: u32,
+	},
+
+	/// The heap of a program, whose end is the program break
+	Heap,
+}
+
+/// A range of virtual addresses that a program may use, with its permissions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryArea {
+	/// The first address, page aligned
+	pub start: u32,
+
+	/// The address after the area: the pages up to it are part of it
+	pub end: u32,
+
+	/// What the area holds
+	pub kind: AreaKind,
+
+	/// Can the program write to the area ?
+	pub is_writable: bool,
+}
+
+impl MemoryArea {
+	/// Creates an area from `start` (aligned down to its page) to `end`
+	pub fn new(start: u32, end: u32, kind: AreaKind, is_writable: bool) -> Self {
+		Self {
+			start: page_start(start),
+			end,
+			kind,
+			is_writable,
+		}
+	}
+
+	/// Returns true if the page of `address` is part of the area
+	pub fn contains(&self, address: u32) -> bool {
+		address >= self.start && (address as u64) < page_end(self.end)
+	}
+
+	/// The addresses the area may ever cover: a stack reserves the range it grows into
+	fn reserved_range(&self) -> (u64, u64) {
+		let lowest = match self.kind {
+			AreaKind::Stack {
+				limit,
+			} => limit.min(self.start),
+			_ => self.start,
+		};
+		(page_start(lowest) as u64, page_end(self.end))
+	}
+
+	/// Returns true if the two areas may share a page
+	fn overlaps(&self, other: &MemoryArea) -> bool {
+		let (start, end) = self.reserved_range();
+		let (other_start, other_end) = other.reserved_range();
+		start < other_end && other_start < end
+	}
+}
+
+/// The [MemoryArea]s of an address space, sorted by address, without overlaps
+#[derive(Debug, Clone, Default)]
+pub struct MemoryAreas {
+	areas: Vec<MemoryArea>,
+}
+
+impl MemoryAreas {
+	/// Creates an empty list
+	pub const fn new() -> Self {
+		Self {
+			areas: Vec::new(),
+		}
+	}
+
+	/// Adds `area`, unless it overlaps another one (counting the ranges the stacks grow into)
+	///
+	/// Returns false if it does
+	pub fn insert(&mut self, area: MemoryArea) -> bool {
+		if self.areas.iter().any(|other| other.overlaps(&area)) {
+			return false;
+		}
+
+		let index = self.areas.partition_point(|other| other.start < area.start);
+		self.areas.insert(index, area);
+		true
+	}
+
+	/// Returns the area that holds `address`
+	pub fn find(&self, address: u32) -> Option<&MemoryArea> {
+		self.areas.iter().find(|area| area.contains(address))
+	}
+
+	/// Returns the area where a fault at `address` may map a page: the area that holds it, or a
+	/// stack above it, that then grows down to its page
+	///
+	/// The stack only grows if `stack_pointer` (for faults from user mode) isn't too far above
+	/// (cf. [STACK_ACCESS_SLACK])
+	pub fn find_for_fault(
+		&mut self,
+		address: u32,
+		stack_pointer: Option<u32>,
+	) -> Option<&MemoryArea> {
+		if let Some(index) = self.areas.iter().position(|area| area.contains(address)) {
+			return Some(&self.areas[index]);
+		}
+
+		let area = self.areas.iter_mut().find(|area| {
+			let AreaKind::Stack {
+				limit,
+			} = area.kind
+			else {
+				return false;
+			};
+			(limit..area.start).contains(&address)
+		})?;
+
+		if stack_pointer
+			.is_some_and(|stack_pointer| address.saturating_add(STACK_ACCESS_SLACK) < stack_pointer)
+		{
+			return None;
+		}
+
+		area.start = page_start(address);
+		Some(area)
+	}
+
+	/// Returns the heap area, if there is one
+	pub fn heap(&self) -> Option<&MemoryArea> {
+		self.areas.iter().find(|area| area.kind == AreaKind::Heap)
+	}
+
+	/// Moves the end of the heap to `end`, unless it would go below its start or reach another
+	/// area
+	///
+	/// Returns the previous end, or None if the heap can't move there (or there is none)
+	pub fn set_heap_end(&mut self, end: u32) -> Option<u32> {
+		let index = self.areas.iter().position(|area| area.kind == AreaKind::Heap)?;
+		let heap = self.areas[index];
+
+		if end < heap.start {
+			return None;
+		}
+
+		let resized = MemoryArea {
+			end,
+			..heap
+		};
+		let overlaps = self
+			.areas
+			.iter()
+			.enumerate()
+			.any(|(other_index, other)| other_index != index && other.overlaps(&resized));
+		if overlaps {
+			return None;
+		}
+
+		self.areas[index] = resized;
+		Some(heap.end)
+	}
+
+	/// The areas, sorted by address
+	pub fn iter(&self) -> impl Iterator<Item = &MemoryArea> {
+		self.areas.iter()
+	}
+}
+
+/// The start of the page of `address`
+pub fn page_start(address: u32) -> u32 {
+	address & !(FRAME_SIZE as u32 - 1)
+}
+
+/// `address` rounded up to a page boundary (a u64, as the last page ends at 4 GiB)
+pub fn page_end(address: u32) -> u64 {
+	(address as u64).next_multiple_of(FRAME_SIZE as u64)
+}
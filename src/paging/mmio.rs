@@ -0,0 +1,82 @@
+//! Mappings of memory-mapped devices (e.g. the APICs) and firmware tables
+//!
+//! Their physical addresses are usually far above the kernel mapping (e.g. 0xFEE00000 for the
+//! local APIC), so [map_mmio] maps them, uncached, in a window just below the page tables
+//! backdoor. Mappings are never removed.
+//!
+//! [map_physical] is for firmware tables, which may be in the kernel mapping or not.
+
+use spin::Mutex;
+
+use super::page_directory::{
+	PAGE_TABLES_ADDRESS,
+	PageDirectory,
+	PageEntryFlags,
+};
+use super::pmm::FRAME_SIZE;
+use super::{
+	KERNEL_MAPPED_SIZE,
+	phys_to_virt,
+};
+
+/// Start of the virtual window of the device mappings
+pub(super) const MMIO_VIRTUAL_BASE: usize = 0xff00_0000;
+
+/// End of the window (the page tables backdoor, cf. [PageDirectory::setup_directory_backdoor])
+const MMIO_VIRTUAL_END: usize = PAGE_TABLES_ADDRESS;
+
+/// Next free virtual address of the window
+static NEXT_MMIO_ADDRESS: Mutex<usize> = Mutex::new(MMIO_VIRTUAL_BASE);
+
+/// Maps `size` bytes of device memory at `physical_address`, and returns their virtual address
+///
+/// The pages are writable and uncached. Panics if the window is full.
+///
+/// # Safety
+///  - paging must be initialized (cf. [crate::paging::init_virtual_memory])
+///  - the memory must not be RAM used by something else, as it is mapped uncached
+pub unsafe fn map_mmio(physical_address: u32, size: usize) -> usize {
+	let offset = physical_address as usize % FRAME_SIZE;
+	let first_frame = physical_address - offset as u32;
+	let page_count = (offset + size).div_ceil(FRAME_SIZE);
+
+	let virtual_base = {
+		let mut next = NEXT_MMIO_ADDRESS.lock();
+		let base = *next;
+
+		assert!(
+			base + page_count * FRAME_SIZE <= MMIO_VIRTUAL_END,
+			"no room left to map {size} bytes of MMIO at {physical_address:#x}"
+		);
+		*next += page_count * FRAME_SIZE;
+		base
+	};
+
+	let flags = PageEntryFlags::new()
+		.with_is_present(true)
+		.with_is_writable(true)
+		.with_is_write_through(true)
+		.with_is_cache_disabled(true);
+
+	for page in 0..page_count {
+		let virtual_addr = (virtual_base + page * FRAME_SIZE) as u32;
+		let physical_addr = first_frame + (page * FRAME_SIZE) as u32;
+
+		unsafe { PageDirectory::map_page_with_flags(virtual_addr, physical_addr, flags) };
+	}
+
+	virtual_base + offset
+}
+
+/// Returns a virtual address for `size` bytes of physical memory at `physical_address`: in the
+/// kernel mapping if they are in it, or through [map_mmio] otherwise
+///
+/// # Safety
+///  - same as [map_mmio]
+pub unsafe fn map_physical(physical_address: u32, size: usize) -> usize {
+	if (physical_address as usize).saturating_add(size) <= KERNEL_MAPPED_SIZE {
+		phys_to_virt(physical_address)
+	} else {
+		unsafe { map_mmio(physical_address, size) }
+	}
+}
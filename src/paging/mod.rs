@@ -3,6 +3,7 @@
 //! At the bottom, we're using a [FrameAllocator] (called a Physical Memory Manager, or PMM)
 //! that holds a bitmap representing a 4GiB memory space divided into 4KiB physical page frames.
 //! The i-th boolean in the bitmap tells us if the i-th page frame is used (true) or not (false).
+//! It's a buddy allocator, so it can also hand out 2^n physically contiguous frames.
 //!
 //! On top of that, we handle virtual memory with a [PageDirectory],
 //! which holds 1023 [PagePointer]s to [PageTable]s,
@@ -38,37 +39,233 @@
 //! Voilà! The CPU will then return the [PageDirectory] (if we ask for size_of([PageDirectory])
 //! bytes)
 //!
+//! User programs each get an [AddressSpace]: a [PageDirectory] of their own, that shares the
+//! kernel page tables, and a list of [MemoryArea]s whose pages are mapped on the first access.
+//!
 //! #### Documentation
 //!
 //! You can read [https://wiki.osdev.org/Memory_management] and [https://wiki.osdev.org/X86_Paging]
 //! for a better understanding.
 //!
-//! [FrameAllocator]: self::pmm::FrameAllocator
 //! [PagePointer]: self::paging::PagePointer
+//! [AddressSpace]: self::address_space::AddressSpace
+//! [MemoryArea]: self::memory_area::MemoryArea
 
+pub mod address_space;
+pub mod memory_area;
+pub mod mmio;
 mod multiboot;
 pub mod page_directory;
 pub mod page_fault;
 pub mod pmm;
 
-use self::multiboot::MemoryMapEntry;
+use alloc::string::{
+	String,
+	ToString,
+};
+use core::ffi::{
+	CStr,
+	c_char,
+};
+
+use spin::Mutex;
+
+use self::mmio::map_physical;
 pub use self::multiboot::{
+	BootModule,
 	GRUB_MULTIBOOT_MAGIC,
+	MemoryRegion,
 	MultibootInfo,
 };
+use self::multiboot::{
+	MULTIBOOT_INFO_CMDLINE,
+	MULTIBOOT_INFO_MODS,
+	MemoryMapEntry,
+	MultibootModule,
+};
 use self::page_directory::{
 	PageDirectory,
-	PageEntryFlags,
-	PageTable,
+	flush_tlb,
+};
+use self::pmm::{
+	FrameAllocator,
+	PHYSICAL_ALLOCATOR,
 };
-use self::pmm::{PHYSICAL_ALLOCATOR, kmalloc, FRAME_SIZE};
+
+/// Virtual address of the physical address 0 in the kernel mapping (the kernel is loaded at
+/// 1 MiB, and linked at `KERNEL_VIRTUAL_BASE + 1 MiB`, cf. tools/build/link.ld)
+pub const KERNEL_VIRTUAL_BASE: usize = 0xc000_0000;
+
+/// Size of the physical memory mapped at [KERNEL_VIRTUAL_BASE] by `boot.s`
+/// (BOOT_PAGE_TABLES * 4 MiB, cf. tools/build/boot.s)
+pub const KERNEL_MAPPED_SIZE: usize = 8 * 1024 * 1024;
 
 // kernel start and end addresses from link.ld (tools/build/link.ld)
 unsafe extern "C" {
-	/// 0x00100000
+	/// 0xC0100000
 	pub(crate) static kernel_start: u32;
 
 	static kernel_end: u32;
+
+	/// The [PageDirectory] used by `boot.s` to enable paging (cf. [init_virtual_memory])
+	static mut boot_page_directory: u32;
+
+	/// The page below the boot stack (cf. [init_virtual_memory])
+	static stack_guard: u32;
+}
+
+/// Returns the virtual address of a physical address in the kernel mapping
+///
+/// `physical_address` must be in the first [KERNEL_MAPPED_SIZE] bytes of physical memory
+pub const fn phys_to_virt(physical_address: u32) -> usize {
+	debug_assert!((physical_address as usize) < KERNEL_MAPPED_SIZE, "not in the kernel mapping");
+	physical_address as usize + KERNEL_VIRTUAL_BASE
+}
+
+/// Returns the physical address of a virtual address in the kernel mapping
+/// (e.g. the kernel image)
+pub const fn virt_to_phys(virtual_address: usize) -> u32 {
+	debug_assert!(
+		virtual_address >= KERNEL_VIRTUAL_BASE
+			&& virtual_address < KERNEL_VIRTUAL_BASE + KERNEL_MAPPED_SIZE,
+		"not in the kernel mapping"
+	);
+	(virtual_address - KERNEL_VIRTUAL_BASE) as u32
+}
+
+/// Returns the physical address of the kernel [PageDirectory] (the one of the kernel threads)
+pub fn kernel_page_directory() -> u32 {
+	virt_to_phys(&raw const boot_page_directory as usize)
+}
+
+/// Maximum number of bootloader memory map entries kept by [init_physical_memory]
+const MAX_MEMORY_REGIONS: usize = 32;
+
+/// The bootloader memory map, as parsed by [init_physical_memory] (cf. [memory_map])
+static MEMORY_MAP: Mutex<([MemoryRegion; MAX_MEMORY_REGIONS], usize)> =
+	Mutex::new(([MemoryRegion::empty(); MAX_MEMORY_REGIONS], 0));
+
+/// Calls `f` on every entry of the bootloader memory map, in the order given by the bootloader
+///
+/// Note: only the first [MAX_MEMORY_REGIONS] entries are kept
+pub fn memory_map(mut f: impl FnMut(&MemoryRegion)) {
+	let memory_map = MEMORY_MAP.lock();
+	let (regions, count) = &*memory_map;
+
+	regions[..*count].iter().for_each(&mut f);
+}
+
+/// Maximum number of bootloader modules kept by [init_physical_memory]
+const MAX_BOOT_MODULES: usize = 8;
+
+/// The bootloader modules, as parsed by [init_physical_memory] (cf. [boot_modules])
+static BOOT_MODULES: Mutex<([BootModule; MAX_BOOT_MODULES], usize)> =
+	Mutex::new(([BootModule::empty(); MAX_BOOT_MODULES], 0));
+
+/// Calls `f` on every module loaded by the bootloader, in the order of grub.cfg
+///
+/// Note: only the first [MAX_BOOT_MODULES] modules are kept
+pub fn boot_modules(mut f: impl FnMut(&BootModule)) {
+	let boot_modules = BOOT_MODULES.lock();
+	let (modules, count) = &*boot_modules;
+
+	modules[..*count].iter().for_each(&mut f);
+}
+
+/// Returns the bytes of the bootloader module named `name` (cf. [BootModule::name])
+pub fn boot_module(name: &str) -> Option<&'static [u8]> {
+	let boot_modules = BOOT_MODULES.lock();
+	let (modules, count) = &*boot_modules;
+
+	modules[..*count].iter().find(|module| module.name() == name)?.bytes()
+}
+
+/// Maximum length of the kernel command line kept by [init_physical_memory]
+const MAX_COMMAND_LINE_LENGTH: usize = 256;
+
+/// The kernel command line given by the bootloader (cf. [kernel_parameter])
+static COMMAND_LINE: Mutex<([u8; MAX_COMMAND_LINE_LENGTH], usize)> =
+	Mutex::new(([0; MAX_COMMAND_LINE_LENGTH], 0));
+
+/// Returns the value of the `name=value` word of the kernel command line (e.g. `init=hello`, on
+/// the `multiboot` line of grub.cfg)
+///
+/// Note: only the first [MAX_COMMAND_LINE_LENGTH] bytes of the command line are kept
+pub fn kernel_parameter(name: &str) -> Option<String> {
+	let command_line = COMMAND_LINE.lock();
+	let (bytes, length) = &*command_line;
+
+	str::from_utf8(&bytes[..*length]).ok()?.split_whitespace().find_map(|word| {
+		let (key, value) = word.split_once('=')?;
+		(key == name).then(|| value.to_string())
+	})
+}
+
+/// Copies the kernel command line of the bootloader (its memory may be reused)
+fn record_command_line(mb_info: &MultibootInfo) {
+	if mb_info.flags & MULTIBOOT_INFO_CMDLINE == 0 {
+		return;
+	}
+
+	let mut command_line = COMMAND_LINE.lock();
+	let (bytes, length) = &mut *command_line;
+
+	let string = unsafe { c_string(mb_info.cmdline) };
+	*length = string.len().min(MAX_COMMAND_LINE_LENGTH);
+	bytes[..*length].copy_from_slice(&string[..*length]);
+}
+
+/// Maps the bootloader modules recorded by [init_physical_memory] (cf. [BootModule::bytes])
+///
+/// Must be called once, after [init_virtual_memory]
+pub fn map_boot_modules() {
+	let mut boot_modules = BOOT_MODULES.lock();
+	let (modules, count) = &mut *boot_modules;
+
+	for module in modules[..*count].iter_mut().filter(|module| module.size() > 0) {
+		// Safety: the module memory is reserved (cf. record_boot_modules)
+		module.virtual_address = unsafe { map_physical(module.start, module.size()) };
+	}
+}
+
+/// Copies the module list of the bootloader, and reserves the memory of the modules
+fn record_boot_modules(mb_info: &MultibootInfo, allocator: &mut FrameAllocator) {
+	if mb_info.flags & MULTIBOOT_INFO_MODS == 0 {
+		return;
+	}
+
+	let mut boot_modules = BOOT_MODULES.lock();
+	let (modules, count) = &mut *boot_modules;
+
+	let entries = unsafe {
+		core::slice::from_raw_parts(
+			phys_to_virt(mb_info.mods_addr) as *const MultibootModule,
+			mb_info.mods_count as usize,
+		)
+	};
+
+	for entry in entries {
+		allocator.reserve_region(entry.mod_start, entry.mod_end);
+
+		if *count < MAX_BOOT_MODULES {
+			let command_line = unsafe { c_string(entry.string) };
+			modules[*count] = BootModule::new(entry.mod_start, entry.mod_end, command_line);
+			*count += 1;
+		}
+	}
+}
+
+/// Returns the bytes of the C string at `physical_address` (empty if it isn't in the kernel
+/// mapping)
+///
+/// # Safety
+///  - `physical_address` must point to a nul-terminated string
+unsafe fn c_string(physical_address: u32) -> &'static [u8] {
+	if physical_address == 0 || physical_address as usize >= KERNEL_MAPPED_SIZE {
+		return &[];
+	}
+
+	unsafe { CStr::from_ptr(phys_to_virt(physical_address) as *const c_char) }.to_bytes()
 }
 
 /// Initializes the physical memory.
@@ -86,19 +283,32 @@ pub fn init_physical_memory(mb_info: &MultibootInfo) {
 	// at this point, every address in the allocator is marked as used
 	let mut allocator = PHYSICAL_ALLOCATOR.lock();
 
+	let mut memory_map = MEMORY_MAP.lock();
+	let (regions, count) = &mut *memory_map;
+
 	let mut current_addr = mb_info.mmap_addr;
 	let end_addr = mb_info.mmap_addr + mb_info.mmap_length;
 
 	// iterate over the memory map to know which addresses are usable (free)
 	while current_addr < end_addr {
 		// read the entry at current_addr
-		let entry = unsafe { &*(current_addr as *const MemoryMapEntry) };
+		let entry = unsafe { &*(phys_to_virt(current_addr) as *const MemoryMapEntry) };
+
+		if *count < MAX_MEMORY_REGIONS {
+			regions[*count] = MemoryRegion::from(entry);
+			*count += 1;
+		}
 
 		// if the entry is usable (free) and valid (32 bits), we mark it as free in our allocator
-		if entry.is_usable() && entry.is_32_bit_address() {
+		if entry.is_32_bit_address() {
 			let start = entry.base_addr_low;
 			let end = start.saturating_add(entry.length_low);
-			allocator.free_region(start, end);
+
+			if entry.is_usable() {
+				allocator.free_region(start, end);
+			} else {
+				allocator.add_unusable_region(start, end);
+			}
 		}
 
 		// entry.size doesn't count itself, so we add it (u32 = 4 bytes)
@@ -108,53 +318,51 @@ pub fn init_physical_memory(mb_info: &MultibootInfo) {
 	}
 
 	// reserve the kernel memory space
-	let kernel_start_address = &raw const kernel_start;
-	let kernel_end_address = &raw const kernel_end;
-	allocator.reserve_region(kernel_start_address as u32, kernel_end_address as u32);
+	let kernel_start_address = virt_to_phys(&raw const kernel_start as usize);
+	let kernel_end_address = virt_to_phys(&raw const kernel_end as usize);
+	allocator.reserve_region(kernel_start_address, kernel_end_address);
 
 	// reserve the hardware memory space (VGA, BIOS, etc...)
 	allocator.reserve_region(0x0, 0x100000);
+
+	// the programs loaded by the bootloader (cf. crate::user)
+	record_boot_modules(mb_info, &mut allocator);
+	record_command_line(mb_info);
 }
 
-/// Initializes the virtual memory.
+/// Finishes the kernel page directory set up by `boot.s` (tools/build/boot.s)
+///
+/// `boot.s` maps the first [KERNEL_MAPPED_SIZE] bytes of physical memory twice: at
+/// [KERNEL_VIRTUAL_BASE], where the kernel is linked, and at 0 (identity map), so the CPU could
+/// keep running the boot code when paging was enabled.
+///
+/// We create the backdoor to the [PageDirectory] by setting its own address into the last
+/// pointer of its own array, so we can edit it from now on, and remove the identity map, so the
+/// lower half of the address space is left to user space.
 ///
-/// It creates the [PageDirectory] and maps the first 4MB of physical memory to the same addresses
-/// in virtual memory. After that, when the CPU looks for any virtual addr below 4MB, it will
-/// be mapped to the exact same physical addr.
-/// This is needed because when enabling paging, the CPU must find the kernel code (+vga screen,
-/// etc...) at the same address.
+/// The page tables of the kernel half are all allocated here, so the address spaces of the user
+/// programs can share them (cf. [address_space::AddressSpace]).
 ///
-/// It then creates a backdoor to the [PageDirectory] by setting its own address into the last
-/// pointer of its own array, so we can access it after enabling paging.
+/// We also unmap the page below the kernel stack, so an overflow causes a double fault (handled on
+/// its own stack, cf. [crate::gdt::tss]) instead of overwriting the page tables.
 ///
 /// # Safety
-///  - physical memory must be initialized
-pub unsafe fn init_virtual_memory() -> u32 {
-	let directory_phys_address = kmalloc().expect("Out of memory");
-	let directory = unsafe { &mut *(directory_phys_address as *mut PageDirectory) };
+///  - must be called once, before anything maps pages (e.g. the heap)
+///  - nothing must use the identity map anymore
+pub unsafe fn init_virtual_memory() {
+	let directory = unsafe { &mut *(&raw mut boot_page_directory as *mut PageDirectory) };
 
-	// clear the page frame
-	unsafe { core::ptr::write_bytes(directory_phys_address as *mut u8, 0, FRAME_SIZE) };
-
-	//  This table will cover the first 4MB of RAM
-	let table0_phys = kmalloc().expect("Out of memory");
-	let table0 = unsafe { &mut *(table0_phys as *mut PageTable) };
-
-	// fill table0 with physical addresses in the first 4MB
-	// 4MB / FRAME_SIZE (4KiB) = 1024 page frames
-	for i in 0..1024 {
-		let addr = (i * FRAME_SIZE) as u32;
-		let flags = PageEntryFlags::new().with_is_present(true).with_is_writable(true);
+	// setup the backdoor (cf. setup_directory_backdoor() documentation)
+	directory.setup_directory_backdoor(virt_to_phys(&raw const *directory as usize));
 
-		table0[i].set(addr, flags);
+	// remove the identity map
+	for pointer in &mut directory.table_pointers[..KERNEL_VIRTUAL_BASE >> 22] {
+		pointer.clear();
 	}
 
-	// put table0 into the first pointer of the directory
-	let table0_flags = PageEntryFlags::new().with_is_present(true).with_is_writable(true);
-	directory[0].set(table0_phys, table0_flags);
+	unsafe { flush_tlb() };
 
-	// setup the backdoor (cf. setup_directory_backdoor() documentation)
-	directory.setup_directory_backdoor();
+	unsafe { PageDirectory::allocate_kernel_tables() };
 
-	directory_phys_address
+	unsafe { PageDirectory::unmap_page(&raw const stack_guard as u32) };
 }
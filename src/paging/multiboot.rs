@@ -44,3 +44,129 @@ impl MemoryMapEntry {
 		self.region_type == 1
 	}
 }
+
+/// Copy of a [MemoryMapEntry] (the bootloader memory map isn't in reserved memory, so it can be
+/// overwritten once the frame allocator is initialized)
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryRegion {
+	pub base_addr: u64,
+	pub length: u64,
+	pub region_type: u32,
+}
+
+impl MemoryRegion {
+	pub const fn empty() -> Self {
+		Self {
+			base_addr: 0,
+			length: 0,
+			region_type: 0,
+		}
+	}
+
+	/// Name of the region type, as defined by the multiboot specification
+	pub fn type_name(&self) -> &'static str {
+		match self.region_type {
+			1 => "usable",
+			3 => "ACPI reclaimable",
+			4 => "ACPI NVS",
+			5 => "bad memory",
+			_ => "reserved",
+		}
+	}
+}
+
+impl From<&MemoryMapEntry> for MemoryRegion {
+	fn from(entry: &MemoryMapEntry) -> Self {
+		Self {
+			base_addr: ((entry.base_addr_high as u64) << 32) | entry.base_addr_low as u64,
+			length: ((entry.length_high as u64) << 32) | entry.length_low as u64,
+			region_type: entry.region_type,
+		}
+	}
+}
+
+/// Bit of [MultibootInfo::flags] set if [MultibootInfo::cmdline] is valid
+pub const MULTIBOOT_INFO_CMDLINE: u32 = 1 << 2;
+
+/// Bit of [MultibootInfo::flags] set if [MultibootInfo::mods_count] and
+/// [MultibootInfo::mods_addr] are valid
+pub const MULTIBOOT_INFO_MODS: u32 = 1 << 3;
+
+/// Entries given by the bootloader at [MultibootInfo::mods_addr], one per `module` line of
+/// grub.cfg (cf. tools/build/grub.cfg)
+#[repr(C)]
+pub struct MultibootModule {
+	/// Physical address of the first byte of the module
+	pub mod_start: u32,
+
+	/// Physical address of the byte after the module
+	pub mod_end: u32,
+
+	/// Physical address of the module command line (a C string)
+	pub string: u32,
+
+	pub reserved: u32,
+}
+
+/// Maximum length of a [BootModule] name
+pub const MAX_MODULE_NAME_LENGTH: usize = 32;
+
+/// Copy of a [MultibootModule] (cf. [crate::paging::boot_modules])
+#[derive(Debug, Clone, Copy)]
+pub struct BootModule {
+	/// Physical address of the first byte of the module
+	pub start: u32,
+
+	/// Physical address of the byte after the module
+	pub end: u32,
+
+	/// Where the module is mapped, once paging is set up (0 before)
+	pub virtual_address: usize,
+
+	name: [u8; MAX_MODULE_NAME_LENGTH],
+	name_length: usize,
+}
+
+impl BootModule {
+	/// A module without name nor content
+	pub const fn empty() -> Self {
+		Self {
+			start: 0,
+			end: 0,
+			virtual_address: 0,
+			name: [0; MAX_MODULE_NAME_LENGTH],
+			name_length: 0,
+		}
+	}
+
+	/// Creates a module named after the file name of the first word of `command_line`
+	/// (e.g. `hello` for `/boot/hello arg`)
+	pub fn new(start: u32, end: u32, command_line: &[u8]) -> Self {
+		let path = command_line.split(|&c| c == b' ').next().unwrap_or_default();
+		let file_name = path.rsplit(|&c| c == b'/').next().unwrap_or_default();
+
+		let mut module = Self::empty();
+		module.start = start;
+		module.end = end;
+		module.name_length = file_name.len().min(MAX_MODULE_NAME_LENGTH);
+		module.name[..module.name_length].copy_from_slice(&file_name[..module.name_length]);
+		module
+	}
+
+	/// The file name of the module
+	pub fn name(&self) -> &str {
+		str::from_utf8(&self.name[..self.name_length]).unwrap_or("?")
+	}
+
+	/// Size of the module, in bytes
+	pub fn size(&self) -> usize {
+		(self.end - self.start) as usize
+	}
+
+	/// The bytes of the module, or None if it isn't mapped yet
+	pub fn bytes(&self) -> Option<&'static [u8]> {
+		(self.virtual_address != 0).then(|| unsafe {
+			core::slice::from_raw_parts(self.virtual_address as *const u8, self.size())
+		})
+	}
+}
@@ -6,7 +6,6 @@ use core::ops::{
 
 use modular_bitfield::specifiers::{
 	B2,
-	B4,
 	B20,
 };
 use modular_bitfield::{
@@ -14,37 +13,42 @@ use modular_bitfield::{
 	bitfield,
 };
 
+use crate::paging::KERNEL_VIRTUAL_BASE;
 use crate::paging::pmm::kmalloc;
 
-pub const PAGE_TABLES_ADDRESS: usize = 0xFFC00000;
+pub const PAGE_TABLES_ADDRESS: usize = 0xffc00000;
 
-/// Enables paging
+/// Flushes the whole TLB (the CPU cache of the page mappings) by reloading CR3
+///
+/// Needed after removing or changing many mappings at once ([PageDirectory::map_page] and
+/// [PageDirectory::unmap_page] flush their own page)
 ///
 /// # Safety
-///  - physical/virtual memory must have been well initialized (good luck ^^)
-pub unsafe fn enable_paging(directory_phys_addr: u32) {
+///  - paging must be enabled
+pub unsafe fn flush_tlb() {
 	unsafe {
-		// Put the PageDirectory in the CR3 register
-		// this is the only purpose of this register
 		asm!(
+			"mov {0}, cr3",
 			"mov cr3, {0}",
-			in(reg) directory_phys_addr
+			out(reg) _,
 		);
+	}
+}
 
-		// read cr0
-		// this register holds many flags to switch on and off CPU features
-		// bit 31 is the one for paging
-		let mut cr0: u32;
-		asm!("mov {0}, cr0", out(reg) cr0);
-
-		// set bit 31 to 1
-		cr0 |= 1 << 31;
+/// Returns the physical address of the active [PageDirectory] (the CR3 register)
+pub fn active_page_directory() -> u32 {
+	let value: u32;
+	unsafe { asm!("mov {}, cr3", out(reg) value) };
+	value
+}
 
-		asm!(
-			"mov cr0, {0}",
-			in(reg) cr0,
-		);
-	}
+/// Makes the [PageDirectory] at `directory_phys_addr` the active one, which also flushes the TLB
+///
+/// # Safety
+///  - the directory must map the kernel like the active one (cf.
+///    [crate::paging::address_space::AddressSpace::new])
+pub unsafe fn load_page_directory(directory_phys_addr: u32) {
+	unsafe { asm!("mov cr3, {}", in(reg) directory_phys_addr) };
 }
 
 /// Contains 1023 pointers to [PageTable]s
@@ -61,6 +65,7 @@ pub struct PageDirectory {
 }
 
 impl PageDirectory {
+	/// Creates a [PageDirectory] with every [PagePointer] cleared
 	pub const fn zeroed() -> Self {
 		PageDirectory {
 			table_pointers: [PagePointer::zeroed(); 1023],
@@ -71,16 +76,34 @@ impl PageDirectory {
 	/// Connects a virtual address to a physical address
 	///
 	/// This function assumes that paging is enabled and `self.backdoor` is initialized.
-	///  
-	/// # Safety:
+	///
+	/// # Safety
 	///  - Paging must be turned on
 	///  - `setup_directory_backdoor` must have been called
 	///  - `physical_addr` must point to a valid, allocated physical address
-	pub(crate) unsafe fn map_page(
+	pub unsafe fn map_page(
 		virtual_addr: u32,
 		physical_addr: u32,
 		is_user_space: bool,
 		is_writable: bool,
+	) {
+		let flags = PageEntryFlags::new()
+			.with_is_present(true)
+			.with_is_user_space(is_user_space)
+			.with_is_writable(is_writable);
+
+		unsafe { Self::map_page_with_flags(virtual_addr, physical_addr, flags) };
+	}
+
+	/// Connects a virtual address to a physical address, with custom `flags` (e.g. to disable
+	/// caching, cf. [crate::paging::mmio])
+	///
+	/// # Safety
+	///  - same as [PageDirectory::map_page]
+	pub unsafe fn map_page_with_flags(
+		virtual_addr: u32,
+		physical_addr: u32,
+		flags: PageEntryFlags,
 	) {
 		// cut the virtual address in the following way:
 		//  - 10 first bits: directory offset
@@ -98,11 +121,6 @@ impl PageDirectory {
 
 		let backdoor_table = unsafe { directory.get_page_table(dir_offset) };
 
-		let flags = PageEntryFlags::new()
-			.with_is_present(true)
-			.with_is_user_space(is_user_space)
-			.with_is_writable(is_writable);
-
 		backdoor_table[table_offset].set(physical_addr, flags);
 
 		// invlpg (Invalidate Page) tells the CPU we changed the mapping for this virtual address
@@ -111,10 +129,14 @@ impl PageDirectory {
 		}
 	}
 
-	/// # Safety:
+	/// Disconnects a virtual address from its physical address
+	///
+	/// Returns the physical address it was mapped to, or None if there was no [PageTable] for it
+	///
+	/// # Safety
 	///  - Paging must be turned on
 	///  - `setup_directory_backdoor` must have been called
-	pub(crate) unsafe fn unmap_page(virtual_addr: u32) -> Option<u32> {
+	pub unsafe fn unmap_page(virtual_addr: u32) -> Option<u32> {
 		let dir_offset = (virtual_addr >> 22) as usize; // Top 10 bits
 		let table_offset = ((virtual_addr >> 12) & 0x3ff) as usize; // Middle 10 bits
 
@@ -135,6 +157,45 @@ impl PageDirectory {
 		Some(physical_addr)
 	}
 
+	/// Returns the physical address and flags of the page mapped at `virtual_addr`, or None if it
+	/// isn't mapped
+	///
+	/// # Safety
+	///  - Paging must be turned on
+	///  - `setup_directory_backdoor` must have been called
+	pub unsafe fn translate(virtual_addr: u32) -> Option<(u32, PageEntryFlags)> {
+		let dir_offset = (virtual_addr >> 22) as usize; // Top 10 bits
+		let table_offset = ((virtual_addr >> 12) & 0x3ff) as usize; // Middle 10 bits
+
+		let directory = unsafe { Self::backdoor_directory() };
+
+		if !directory[dir_offset].flags().is_present() {
+			return None;
+		}
+
+		let backdoor_table = unsafe { directory.get_page_table(dir_offset) };
+		let page = backdoor_table[table_offset];
+
+		page.flags().is_present().then(|| (page.physical_addr(), page.flags()))
+	}
+
+	/// Allocates the missing [PageTable]s of the kernel half (from `KERNEL_VIRTUAL_BASE`), so
+	/// every [crate::paging::address_space::AddressSpace] can share them: a table allocated later
+	/// would only be in the directory that was active then
+	///
+	/// # Safety
+	///  - Paging must be turned on
+	///  - [PageDirectory::setup_directory_backdoor] must have been called
+	pub(crate) unsafe fn allocate_kernel_tables() {
+		let directory = unsafe { Self::backdoor_directory() };
+
+		for dir_index in KERNEL_VIRTUAL_BASE >> 22..directory.table_pointers.len() {
+			if !directory[dir_index].flags().is_present() {
+				unsafe { directory.allocate_table_at(dir_index) };
+			}
+		}
+	}
+
 	/// Allocate a [PageTable] and set a pointer to it at `backdoor_directory()[dir_index]`
 	///
 	/// # Safety:
@@ -170,8 +231,8 @@ impl PageDirectory {
 	/// anymore, as it would treat this address as a virtual address, and thus translate it into a
 	/// completely different physical address.
 	///
-	/// To solve this, before activating paging, we make the last [PagePointer] of the
-	/// [PageDirectory] points to the directory's own physical address.
+	/// To solve this, we make the last [PagePointer] of the [PageDirectory] points to the
+	/// directory's own physical address (`directory_phys_addr`).
 	/// We will then be able to access the [PageDirectory] using the `0xFFFFF000` virtual address.
 	///
 	/// When we access the `0xFFFFF000` virtual address, the CPU will access the [PageDirectory] by
@@ -189,11 +250,10 @@ impl PageDirectory {
 	/// # Notes
 	///  - I only created [PageDirectory::backdoor] for type safety, but, in memory, it's
 	///    represented exactly as the 1024-th [PagePointer] of the pointer array
-	pub(crate) fn setup_directory_backdoor(&mut self) {
-		let self_addr = &raw const *self;
+	pub(crate) fn setup_directory_backdoor(&mut self, directory_phys_addr: u32) {
 		let flags = PageEntryFlags::new().with_is_present(true).with_is_writable(true);
 
-		self.backdoor.set(self_addr as u32, flags);
+		self.backdoor.set(directory_phys_addr, flags);
 	}
 
 	/// Creates a reference to the 'original' [PageDirectory]
@@ -327,11 +387,13 @@ impl PagePointer {
 		self.0 = RawPageEntry::new()
 	}
 
-	fn flags(&self) -> PageEntryFlags {
+	/// The flags of the entry
+	pub fn flags(&self) -> PageEntryFlags {
 		self.0.flags()
 	}
 
-	fn physical_addr(&self) -> u32 {
+	/// The physical address the entry points to
+	pub fn physical_addr(&self) -> u32 {
 		b20_to_page_frame_address(self.0.physical_address())
 	}
 }
@@ -346,7 +408,7 @@ pub struct RawPageEntry {
 }
 
 #[bitfield(bits = 12)]
-#[derive(Specifier)]
+#[derive(Specifier, Clone, Copy)]
 pub struct PageEntryFlags {
 	/// Must be 1 for the [PagePointer] to be valid
 	pub is_present: bool,
@@ -357,10 +419,11 @@ pub struct PageEntryFlags {
 	/// True if user (ring 3) can access
 	pub is_user_space: bool,
 
-	// some cache control flags
-	// not used for now
-	#[skip]
-	reserved_1: B2,
+	/// Writes go straight to memory instead of the cache
+	pub is_write_through: bool,
+
+	/// The page is never cached (for memory-mapped devices)
+	pub is_cache_disabled: bool,
 
 	/// CPU sets this when page is read/written
 	pub is_accessed: bool,
@@ -372,8 +435,17 @@ pub struct PageEntryFlags {
 	/// 0 for 4KiB, 1 for 4MiB
 	pub is_4_mb_pages: bool,
 
+	/// For [PagePointer] on [PageTable] only: the mapping survives CR3 reloads (unused, as we
+	/// don't enable it in CR4)
+	#[skip]
+	is_global: bool,
+
+	/// Ignored by the CPU: the page is shared read-only with other address spaces, and writing to
+	/// it gives a copy of its own to the writer (cf. [crate::paging::address_space])
+	pub is_copy_on_write: bool,
+
 	#[skip]
-	reserved_2: B4,
+	reserved_2: B2,
 }
 
 /// Takes a page frame address (4KB aligned), and moves the significant bits (last 20 bits)
@@ -3,35 +3,57 @@ use core::arch::asm;
 use modular_bitfield::bitfield;
 use modular_bitfield::specifiers::B27;
 
-use crate::idt::InterruptStackFrame;
+use crate::idt::exceptions::fault_handler;
+use crate::idt::interrupts::enable_hardware_interrupts;
+use crate::idt::trap::TrapFrame;
+use crate::paging::address_space::{
+	resolve_copy_on_write,
+	resolve_demand_fault,
+};
 use crate::println;
+use crate::process::signal::{
+	SIGSEGV,
+	force_signal,
+};
 
-pub extern "x86-interrupt" fn page_fault_interrupt_handler(
-	stack_frame: &mut InterruptStackFrame,
-	error_code: u32,
-) {
+pub fn page_fault_interrupt_handler(frame: &mut TrapFrame) {
 	// the virtual address that caused the page fault
 	let faulting_address = read_cr2();
 
-	let parsed_error = PageFaultErrorCode::from_bytes(error_code.to_le_bytes());
-
-	if !parsed_error.is_user_mode() {
-		// FATA: the Kernel itself caused the fault
-		println!(
-			"EXCEPTION: PAGE FAULT\n\
-			Accessed Address: {faulting_address:#010X}\n\
-			Error Context: {parsed_error:#?}\n\
-			Stack Frame: {stack_frame:#?}",
-		)
-	} else {
-		println!("Segmentation Fault (Core Dumped).");
-		println!("User process tried to access invalid memory at {:#x}.", faulting_address);
+	let parsed_error = PageFaultErrorCode::from_bytes(frame.error_code.to_le_bytes());
+
+	// a new frame may wait for the frame allocator, held by a preempted task (interrupt flag)
+	if frame.eflags & (1 << 9) != 0 {
+		unsafe { enable_hardware_interrupts() };
+	}
+
+	// a write to a page shared by fork: the writer gets a copy of its own, and tries again
+	if parsed_error.is_mapped()
+		&& parsed_error.is_write()
+		&& resolve_copy_on_write(faulting_address)
+	{
+		return;
 	}
 
-	// hang the current thread
-	loop {
-		unsafe { core::arch::asm!("hlt") };
+	// the first access to a page of a memory area (or just below a stack): it gets a zeroed frame
+	let stack_pointer = parsed_error.is_user_mode().then_some(frame.user_esp);
+	if !parsed_error.is_mapped()
+		&& resolve_demand_fault(faulting_address, parsed_error.is_write(), stack_pointer)
+	{
+		return;
 	}
+
+	if parsed_error.is_user_mode() {
+		// delivered before the program resumes (cf. crate::idt::trap)
+		force_signal(SIGSEGV);
+		return;
+	}
+
+	// FATAL: the kernel itself caused the fault
+	println!("Accessed Address: {faulting_address:#010X}\nError Context: {parsed_error:#?}");
+
+	// disables interrupts again, dumps the registers and halts (or fails the test)
+	fault_handler(frame)
 }
 
 /// Returns the virtual address that caused the page fault (from the CR2 register)
@@ -51,8 +73,9 @@ pub fn read_cr2() -> u32 {
 pub struct PageFaultErrorCode {
 	/// Was the virtual address mapped in the [PageDirectory] ?
 	///
-	/// false = virtual address was mapped
-	/// true = virtual address was not mapped
+	/// false = virtual address was not mapped
+	/// true = virtual address was mapped, but the access wasn't allowed (e.g. a write to a
+	/// read-only page)
 	///
 	/// [PageDirectory]: crate::paging::page_directory::PageDirectory
 	pub is_mapped: bool,
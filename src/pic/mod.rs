@@ -9,7 +9,7 @@ pub fn io_wait() {
 
 const PIC1_CMD: u16 = 0x20;
 const PIC1_DATA: u16 = 0x21;
-const PIC1_OFFSET: u8 = 32;
+pub const PIC1_OFFSET: u8 = 32;
 
 const PIC2_CMD: u16 = 0xa0;
 const PIC2_DATA: u16 = 0xa1;
@@ -17,6 +17,9 @@ const PIC2_OFFSET: u8 = 40;
 
 const PIC_EOI: u8 = 0x20;
 
+/// OCW3 command to read the In-Service Register on the next read of the command port
+const PIC_READ_ISR: u8 = 0x0b;
+
 /// Initialize the Master and Slave PICs
 pub fn init_pics() {
 	unsafe {
@@ -43,33 +46,61 @@ pub fn init_pics() {
 		outb(PIC2_DATA, 0x01);
 		io_wait();
 
-		// Disabling every IRQs on both PICs
+		// Disabling every IRQs on both PICs (they are enabled by request_irq)
 		outb(PIC1_DATA, 0xff);
 		outb(PIC2_DATA, 0xff);
-
-		// Then enabling only IRQs that we use
-		enable_irq(Irq::Keyboard);
 	}
 }
 
 /// SAFETY: pics must be initialized
 pub unsafe fn enable_irq(irq: Irq) {
-	let port;
-	let mut relative_id = irq as u8;
+	let (port, relative_id) = irq.mask_port_and_bit();
+
+	let mut mask = unsafe { inb(port) };
+	mask &= !(1 << relative_id);
+
+	unsafe { outb(port, mask) };
 
+	// the slave PIC can only raise its IRQs through the cascade line
 	if irq.is_from_slave_pic() {
-		port = PIC2_DATA;
-		relative_id -= 8; // so it matches with the PIC2's internal checklist
-	} else {
-		port = PIC1_DATA;
+		unsafe { enable_irq(Irq::Cascade) };
+	}
+}
+
+/// Masks every line of both PICs (e.g. when the I/O APIC replaces them)
+pub fn mask_all_irqs() {
+	unsafe {
+		outb(PIC1_DATA, 0xff);
+		outb(PIC2_DATA, 0xff);
 	}
+}
+
+/// SAFETY: pics must be initialized
+pub unsafe fn disable_irq(irq: Irq) {
+	let (port, relative_id) = irq.mask_port_and_bit();
 
 	let mut mask = unsafe { inb(port) };
-	mask &= !(1 << relative_id);
+	mask |= 1 << relative_id;
 
 	unsafe { outb(port, mask) };
 }
 
+/// Returns true if the PIC is currently handling `irq` (its bit is set in the In-Service Register)
+///
+/// The PICs raise IRQ7 and IRQ15 when an interrupt disappears before being acknowledged: these
+/// spurious IRQs aren't in service
+pub fn is_in_service(irq: Irq) -> bool {
+	let (port, relative_id) =
+		if irq.is_from_slave_pic() { (PIC2_CMD, irq as u8 - 8) } else { (PIC1_CMD, irq as u8) };
+
+	let isr = unsafe {
+		outb(port, PIC_READ_ISR);
+		inb(port)
+	};
+
+	isr & (1 << relative_id) != 0
+}
+
 /// Tells to Slave/Master PICs that we finished to handle an IRQ
 pub fn send_end_of_interrupt(irq: Irq) {
 	// If the interrupt came from the Slave (interrupts 40-47), we thank the Slave
@@ -85,15 +116,97 @@ pub fn send_end_of_interrupt(irq: Irq) {
 	}
 }
 
-/// IRQ IDs
+/// Tells the master PIC that we finished to handle a spurious IRQ of the slave PIC (IRQ15)
+///
+/// The slave didn't raise a real interrupt, but the master did on the cascade line
+pub fn send_master_end_of_interrupt() {
+	unsafe { outb(PIC1_CMD, PIC_EOI) };
+}
+
+/// Number of IRQ lines of the two PICs
+pub const IRQ_LINES: usize = 16;
+
+/// IRQ IDs (the usual devices of each PIC line)
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Irq {
+	/// PIT channel 0
+	Timer = 0,
+	/// PS/2 keyboard
 	Keyboard = 1,
+	/// Slave PIC (never raised itself)
+	Cascade = 2,
+	/// Serial ports 2 and 4
+	Com2 = 3,
+	/// Serial ports 1 and 3
+	Com1 = 4,
+	/// Parallel port 2 (or sound card)
+	Lpt2 = 5,
+	/// Floppy disk controller
+	Floppy = 6,
+	/// Parallel port 1 (or spurious master IRQ)
+	Lpt1 = 7,
+	/// CMOS real-time clock
+	Rtc = 8,
+	/// Free (often ACPI)
+	Free9 = 9,
+	/// Free
+	Free10 = 10,
+	/// Free
+	Free11 = 11,
+	/// PS/2 mouse
+	Mouse = 12,
+	/// FPU / coprocessor
+	Fpu = 13,
+	/// Primary ATA disk
+	PrimaryAta = 14,
+	/// Secondary ATA disk (or spurious slave IRQ)
+	SecondaryAta = 15,
 }
 
 impl Irq {
+	/// Every line, indexed by number
+	pub const ALL: [Irq; IRQ_LINES] = [
+		Irq::Timer,
+		Irq::Keyboard,
+		Irq::Cascade,
+		Irq::Com2,
+		Irq::Com1,
+		Irq::Lpt2,
+		Irq::Floppy,
+		Irq::Lpt1,
+		Irq::Rtc,
+		Irq::Free9,
+		Irq::Free10,
+		Irq::Free11,
+		Irq::Mouse,
+		Irq::Fpu,
+		Irq::PrimaryAta,
+		Irq::SecondaryAta,
+	];
+
+	/// Returns the IRQ raised on the `vector` interrupt, if it comes from the PICs
+	pub fn from_vector(vector: u32) -> Option<Self> {
+		let line = vector.checked_sub(PIC1_OFFSET as u32)?;
+		Self::ALL.get(line as usize).copied()
+	}
+
+	/// The interrupt vector of the line, once the PICs are remapped
+	pub const fn vector(self) -> u8 {
+		PIC1_OFFSET + self as u8
+	}
+
+	/// Returns true for the lines of the slave PIC (8 to 15)
 	pub const fn is_from_slave_pic(self) -> bool {
 		self as u8 >= 8
 	}
+
+	/// Returns the data port of the PIC handling the line, and the line bit in its mask
+	const fn mask_port_and_bit(self) -> (u16, u8) {
+		if self.is_from_slave_pic() {
+			(PIC2_DATA, self as u8 - 8) // so it matches with the PIC2's internal checklist
+		} else {
+			(PIC1_DATA, self as u8)
+		}
+	}
 }
@@ -0,0 +1,160 @@
+//! Programmable Interval Timer (8253/8254)
+//!
+//! Channel 0 raises IRQ0 at [frequency] Hz. Each interrupt is a tick: it advances the monotonic
+//! clock ([ticks], [uptime]) and runs the expired software timers (cf. [timer]).
+
+pub mod timer;
+
+use core::time::Duration;
+
+use spin::Mutex;
+
+use crate::idt::interrupts::without_interrupts;
+use crate::idt::trap::TrapFrame;
+use crate::irq::request_irq;
+use crate::pic::Irq;
+use crate::shared::{
+	inb,
+	outb,
+};
+
+const PIT_CHANNEL0_DATA: u16 = 0x40;
+const PIT_COMMAND: u16 = 0x43;
+
+/// Channel 0, low then high byte of the reload value, mode 2 (rate generator), binary counter
+const CHANNEL0_RATE_GENERATOR: u8 = 0b0011_0100;
+
+/// Channel 0, latch the current count (so both bytes are read from the same value)
+const CHANNEL0_LATCH: u8 = 0b0000_0000;
+
+/// Frequency of the PIT oscillator, in Hz
+pub const PIT_BASE_FREQUENCY: u32 = 1_193_182;
+
+/// Tick frequency set by [crate::init], in Hz (1 tick per millisecond)
+pub const DEFAULT_FREQUENCY: u32 = 1000;
+
+/// The monotonic clock, advanced by [timer_interrupt_handler]
+///
+/// Always locked with interrupts disabled, as the handler locks it too
+static CLOCK: Mutex<Clock> = Mutex::new(Clock {
+	ticks: 0,
+	uptime_ns: 0,
+	divisor: 0,
+});
+
+struct Clock {
+	ticks: u64,
+
+	/// Counted in nanoseconds rather than ticks, so it stays right if the frequency changes
+	uptime_ns: u64,
+
+	/// Reload value of channel 0 (0 until [init_pit])
+	divisor: u32,
+}
+
+impl Clock {
+	/// Duration of a tick
+	fn tick_ns(&self) -> u64 {
+		self.divisor as u64 * 1_000_000_000 / PIT_BASE_FREQUENCY as u64
+	}
+}
+
+/// Programs channel 0 at `frequency` Hz, and starts counting ticks on IRQ0
+pub fn init_pit(frequency: u32) {
+	set_frequency(frequency);
+
+	request_irq(Irq::Timer, timer_interrupt_handler, "pit").expect("the timer line is free");
+}
+
+/// Changes the tick frequency, and returns the actual one (the PIT divides its base frequency by
+/// an integer between 1 and 65536)
+pub fn set_frequency(frequency: u32) -> u32 {
+	assert!(frequency > 0, "the PIT frequency can't be 0");
+
+	let divisor = (PIT_BASE_FREQUENCY / frequency).clamp(1, 65536);
+
+	without_interrupts(|| {
+		CLOCK.lock().divisor = divisor;
+
+		// a reload value of 0 means 65536
+		unsafe {
+			outb(PIT_COMMAND, CHANNEL0_RATE_GENERATOR);
+			outb(PIT_CHANNEL0_DATA, divisor as u8);
+			outb(PIT_CHANNEL0_DATA, (divisor >> 8) as u8);
+		}
+	});
+
+	PIT_BASE_FREQUENCY / divisor
+}
+
+/// Current tick frequency, in Hz
+pub fn frequency() -> u32 {
+	let divisor = without_interrupts(|| CLOCK.lock().divisor);
+	PIT_BASE_FREQUENCY / divisor.max(1)
+}
+
+/// Number of timer interrupts since [init_pit]
+pub fn ticks() -> u64 {
+	without_interrupts(|| CLOCK.lock().ticks)
+}
+
+/// Time elapsed since [init_pit], with the precision of a tick
+pub fn uptime() -> Duration {
+	Duration::from_nanos(without_interrupts(|| CLOCK.lock().uptime_ns))
+}
+
+/// Waits for `ms` milliseconds, halting the CPU between ticks
+///
+/// Interrupts must be enabled, or the CPU is never woken up
+pub fn sleep_ms(ms: u64) {
+	let deadline = uptime() + Duration::from_millis(ms);
+
+	while uptime() < deadline {
+		unsafe { core::arch::asm!("hlt") };
+	}
+}
+
+/// Waits for `ms` milliseconds by polling the PIT counter
+///
+/// Unlike [sleep_ms], it doesn't need interrupts (e.g. to calibrate another timer)
+pub fn busy_sleep_ms(ms: u64) {
+	let divisor = without_interrupts(|| CLOCK.lock().divisor);
+	assert!(divisor != 0, "the PIT isn't initialized");
+
+	let target = ms * PIT_BASE_FREQUENCY as u64 / 1000;
+	let mut elapsed = 0;
+	let mut previous = read_count();
+
+	while elapsed < target {
+		let count = read_count();
+
+		// the counter goes down to 1, then restarts from the divisor
+		elapsed +=
+			if count <= previous { previous - count } else { previous + divisor - count } as u64;
+
+		previous = count;
+		core::hint::spin_loop();
+	}
+}
+
+/// Reads the current value of the channel 0 counter
+fn read_count() -> u32 {
+	without_interrupts(|| unsafe {
+		outb(PIT_COMMAND, CHANNEL0_LATCH);
+		let low = inb(PIT_CHANNEL0_DATA) as u32;
+		let high = inb(PIT_CHANNEL0_DATA) as u32;
+		(high << 8) | low
+	})
+}
+
+/// Advances the clock, then runs the expired software timers
+fn timer_interrupt_handler(_frame: &mut TrapFrame) {
+	let now = {
+		let mut clock = CLOCK.lock();
+		clock.ticks += 1;
+		clock.uptime_ns += clock.tick_ns();
+		clock.uptime_ns
+	};
+
+	timer::run_expired_timers(now);
+}
@@ -0,0 +1,128 @@
+//! Software timers, run by the PIT interrupt handler
+//!
+//! A timer calls its callback once its deadline is reached, then is removed ([add_oneshot_timer])
+//! or rearmed ([add_periodic_timer]). Callbacks run in the interrupt handler, with interrupts
+//! disabled: they must be short, and must not sleep.
+
+use core::time::Duration;
+
+use spin::Mutex;
+
+use crate::idt::interrupts::without_interrupts;
+use crate::pit::uptime;
+
+/// Maximum number of pending timers
+pub const MAX_TIMERS: usize = 32;
+
+/// Identifies a timer, to cancel it (cf. [cancel_timer])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerId(u32);
+
+#[derive(Clone, Copy)]
+struct Timer {
+	id: TimerId,
+
+	/// Uptime at which the callback runs, in nanoseconds
+	deadline_ns: u64,
+
+	/// None for one-shot timers
+	period_ns: Option<u64>,
+
+	callback: fn(),
+}
+
+struct Timers {
+	slots: [Option<Timer>; MAX_TIMERS],
+	next_id: u32,
+}
+
+/// Always locked with interrupts disabled, as the interrupt handler locks it too
+static TIMERS: Mutex<Timers> = Mutex::new(Timers {
+	slots: [None; MAX_TIMERS],
+	next_id: 0,
+});
+
+/// Calls `callback` once, after `delay`
+///
+/// Returns None if there are already [MAX_TIMERS] timers
+pub fn add_oneshot_timer(delay: Duration, callback: fn()) -> Option<TimerId> {
+	add_timer(delay, None, callback)
+}
+
+/// Calls `callback` every `period`, until the timer is cancelled
+///
+/// Returns None if there are already [MAX_TIMERS] timers
+pub fn add_periodic_timer(period: Duration, callback: fn()) -> Option<TimerId> {
+	assert!(!period.is_zero(), "a periodic timer needs a period");
+
+	add_timer(period, Some(period.as_nanos() as u64), callback)
+}
+
+/// Removes a timer, so its callback isn't called anymore
+///
+/// Returns false if it didn't exist (e.g. a one-shot timer that already ran)
+pub fn cancel_timer(id: TimerId) -> bool {
+	without_interrupts(|| {
+		let mut timers = TIMERS.lock();
+		let slot = timers.slots.iter_mut().find(|slot| slot.is_some_and(|timer| timer.id == id));
+
+		slot.map(|slot| *slot = None).is_some()
+	})
+}
+
+fn add_timer(delay: Duration, period_ns: Option<u64>, callback: fn()) -> Option<TimerId> {
+	let deadline_ns = (uptime() + delay).as_nanos() as u64;
+
+	without_interrupts(|| {
+		let mut timers = TIMERS.lock();
+
+		let id = TimerId(timers.next_id);
+		let slot = timers.slots.iter_mut().find(|slot| slot.is_none())?;
+		*slot = Some(Timer {
+			id,
+			deadline_ns,
+			period_ns,
+			callback,
+		});
+
+		timers.next_id = timers.next_id.wrapping_add(1);
+		Some(id)
+	})
+}
+
+/// Calls the callbacks of the timers whose deadline is before `now_ns`, then removes or rearms
+/// them (called by the PIT interrupt handler)
+pub(super) fn run_expired_timers(now_ns: u64) {
+	// copied, so callbacks can add or cancel timers
+	let mut expired = [None; MAX_TIMERS];
+
+	{
+		let mut timers = TIMERS.lock();
+
+		for (slot, callback) in timers.slots.iter_mut().zip(&mut expired) {
+			let Some(timer) = slot else {
+				continue;
+			};
+			if timer.deadline_ns > now_ns {
+				continue;
+			}
+
+			*callback = Some(timer.callback);
+
+			match timer.period_ns {
+				// skip the missed periods, rather than running the callback several times in a row
+				Some(period_ns) => {
+					timer.deadline_ns += period_ns;
+					if timer.deadline_ns <= now_ns {
+						timer.deadline_ns = now_ns + period_ns;
+					}
+				}
+				None => *slot = None,
+			}
+		}
+	}
+
+	for callback in expired.into_iter().flatten() {
+		callback();
+	}
+}
@@ -0,0 +1,395 @@
+//! User processes: their ids, parents and exit statuses
+//!
+//! Each user program runs as a process, in a task of its own (cf. [crate::user]). A process
+//! started by the kernel ([crate::user::run_user_program]) has no parent: the kernel code that
+//! started it gets its exit code. A process created by `fork` is a child of the forking one.
+//!
+//! When a process exits, its address space is freed at once, but a child stays in the table as
+//! a zombie, with its exit code, until its parent reaps it with [wait_child]. The children of an
+//! exiting process are orphans: they become children of init ([INIT_PID], cf. [run_init]), or
+//! are reaped when they exit if init doesn't run.
+//!
+//! Processes also send and receive signals (cf. [signal]).
+//!
+//! The process lock is never taken with interrupts disabled.
+
+pub mod signal;
+
+use alloc::collections::BTreeMap;
+use core::fmt;
+
+use spin::Mutex;
+
+use self::signal::{
+	SIGCHLD,
+	Signal,
+	SignalState,
+};
+use crate::elf::{
+	Elf,
+	load,
+};
+use crate::paging::{
+	boot_module,
+	kernel_parameter,
+};
+use crate::task::{
+	TaskId,
+	block_current,
+	current_task_id,
+	wake,
+};
+use crate::user::run_process;
+
+/// Id of the init process, that adopts the orphans
+pub const INIT_PID: Pid = Pid(1);
+
+/// The boot module run as init when the kernel command line has no `init=` (cf.
+/// [kernel_parameter])
+pub const DEFAULT_INIT: &str = "init";
+
+static PROCESSES: Mutex<ProcessTable> = Mutex::new(ProcessTable::new());
+
+/// Identifies a process (ids aren't reused)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pid(pub u32);
+
+/// What a process is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+	/// Its program runs (or its task is about to run it)
+	Running,
+
+	/// A signal stopped it, until SIGCONT (cf. [signal])
+	Stopped,
+
+	/// Its program ended, and its parent didn't reap it yet
+	Zombie(ExitStatus),
+}
+
+/// How a process ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+	/// It exited with the code
+	Exited(i32),
+
+	/// The default action of a signal terminated it
+	Signaled {
+		/// The signal
+		signal: Signal,
+
+		/// The default action of the signal dumps a core (cf.
+		/// [DefaultAction::Core](signal::DefaultAction::Core))
+		core_dumped: bool,
+	},
+}
+
+impl ExitStatus {
+	/// The status word stored by `waitpid`: the low byte of the exit code in the second byte, or
+	/// the signal (with 0x80 for a core dump)
+	pub fn wait_status(&self) -> u32 {
+		match *self {
+			Self::Exited(code) => ((code as u32) & 0xff) << 8,
+			Self::Signaled {
+				signal,
+				core_dumped,
+			} => signal | if core_dumped { 0x80 } else { 0 },
+		}
+	}
+}
+
+/// Errors returned by [wait_child]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitError {
+	/// The running task isn't a process
+	NotAProcess,
+
+	/// The process has no such child
+	NoChild,
+
+	/// A signal arrived while it was waiting
+	Interrupted,
+}
+
+impl fmt::Display for WaitError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::NotAProcess => write!(f, "the running task isn't a process"),
+			Self::NoChild => write!(f, "no such child process"),
+			Self::Interrupted => write!(f, "interrupted by a signal"),
+		}
+	}
+}
+
+/// A user process
+struct Process {
+	/// None for the processes started by the kernel, and for the orphans while init doesn't run
+	parent: Option<Pid>,
+
+	/// None until the task of a forked child starts (cf. [attach_current_task])
+	task: Option<TaskId>,
+
+	state: ProcessState,
+
+	signals: SignalState,
+
+	/// The signal whose default action terminates it, and whether it dumps a core
+	killed_by: Option<(Signal, bool)>,
+}
+
+/// What [processes] reports about a process
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessInfo {
+	/// The process
+	pub pid: Pid,
+
+	/// Its parent, if it has one
+	pub parent: Option<Pid>,
+
+	/// The task running its program
+	pub task: Option<TaskId>,
+
+	/// Its state
+	pub state: ProcessState,
+}
+
+struct ProcessTable {
+	processes: BTreeMap<Pid, Process>,
+
+	/// [INIT_PID] is only given by [run_init]
+	next_pid: u32,
+}
+
+impl ProcessTable {
+	const fn new() -> Self {
+		Self {
+			processes: BTreeMap::new(),
+			next_pid: INIT_PID.0 + 1,
+		}
+	}
+
+	/// The process whose program runs in the running task
+	fn current(&self) -> Option<Pid> {
+		let task = current_task_id();
+
+		self.processes
+			.iter()
+			.find(|(_, process)| {
+				process.task == Some(task) && !matches!(process.state, ProcessState::Zombie(_))
+			})
+			.map(|(&pid, _)| pid)
+	}
+
+	fn insert(
+		&mut self,
+		pid: Pid,
+		parent: Option<Pid>,
+		task: Option<TaskId>,
+		signals: SignalState,
+	) {
+		self.processes.insert(
+			pid,
+			Process {
+				parent,
+				task,
+				state: ProcessState::Running,
+				signals,
+				killed_by: None,
+			},
+		);
+	}
+
+	fn next_pid(&mut self) -> Pid {
+		let pid = Pid(self.next_pid);
+		self.next_pid += 1;
+		pid
+	}
+
+	/// Wakes the task of `pid`, that may be waiting for a child (cf. [wait_child])
+	fn wake(&self, pid: Pid) {
+		if let Some(task) = self.processes.get(&pid).and_then(|process| process.task) {
+			wake(task);
+		}
+	}
+}
+
+/// Adds a process without parent, run by the running task, and returns its id
+pub(crate) fn start_process() -> Pid {
+	let mut table = PROCESSES.lock();
+
+	let pid = table.next_pid();
+	table.insert(pid, None, Some(current_task_id()), SignalState::new());
+	pid
+}
+
+/// Adds a child of the running process, whose task starts later (cf. [attach_current_task])
+///
+/// It gets the signal actions and mask of its parent, without the pending signals. Returns None
+/// if the running task isn't a process
+pub(crate) fn add_child() -> Option<Pid> {
+	let mut table = PROCESSES.lock();
+
+	let parent = table.current()?;
+	let signals = table.processes.get(&parent)?.signals.fork();
+	let pid = table.next_pid();
+	table.insert(pid, Some(parent), None, signals);
+	Some(pid)
+}
+
+/// Removes a child added by [add_child] whose task couldn't be created
+pub(crate) fn remove_child(pid: Pid) {
+	PROCESSES.lock().processes.remove(&pid);
+}
+
+/// Makes the running task the one of `pid` (a child added by [add_child])
+pub(crate) fn attach_current_task(pid: Pid) {
+	if let Some(process) = PROCESSES.lock().processes.get_mut(&pid) {
+		process.task = Some(current_task_id());
+	}
+}
+
+/// Id of the running process, if the running task is one
+pub fn current_pid() -> Option<Pid> {
+	PROCESSES.lock().current()
+}
+
+/// Id of the parent of the running process, if it has one
+pub fn current_parent_pid() -> Option<Pid> {
+	let table = PROCESSES.lock();
+	table.processes.get(&table.current()?)?.parent
+}
+
+/// Ends the process `pid`, whose address space was freed, with `exit_code` (unless a signal
+/// killed it)
+///
+/// It becomes a zombie until its parent reaps it (the parent gets SIGCHLD), or is removed if it
+/// has no parent. Its children become children of init.
+pub(crate) fn exit_process(pid: Pid, exit_code: i32) {
+	let mut table = PROCESSES.lock();
+
+	let has_init = pid != INIT_PID && table.processes.contains_key(&INIT_PID);
+	let adoptive_parent = has_init.then_some(INIT_PID);
+
+	let mut orphan_zombies = false;
+	table.processes.retain(|_, child| {
+		if child.parent != Some(pid) {
+			return true;
+		}
+
+		child.parent = adoptive_parent;
+		let is_zombie = matches!(child.state, ProcessState::Zombie(_));
+		orphan_zombies |= is_zombie;
+
+		// nobody can reap it anymore
+		has_init || !is_zombie
+	});
+
+	if orphan_zombies && has_init {
+		table.wake(INIT_PID);
+	}
+
+	let parent = table.processes.get(&pid).and_then(|process| process.parent);
+	match parent {
+		Some(parent) if table.processes.contains_key(&parent) => {
+			if let Some(process) = table.processes.get_mut(&pid) {
+				let status = match process.killed_by {
+					Some((signal, core_dumped)) => ExitStatus::Signaled {
+						signal,
+						core_dumped,
+					},
+					None => ExitStatus::Exited(exit_code),
+				};
+				process.state = ProcessState::Zombie(status);
+			}
+			table.post_signal(parent, SIGCHLD);
+			table.wake(parent);
+		}
+		_ => {
+			table.processes.remove(&pid);
+		}
+	}
+}
+
+/// Waits until a child of the running process (`pid`, or any child if None) is a zombie, then
+/// reaps it, and returns its id and exit status
+///
+/// With `no_hang`, returns None at once instead of waiting. A signal to deliver to the running
+/// process ends the wait.
+pub fn wait_child(pid: Option<Pid>, no_hang: bool) -> Result<Option<(Pid, ExitStatus)>, WaitError> {
+	loop {
+		{
+			let mut table = PROCESSES.lock();
+			let parent = table.current().ok_or(WaitError::NotAProcess)?;
+
+			let mut children = table.processes.iter().filter(|&(&child, process)| {
+				process.parent == Some(parent) && pid.is_none_or(|pid| pid == child)
+			});
+
+			if children.clone().next().is_none() {
+				return Err(WaitError::NoChild);
+			}
+			let zombie = children.find_map(|(&child, process)| match process.state {
+				ProcessState::Zombie(status) => Some((child, status)),
+				ProcessState::Running | ProcessState::Stopped => None,
+			});
+
+			if let Some((child, status)) = zombie {
+				table.processes.remove(&child);
+				return Ok(Some((child, status)));
+			}
+			if no_hang {
+				return Ok(None);
+			}
+
+			let interrupted = table
+				.processes
+				.get(&parent)
+				.is_some_and(|process| process.signals.has_deliverable());
+			if interrupted {
+				return Err(WaitError::Interrupted);
+			}
+		}
+
+		// a child exiting meanwhile makes it return at once
+		block_current();
+	}
+}
+
+/// Calls `f` with the info of every process, by id
+pub fn processes(mut f: impl FnMut(&ProcessInfo)) {
+	let table = PROCESSES.lock();
+
+	for (&pid, process) in &table.processes {
+		f(&ProcessInfo {
+			pid,
+			parent: process.parent,
+			task: process.task,
+			state: process.state,
+		});
+	}
+}
+
+/// Runs the init program in the running task, as [INIT_PID]: the boot module named by the `init=`
+/// kernel parameter, or [DEFAULT_INIT]
+///
+/// Init adopts the orphans, so it must never exit: the kernel panics if it does
+pub fn run_init() -> ! {
+	let name = kernel_parameter("init");
+	let name = name.as_deref().unwrap_or(DEFAULT_INIT);
+
+	let Some(image) = boot_module(name) else {
+		panic!("init: no boot module named {name}");
+	};
+	let program = Elf::parse(image)
+		.and_then(|elf| load(&elf, &[name], &[]))
+		.unwrap_or_else(|error| panic!("init: {name}: {error}"));
+
+	{
+		let mut table = PROCESSES.lock();
+		assert!(!table.processes.contains_key(&INIT_PID), "init already runs");
+		table.insert(INIT_PID, None, Some(current_task_id()), SignalState::new());
+	}
+
+	let exit_code = run_process(INIT_PID, program);
+	panic!("init exited with code {exit_code}");
+}
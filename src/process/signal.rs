@@ -0,0 +1,687 @@
+//! POSIX-style signals
+//!
+//! Each process has a set of pending signals, a mask of blocked ones, and an action per signal
+//! (cf. [SigAction]). [send_signal] makes a signal pending. A pending signal that isn't blocked
+//! is delivered when the process goes back to user mode (cf. [deliver_signals], called at the
+//! end of every interrupt from user mode).
+//!
+//! The default action of a signal (cf. [default_action]) ignores it, stops or continues the
+//! process, or terminates it. A user handler runs on the user stack of the program: the
+//! interrupted context is saved in a [SignalFrame] below the stack pointer, and the handler
+//! returns to a trampoline that calls `sigreturn`, which restores it.
+//!
+//! The exceptions of user programs (e.g. a page fault outside of their memory areas) are
+//! signals too (cf. [exception_signal]), that can't be ignored or blocked (cf. [force_signal]).
+//!
+//! The numbers, masks and structures are the Linux i386 ones.
+
+use core::fmt;
+use core::mem::{
+	offset_of,
+	size_of,
+};
+
+use super::{
+	PROCESSES,
+	Pid,
+	ProcessState,
+	ProcessTable,
+};
+use crate::idt::interrupts::enable_hardware_interrupts;
+use crate::idt::trap::TrapFrame;
+use crate::paging::address_space::prepare_user_write;
+use crate::println;
+use crate::task::block_current;
+use crate::user::syscall::SYS_SIGRETURN;
+use crate::user::{
+	EXIT_CODE_KILLED,
+	UserContext,
+	return_to_kernel,
+};
+
+/// A signal number, from 1 to [SIGNAL_COUNT] - 1
+pub type Signal = u32;
+
+/// Hangup
+pub const SIGHUP: Signal = 1;
+/// Interrupt from the keyboard
+pub const SIGINT: Signal = 2;
+/// Quit from the keyboard
+pub const SIGQUIT: Signal = 3;
+/// Illegal instruction
+pub const SIGILL: Signal = 4;
+/// Breakpoint or single step
+pub const SIGTRAP: Signal = 5;
+/// Abort
+pub const SIGABRT: Signal = 6;
+/// Bus error (e.g. a misaligned access)
+pub const SIGBUS: Signal = 7;
+/// Arithmetic error (e.g. a division by zero)
+pub const SIGFPE: Signal = 8;
+/// Kill (can't be caught, blocked or ignored)
+pub const SIGKILL: Signal = 9;
+/// User-defined signal 1
+pub const SIGUSR1: Signal = 10;
+/// Invalid memory access
+pub const SIGSEGV: Signal = 11;
+/// User-defined signal 2
+pub const SIGUSR2: Signal = 12;
+/// Broken pipe
+pub const SIGPIPE: Signal = 13;
+/// Timer signal
+pub const SIGALRM: Signal = 14;
+/// Termination
+pub const SIGTERM: Signal = 15;
+/// Coprocessor stack fault
+pub const SIGSTKFLT: Signal = 16;
+/// A child stopped or ended
+pub const SIGCHLD: Signal = 17;
+/// Continues a stopped process
+pub const SIGCONT: Signal = 18;
+/// Stops the process (can't be caught, blocked or ignored)
+pub const SIGSTOP: Signal = 19;
+/// Stop from the terminal
+pub const SIGTSTP: Signal = 20;
+/// Terminal input for a background process
+pub const SIGTTIN: Signal = 21;
+/// Terminal output for a background process
+pub const SIGTTOU: Signal = 22;
+/// Urgent condition on a socket
+pub const SIGURG: Signal = 23;
+/// CPU time limit exceeded
+pub const SIGXCPU: Signal = 24;
+/// File size limit exceeded
+pub const SIGXFSZ: Signal = 25;
+/// Virtual alarm clock
+pub const SIGVTALRM: Signal = 26;
+/// Profiling timer expired
+pub const SIGPROF: Signal = 27;
+/// The terminal was resized
+pub const SIGWINCH: Signal = 28;
+/// I/O is possible
+pub const SIGIO: Signal = 29;
+/// Power failure
+pub const SIGPWR: Signal = 30;
+/// Bad system call
+pub const SIGSYS: Signal = 31;
+
+/// Number of signals, counting the invalid 0 (which [send_signal] only uses to check that a
+/// process exists)
+pub const SIGNAL_COUNT: usize = 32;
+
+/// The name of each signal, by number
+pub const SIGNAL_NAMES: [&str; SIGNAL_COUNT] = [
+	"",
+	"SIGHUP",
+	"SIGINT",
+	"SIGQUIT",
+	"SIGILL",
+	"SIGTRAP",
+	"SIGABRT",
+	"SIGBUS",
+	"SIGFPE",
+	"SIGKILL",
+	"SIGUSR1",
+	"SIGSEGV",
+	"SIGUSR2",
+	"SIGPIPE",
+	"SIGALRM",
+	"SIGTERM",
+	"SIGSTKFLT",
+	"SIGCHLD",
+	"SIGCONT",
+	"SIGSTOP",
+	"SIGTSTP",
+	"SIGTTIN",
+	"SIGTTOU",
+	"SIGURG",
+	"SIGXCPU",
+	"SIGXFSZ",
+	"SIGVTALRM",
+	"SIGPROF",
+	"SIGWINCH",
+	"SIGIO",
+	"SIGPWR",
+	"SIGSYS",
+];
+
+/// [SigAction::handler] of the default action (cf. [default_action])
+pub const SIG_DFL: u32 = 0;
+
+/// [SigAction::handler] that discards the signal
+pub const SIG_IGN: u32 = 1;
+
+/// [SigAction::flags]: the handler returns to [SigAction::restorer], instead of the trampoline
+/// of the [SignalFrame]
+pub const SA_RESTORER: u32 = 0x0400_0000;
+
+/// [SigAction::flags]: the signal isn't blocked while its handler runs
+pub const SA_NODEFER: u32 = 0x4000_0000;
+
+/// [SigAction::flags]: the action goes back to [SIG_DFL] once the handler is called
+pub const SA_RESETHAND: u32 = 0x8000_0000;
+
+/// `sigprocmask` operation: blocks the signals of the set
+pub const SIG_BLOCK: u32 = 0;
+
+/// `sigprocmask` operation: unblocks the signals of the set
+pub const SIG_UNBLOCK: u32 = 1;
+
+/// `sigprocmask` operation: blocks exactly the signals of the set
+pub const SIG_SETMASK: u32 = 2;
+
+/// The signals that can't be caught, blocked or ignored
+const UNBLOCKABLE: u32 = signal_bit(SIGKILL) | signal_bit(SIGSTOP);
+
+/// The signals whose default action stops the process
+const STOP_SIGNALS: u32 =
+	signal_bit(SIGSTOP) | signal_bit(SIGTSTP) | signal_bit(SIGTTIN) | signal_bit(SIGTTOU);
+
+/// The flags of the program that `sigreturn` restores: CF, PF, AF, ZF, SF, TF, DF, OF and AC
+const USER_FLAGS: u32 = 0x0004_0dd5;
+
+/// The trap (TF) and direction (DF) flags, cleared for the handlers
+const TRAP_FLAG: u32 = 1 << 8;
+const DIRECTION_FLAG: u32 = 1 << 10;
+
+/// `pop eax` (the signal number), `mov eax, SYS_SIGRETURN`, `int 0x80`
+const SIGRETURN_TRAMPOLINE: [u8; 8] = {
+	let number = (SYS_SIGRETURN as u32).to_le_bytes();
+	[0x58, 0xb8, number[0], number[1], number[2], number[3], 0xcd, 0x80]
+};
+
+/// Errors returned by the signal functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalError {
+	/// There is no such process (or the running task isn't one)
+	NoProcess,
+
+	/// The number isn't a signal, or the signal can't get this action
+	InvalidSignal,
+}
+
+impl fmt::Display for SignalError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::NoProcess => write!(f, "no such process"),
+			Self::InvalidSignal => write!(f, "invalid signal"),
+		}
+	}
+}
+
+/// What a signal does when its action is [SIG_DFL]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultAction {
+	/// Ends the process
+	Terminate,
+
+	/// Ends the process, that dumps a core (only reported in its exit status, cf.
+	/// [ExitStatus](super::ExitStatus))
+	Core,
+
+	/// Discards the signal
+	Ignore,
+
+	/// Stops the process, until it gets [SIGCONT]
+	Stop,
+
+	/// Continues the process if it was stopped
+	Continue,
+}
+
+/// Returns the default action of `signal`
+pub const fn default_action(signal: Signal) -> DefaultAction {
+	match signal {
+		SIGQUIT | SIGILL | SIGTRAP | SIGABRT | SIGBUS | SIGFPE | SIGSEGV | SIGXCPU | SIGXFSZ
+		| SIGSYS => DefaultAction::Core,
+		SIGCHLD | SIGURG | SIGWINCH => DefaultAction::Ignore,
+		SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => DefaultAction::Stop,
+		SIGCONT => DefaultAction::Continue,
+		_ => DefaultAction::Terminate,
+	}
+}
+
+/// The bit of `signal` in the signal masks
+pub const fn signal_bit(signal: Signal) -> u32 {
+	1 << (signal - 1)
+}
+
+/// Returns the signal of the CPU exception `vector`, caused by a user program
+pub const fn exception_signal(vector: u32) -> Signal {
+	match vector {
+		// divide error, x87 and SIMD floating-point exceptions
+		0 | 16 | 19 => SIGFPE,
+		1 | 3 => SIGTRAP,
+		6 => SIGILL,
+		17 => SIGBUS,
+		// e.g. page and general protection faults
+		_ => SIGSEGV,
+	}
+}
+
+/// What a process does with a signal (the layout of the Linux i386 `sigaction` system call)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct SigAction {
+	/// [SIG_DFL], [SIG_IGN], or the address of the handler, called with the signal number
+	pub handler: u32,
+
+	/// The signals blocked while the handler runs (with the signal itself, cf. [SA_NODEFER])
+	pub mask: u32,
+
+	/// `SA_*` flags
+	pub flags: u32,
+
+	/// Where the handler returns, with [SA_RESTORER]
+	pub restorer: u32,
+}
+
+impl SigAction {
+	/// The default action (cf. [default_action])
+	pub const DEFAULT: Self = Self {
+		handler: SIG_DFL,
+		mask: 0,
+		flags: 0,
+		restorer: 0,
+	};
+}
+
+/// The signals of a process
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SignalState {
+	/// Masks of [signal_bit]s
+	pending: u32,
+	blocked: u32,
+
+	/// By signal number
+	actions: [SigAction; SIGNAL_COUNT],
+}
+
+impl SignalState {
+	/// No signal pending or blocked, and the default actions
+	pub(crate) const fn new() -> Self {
+		Self {
+			pending: 0,
+			blocked: 0,
+			actions: [SigAction::DEFAULT; SIGNAL_COUNT],
+		}
+	}
+
+	/// The signals of a child created by `fork`: the same actions and mask, without the pending
+	/// signals
+	pub(crate) fn fork(&self) -> Self {
+		Self {
+			pending: 0,
+			..*self
+		}
+	}
+
+	/// Returns true if `signal` is discarded when it arrives
+	fn is_ignored(&self, signal: Signal) -> bool {
+		match self.actions[signal as usize].handler {
+			SIG_IGN => true,
+			SIG_DFL => default_action(signal) == DefaultAction::Ignore,
+			_ => false,
+		}
+	}
+
+	/// The lowest pending signal that isn't blocked
+	fn next_deliverable(&self) -> Option<Signal> {
+		let deliverable = self.pending & !self.blocked;
+		(deliverable != 0).then(|| deliverable.trailing_zeros() + 1)
+	}
+
+	/// Returns true if a signal will be delivered when the process goes back to user mode
+	pub(crate) fn has_deliverable(&self) -> bool {
+		self.next_deliverable().is_some()
+	}
+}
+
+/// Saved on the user stack when a handler is called: the handler starts with its stack pointer
+/// at [SignalFrame::return_address]
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct SignalFrame {
+	/// Where the handler returns: [SigAction::restorer], or [SignalFrame::trampoline]
+	return_address: u32,
+
+	/// The argument of the handler
+	signal: Signal,
+
+	/// The interrupted program
+	context: UserContext,
+
+	/// The mask of blocked signals before the handler
+	blocked: u32,
+
+	/// Calls `sigreturn` (cf. [SIGRETURN_TRAMPOLINE]): the user stack is executable
+	trampoline: [u8; 8],
+}
+
+impl SignalFrame {
+	/// How far below the stack pointer `sigreturn` finds the frame, once the handler returned and
+	/// the trampoline popped the signal number
+	pub(crate) const SIGRETURN_OFFSET: u32 = offset_of!(SignalFrame, context) as u32;
+}
+
+impl ProcessTable {
+	/// Makes `signal` pending for `pid`, unless it is ignored, and wakes it
+	///
+	/// [SIGCONT] and [SIGKILL] continue a stopped process, and [SIGCONT] discards the pending
+	/// stop signals (and the other way around). Returns false if there is no such process.
+	pub(super) fn post_signal(&mut self, pid: Pid, signal: Signal) -> bool {
+		let Some(process) = self.processes.get_mut(&pid) else {
+			return false;
+		};
+		if matches!(process.state, ProcessState::Zombie(_)) {
+			return true;
+		}
+
+		let signals = &mut process.signals;
+		match signal {
+			SIGCONT | SIGKILL => {
+				if signal == SIGCONT {
+					signals.pending &= !STOP_SIGNALS;
+				}
+				if process.state == ProcessState::Stopped {
+					process.state = ProcessState::Running;
+				}
+			}
+			signal if signal_bit(signal) & STOP_SIGNALS != 0 => {
+				signals.pending &= !signal_bit(SIGCONT);
+			}
+			_ => {}
+		}
+
+		if !signals.is_ignored(signal) {
+			signals.pending |= signal_bit(signal);
+		}
+
+		// e.g. waiting for a child, or stopped
+		self.wake(pid);
+		true
+	}
+}
+
+/// Sends `signal` to the process `pid`
+///
+/// The signal 0 only checks that the process exists
+pub fn send_signal(pid: Pid, signal: Signal) -> Result<(), SignalError> {
+	if signal as usize >= SIGNAL_COUNT {
+		return Err(SignalError::InvalidSignal);
+	}
+
+	let mut table = PROCESSES.lock();
+	if signal == 0 {
+		return table.processes.contains_key(&pid).then_some(()).ok_or(SignalError::NoProcess);
+	}
+
+	table.post_signal(pid, signal).then_some(()).ok_or(SignalError::NoProcess)
+}
+
+/// Sends `signal` to the running process, for an exception it caused: the signal is unblocked,
+/// and gets its default action back if it was ignored
+pub(crate) fn force_signal(signal: Signal) {
+	// the process lock is never taken with interrupts disabled, and the program had them enabled
+	unsafe { enable_hardware_interrupts() };
+
+	let mut table = PROCESSES.lock();
+	let Some(process) = table.current().and_then(|pid| table.processes.get_mut(&pid)) else {
+		return;
+	};
+
+	let signals = &mut process.signals;
+	signals.blocked &= !signal_bit(signal);
+	if signals.actions[signal as usize].handler == SIG_IGN {
+		signals.actions[signal as usize] = SigAction::DEFAULT;
+	}
+	signals.pending |= signal_bit(signal);
+}
+
+/// Sets the action of `signal` for the running process, if `action` isn't None, and returns the
+/// previous one
+pub fn set_signal_action(
+	signal: Signal,
+	action: Option<SigAction>,
+) -> Result<SigAction, SignalError> {
+	if signal == 0 || signal as usize >= SIGNAL_COUNT {
+		return Err(SignalError::InvalidSignal);
+	}
+	if action.is_some() && signal_bit(signal) & UNBLOCKABLE != 0 {
+		return Err(SignalError::InvalidSignal);
+	}
+
+	let mut table = PROCESSES.lock();
+	let pid = table.current().ok_or(SignalError::NoProcess)?;
+	let signals = &mut table.processes.get_mut(&pid).ok_or(SignalError::NoProcess)?.signals;
+
+	let previous = signals.actions[signal as usize];
+	if let Some(action) = action {
+		signals.actions[signal as usize] = action;
+
+		// discards the pending signal, as POSIX asks
+		if signals.is_ignored(signal) {
+			signals.pending &= !signal_bit(signal);
+		}
+	}
+	Ok(previous)
+}
+
+/// How `sigprocmask` changes the blocked signals
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaskChange {
+	/// Blocks the signals of the mask ([SIG_BLOCK])
+	Block(u32),
+
+	/// Unblocks the signals of the mask ([SIG_UNBLOCK])
+	Unblock(u32),
+
+	/// Blocks exactly the signals of the mask ([SIG_SETMASK])
+	Set(u32),
+}
+
+/// Changes the blocked signals of the running process, if `change` isn't None, and returns the
+/// previous mask
+///
+/// [SIGKILL] and [SIGSTOP] are never blocked
+pub fn change_blocked_signals(change: Option<MaskChange>) -> Result<u32, SignalError> {
+	let mut table = PROCESSES.lock();
+	let pid = table.current().ok_or(SignalError::NoProcess)?;
+	let signals = &mut table.processes.get_mut(&pid).ok_or(SignalError::NoProcess)?.signals;
+
+	let previous = signals.blocked;
+	let blocked = match change {
+		None => previous,
+		Some(MaskChange::Block(mask)) => previous | mask,
+		Some(MaskChange::Unblock(mask)) => previous & !mask,
+		Some(MaskChange::Set(mask)) => mask,
+	};
+	signals.blocked = blocked & !UNBLOCKABLE;
+
+	Ok(previous)
+}
+
+/// What [deliver_signals] does with the next signal, once the process lock is released
+enum Delivery {
+	/// Calls the handler, then restores the mask
+	Handler(Signal, SigAction, u32),
+
+	/// Waits until the process is continued
+	Stop(Pid),
+
+	/// Ends the program, that dumps a core or not
+	Kill(Pid, Signal, bool),
+}
+
+/// Delivers the pending signals of the running process that aren't blocked, before the program
+/// interrupted by `frame` resumes
+///
+/// A handler gets a [SignalFrame] on the user stack, and `frame` resumes into it. A signal that
+/// terminates the process never returns (cf. [return_to_kernel]).
+pub fn deliver_signals(frame: &mut TrapFrame) {
+	// cf. force_signal
+	unsafe { enable_hardware_interrupts() };
+
+	loop {
+		let delivery = {
+			let mut table = PROCESSES.lock();
+			let Some(pid) = table.current() else {
+				return;
+			};
+			let Some(process) = table.processes.get_mut(&pid) else {
+				return;
+			};
+
+			let Some(signal) = process.signals.next_deliverable() else {
+				return;
+			};
+			process.signals.pending &= !signal_bit(signal);
+
+			let action = process.signals.actions[signal as usize];
+			match (action.handler, default_action(signal)) {
+				(SIG_IGN, _) => continue,
+				(SIG_DFL, DefaultAction::Ignore | DefaultAction::Continue) => continue,
+				(SIG_DFL, DefaultAction::Stop) => {
+					process.state = ProcessState::Stopped;
+					Delivery::Stop(pid)
+				}
+				(SIG_DFL, default) => {
+					let core_dumped = default == DefaultAction::Core;
+					process.killed_by = Some((signal, core_dumped));
+					Delivery::Kill(pid, signal, core_dumped)
+				}
+				_ => {
+					let signals = &mut process.signals;
+					let blocked = signals.blocked;
+
+					let mut mask = action.mask;
+					if action.flags & SA_NODEFER == 0 {
+						mask |= signal_bit(signal);
+					}
+					signals.blocked = (blocked | mask) & !UNBLOCKABLE;
+
+					if action.flags & SA_RESETHAND != 0 {
+						signals.actions[signal as usize] = SigAction::DEFAULT;
+					}
+					Delivery::Handler(signal, action, blocked)
+				}
+			}
+		};
+
+		match delivery {
+			Delivery::Handler(signal, action, blocked) => {
+				if push_signal_frame(frame, signal, &action, blocked) {
+					// the next signals are delivered when the handler returns (cf. sigreturn)
+					return;
+				}
+
+				// the stack can't hold the frame
+				restore_blocked_signals(blocked);
+				if signal == SIGSEGV {
+					let _ = set_signal_action(SIGSEGV, Some(SigAction::DEFAULT));
+				}
+				force_signal(SIGSEGV);
+			}
+			Delivery::Stop(pid) => {
+				// SIGCONT or SIGKILL continue it (cf. ProcessTable::post_signal)
+				while is_stopped(pid) {
+					block_current();
+				}
+			}
+			Delivery::Kill(pid, signal, core_dumped) => {
+				if core_dumped {
+					println!(
+						"process {}: {} at {:#010x} (core dumped)",
+						pid.0, SIGNAL_NAMES[signal as usize], frame.eip
+					);
+				}
+
+				// back to the kernel code that started the program (cf. exit_process)
+				return_to_kernel(EXIT_CODE_KILLED);
+			}
+		}
+	}
+}
+
+/// Saves the program interrupted by `frame` in a [SignalFrame] below its stack pointer, and makes
+/// `frame` resume into the handler of `action`
+///
+/// Returns false if the frame can't be written there
+fn push_signal_frame(
+	frame: &mut TrapFrame,
+	signal: Signal,
+	action: &SigAction,
+	blocked: u32,
+) -> bool {
+	let size = size_of::<SignalFrame>() as u32;
+
+	// the stack is 16-byte aligned before the return address is pushed, as for any call
+	let Some(address) =
+		frame.user_esp.checked_sub(size).map(|top| ((top + 4) & !0xf).wrapping_sub(4))
+	else {
+		return false;
+	};
+	if !prepare_user_write(address, size, frame.user_esp) {
+		return false;
+	}
+
+	let return_address = if action.flags & SA_RESTORER != 0 {
+		action.restorer
+	} else {
+		address + offset_of!(SignalFrame, trampoline) as u32
+	};
+
+	let signal_frame = SignalFrame {
+		return_address,
+		signal,
+		context: UserContext::from_frame(frame),
+		blocked,
+		trampoline: SIGRETURN_TRAMPOLINE,
+	};
+	// Safety: the pages are mapped and writable (cf. prepare_user_write)
+	unsafe { (address as *mut SignalFrame).write_unaligned(signal_frame) };
+
+	frame.eip = action.handler;
+	frame.user_esp = address;
+	frame.registers.eax = signal;
+	frame.registers.ecx = 0;
+	frame.registers.edx = 0;
+	frame.eflags &= !(TRAP_FLAG | DIRECTION_FLAG);
+	true
+}
+
+/// Makes `frame` resume the program saved in `saved` by [push_signal_frame], and restores the
+/// blocked signals (the `sigreturn` system call)
+///
+/// Returns the saved eax, that the system call returns
+pub(crate) fn restore_signal_frame(frame: &mut TrapFrame, saved: &SignalFrame) -> u32 {
+	let context = &saved.context;
+
+	frame.registers.ebx = context.ebx;
+	frame.registers.ecx = context.ecx;
+	frame.registers.edx = context.edx;
+	frame.registers.esi = context.esi;
+	frame.registers.edi = context.edi;
+	frame.registers.ebp = context.ebp;
+	frame.eip = context.eip;
+	frame.user_esp = context.esp;
+
+	// the handler can't change the privileged flags (e.g. IOPL)
+	frame.eflags = (frame.eflags & !USER_FLAGS) | (context.eflags & USER_FLAGS);
+
+	restore_blocked_signals(saved.blocked);
+	context.eax
+}
+
+/// Sets the blocked signals of the running process back to `blocked`
+fn restore_blocked_signals(blocked: u32) {
+	let _ = change_blocked_signals(Some(MaskChange::Set(blocked)));
+}
+
+fn is_stopped(pid: Pid) -> bool {
+	PROCESSES
+		.lock()
+		.processes
+		.get(&pid)
+		.is_some_and(|process| process.state == ProcessState::Stopped)
+}
@@ -0,0 +1,305 @@
+//! CMOS real-time clock
+//!
+//! The RTC keeps the date and time while the machine is off. Its registers are read through the
+//! CMOS ports, in BCD or binary and in 12 or 24-hour format depending on status register B, and
+//! can't be trusted while the chip updates them (once per second).
+//!
+//! It can also raise periodic interrupts on IRQ8, the first line of the slave PIC
+//! (cf. [enable_periodic_interrupt]).
+
+use core::fmt;
+
+use spin::Mutex;
+
+use crate::idt::interrupts::without_interrupts;
+use crate::idt::trap::TrapFrame;
+use crate::irq::{
+	IrqError,
+	free_irq,
+	request_irq,
+};
+use crate::pic::Irq;
+use crate::shared::{
+	inb,
+	outb,
+};
+
+const CMOS_INDEX: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+const REGISTER_SECONDS: u8 = 0x00;
+const REGISTER_MINUTES: u8 = 0x02;
+const REGISTER_HOURS: u8 = 0x04;
+const REGISTER_DAY: u8 = 0x07;
+const REGISTER_MONTH: u8 = 0x08;
+const REGISTER_YEAR: u8 = 0x09;
+const REGISTER_STATUS_A: u8 = 0x0a;
+const REGISTER_STATUS_B: u8 = 0x0b;
+const REGISTER_STATUS_C: u8 = 0x0c;
+
+/// Not standard, but where QEMU and most BIOSes keep it (the ACPI FADT gives the real one)
+const REGISTER_CENTURY: u8 = 0x32;
+
+/// Status A: the RTC is updating its registers
+const UPDATE_IN_PROGRESS: u8 = 1 << 7;
+
+/// Status B: periodic interrupt enable
+const PERIODIC_INTERRUPT: u8 = 1 << 6;
+/// Status B: registers are in binary (BCD otherwise)
+const BINARY_MODE: u8 = 1 << 2;
+/// Status B: hours are in 24-hour format (12-hour otherwise, with bit 7 set for PM)
+const HOUR_24_MODE: u8 = 1 << 1;
+
+/// Hours register bit set for PM hours in 12-hour format
+const HOUR_PM: u8 = 1 << 7;
+
+/// Number of periodic interrupts since [enable_periodic_interrupt]
+static PERIODIC_TICKS: Mutex<u64> = Mutex::new(0);
+
+/// A date and time, as kept by the RTC (usually UTC)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+	/// Full year (e.g. 2026)
+	pub year: u16,
+	/// 1 to 12
+	pub month: u8,
+	/// 1 to 31
+	pub day: u8,
+	/// 0 to 23
+	pub hour: u8,
+	/// 0 to 59
+	pub minute: u8,
+	/// 0 to 59
+	pub second: u8,
+}
+
+impl DateTime {
+	/// Number of seconds since the Unix epoch (1970-01-01 00:00:00), negative before it
+	pub fn to_unix_timestamp(&self) -> i64 {
+		let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+		let seconds = self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+
+		days * 86400 + seconds
+	}
+}
+
+impl fmt::Display for DateTime {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+			self.year, self.month, self.day, self.hour, self.minute, self.second
+		)
+	}
+}
+
+/// Number of days between 1970-01-01 and a date of the proleptic Gregorian calendar
+///
+/// cf. Howard Hinnant's `days_from_civil` (years start in March, so the leap day is the last one)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year.div_euclid(400);
+	let year_of_era = year - era * 400;
+	let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+	era * 146097 + day_of_era - 719468
+}
+
+/// Reads the current date and time from the RTC
+pub fn now() -> DateTime {
+	// the registers may change between two reads, so we read until we get the same values twice
+	let mut registers = read_registers();
+	loop {
+		let again = read_registers();
+		if again == registers {
+			break;
+		}
+		registers = again;
+	}
+
+	let status_b = read_cmos(REGISTER_STATUS_B);
+	decode(registers, status_b)
+}
+
+/// Raw values of the seconds, minutes, hours, day, month, year and century registers
+type RawRegisters = [u8; 7];
+
+fn read_registers() -> RawRegisters {
+	while read_cmos(REGISTER_STATUS_A) & UPDATE_IN_PROGRESS != 0 {
+		core::hint::spin_loop();
+	}
+
+	[
+		REGISTER_SECONDS,
+		REGISTER_MINUTES,
+		REGISTER_HOURS,
+		REGISTER_DAY,
+		REGISTER_MONTH,
+		REGISTER_YEAR,
+		REGISTER_CENTURY,
+	]
+	.map(read_cmos)
+}
+
+/// Converts the registers to a [DateTime], according to the formats of status register B
+fn decode(registers: RawRegisters, status_b: u8) -> DateTime {
+	let [second, minute, hour, day, month, year, century] = registers;
+
+	let to_binary = |value: u8| {
+		if status_b & BINARY_MODE != 0 { value } else { bcd_to_binary(value) }
+	};
+
+	let is_pm = status_b & HOUR_24_MODE == 0 && hour & HOUR_PM != 0;
+	let mut hour = to_binary(hour & !HOUR_PM);
+	if status_b & HOUR_24_MODE == 0 {
+		// 12 AM is midnight, 12 PM is noon
+		hour = (hour % 12) + if is_pm { 12 } else { 0 };
+	}
+
+	// without a century register, we assume the 21st century
+	let century = match to_binary(century) {
+		century @ 19..=21 => century as u16,
+		_ => 20,
+	};
+
+	DateTime {
+		year: century * 100 + to_binary(year) as u16,
+		month: to_binary(month),
+		day: to_binary(day),
+		hour,
+		minute: to_binary(minute),
+		second: to_binary(second),
+	}
+}
+
+/// Converts a binary-coded decimal (e.g. 0x59) to binary (59)
+const fn bcd_to_binary(value: u8) -> u8 {
+	(value >> 4) * 10 + (value & 0x0f)
+}
+
+/// Enables the periodic interrupt, at `32768 >> (rate - 1)` Hz (`rate` between 3 and 15, so from
+/// 8 kHz to 2 Hz)
+pub fn enable_periodic_interrupt(rate: u8) -> Result<(), IrqError> {
+	assert!((3..=15).contains(&rate), "invalid RTC rate {rate}");
+
+	request_irq(Irq::Rtc, rtc_interrupt_handler, "rtc")?;
+
+	without_interrupts(|| {
+		let status_a = read_cmos(REGISTER_STATUS_A);
+		write_cmos(REGISTER_STATUS_A, (status_a & 0xf0) | rate);
+
+		let status_b = read_cmos(REGISTER_STATUS_B);
+		write_cmos(REGISTER_STATUS_B, status_b | PERIODIC_INTERRUPT);
+
+		// an interrupt that was already pending would never be acknowledged
+		read_cmos(REGISTER_STATUS_C);
+	});
+	Ok(())
+}
+
+/// Disables the periodic interrupt
+pub fn disable_periodic_interrupt() -> Result<(), IrqError> {
+	without_interrupts(|| {
+		let status_b = read_cmos(REGISTER_STATUS_B);
+		write_cmos(REGISTER_STATUS_B, status_b & !PERIODIC_INTERRUPT);
+	});
+
+	free_irq(Irq::Rtc, "rtc")
+}
+
+/// Number of periodic interrupts received (cf. [enable_periodic_interrupt])
+pub fn periodic_ticks() -> u64 {
+	without_interrupts(|| *PERIODIC_TICKS.lock())
+}
+
+fn rtc_interrupt_handler(_frame: &mut TrapFrame) {
+	*PERIODIC_TICKS.lock() += 1;
+
+	// the RTC doesn't raise another interrupt until status C is read
+	read_cmos(REGISTER_STATUS_C);
+}
+
+/// Reads a CMOS register
+///
+/// Selecting the register and reading it must not be interrupted, as a handler could select
+/// another one in between
+fn read_cmos(register: u8) -> u8 {
+	without_interrupts(|| unsafe {
+		outb(CMOS_INDEX, register);
+		inb(CMOS_DATA)
+	})
+}
+
+/// Writes a CMOS register (cf. [read_cmos])
+fn write_cmos(register: u8, value: u8) {
+	without_interrupts(|| unsafe {
+		outb(CMOS_INDEX, register);
+		outb(CMOS_DATA, value);
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::pit::sleep_ms;
+
+	fn date(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+		DateTime {
+			year,
+			month,
+			day,
+			hour,
+			minute,
+			second,
+		}
+	}
+
+	#[test_case]
+	fn unix_timestamps() {
+		assert_eq!(date(1970, 1, 1, 0, 0, 0).to_unix_timestamp(), 0);
+		assert_eq!(date(2000, 3, 1, 0, 0, 0).to_unix_timestamp(), 951868800);
+		assert_eq!(date(2038, 1, 19, 3, 14, 8).to_unix_timestamp(), 1 << 31);
+		assert_eq!(date(1969, 12, 31, 23, 59, 59).to_unix_timestamp(), -1);
+	}
+
+	#[test_case]
+	fn decodes_bcd_and_12_hour_format() {
+		// 2026-10-18 11:30:59 PM, in BCD
+		let registers = [0x59, 0x30, HOUR_PM | 0x11, 0x18, 0x10, 0x26, 0x20];
+		assert_eq!(decode(registers, 0), date(2026, 10, 18, 23, 30, 59));
+
+		// 12 AM is midnight
+		let registers = [0x00, 0x00, 0x12, 0x01, 0x01, 0x00, 0x20];
+		assert_eq!(decode(registers, 0).hour, 0);
+	}
+
+	#[test_case]
+	fn decodes_binary_and_24_hour_format() {
+		let registers = [59, 30, 23, 18, 10, 26, 0];
+		let date_time = decode(registers, BINARY_MODE | HOUR_24_MODE);
+
+		// no century register
+		assert_eq!(date_time, date(2026, 10, 18, 23, 30, 59));
+	}
+
+	#[test_case]
+	fn now_is_a_valid_date() {
+		let now = now();
+
+		assert!(now.year >= 2000);
+		assert!((1..=12).contains(&now.month));
+		assert!((1..=31).contains(&now.day));
+		assert!(now.hour < 24 && now.minute < 60 && now.second < 60);
+	}
+
+	#[test_case]
+	fn periodic_interrupt_ticks() {
+		// 1024 Hz
+		enable_periodic_interrupt(6).unwrap();
+		sleep_ms(20);
+		disable_periodic_interrupt().unwrap();
+
+		assert!(periodic_ticks() > 0);
+	}
+}
@@ -0,0 +1,242 @@
+//! 16550 UART serial port driver
+//!
+//! We only drive COM1 (I/O port `0x3f8`), which QEMU redirects to the host with `-serial stdio`.
+//! This lets us read the kernel output (and panic messages) when running headless.
+//!
+//! You can read [https://wiki.osdev.org/Serial_Ports] for a better understanding.
+
+use core::fmt::Write;
+use core::sync::atomic::{
+	AtomicBool,
+	Ordering,
+};
+
+use lazy_static::lazy_static;
+use modular_bitfield::prelude::*;
+use spin::Mutex;
+
+use crate::shared::{
+	inb,
+	outb,
+};
+
+/// Base I/O port of the first serial port
+pub const COM1: u16 = 0x3f8;
+
+/// Default baud rate of [GLOBAL_SERIAL_PORT]
+pub const DEFAULT_BAUD_RATE: u32 = 38400;
+
+/// The UART clock runs at 115200 Hz, the baud rate is set by dividing it
+const UART_CLOCK_HZ: u32 = 115200;
+
+// Registers offsets, relative to the base port
+const DATA: u16 = 0; // DLAB = 0: data register, DLAB = 1: divisor low byte
+const INTERRUPT_ENABLE: u16 = 1; // DLAB = 0: interrupt enable, DLAB = 1: divisor high byte
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+
+/// Line Status Register bit set when the transmitter holding register is empty
+const LINE_STATUS_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+/// If true, [crate::print!] also writes to [GLOBAL_SERIAL_PORT]
+static CONSOLE_MIRRORING: AtomicBool = AtomicBool::new(true);
+
+lazy_static! {
+	/// Global COM1 [SerialPort] protected by a [Mutex]
+	pub static ref GLOBAL_SERIAL_PORT: Mutex<SerialPort> = {
+		let mut port = SerialPort::new(COM1);
+
+		port.init(DEFAULT_BAUD_RATE, LineControl::eight_n_one());
+
+		Mutex::new(port)
+	};
+}
+
+#[doc(hidden)]
+pub fn _print(args: core::fmt::Arguments) {
+	GLOBAL_SERIAL_PORT.lock().write_fmt(args).unwrap();
+}
+
+/// Returns true if [crate::print!] output is mirrored to the serial port
+pub fn is_console_mirrored() -> bool {
+	CONSOLE_MIRRORING.load(Ordering::Relaxed)
+}
+
+/// Enables/disables the mirroring of [crate::print!] (and panic messages) to the serial port
+pub fn set_console_mirroring(enabled: bool) {
+	CONSOLE_MIRRORING.store(enabled, Ordering::Relaxed);
+}
+
+/// A 16550 UART, accessed through the I/O ports starting at `base_port`
+pub struct SerialPort {
+	base_port: u16,
+
+	/// false if the port failed its loopback test (e.g. no UART plugged in)
+	is_functional: bool,
+}
+
+impl SerialPort {
+	/// Creates an uninitialized [SerialPort]
+	pub const fn new(base_port: u16) -> Self {
+		Self {
+			base_port,
+			is_functional: false,
+		}
+	}
+
+	/// Sets the baud rate and line configuration, enables the FIFOs
+	/// and checks that the UART works with a loopback test
+	///
+	/// `baud_rate` must divide 115200 (e.g. 115200, 57600, 38400, 9600)
+	pub fn init(&mut self, baud_rate: u32, line_control: LineControl) {
+		debug_assert!(
+			baud_rate != 0 && UART_CLOCK_HZ.is_multiple_of(baud_rate),
+			"baud rate must divide {UART_CLOCK_HZ}"
+		);
+		let divisor = (UART_CLOCK_HZ / baud_rate) as u16;
+
+		unsafe {
+			// disable UART interrupts, we only poll
+			self.write_register(INTERRUPT_ENABLE, 0x00);
+
+			// with DLAB set, the first two registers hold the baud rate divisor
+			self.write_register(
+				LINE_CONTROL,
+				line_control.with_divisor_latch_access(true).into_bytes()[0],
+			);
+			self.write_register(DATA, divisor as u8);
+			self.write_register(INTERRUPT_ENABLE, (divisor >> 8) as u8);
+
+			// clear DLAB and set the line configuration
+			self.write_register(
+				LINE_CONTROL,
+				line_control.with_divisor_latch_access(false).into_bytes()[0],
+			);
+
+			// enable FIFOs, clear them, with a 14 bytes threshold
+			self.write_register(FIFO_CONTROL, 0xc7);
+
+			// loopback mode (+ OUT1/OUT2/RTS), to test the UART
+			self.write_register(MODEM_CONTROL, 0x1e);
+			self.write_register(DATA, 0xae);
+			self.is_functional = self.read_register(DATA) == 0xae;
+
+			// normal operation mode (DTR + RTS + OUT1 + OUT2)
+			self.write_register(MODEM_CONTROL, 0x0f);
+		}
+	}
+
+	/// Returns false if the loopback test of [SerialPort::init] failed
+	pub fn is_functional(&self) -> bool {
+		self.is_functional
+	}
+
+	/// Sends a byte, waiting for the transmitter to be ready
+	pub fn send(&mut self, byte: u8) {
+		if !self.is_functional {
+			return;
+		}
+
+		while !self.is_transmit_empty() {
+			core::hint::spin_loop();
+		}
+
+		unsafe { self.write_register(DATA, byte) };
+	}
+
+	fn is_transmit_empty(&self) -> bool {
+		unsafe { self.read_register(LINE_STATUS) & LINE_STATUS_TRANSMIT_EMPTY != 0 }
+	}
+
+	/// # Safety
+	///  - `offset` must be a valid 16550 register offset
+	unsafe fn write_register(&self, offset: u16, value: u8) {
+		unsafe { outb(self.base_port + offset, value) }
+	}
+
+	/// # Safety
+	///  - `offset` must be a valid 16550 register offset
+	unsafe fn read_register(&self, offset: u16) -> u8 {
+		unsafe { inb(self.base_port + offset) }
+	}
+}
+
+impl Write for SerialPort {
+	fn write_str(&mut self, s: &str) -> core::fmt::Result {
+		for byte in s.bytes() {
+			// terminals expect a carriage return before each line feed
+			if byte == b'\n' {
+				self.send(b'\r');
+			}
+			self.send(byte);
+		}
+
+		Ok(())
+	}
+}
+
+/// Line Control Register (data bits, parity, stop bits)
+#[bitfield(bits = 8)]
+#[derive(Specifier, Clone, Copy)]
+pub struct LineControl {
+	/// Number of data bits per character
+	pub word_length: WordLength,
+
+	/// false = 1 stop bit, true = 2 stop bits (1.5 with 5 bits characters)
+	pub two_stop_bits: bool,
+
+	pub parity: Parity,
+
+	/// Forces the line to the spacing (logic 0) state
+	pub break_enable: bool,
+
+	/// Divisor Latch Access Bit: when set, the first two registers hold the baud rate divisor
+	pub divisor_latch_access: bool,
+}
+
+impl LineControl {
+	/// 8 data bits, no parity, 1 stop bit (the most common configuration)
+	pub fn eight_n_one() -> Self {
+		Self::new().with_word_length(WordLength::Eight).with_parity(Parity::None)
+	}
+}
+
+/// Number of data bits per character
+#[derive(Specifier, Clone, Copy, PartialEq, Eq)]
+#[bits = 2]
+pub enum WordLength {
+	Five = 0,
+	Six = 1,
+	Seven = 2,
+	Eight = 3,
+}
+
+/// Parity bit configuration
+#[derive(Specifier, Clone, Copy, PartialEq, Eq)]
+#[bits = 3]
+pub enum Parity {
+	None = 0b000,
+	Odd = 0b001,
+	Even = 0b011,
+	Mark = 0b101,
+	Space = 0b111,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test_case]
+	fn com1_passes_loopback_test() {
+		assert!(GLOBAL_SERIAL_PORT.lock().is_functional());
+	}
+
+	#[test_case]
+	fn serial_println_many_lines() {
+		for _ in 0..50 {
+			crate::serial_println!("serial_println_many_lines output");
+		}
+	}
+}
@@ -35,3 +35,86 @@ pub unsafe fn inb(port: u16) -> u8 {
 	);
 	ret
 }
+
+/// Write 16 bits to `port`
+#[inline]
+pub unsafe fn outw(port: u16, val: u16) {
+	unsafe {
+		asm!(
+			"out dx, ax",
+			in("ax") val,
+			in("dx") port,
+			options(nostack, preserves_flags)
+		);
+	}
+}
+
+/// Read 16 bits from `port`
+#[inline]
+pub unsafe fn inw(port: u16) -> u16 {
+	let ret: u16;
+	unsafe {
+		asm!(
+			"in ax, dx",
+			in("dx") port,
+			out("ax") ret,
+			options(nostack, preserves_flags)
+		);
+	}
+	ret
+}
+
+/// Result of the `cpuid` instruction
+pub struct CpuidResult {
+	pub eax: u32,
+	pub ebx: u32,
+	pub ecx: u32,
+	pub edx: u32,
+}
+
+/// Runs `cpuid` for the `leaf` function
+#[inline]
+pub fn cpuid(leaf: u32) -> CpuidResult {
+	let result = core::arch::x86::__cpuid(leaf);
+
+	CpuidResult {
+		eax: result.eax,
+		ebx: result.ebx,
+		ecx: result.ecx,
+		edx: result.edx,
+	}
+}
+
+/// Reads a Model Specific Register
+///
+/// SAFETY: `msr` must exist on this CPU
+#[inline]
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+	let (low, high): (u32, u32);
+	unsafe {
+		asm!(
+			"rdmsr",
+			in("ecx") msr,
+			out("eax") low,
+			out("edx") high,
+			options(nostack, preserves_flags)
+		);
+	}
+	((high as u64) << 32) | low as u64
+}
+
+/// Writes a Model Specific Register
+///
+/// SAFETY: `msr` must exist on this CPU, and `value` must be valid for it
+#[inline]
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+	unsafe {
+		asm!(
+			"wrmsr",
+			in("ecx") msr,
+			in("eax") value as u32,
+			in("edx") (value >> 32) as u32,
+			options(nostack, preserves_flags)
+		);
+	}
+}
@@ -0,0 +1,9 @@
+use crate::println;
+use crate::rtc::now;
+
+/// Prints the date and time of the RTC, and the matching Unix timestamp
+pub fn date() {
+	let now = now();
+
+	println!("{now} UTC ({})", now.to_unix_timestamp());
+}
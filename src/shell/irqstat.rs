@@ -0,0 +1,23 @@
+use crate::irq::{
+	irq_controller_name,
+	irq_stats,
+};
+use crate::{
+	print,
+	println,
+};
+
+/// Prints the interrupt counters of every IRQ line, with the names of its handlers
+pub fn irqstat() {
+	println!("Delivered by the {}", irq_controller_name());
+	println!("IRQ      count  spurious  line: handlers");
+
+	irq_stats(|line| {
+		print!("{:>3} {:>10} {:>9}  {:?}:", line.irq as u8, line.count, line.spurious, line.irq);
+
+		for name in line.handlers.iter().flatten() {
+			print!(" {name}");
+		}
+		println!();
+	});
+}
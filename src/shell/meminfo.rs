@@ -0,0 +1,44 @@
+use crate::allocator::VIRTUAL_ALLOCATOR;
+use crate::paging::memory_map;
+use crate::paging::pmm::{
+	FRAME_SIZE,
+	PHYSICAL_ALLOCATOR,
+};
+use crate::println;
+
+/// Prints the physical memory counters, the heap usage and the bootloader memory map
+pub fn meminfo() {
+	let frames = PHYSICAL_ALLOCATOR.lock().stats();
+	let kib = |frame_count: usize| frame_count * FRAME_SIZE / 1024;
+
+	println!("Physical memory (4 KiB frames):");
+	println!("  total:    {:>8} KiB ({} frames)", kib(frames.total_frames), frames.total_frames);
+	println!("  usable:   {:>8} KiB ({} frames)", kib(frames.usable_frames), frames.usable_frames);
+	println!(
+		"  reserved: {:>8} KiB ({} frames)",
+		kib(frames.reserved_frames),
+		frames.reserved_frames
+	);
+	println!("  free:     {:>8} KiB ({} frames)", kib(frames.free_frames), frames.free_frames);
+
+	let heap = VIRTUAL_ALLOCATOR.stats();
+
+	println!("Heap:");
+	println!("  mapped:   {:>8} KiB (max {} KiB)", heap.size / 1024, heap.max_size / 1024);
+	println!("  in use:   {:>8} bytes (peak {} bytes)", heap.used_bytes, heap.peak_used_bytes);
+	println!(
+		"  free:     {:>8} bytes in {} regions (largest: {} bytes)",
+		heap.free_bytes, heap.free_regions, heap.largest_free_region
+	);
+
+	println!("Memory map:");
+	memory_map(|region| {
+		println!(
+			"  {:#010x} - {:#010x}  {} ({})",
+			region.base_addr,
+			region.base_addr + region.length,
+			region.type_name(),
+			region.region_type
+		);
+	});
+}
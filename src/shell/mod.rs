@@ -1,8 +1,34 @@
+mod date;
+mod irqstat;
+mod meminfo;
+mod ps;
+mod run;
+mod uptime;
+
 use core::arch::asm;
 use core::str;
+use core::sync::atomic::{
+	AtomicU32,
+	Ordering,
+};
 
+use self::date::date;
+use self::irqstat::irqstat;
+use self::meminfo::meminfo;
+use self::ps::ps;
+use self::run::run;
+use self::uptime::uptime;
+use crate::acpi::power::{
+	reboot,
+	shutdown,
+};
 use crate::gdt::dump::dump_kernel_stack;
-use crate::shared::outb;
+use crate::task::{
+	TaskId,
+	block_current,
+	current_task_id,
+	wake,
+};
 use crate::vga::{
 	GLOBAL_VGA_SCREEN,
 	VGA_BUFFER_WIDTH,
@@ -16,35 +42,57 @@ static mut COMMAND_BUFFER: [u8; VGA_BUFFER_WIDTH] = [0; VGA_BUFFER_WIDTH];
 static mut COMMAND_LENGTH: usize = 0;
 static mut COMMAND_READY: bool = false;
 
+/// Id of the task running [shell_loop], woken when a command is ready (u32::MAX before it starts)
+static SHELL_TASK: AtomicU32 = AtomicU32::new(u32::MAX);
+
 /// Runs an interactive command interpreter loop
 ///
-/// available commands are stack, halt, reboot, and clear
+/// available commands are stack, meminfo, irqstat, uptime, date, ps, run, halt, shutdown, reboot,
+/// and clear
 pub fn shell_loop() -> ! {
+	SHELL_TASK.store(current_task_id().0, Ordering::Relaxed);
+
 	loop {
 		unsafe {
-			// freeze until next interrupt
-			asm!("hlt");
-
 			if !COMMAND_READY {
+				// until the keyboard handler reads a newline
+				block_current();
 				continue;
 			}
 
 			match str::from_utf8_unchecked(&COMMAND_BUFFER[0..COMMAND_LENGTH]) {
 				"stack" => dump_kernel_stack(),
 
+				"meminfo" => meminfo(),
+
+				"irqstat" => irqstat(),
+
+				"uptime" => uptime(),
+
+				"date" => date(),
+
+				"ps" => ps(),
+
 				"halt" => {
 					println!("System halted");
 					idt::interrupts::disable_hardware_interrupts();
 					asm!("hlt"); // freeze CPU
 				}
 
+				"shutdown" => {
+					println!("Powering off...");
+					shutdown();
+				}
+
 				"reboot" => {
 					println!("Rebooting...");
-					outb(0x64, 0xfe); // magic trick :)
+					reboot();
 				}
 
 				"clear" => GLOBAL_VGA_SCREEN.lock().clear(),
 
+				str if str.split_whitespace().next() == Some("run") => run(str),
+
 				str if COMMAND_LENGTH > 0 => println!("Unknown command: {str}"),
 				_ => {}
 			}
@@ -68,7 +116,14 @@ pub fn add_char_to_command_buffer(c: u8) {
 				}
 			}
 
-			b'\n' => COMMAND_READY = true,
+			b'\n' => {
+				COMMAND_READY = true;
+
+				let shell_task = SHELL_TASK.load(Ordering::Relaxed);
+				if shell_task != u32::MAX {
+					wake(TaskId(shell_task));
+				}
+			}
 
 			_ => {
 				if COMMAND_LENGTH >= VGA_BUFFER_WIDTH {
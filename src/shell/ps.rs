@@ -0,0 +1,42 @@
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use crate::println;
+use crate::process::{
+	ProcessInfo,
+	ProcessState,
+	processes,
+};
+use crate::task::tasks;
+
+/// Prints the id, state and name of every task, with the id of the process it runs, then the
+/// zombie processes
+pub fn ps() {
+	let mut process_list = Vec::new();
+	processes(|process| process_list.push(*process));
+
+	let is_zombie = |process: &ProcessInfo| matches!(process.state, ProcessState::Zombie(_));
+	let running = |task| {
+		process_list
+			.iter()
+			.find(|process: &&ProcessInfo| process.task == Some(task) && !is_zombie(process))
+	};
+
+	println!(" ID   PID  STATE    NAME");
+
+	tasks(|task| {
+		// the kernel threads run no process
+		let pid = running(task.id).map_or("-".to_string(), |process| process.pid.0.to_string());
+		println!(
+			"{:>3}  {:>4}  {:<7}  {}",
+			task.id.0,
+			pid,
+			format_args!("{:?}", task.state),
+			task.name
+		);
+	});
+
+	for process in process_list.iter().filter(|process| is_zombie(process)) {
+		println!("  -  {:>4}  {:?}", process.pid.0, process.state);
+	}
+}
@@ -0,0 +1,30 @@
+use alloc::vec::Vec;
+
+use crate::paging::{
+	boot_module,
+	boot_modules,
+};
+use crate::println;
+use crate::user::run_user_program;
+
+/// Runs the bootloader module named by the first argument (`run hello a b`), or lists the
+/// modules without argument
+pub fn run(command: &str) {
+	let args: Vec<&str> = command.split_whitespace().skip(1).collect();
+
+	let Some(&name) = args.first() else {
+		println!("usage: run <module> [arguments...]");
+		boot_modules(|module| println!("  {} ({} bytes)", module.name(), module.size()));
+		return;
+	};
+
+	let Some(image) = boot_module(name) else {
+		println!("run: no module named {name}");
+		return;
+	};
+
+	match run_user_program(image, &args, &[]) {
+		Ok(exit_code) => println!("{name} exited with code {exit_code}"),
+		Err(error) => println!("run: {name}: {error}"),
+	}
+}
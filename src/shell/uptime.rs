@@ -0,0 +1,24 @@
+use crate::pit::{
+	frequency,
+	ticks,
+};
+use crate::{
+	pit,
+	println,
+};
+
+/// Prints the time elapsed since boot, and the number of timer ticks
+pub fn uptime() {
+	let uptime = pit::uptime();
+	let seconds = uptime.as_secs();
+
+	println!(
+		"up {}:{:02}:{:02}.{:03} ({} ticks at {} Hz)",
+		seconds / 3600,
+		seconds / 60 % 60,
+		seconds % 60,
+		uptime.subsec_millis(),
+		ticks(),
+		frequency()
+	);
+}
@@ -0,0 +1,472 @@
+//! Kernel threads and round-robin scheduler
+//!
+//! Each task runs on its own kernel stack, and switching tasks is switching stacks (cf.
+//! [switch]). The PIT asks for a reschedule every [TIME_SLICE], which happens when the interrupt
+//! returns (cf. [preempt_if_needed]): the next ready task, in slot order, gets the CPU. Tasks can
+//! also give it back with [yield_now], or wait in [block_current] until another task or an
+//! interrupt handler calls [wake].
+//!
+//! Each task also keeps its page directory: the kernel one, or the address space of the user
+//! program it runs (cf. [crate::paging::address_space]), which is loaded back when it gets the
+//! CPU.
+//!
+//! The boot flow becomes the `main` task, and an `idle` task runs when no other task is ready.
+//!
+//! The scheduler lock is only taken with interrupts disabled, and nothing is allocated or freed
+//! while it is held: the preempted task may be holding the heap lock.
+
+mod switch;
+
+use alloc::boxed::Box;
+use alloc::vec;
+use core::arch::asm;
+use core::sync::atomic::{
+	AtomicBool,
+	Ordering,
+};
+use core::time::Duration;
+use core::{
+	fmt,
+	mem,
+};
+
+use spin::Mutex;
+
+use self::switch::{
+	ThreadEntry,
+	initial_stack_pointer,
+	switch_context,
+};
+use crate::gdt::tss::set_kernel_stack;
+use crate::idt::interrupts::{
+	disable_hardware_interrupts,
+	without_interrupts,
+};
+use crate::paging::kernel_page_directory;
+use crate::paging::page_directory::{
+	active_page_directory,
+	load_page_directory,
+};
+use crate::pit::timer::add_periodic_timer;
+
+/// Maximum number of tasks, dead ones included until their slot is reused
+pub const MAX_TASKS: usize = 64;
+
+/// Size of the kernel stack of each thread
+pub const KERNEL_STACK_SIZE: usize = 16 * 1024;
+
+/// How long a task runs before the next ready one is scheduled
+pub const TIME_SLICE: Duration = Duration::from_millis(10);
+
+static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::new());
+
+/// Set by the PIT at the end of a time slice (cf. [preempt_if_needed])
+static NEED_RESCHED: AtomicBool = AtomicBool::new(false);
+
+/// Identifies a task (ids aren't reused)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TaskId(pub u32);
+
+/// What a task is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+	/// On the CPU
+	Running,
+
+	/// Waiting for the CPU
+	Ready,
+
+	/// Waiting for [wake]
+	Blocked,
+
+	/// Returned from its entry function (or called [exit_current])
+	Dead,
+}
+
+/// Errors returned by [spawn_kernel_thread]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+	/// There are already [MAX_TASKS] live tasks
+	TooManyTasks,
+}
+
+impl fmt::Display for SpawnError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::TooManyTasks => write!(f, "there are already {MAX_TASKS} tasks"),
+		}
+	}
+}
+
+/// A kernel thread
+pub struct Task {
+	id: TaskId,
+	name: &'static str,
+	state: TaskState,
+
+	/// Set by [wake] while the task isn't blocked, so its next [block_current] returns at once
+	wakeup_pending: bool,
+
+	/// None for the `main` task, which runs on the boot stack (cf. tools/build/boot.s)
+	kernel_stack: Option<Box<[u8]>>,
+
+	/// Saved by [switch_context] while the task doesn't run
+	stack_pointer: u32,
+
+	/// Where the CPU switches when an interrupt comes from user mode (0 to leave the TSS as is,
+	/// cf. [set_kernel_stack_top])
+	kernel_stack_top: u32,
+
+	/// Physical address of the page directory the task runs with, saved when it stops running
+	page_directory: u32,
+}
+
+/// What [tasks] reports about a task
+#[derive(Debug, Clone, Copy)]
+pub struct TaskInfo {
+	/// The task
+	pub id: TaskId,
+
+	/// Its name, given to [spawn_kernel_thread]
+	pub name: &'static str,
+
+	/// Its state
+	pub state: TaskState,
+}
+
+struct Scheduler {
+	tasks: [Option<Task>; MAX_TASKS],
+
+	/// Slot of the running task
+	current: usize,
+
+	/// Slot of the idle task, only scheduled when no other task is ready
+	idle: usize,
+
+	next_id: u32,
+
+	/// Set by [init_scheduler]
+	is_running: bool,
+}
+
+impl Scheduler {
+	const fn new() -> Self {
+		Self {
+			tasks: [const { None }; MAX_TASKS],
+			current: 0,
+			idle: 0,
+			next_id: 0,
+			is_running: false,
+		}
+	}
+
+	fn current_task(&mut self) -> Option<&mut Task> {
+		self.tasks[self.current].as_mut()
+	}
+
+	fn task(&mut self, id: TaskId) -> Option<&mut Task> {
+		self.tasks.iter_mut().flatten().find(|task| task.id == id)
+	}
+
+	/// A slot for a new task: an empty one, or the one of a dead task (but not the running one)
+	fn free_slot(&self) -> Option<usize> {
+		(0..MAX_TASKS).find(|&slot| {
+			slot != self.current
+				&& self.tasks[slot].as_ref().is_none_or(|task| task.state == TaskState::Dead)
+		})
+	}
+
+	/// Puts a new ready task in `slot` (cf. [Scheduler::free_slot]), and returns its id and the
+	/// dead task it replaced (whose stack must be freed once the lock is released)
+	fn insert(
+		&mut self,
+		slot: usize,
+		name: &'static str,
+		kernel_stack: Option<Box<[u8]>>,
+		stack_pointer: u32,
+	) -> (TaskId, Option<Task>) {
+		let id = TaskId(self.next_id);
+		self.next_id += 1;
+
+		let kernel_stack_top =
+			kernel_stack.as_ref().map_or(0, |stack| stack.as_ptr_range().end as u32);
+
+		let dead_task = self.tasks[slot].replace(Task {
+			id,
+			name,
+			state: TaskState::Ready,
+			wakeup_pending: false,
+			kernel_stack,
+			stack_pointer,
+			kernel_stack_top,
+			page_directory: kernel_page_directory(),
+		});
+
+		(id, dead_task)
+	}
+
+	/// The first ready task after the current one, the idle task excepted
+	fn next_ready(&self) -> Option<usize> {
+		(1..=MAX_TASKS).map(|offset| (self.current + offset) % MAX_TASKS).find(|&slot| {
+			slot != self.idle
+				&& self.tasks[slot].as_ref().is_some_and(|task| task.state == TaskState::Ready)
+		})
+	}
+}
+
+/// Turns the boot flow into the `main` task, creates the idle task, and starts the time slices
+///
+/// Must be called once, after [crate::allocator::init_virtual_allocator] and
+/// [crate::pit::init_pit]
+pub fn init_scheduler() {
+	let (idle_stack, idle_stack_pointer, _) = new_kernel_stack(idle_loop);
+
+	without_interrupts(|| {
+		let mut scheduler = SCHEDULER.lock();
+
+		let main = scheduler.free_slot().expect("the scheduler is empty");
+		scheduler.insert(main, "main", None, 0);
+		scheduler.current = main;
+		scheduler.tasks[main].as_mut().expect("main was inserted").state = TaskState::Running;
+
+		let idle = scheduler.free_slot().expect("the scheduler is empty");
+		scheduler.insert(idle, "idle", Some(idle_stack), idle_stack_pointer);
+		scheduler.idle = idle;
+
+		scheduler.is_running = true;
+	});
+
+	add_periodic_timer(TIME_SLICE, request_reschedule).expect("a timer is free for the scheduler");
+}
+
+/// Creates a kernel thread that runs `entry`, then ends
+pub fn spawn_kernel_thread(
+	name: &'static str,
+	entry: impl FnOnce() + Send + 'static,
+) -> Result<TaskId, SpawnError> {
+	let (kernel_stack, stack_pointer, entry) = new_kernel_stack(entry);
+
+	// the stack goes back out if there is no free slot
+	let result = without_interrupts(|| {
+		let mut scheduler = SCHEDULER.lock();
+		match scheduler.free_slot() {
+			Some(slot) => Ok(scheduler.insert(slot, name, Some(kernel_stack), stack_pointer)),
+			None => Err(kernel_stack),
+		}
+	});
+
+	// freed with interrupts enabled, and without the scheduler lock
+	match result {
+		Ok((id, dead_task)) => {
+			drop(dead_task);
+			Ok(id)
+		}
+		Err(kernel_stack) => {
+			// the thread will never run to free them
+			drop(unsafe { Box::from_raw(entry) });
+			drop(kernel_stack);
+			Err(SpawnError::TooManyTasks)
+		}
+	}
+}
+
+/// Id of the running task
+pub fn current_task_id() -> TaskId {
+	without_interrupts(|| {
+		let mut scheduler = SCHEDULER.lock();
+		scheduler.current_task().map_or(TaskId(0), |task| task.id)
+	})
+}
+
+/// Name of the running task
+pub fn current_task_name() -> &'static str {
+	without_interrupts(|| {
+		let mut scheduler = SCHEDULER.lock();
+		scheduler.current_task().map_or("main", |task| task.name)
+	})
+}
+
+/// Lets the next ready task run, if any
+pub fn yield_now() {
+	without_interrupts(schedule);
+}
+
+/// Suspends the running task until [wake] is called with its id
+///
+/// Returns at once if [wake] was called since the last time the task blocked, so checking a
+/// condition and blocking doesn't miss a wake up from an interrupt handler in between
+pub fn block_current() {
+	without_interrupts(|| {
+		{
+			let mut scheduler = SCHEDULER.lock();
+			if !scheduler.is_running {
+				return;
+			}
+
+			let task = scheduler.current_task().expect("the scheduler runs a task");
+			if mem::take(&mut task.wakeup_pending) {
+				return;
+			}
+			task.state = TaskState::Blocked;
+		}
+
+		schedule();
+	});
+}
+
+/// Makes the `id` task ready again if it is blocked, or makes its next [block_current] return at
+/// once otherwise
+///
+/// Returns false if there is no such (live) task. Can be called from interrupt handlers
+pub fn wake(id: TaskId) -> bool {
+	without_interrupts(|| {
+		let mut scheduler = SCHEDULER.lock();
+		let Some(task) = scheduler.task(id) else {
+			return false;
+		};
+
+		match task.state {
+			TaskState::Dead => return false,
+			TaskState::Blocked => task.state = TaskState::Ready,
+			TaskState::Running | TaskState::Ready => task.wakeup_pending = true,
+		}
+		true
+	})
+}
+
+/// Ends the running task
+///
+/// Its stack is freed when its slot is reused
+pub fn exit_current() -> ! {
+	disable_hardware_interrupts();
+
+	SCHEDULER.lock().current_task().expect("the scheduler runs a task").state = TaskState::Dead;
+	schedule();
+
+	unreachable!("a dead task was scheduled")
+}
+
+/// Calls `f` with the info of every task, in slot order
+pub fn tasks(mut f: impl FnMut(&TaskInfo)) {
+	// copied, as `f` may take locks a preempted task holds
+	let infos = without_interrupts(|| {
+		SCHEDULER.lock().tasks.each_ref().map(|task| {
+			task.as_ref().map(|task| TaskInfo {
+				id: task.id,
+				name: task.name,
+				state: task.state,
+			})
+		})
+	});
+
+	for info in infos.iter().flatten() {
+		f(info);
+	}
+}
+
+/// Switches to the next task if the time slice of the running one is over
+///
+/// Called when an interrupt handler returns, so the interrupted task resumes when it is
+/// scheduled again
+pub fn preempt_if_needed() {
+	if NEED_RESCHED.swap(false, Ordering::Relaxed) {
+		// system calls run with interrupts enabled
+		without_interrupts(schedule);
+	}
+}
+
+/// Top of the kernel stack the CPU switches to when an interrupt comes from user mode, for the
+/// running task
+pub fn kernel_stack_top() -> u32 {
+	without_interrupts(|| {
+		let mut scheduler = SCHEDULER.lock();
+		scheduler.current_task().map_or(0, |task| task.kernel_stack_top)
+	})
+}
+
+/// Sets where the CPU switches when an interrupt comes from user mode, for the running task
+/// (the scheduler restores it in the TSS each time the task runs)
+pub fn set_kernel_stack_top(top: u32) {
+	without_interrupts(|| {
+		if let Some(task) = SCHEDULER.lock().current_task() {
+			task.kernel_stack_top = top;
+		}
+		set_kernel_stack(top);
+	});
+}
+
+/// Called by the PIT every [TIME_SLICE]
+fn request_reschedule() {
+	NEED_RESCHED.store(true, Ordering::Relaxed);
+}
+
+/// Switches to the next ready task, or to the idle task if the running one can't continue
+///
+/// Must be called with interrupts disabled. Returns when the running task is scheduled again
+fn schedule() {
+	let (old_stack_pointer, new_stack_pointer) = {
+		let mut scheduler = SCHEDULER.lock();
+		if !scheduler.is_running {
+			return;
+		}
+
+		let current = scheduler.current;
+		let is_runnable =
+			scheduler.tasks[current].as_ref().is_some_and(|task| task.state == TaskState::Running);
+
+		let next = match scheduler.next_ready() {
+			Some(next) => next,
+			None if is_runnable => return,
+			None => scheduler.idle,
+		};
+
+		if is_runnable && let Some(task) = scheduler.tasks[current].as_mut() {
+			task.state = TaskState::Ready;
+		}
+
+		let next_task = scheduler.tasks[next].as_mut().expect("the next task exists");
+		next_task.state = TaskState::Running;
+
+		if next_task.kernel_stack_top != 0 {
+			set_kernel_stack(next_task.kernel_stack_top);
+		}
+
+		let new_stack_pointer = next_task.stack_pointer;
+		let new_page_directory = next_task.page_directory;
+		scheduler.current = next;
+
+		let old_task = scheduler.tasks[current].as_mut().expect("the current task exists");
+		old_task.page_directory = active_page_directory();
+
+		// reloading CR3 flushes the TLB, so only when the directory changes
+		if new_page_directory != old_task.page_directory {
+			unsafe { load_page_directory(new_page_directory) };
+		}
+
+		(&raw mut old_task.stack_pointer, new_stack_pointer)
+	};
+
+	// the slots don't move while interrupts are disabled
+	unsafe { switch_context(old_stack_pointer, new_stack_pointer) };
+}
+
+/// Allocates a kernel stack that starts running `entry` (cf. [initial_stack_pointer])
+///
+/// Also returns the pointer to `entry`, that must be freed if the task never runs
+fn new_kernel_stack(entry: impl FnOnce() + Send + 'static) -> (Box<[u8]>, u32, *mut ThreadEntry) {
+	let entry = Box::into_raw(Box::new(Box::new(entry) as ThreadEntry));
+
+	let mut stack = vec![0; KERNEL_STACK_SIZE].into_boxed_slice();
+	let stack_pointer = initial_stack_pointer(&mut stack, entry);
+
+	(stack, stack_pointer, entry)
+}
+
+/// Runs when no other task is ready
+fn idle_loop() {
+	loop {
+		// until the next interrupt, that may wake a task
+		unsafe { asm!("hlt") };
+		yield_now();
+	}
+}
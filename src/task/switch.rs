@@ -0,0 +1,86 @@
+//! Context switch between kernel stacks
+//!
+//! A task that doesn't run is suspended in [switch_context]: its callee-saved registers and the
+//! return address into the scheduler are on top of its stack, and the scheduler keeps its stack
+//! pointer. A new task gets a stack that looks the same, but returns into [thread_entry].
+
+use alloc::boxed::Box;
+use core::arch::naked_asm;
+
+use super::exit_current;
+use crate::idt::interrupts::enable_hardware_interrupts;
+
+/// Saves the callee-saved registers on the current stack, stores the stack pointer in
+/// `old_stack_pointer`, then loads `new_stack_pointer` and restores the registers of the task
+/// saved there
+///
+/// Returns when another task switches back to this one
+///
+/// # Safety
+///  - interrupts must be disabled
+///  - `new_stack_pointer` must have been saved by this function, or built by
+///    [initial_stack_pointer]
+#[unsafe(naked)]
+pub(super) unsafe extern "C" fn switch_context(
+	old_stack_pointer: *mut u32,
+	new_stack_pointer: u32,
+) {
+	naked_asm!(
+		"mov eax, [esp + 4]",
+		"mov edx, [esp + 8]",
+		// cdecl callee-saved registers (the return address is already on the stack)
+		"push ebp",
+		"push ebx",
+		"push esi",
+		"push edi",
+		"mov [eax], esp",
+		"mov esp, edx",
+		"pop edi",
+		"pop esi",
+		"pop ebx",
+		"pop ebp",
+		"ret",
+	)
+}
+
+/// What a new thread runs, boxed again so [thread_entry] gets a thin pointer to it
+pub(super) type ThreadEntry = Box<dyn FnOnce() + Send>;
+
+/// Prepares `stack` so that [switch_context] starts running `entry` on it, and returns the stack
+/// pointer to give to [switch_context]
+///
+/// `entry` comes from [Box::into_raw], and is freed by [thread_entry]
+pub(super) fn initial_stack_pointer(stack: &mut [u8], entry: *mut ThreadEntry) -> u32 {
+	let words = [
+		// edi, esi, ebx, ebp
+		0,
+		0,
+		0,
+		0,
+		// popped by the `ret` of switch_context
+		thread_entry as *const () as u32,
+		// fake return address of thread_entry, then its argument
+		0,
+		entry as u32,
+	];
+
+	// the top of the stack, aligned on 16 bytes
+	let top = (stack.as_mut_ptr() as usize + stack.len()) & !0xf;
+	let stack_pointer = top - size_of_val(&words);
+
+	unsafe { (stack_pointer as *mut [u32; 7]).write(words) };
+	stack_pointer as u32
+}
+
+/// First function of every kernel thread: runs `entry` (given to [initial_stack_pointer]), then
+/// ends the task
+extern "C" fn thread_entry(entry: *mut ThreadEntry) -> ! {
+	// the scheduler switched here with interrupts disabled
+	unsafe { enable_hardware_interrupts() };
+
+	// Safety: the pointer was only given to this thread
+	let entry = *unsafe { Box::from_raw(entry) };
+	entry();
+
+	exit_current()
+}
@@ -0,0 +1,87 @@
+//! Kernel-mode test framework
+//!
+//! Test kernels (the lib unit tests and every file in `tests/`) are booted by QEMU
+//! (cf. `tools/test/runner.sh`). They report their results over COM1 and end QEMU through its
+//! `isa-debug-exit` device, whose exit status tells the runner if the tests passed.
+
+use core::sync::atomic::{
+	AtomicBool,
+	Ordering,
+};
+
+use crate::idt::interrupts::disable_hardware_interrupts;
+use crate::serial_println;
+use crate::shared::outb;
+
+/// I/O port of QEMU's `isa-debug-exit` device (`-device isa-debug-exit,iobase=0xf4`)
+const ISA_DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// Set by [test_runner], so the panic handler knows it must report a failure and exit QEMU
+static RUNNING_TESTS: AtomicBool = AtomicBool::new(false);
+
+/// Codes written to the `isa-debug-exit` device
+///
+/// QEMU then exits with the status `(code << 1) | 1`, so 33 for [QemuExitCode::Success] and 35
+/// for [QemuExitCode::Failed]. We avoid 0, as QEMU also exits with 1 when it fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum QemuExitCode {
+	/// Every test passed
+	Success = 0x10,
+
+	/// A test panicked
+	Failed = 0x11,
+}
+
+/// Exits QEMU with `exit_code`
+///
+/// If the `isa-debug-exit` device is missing (e.g. on a real machine), the CPU is halted instead
+pub fn exit_qemu(exit_code: QemuExitCode) -> ! {
+	unsafe { outb(ISA_DEBUG_EXIT_PORT, exit_code as u8) };
+
+	loop {
+		disable_hardware_interrupts();
+		unsafe { core::arch::asm!("hlt") };
+	}
+}
+
+/// A function that can be run by [test_runner]
+pub trait Testable {
+	/// Runs the test, printing its name and result to the serial port
+	fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+	fn run(&self) {
+		crate::serial_print!("{}...\t", core::any::type_name::<T>());
+		self();
+		serial_println!("[ok]");
+	}
+}
+
+/// Runs every `#[test_case]` function, then exits QEMU
+///
+/// A failing test panics, and the panic handler exits QEMU with [QemuExitCode::Failed]
+pub fn test_runner(tests: &[&dyn Testable]) {
+	RUNNING_TESTS.store(true, Ordering::Relaxed);
+
+	serial_println!("Running {} tests", tests.len());
+	for test in tests {
+		test.run();
+	}
+
+	exit_qemu(QemuExitCode::Success);
+}
+
+/// Returns true if the kernel is running tests (cf. [test_runner])
+pub fn is_running_tests() -> bool {
+	RUNNING_TESTS.load(Ordering::Relaxed)
+}
+
+/// Reports the failed test and exits QEMU
+pub fn test_panic_handler(info: &core::panic::PanicInfo) -> ! {
+	serial_println!("[failed]\n");
+	serial_println!("Error: {info}\n");
+
+	exit_qemu(QemuExitCode::Failed);
+}
@@ -0,0 +1,257 @@
+//! User mode (ring 3) programs
+//!
+//! [run_user_program] loads an executable (cf. [crate::elf]) in an address space of its own
+//! (cf. [crate::paging::address_space]) and runs it in the calling task: [enter_user_mode] saves
+//! the kernel registers on the task stack, points the TSS right below them (so interrupts from the
+//! program use the rest of the stack), and `iret`s to the entry point with the user segments.
+//!
+//! The program talks to the kernel with `int 0x80` (cf. [syscall]). When it exits, or when a
+//! signal kills it (e.g. for an exception, cf. [crate::process::signal]), [return_to_kernel]
+//! drops the interrupt frames and restores the saved registers, so [enter_user_mode] returns the
+//! exit code to the kernel.
+//!
+//! Each program is a process (cf. [crate::process]), that exits once its address space is
+//! freed. A program can also `fork`: the child process runs in a new task, in a copy-on-write
+//! copy of the address space (cf. [run_forked_child]).
+
+pub mod syscall;
+
+use core::arch::{
+	asm,
+	naked_asm,
+};
+use core::mem::offset_of;
+
+use crate::elf::{
+	Elf,
+	ElfError,
+	LoadedProgram,
+	load,
+};
+use crate::gdt::{
+	USER_CODE_SELECTOR,
+	USER_DATA_SELECTOR,
+	USER_STACK_SELECTOR,
+};
+use crate::idt::interrupts::enable_hardware_interrupts;
+use crate::idt::trap::TrapFrame;
+use crate::paging::address_space::AddressSpace;
+use crate::process::{
+	Pid,
+	attach_current_task,
+	exit_process,
+	start_process,
+};
+use crate::task::{
+	kernel_stack_top,
+	set_kernel_stack_top,
+};
+
+/// Exit code of the programs killed by a signal
+pub const EXIT_CODE_KILLED: i32 = -1;
+
+/// Interrupts enabled, and the bit 1 that is always set
+const INITIAL_EFLAGS: u32 = 0x202;
+
+/// The registers a program starts or resumes with (cf. [enter_user_mode])
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct UserContext {
+	/// General-purpose registers
+	pub ebx: u32,
+	/// cf. [UserContext::ebx]
+	pub ecx: u32,
+	/// cf. [UserContext::ebx]
+	pub edx: u32,
+	/// cf. [UserContext::ebx]
+	pub esi: u32,
+	/// cf. [UserContext::ebx]
+	pub edi: u32,
+	/// cf. [UserContext::ebx]
+	pub ebp: u32,
+	/// cf. [UserContext::ebx]
+	pub eax: u32,
+
+	/// Address of the next instruction
+	pub eip: u32,
+
+	/// User stack pointer
+	pub esp: u32,
+
+	/// Flags (interrupts are always enabled)
+	pub eflags: u32,
+}
+
+impl UserContext {
+	/// The context of a program at its entry point: the registers are cleared, so no kernel value
+	/// leaks
+	pub fn new(entry: u32, stack_pointer: u32) -> Self {
+		Self {
+			ebx: 0,
+			ecx: 0,
+			edx: 0,
+			esi: 0,
+			edi: 0,
+			ebp: 0,
+			eax: 0,
+			eip: entry,
+			esp: stack_pointer,
+			eflags: INITIAL_EFLAGS,
+		}
+	}
+
+	/// The context of the program interrupted by `frame` (cf. [TrapFrame::is_from_user_mode])
+	pub fn from_frame(frame: &TrapFrame) -> Self {
+		let registers = frame.registers;
+
+		Self {
+			ebx: registers.ebx,
+			ecx: registers.ecx,
+			edx: registers.edx,
+			esi: registers.esi,
+			edi: registers.edi,
+			ebp: registers.ebp,
+			eax: registers.eax,
+			eip: frame.eip,
+			esp: frame.user_esp,
+			eflags: frame.eflags | INITIAL_EFLAGS,
+		}
+	}
+}
+
+/// Loads the executable in `image`, runs it with `args` (`args[0]` being its name) and `env` as
+/// a new process without parent, then unloads it
+///
+/// Returns its exit code, once it called `exit` or was killed by a signal
+pub fn run_user_program(image: &[u8], args: &[&str], env: &[&str]) -> Result<i32, ElfError> {
+	let elf = Elf::parse(image)?;
+	let program = load(&elf, args, env)?;
+
+	Ok(run_process(start_process(), program))
+}
+
+/// Runs `program` as the process `pid` in the calling task, then frees it and ends the process
+/// (cf. [exit_process])
+///
+/// Returns its exit code
+pub fn run_process(pid: Pid, program: LoadedProgram) -> i32 {
+	let context = UserContext::new(program.entry, program.stack_pointer);
+	let exit_code = run_in_address_space(&program.address_space, &context);
+
+	// frees the program, and goes back to the kernel page directory
+	drop(program);
+	exit_process(pid, exit_code);
+	exit_code
+}
+
+/// Runs the child process `pid` created by the `fork` system call, in the calling task: the copy
+/// of the parent address space, from the `context` of the parent (cf. [syscall])
+pub fn run_forked_child(pid: Pid, address_space: AddressSpace, context: UserContext) {
+	attach_current_task(pid);
+	let exit_code = run_in_address_space(&address_space, &context);
+
+	drop(address_space);
+	exit_process(pid, exit_code);
+}
+
+/// Runs the program of `address_space` from `context` in the calling task, and returns its exit
+/// code
+fn run_in_address_space(address_space: &AddressSpace, context: &UserContext) -> i32 {
+	address_space.activate();
+
+	let previous_stack_top = kernel_stack_top();
+	let exit_code = unsafe { enter_user_mode(context) };
+	set_kernel_stack_top(previous_stack_top);
+
+	exit_code
+}
+
+/// Runs the user code with the registers of `context`, and returns its exit code (cf.
+/// [return_to_kernel])
+///
+/// # Safety
+///  - `context.eip` and `context.esp` must be in mapped user pages
+#[unsafe(naked)]
+unsafe extern "C" fn enter_user_mode(context: *const UserContext) -> i32 {
+	naked_asm!(
+		// cdecl callee-saved registers, restored by return_to_kernel
+		"push ebp",
+		"push ebx",
+		"push esi",
+		"push edi",
+
+		// interrupts from user mode use the stack below
+		"push esp",
+		"call {set_kernel_stack_top}",
+		"add esp, 4",
+
+		"mov eax, [esp + 20]",
+
+		"mov cx, {data}",
+		"mov ds, cx",
+		"mov es, cx",
+		"mov fs, cx",
+		"mov gs, cx",
+
+		// the frame of an interrupt from user mode: ss, esp, eflags, cs, eip
+		"push {stack}",
+		"push dword ptr [eax + {esp}]",
+		"push dword ptr [eax + {eflags}]",
+		"push {code}",
+		"push dword ptr [eax + {eip}]",
+
+		// the user registers (the kernel ones don't leak, as they're all replaced)
+		"mov ebx, [eax + {ebx}]",
+		"mov ecx, [eax + {ecx}]",
+		"mov edx, [eax + {edx}]",
+		"mov esi, [eax + {esi}]",
+		"mov edi, [eax + {edi}]",
+		"mov ebp, [eax + {ebp}]",
+		"mov eax, [eax + {eax}]",
+		"iretd",
+		set_kernel_stack_top = sym set_user_interrupt_stack,
+		data = const USER_DATA_SELECTOR,
+		stack = const USER_STACK_SELECTOR,
+		code = const USER_CODE_SELECTOR,
+		ebx = const offset_of!(UserContext, ebx),
+		ecx = const offset_of!(UserContext, ecx),
+		edx = const offset_of!(UserContext, edx),
+		esi = const offset_of!(UserContext, esi),
+		edi = const offset_of!(UserContext, edi),
+		ebp = const offset_of!(UserContext, ebp),
+		eax = const offset_of!(UserContext, eax),
+		eip = const offset_of!(UserContext, eip),
+		esp = const offset_of!(UserContext, esp),
+		eflags = const offset_of!(UserContext, eflags),
+	)
+}
+
+/// Called by [enter_user_mode]
+extern "C" fn set_user_interrupt_stack(top: u32) {
+	set_kernel_stack_top(top);
+}
+
+/// Leaves the user program of the running task: makes its [enter_user_mode] return `exit_code`
+///
+/// Must be called from an interrupt that came from user mode (e.g. the `exit` system call)
+pub fn return_to_kernel(exit_code: i32) -> ! {
+	// where enter_user_mode saved the kernel registers
+	let saved_registers = kernel_stack_top();
+
+	// the kernel code that started the program runs with interrupts enabled
+	unsafe { enable_hardware_interrupts() };
+
+	unsafe {
+		asm!(
+			"mov esp, {saved_registers}",
+			"pop edi",
+			"pop esi",
+			"pop ebx",
+			"pop ebp",
+			"ret",
+			saved_registers = in(reg) saved_registers,
+			in("eax") exit_code,
+			options(noreturn),
+		)
+	}
+}
@@ -0,0 +1,466 @@
+//! System calls
+//!
+//! User programs put the system call number in eax and its arguments in ebx, ecx and edx, then
+//! run `int 0x80`. The result comes back in eax: a value, or a negated [Errno]. The numbers are
+//! the Linux i386 ones.
+//!
+//! System calls run with interrupts enabled, in the task of the program, so they can block. The
+//! pending signals are delivered when they return (cf. [crate::process::signal]).
+
+use core::mem::size_of;
+
+use crate::elf::USER_STACK_TOP;
+use crate::idt::interrupts::enable_hardware_interrupts;
+use crate::idt::trap::{
+	SYSCALL_VECTOR,
+	TrapFrame,
+	register_trap_handler,
+};
+use crate::paging::address_space::{
+	AddressSpace,
+	active_area,
+};
+use crate::paging::page_directory::PageDirectory;
+use crate::paging::pmm::FRAME_SIZE;
+use crate::print;
+use crate::process::signal::{
+	MaskChange,
+	SIG_BLOCK,
+	SIG_SETMASK,
+	SIG_UNBLOCK,
+	SIGSEGV,
+	SigAction,
+	SignalError,
+	SignalFrame,
+	change_blocked_signals,
+	force_signal,
+	restore_signal_frame,
+	send_signal,
+	set_signal_action,
+};
+use crate::process::{
+	Pid,
+	WaitError,
+	add_child,
+	current_parent_pid,
+	current_pid,
+	remove_child,
+	wait_child,
+};
+use crate::task::{
+	current_task_name,
+	spawn_kernel_thread,
+	yield_now,
+};
+use crate::user::{
+	UserContext,
+	return_to_kernel,
+	run_forked_child,
+};
+
+/// `exit(code)`: ends the program
+pub const SYS_EXIT: usize = 1;
+
+/// `fork()`: copies the program in a child process, returns the id of the child to the parent,
+/// and 0 to the child
+pub const SYS_FORK: usize = 2;
+
+/// `write(fd, buffer, length)`: writes to the console (fd 1 and 2), returns the length
+pub const SYS_WRITE: usize = 4;
+
+/// `waitpid(pid, status, options)`: waits for the child `pid` (any child for -1) to end, reaps
+/// it, stores its exit status at `status` (if not NULL, cf. [ExitStatus::wait_status]) and
+/// returns its id (0 if it still runs with [WNOHANG])
+///
+/// [ExitStatus::wait_status]: crate::process::ExitStatus::wait_status
+pub const SYS_WAITPID: usize = 7;
+
+/// `getpid()`: returns the id of the process
+pub const SYS_GETPID: usize = 20;
+
+/// `kill(pid, signal)`: sends the signal to the process `pid` (0 only checks that it exists),
+/// returns 0
+pub const SYS_KILL: usize = 37;
+
+/// `brk(address)`: moves the end of the heap, returns the new end (the current one if it can't
+/// move there, e.g. for `brk(0)`)
+pub const SYS_BRK: usize = 45;
+
+/// `getppid()`: returns the id of the parent process (0 for a process started by the kernel)
+pub const SYS_GETPPID: usize = 64;
+
+/// `sigaction(signal, action, old_action)`: sets the [SigAction] of the signal (if `action` isn't
+/// NULL), stores the previous one at `old_action` (if not NULL), returns 0
+pub const SYS_SIGACTION: usize = 67;
+
+/// `sigreturn()`: called when a signal handler returns, resumes the interrupted program (cf.
+/// [SignalFrame])
+pub const SYS_SIGRETURN: usize = 119;
+
+/// `sigprocmask(how, set, old_set)`: blocks ([SIG_BLOCK]), unblocks ([SIG_UNBLOCK]) or sets
+/// ([SIG_SETMASK]) the signals of the mask at `set` (if not NULL), stores the previous mask at
+/// `old_set` (if not NULL), returns 0
+pub const SYS_SIGPROCMASK: usize = 126;
+
+/// `sched_yield()`: lets the other tasks run, returns 0
+pub const SYS_YIELD: usize = 158;
+
+/// Size of [SYSCALL_TABLE]
+pub const SYSCALL_COUNT: usize = SYS_YIELD + 1;
+
+const STDOUT: u32 = 1;
+const STDERR: u32 = 2;
+
+/// `waitpid` option: returns 0 at once if the child still runs
+pub const WNOHANG: u32 = 1;
+
+/// Errors returned by the system calls, negated in eax
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Errno {
+	/// There is no such process
+	NoSuchProcess = 3,
+
+	/// A signal interrupted the system call
+	Interrupted = 4,
+
+	/// Bad file descriptor
+	BadFileDescriptor = 9,
+
+	/// The process has no such child
+	NoChild = 10,
+
+	/// A resource is temporarily unavailable (e.g. every task slot is used)
+	TryAgain = 11,
+
+	/// There is no free memory
+	OutOfMemory = 12,
+
+	/// A pointer argument isn't a mapped user address
+	BadAddress = 14,
+
+	/// An argument isn't valid (e.g. an unknown flag)
+	InvalidArgument = 22,
+
+	/// There is no system call with this number
+	NoSuchSyscall = 38,
+}
+
+/// A system call, with the registers of the program (cf. [arguments])
+pub type SyscallHandler = fn(&mut TrapFrame) -> Result<u32, Errno>;
+
+/// The system calls, indexed by number
+static SYSCALL_TABLE: [Option<SyscallHandler>; SYSCALL_COUNT] = {
+	let mut table: [Option<SyscallHandler>; SYSCALL_COUNT] = [None; SYSCALL_COUNT];
+	table[SYS_EXIT] = Some(sys_exit);
+	table[SYS_FORK] = Some(sys_fork);
+	table[SYS_WRITE] = Some(sys_write);
+	table[SYS_WAITPID] = Some(sys_waitpid);
+	table[SYS_GETPID] = Some(sys_getpid);
+	table[SYS_GETPPID] = Some(sys_getppid);
+	table[SYS_BRK] = Some(sys_brk);
+	table[SYS_KILL] = Some(sys_kill);
+	table[SYS_SIGACTION] = Some(sys_sigaction);
+	table[SYS_SIGRETURN] = Some(sys_sigreturn);
+	table[SYS_SIGPROCMASK] = Some(sys_sigprocmask);
+	table[SYS_YIELD] = Some(sys_yield);
+	table
+};
+
+/// Handles `int 0x80` (the IDT entry is set by [crate::idt::interrupts::init_interrupt_handlers])
+pub fn init_syscalls() {
+	// Safety: only called during init, with interrupts disabled
+	unsafe { register_trap_handler(SYSCALL_VECTOR, syscall_handler) };
+}
+
+fn syscall_handler(frame: &mut TrapFrame) {
+	// the interrupt gate disabled them, but system calls may block
+	unsafe { enable_hardware_interrupts() };
+
+	let handler = SYSCALL_TABLE.get(frame.registers.eax as usize).copied().flatten();
+
+	let result = match handler {
+		Some(handler) => handler(frame),
+		None => Err(Errno::NoSuchSyscall),
+	};
+
+	frame.registers.eax = match result {
+		Ok(value) => value,
+		Err(errno) => (-(errno as i32)) as u32,
+	};
+}
+
+/// The arguments of a system call: ebx, ecx and edx
+fn arguments(frame: &TrapFrame) -> [u32; 3] {
+	[frame.registers.ebx, frame.registers.ecx, frame.registers.edx]
+}
+
+fn sys_exit(frame: &mut TrapFrame) -> Result<u32, Errno> {
+	let [code, ..] = arguments(frame);
+	return_to_kernel(code as i32)
+}
+
+fn sys_fork(frame: &mut TrapFrame) -> Result<u32, Errno> {
+	// Safety: the address space of the program, that run_user_program (or run_forked_child)
+	// doesn't use until the program ends
+	let mut address_space = unsafe { AddressSpace::active() };
+	let child_address_space = address_space.fork().map_err(|_| Errno::OutOfMemory)?;
+
+	// the child returns 0 from the same system call
+	let mut context = UserContext::from_frame(frame);
+	context.eax = 0;
+
+	let child = add_child().ok_or(Errno::TryAgain)?;
+
+	// the closure, with the child address space, is dropped if the task can't be created
+	spawn_kernel_thread(current_task_name(), move || {
+		run_forked_child(child, child_address_space, context)
+	})
+	.inspect_err(|_| remove_child(child))
+	.map_err(|_| Errno::TryAgain)?;
+
+	Ok(child.0)
+}
+
+fn sys_write(frame: &mut TrapFrame) -> Result<u32, Errno> {
+	let [fd, buffer, length] = arguments(frame);
+	if fd != STDOUT && fd != STDERR {
+		return Err(Errno::BadFileDescriptor);
+	}
+
+	let bytes = user_buffer(buffer, length)?;
+	for chunk in bytes.utf8_chunks() {
+		print!("{}", chunk.valid());
+		if !chunk.invalid().is_empty() {
+			print!("{}", char::REPLACEMENT_CHARACTER);
+		}
+	}
+
+	Ok(length)
+}
+
+fn sys_waitpid(frame: &mut TrapFrame) -> Result<u32, Errno> {
+	let [pid, status, options] = arguments(frame);
+	if options & !WNOHANG != 0 {
+		return Err(Errno::InvalidArgument);
+	}
+
+	// process groups (0 and below -1) don't exist
+	let pid = match pid as i32 {
+		-1 => None,
+		pid if pid > 0 => Some(Pid(pid as u32)),
+		_ => return Err(Errno::InvalidArgument),
+	};
+
+	// checked before a child is reaped
+	let status = match status {
+		0 => None,
+		address => Some(user_buffer_mut(address, 4)?),
+	};
+
+	let result = wait_child(pid, options & WNOHANG != 0).map_err(|error| match error {
+		WaitError::NotAProcess | WaitError::NoChild => Errno::NoChild,
+		WaitError::Interrupted => Errno::Interrupted,
+	})?;
+
+	let Some((child, exit_status)) = result else {
+		return Ok(0);
+	};
+
+	if let Some(status) = status {
+		status.copy_from_slice(&exit_status.wait_status().to_le_bytes());
+	}
+	Ok(child.0)
+}
+
+fn sys_getpid(_: &mut TrapFrame) -> Result<u32, Errno> {
+	Ok(current_pid().map_or(0, |pid| pid.0))
+}
+
+fn sys_getppid(_: &mut TrapFrame) -> Result<u32, Errno> {
+	Ok(current_parent_pid().map_or(0, |pid| pid.0))
+}
+
+fn sys_kill(frame: &mut TrapFrame) -> Result<u32, Errno> {
+	let [pid, signal, _] = arguments(frame);
+
+	// process groups (0 and below -1) don't exist, and -1 can't signal every process
+	if pid as i32 <= 0 {
+		return Err(Errno::InvalidArgument);
+	}
+
+	send_signal(Pid(pid), signal).map_err(signal_errno)?;
+	Ok(0)
+}
+
+fn sys_brk(frame: &mut TrapFrame) -> Result<u32, Errno> {
+	let [address, ..] = arguments(frame);
+
+	// Safety: cf. sys_fork
+	let mut address_space = unsafe { AddressSpace::active() };
+	Ok(address_space.set_break(address))
+}
+
+fn sys_sigaction(frame: &mut TrapFrame) -> Result<u32, Errno> {
+	let [signal, action, old_action] = arguments(frame);
+	let size = size_of::<SigAction>() as u32;
+
+	// checked before the action changes
+	let old_action = match old_action {
+		0 => None,
+		address => Some(user_buffer_mut(address, size)?),
+	};
+	let action = match action {
+		0 => None,
+		address => {
+			let bytes = user_buffer(address, size)?;
+			Some(unsafe { (bytes.as_ptr() as *const SigAction).read_unaligned() })
+		}
+	};
+
+	let previous = set_signal_action(signal, action).map_err(signal_errno)?;
+	if let Some(old_action) = old_action {
+		unsafe { (old_action.as_mut_ptr() as *mut SigAction).write_unaligned(previous) };
+	}
+	Ok(0)
+}
+
+fn sys_sigreturn(frame: &mut TrapFrame) -> Result<u32, Errno> {
+	let address = frame.user_esp.wrapping_sub(SignalFrame::SIGRETURN_OFFSET);
+
+	let Ok(bytes) = user_buffer(address, size_of::<SignalFrame>() as u32) else {
+		// the program has nowhere to resume
+		force_signal(SIGSEGV);
+		return Err(Errno::BadAddress);
+	};
+	let saved = unsafe { (bytes.as_ptr() as *const SignalFrame).read_unaligned() };
+
+	// eax is restored too
+	Ok(restore_signal_frame(frame, &saved))
+}
+
+fn sys_sigprocmask(frame: &mut TrapFrame) -> Result<u32, Errno> {
+	let [how, set, old_set] = arguments(frame);
+
+	let old_set = match old_set {
+		0 => None,
+		address => Some(user_buffer_mut(address, 4)?),
+	};
+	let change = match set {
+		0 => None,
+		address => {
+			let mut mask = [0; 4];
+			mask.copy_from_slice(user_buffer(address, 4)?);
+			let mask = u32::from_le_bytes(mask);
+
+			Some(match how {
+				SIG_BLOCK => MaskChange::Block(mask),
+				SIG_UNBLOCK => MaskChange::Unblock(mask),
+				SIG_SETMASK => MaskChange::Set(mask),
+				_ => return Err(Errno::InvalidArgument),
+			})
+		}
+	};
+
+	let previous = change_blocked_signals(change).map_err(signal_errno)?;
+	if let Some(old_set) = old_set {
+		old_set.copy_from_slice(&previous.to_le_bytes());
+	}
+	Ok(0)
+}
+
+fn sys_yield(_: &mut TrapFrame) -> Result<u32, Errno> {
+	yield_now();
+	Ok(0)
+}
+
+fn signal_errno(error: SignalError) -> Errno {
+	match error {
+		SignalError::NoProcess => Errno::NoSuchProcess,
+		SignalError::InvalidSignal => Errno::InvalidArgument,
+	}
+}
+
+/// Returns the `length` bytes at `address`, if they are in mapped user pages, or in memory areas
+/// of the program (their pages are mapped when the kernel reads them)
+fn user_buffer(address: u32, length: u32) -> Result<&'static [u8], Errno> {
+	// no page to check, and a slice can't start at NULL
+	if length == 0 {
+		return Ok(&[]);
+	}
+	if address == 0 {
+		return Err(Errno::BadAddress);
+	}
+
+	let end = address.checked_add(length).ok_or(Errno::BadAddress)?;
+	if end > USER_STACK_TOP {
+		return Err(Errno::BadAddress);
+	}
+
+	let first_page = address & !(FRAME_SIZE as u32 - 1);
+	for page in (first_page..end).step_by(FRAME_SIZE) {
+		match unsafe { PageDirectory::translate(page) } {
+			Some((_, flags)) if flags.is_user_space() => {}
+			None if active_area(page).is_some() => {}
+			_ => return Err(Errno::BadAddress),
+		}
+	}
+
+	Ok(unsafe { core::slice::from_raw_parts(address as *const u8, length as usize) })
+}
+
+/// Returns the `length` bytes at `address`, if they are in user pages the program can write to
+/// (cf. [user_buffer])
+fn user_buffer_mut(address: u32, length: u32) -> Result<&'static mut [u8], Errno> {
+	// no page to check, and a slice can't start at NULL
+	if length == 0 {
+		return Ok(&mut []);
+	}
+	if address == 0 {
+		return Err(Errno::BadAddress);
+	}
+
+	let end = address.checked_add(length).ok_or(Errno::BadAddress)?;
+	if end > USER_STACK_TOP {
+		return Err(Errno::BadAddress);
+	}
+
+	let first_page = address & !(FRAME_SIZE as u32 - 1);
+	for page in (first_page..end).step_by(FRAME_SIZE) {
+		// a copy-on-write page is copied when the kernel writes to it
+		match unsafe { PageDirectory::translate(page) } {
+			Some((_, flags))
+				if flags.is_user_space() && (flags.is_writable() || flags.is_copy_on_write()) => {}
+			None if active_area(page).is_some_and(|area| area.is_writable) => {}
+			_ => return Err(Errno::BadAddress),
+		}
+	}
+
+	Ok(unsafe { core::slice::from_raw_parts_mut(address as *mut u8, length as usize) })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test_case]
+	fn kernel_and_unmapped_buffers_are_rejected() {
+		// the kernel half
+		assert_eq!(user_buffer(0xc010_0000, 16), Err(Errno::BadAddress));
+		assert_eq!(user_buffer(USER_STACK_TOP - 4, 8), Err(Errno::BadAddress));
+		assert_eq!(user_buffer(u32::MAX - 4, 8), Err(Errno::BadAddress));
+
+		// nothing is mapped in user space outside of programs
+		assert_eq!(user_buffer(0x0804_8000, 16), Err(Errno::BadAddress));
+		assert_eq!(user_buffer_mut(0x0804_8000, 4), Err(Errno::BadAddress));
+
+		// NULL
+		assert_eq!(user_buffer(0, 4), Err(Errno::BadAddress));
+		assert_eq!(user_buffer(0, 0), Ok(&[][..]));
+	}
+
+	#[test_case]
+	fn unknown_system_calls_fail() {
+		assert!(SYSCALL_TABLE[0].is_none());
+		assert!(SYSCALL_TABLE[SYS_WRITE].is_some());
+	}
+}
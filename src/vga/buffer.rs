@@ -1,10 +1,12 @@
 use volatile::Volatile;
 
+use crate::paging::KERNEL_VIRTUAL_BASE;
 use crate::vga::ColorCode;
 
 pub const VGA_BUFFER_HEIGHT: usize = 25;
 pub const VGA_BUFFER_WIDTH: usize = 80;
-pub const VGA_BUFFER_ADDRESS: *mut u16 = 0xb8000 as *mut u16;
+/// The VGA text buffer, at the physical address 0xB8000
+pub const VGA_BUFFER_ADDRESS: *mut u16 = (KERNEL_VIRTUAL_BASE + 0xb8000) as *mut u16;
 
 #[repr(transparent)]
 pub struct Buffer {
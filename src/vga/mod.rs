@@ -27,6 +27,7 @@ pub use self::screens::{
 	handle_shortcut_switch_screen,
 	switch_screen,
 };
+use crate::serial;
 
 lazy_static! {
 	/// Global [VgaScreen] protected by a [Mutex] to write to the VGA screen
@@ -49,6 +50,10 @@ lazy_static! {
 pub fn _print(args: core::fmt::Arguments) {
 	use core::fmt::Write;
 	GLOBAL_VGA_SCREEN.lock().write_fmt(args).unwrap();
+
+	if serial::is_console_mirrored() {
+		serial::_print(args);
+	}
 }
 
 /// Holds a reference to the VGA buffer
@@ -205,3 +210,33 @@ impl VgaScreen {
 		self.move_cursor_to_current_pos()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::println;
+
+	#[test_case]
+	fn println_simple() {
+		println!("println_simple output");
+	}
+
+	#[test_case]
+	fn println_many_lines_scrolls() {
+		for _ in 0..200 {
+			println!("println_many_lines_scrolls output");
+		}
+	}
+
+	#[test_case]
+	fn println_writes_to_buffer() {
+		let s = "Some test string that fits on a single line";
+		println!("\n{s}");
+
+		let screen = GLOBAL_VGA_SCREEN.lock();
+		for (i, c) in s.bytes().enumerate() {
+			let screen_char = screen.buffer.read(screen.row_position - 1, i);
+			assert_eq!(screen_char.byte, c);
+		}
+	}
+}
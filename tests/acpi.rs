@@ -0,0 +1,46 @@
+//! Boots a kernel and checks the ACPI tables QEMU provides
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel::acpi_tables;
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _entrypoint(magic_number: u32, multiboot_info_ptr: u32) -> ! {
+	kernel::init(magic_number, multiboot_info_ptr);
+
+	test_main();
+
+	unreachable!()
+}
+
+#[test_case]
+fn rsdp_is_found() {
+	assert!(acpi_tables(|tables| tables.oem_id).is_some());
+}
+
+#[test_case]
+fn madt_describes_the_io_apic() {
+	let madt = acpi_tables(|tables| tables.madt).flatten().expect("QEMU has a MADT");
+
+	assert!(madt.processor_count >= 1);
+	assert_eq!(madt.io_apic_count, 1);
+	assert_eq!(madt.io_apics[0].map(|io_apic| io_apic.address), Some(0xfec0_0000));
+
+	// QEMU routes the PIT (IRQ 0) to GSI 2
+	assert!(madt.overrides.iter().flatten().any(|o| o.source == 0 && o.gsi == 2));
+}
+
+#[test_case]
+fn fadt_and_s5_are_found() {
+	let (fadt, s5_sleep_types) =
+		acpi_tables(|tables| (tables.fadt, tables.s5_sleep_types)).expect("QEMU has ACPI");
+
+	let fadt = fadt.expect("QEMU has a FADT");
+	assert_ne!(fadt.dsdt, 0);
+	assert_ne!(fadt.pm1a_control_block, 0);
+	assert!(s5_sleep_types.is_some());
+}
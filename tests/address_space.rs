@@ -0,0 +1,381 @@
+//! Boots a kernel and checks the address spaces of the user programs
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel::{
+	AddressSpace,
+	AreaKind,
+	MapError,
+	MemoryArea,
+	PageDirectory,
+	active_page_directory,
+	frame_references,
+	frame_stats,
+	kernel_page_directory,
+	kfree,
+	kmalloc,
+	resolve_demand_fault,
+};
+
+/// An unused user address
+const USER_VADDR: u32 = 0x0804_8000;
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _entrypoint(magic_number: u32, multiboot_info_ptr: u32) -> ! {
+	kernel::init(magic_number, multiboot_info_ptr);
+
+	test_main();
+
+	unreachable!()
+}
+
+#[test_case]
+fn kernel_half_is_shared() {
+	let address_space = AddressSpace::new().expect("Out of memory");
+	let code = _entrypoint as *const () as u32;
+
+	assert_eq!(
+		address_space.translate(code).map(|(physical, _)| physical),
+		unsafe { PageDirectory::translate(code) }.map(|(physical, _)| physical)
+	);
+	assert!(address_space.translate(USER_VADDR).is_none());
+}
+
+#[test_case]
+fn inactive_address_spaces_can_be_edited() {
+	let mut address_space = AddressSpace::new().expect("Out of memory");
+	assert!(!address_space.is_active());
+
+	let frame = address_space.map_zeroed_page(USER_VADDR, true, true).expect("Out of memory");
+	address_space.write(USER_VADDR + 8, b"inactive").unwrap();
+
+	let (physical, flags) = address_space.translate(USER_VADDR).expect("the page is mapped");
+	assert_eq!(physical, frame);
+	assert!(flags.is_user_space() && flags.is_writable());
+
+	// the active directory doesn't see it
+	assert!(unsafe { PageDirectory::translate(USER_VADDR) }.is_none());
+	assert_eq!(active_page_directory(), kernel_page_directory());
+}
+
+#[test_case]
+fn activate_switches_the_user_half() {
+	let mut address_space = AddressSpace::new().expect("Out of memory");
+	address_space.map_zeroed_page(USER_VADDR, false, true).expect("Out of memory");
+	address_space.write(USER_VADDR, &0xcafe_babe_u32.to_le_bytes()).unwrap();
+
+	address_space.activate();
+	assert!(address_space.is_active());
+	assert_eq!(active_page_directory(), address_space.directory_address());
+
+	let value = unsafe { (USER_VADDR as *const u32).read_volatile() };
+	assert_eq!(value, 0xcafe_babe);
+
+	// mapped while active
+	let frame = kmalloc().expect("Out of memory");
+	address_space.map_page(USER_VADDR + 0x1000, frame, false, true).unwrap();
+	unsafe { ((USER_VADDR + 0x1000) as *mut u32).write_volatile(42) };
+
+	// dropping the active address space goes back to the kernel one
+	drop(address_space);
+	assert_eq!(active_page_directory(), kernel_page_directory());
+	assert!(unsafe { PageDirectory::translate(USER_VADDR) }.is_none());
+}
+
+#[test_case]
+fn address_spaces_are_independent() {
+	let mut first = AddressSpace::new().expect("Out of memory");
+	let mut second = AddressSpace::new().expect("Out of memory");
+
+	first.map_zeroed_page(USER_VADDR, true, false).expect("Out of memory");
+	second.map_zeroed_page(USER_VADDR, true, true).expect("Out of memory");
+	first.write(USER_VADDR, b"first").unwrap();
+	second.write(USER_VADDR, b"second").unwrap();
+
+	let frame = |address_space: &AddressSpace| address_space.translate(USER_VADDR).unwrap().0;
+	assert_ne!(frame(&first), frame(&second));
+
+	for (address_space, expected) in [(&first, b"first".as_slice()), (&second, b"second")] {
+		address_space.activate();
+		let bytes = unsafe { core::slice::from_raw_parts(USER_VADDR as *const u8, expected.len()) };
+		assert_eq!(bytes, expected);
+	}
+
+	unsafe { PageDirectory::translate(USER_VADDR) }.expect("second is active");
+	drop(second);
+	assert_eq!(active_page_directory(), kernel_page_directory());
+}
+
+#[test_case]
+fn kernel_addresses_are_rejected() {
+	let mut address_space = AddressSpace::new().expect("Out of memory");
+	let frame = kmalloc().expect("Out of memory");
+
+	assert_eq!(
+		address_space.map_page(0xe000_0000, frame, false, true),
+		Err(MapError::KernelAddress(0xe000_0000))
+	);
+	assert_eq!(address_space.unmap_page(0xc010_0000), None);
+	assert_eq!(address_space.write(USER_VADDR, b"x"), Err(MapError::NotMapped(USER_VADDR)));
+
+	kfree(frame);
+}
+
+#[test_case]
+fn unmapped_frames_are_given_back() {
+	let mut address_space = AddressSpace::new().expect("Out of memory");
+	let frame = address_space.map_zeroed_page(USER_VADDR, true, true).expect("Out of memory");
+
+	assert_eq!(address_space.unmap_page(USER_VADDR), Some(frame));
+	assert_eq!(address_space.unmap_page(USER_VADDR), None);
+	assert!(address_space.translate(USER_VADDR).is_none());
+
+	drop(address_space);
+
+	// not freed by the address space
+	kfree(frame);
+}
+
+#[test_case]
+fn dropping_frees_every_frame() {
+	let free_frames = frame_stats().free_frames;
+
+	let mut address_space = AddressSpace::new().expect("Out of memory");
+	for page in 0..8 {
+		// in several page tables
+		let address = USER_VADDR + page * 0x20_0000;
+		address_space.map_zeroed_page(address, true, true).expect("Out of memory");
+	}
+	assert!(frame_stats().free_frames < free_frames);
+
+	drop(address_space);
+	assert_eq!(frame_stats().free_frames, free_frames);
+}
+
+#[test_case]
+fn fork_shares_the_pages() {
+	let mut parent = AddressSpace::new().expect("Out of memory");
+	let writable = parent.map_zeroed_page(USER_VADDR, true, true).expect("Out of memory");
+	let read_only =
+		parent.map_zeroed_page(USER_VADDR + 0x1000, true, false).expect("Out of memory");
+
+	let child = parent.fork().expect("Out of memory");
+
+	for address_space in [&parent, &child] {
+		let (frame, flags) = address_space.translate(USER_VADDR).unwrap();
+		assert_eq!(frame, writable);
+		assert!(!flags.is_writable() && flags.is_copy_on_write() && flags.is_user_space());
+
+		let (frame, flags) = address_space.translate(USER_VADDR + 0x1000).unwrap();
+		assert_eq!(frame, read_only);
+		assert!(!flags.is_writable() && !flags.is_copy_on_write());
+	}
+
+	assert_eq!(frame_references(writable), 2);
+	assert_eq!(frame_references(read_only), 2);
+
+	drop(child);
+	assert_eq!(frame_references(writable), 1);
+}
+
+/// Writes `value` at [USER_VADDR] in `address_space`, through the CPU (so copy-on-write pages
+/// fault)
+fn write_through_cpu(address_space: &AddressSpace, value: u32) {
+	address_space.activate();
+	unsafe { (USER_VADDR as *mut u32).write_volatile(value) };
+}
+
+fn read_through_cpu(address_space: &AddressSpace) -> u32 {
+	address_space.activate();
+	unsafe { (USER_VADDR as *const u32).read_volatile() }
+}
+
+#[test_case]
+fn writes_copy_shared_pages() {
+	let mut parent = AddressSpace::new().expect("Out of memory");
+	let frame = parent.map_zeroed_page(USER_VADDR, true, true).expect("Out of memory");
+	parent.write(USER_VADDR, &1u32.to_le_bytes()).unwrap();
+
+	let child = parent.fork().expect("Out of memory");
+
+	// the parent gets a copy, the child keeps the frame
+	write_through_cpu(&parent, 2);
+
+	let (parent_frame, flags) = parent.translate(USER_VADDR).unwrap();
+	assert_ne!(parent_frame, frame);
+	assert!(flags.is_writable() && !flags.is_copy_on_write());
+	assert_eq!(frame_references(frame), 1);
+
+	assert_eq!(read_through_cpu(&child), 1);
+	assert_eq!(read_through_cpu(&parent), 2);
+
+	// the child is the last user of the frame, so it takes it back without a copy
+	write_through_cpu(&child, 3);
+	let (child_frame, flags) = child.translate(USER_VADDR).unwrap();
+	assert_eq!(child_frame, frame);
+	assert!(flags.is_writable() && !flags.is_copy_on_write());
+
+	assert_eq!(read_through_cpu(&child), 3);
+	assert_eq!(read_through_cpu(&parent), 2);
+
+	drop(child);
+	drop(parent);
+	assert_eq!(active_page_directory(), kernel_page_directory());
+}
+
+#[test_case]
+fn forks_release_every_frame() {
+	let free_frames = frame_stats().free_frames;
+
+	let mut parent = AddressSpace::new().expect("Out of memory");
+	for page in 0..4 {
+		parent.map_zeroed_page(USER_VADDR + page * 0x1000, true, true).expect("Out of memory");
+	}
+
+	let mut child = parent.fork().expect("Out of memory");
+	let grandchild = child.fork().expect("Out of memory");
+	write_through_cpu(&child, 1);
+
+	drop(parent);
+	drop(grandchild);
+	drop(child);
+	assert_eq!(frame_stats().free_frames, free_frames);
+}
+
+#[test_case]
+fn overlapping_areas_are_rejected() {
+	let mut address_space = AddressSpace::new().expect("Out of memory");
+	let area = MemoryArea::new(USER_VADDR, USER_VADDR + 0x2000, AreaKind::Anonymous, true);
+	address_space.add_area(area).unwrap();
+
+	let overlapping =
+		MemoryArea::new(USER_VADDR + 0x1000, USER_VADDR + 0x3000, AreaKind::Heap, true);
+	assert_eq!(address_space.add_area(overlapping), Err(MapError::AreaInUse(USER_VADDR + 0x1000)));
+
+	// a stack reserves the range it grows into
+	let stack = MemoryArea::new(
+		USER_VADDR + 0x10_0000,
+		USER_VADDR + 0x11_0000,
+		AreaKind::Stack {
+			limit: USER_VADDR + 0x8000,
+		},
+		true,
+	);
+	address_space.add_area(stack).unwrap();
+
+	let below_stack =
+		MemoryArea::new(USER_VADDR + 0x9000, USER_VADDR + 0xa000, AreaKind::Anonymous, true);
+	assert_eq!(address_space.add_area(below_stack), Err(MapError::AreaInUse(USER_VADDR + 0x9000)));
+
+	let kernel = MemoryArea::new(0xbfff_f000, 0xc000_1000, AreaKind::Anonymous, true);
+	assert_eq!(address_space.add_area(kernel), Err(MapError::KernelAddress(0xc000_1000)));
+
+	assert_eq!(address_space.areas(), [area, stack]);
+	assert_eq!(address_space.area(USER_VADDR + 0x1fff), Some(area));
+	assert_eq!(address_space.area(USER_VADDR + 0x2000), None);
+}
+
+#[test_case]
+fn areas_are_mapped_on_the_first_access() {
+	let free_frames = frame_stats().free_frames;
+
+	let mut address_space = AddressSpace::new().expect("Out of memory");
+	let area = MemoryArea::new(USER_VADDR, USER_VADDR + 0x4000, AreaKind::Anonymous, true);
+	address_space.add_area(area).unwrap();
+	assert!(address_space.translate(USER_VADDR).is_none());
+
+	write_through_cpu(&address_space, 5);
+	assert_eq!(read_through_cpu(&address_space), 5);
+
+	let (_, flags) = address_space.translate(USER_VADDR).expect("the fault mapped the page");
+	assert!(flags.is_user_space() && flags.is_writable());
+	assert!(address_space.translate(USER_VADDR + 0x1000).is_none());
+
+	drop(address_space);
+	assert_eq!(frame_stats().free_frames, free_frames);
+}
+
+#[test_case]
+fn faults_must_match_the_area() {
+	let mut address_space = AddressSpace::new().expect("Out of memory");
+	let read_only = MemoryArea::new(USER_VADDR, USER_VADDR + 0x1000, AreaKind::Anonymous, false);
+	address_space.add_area(read_only).unwrap();
+	address_space.activate();
+
+	assert!(!resolve_demand_fault(USER_VADDR, true, None));
+	assert!(!resolve_demand_fault(USER_VADDR + 0x1000, false, None));
+	assert!(!resolve_demand_fault(0xc010_0000, false, None));
+
+	assert!(resolve_demand_fault(USER_VADDR, false, None));
+	let (_, flags) = address_space.translate(USER_VADDR).unwrap();
+	assert!(!flags.is_writable());
+}
+
+#[test_case]
+fn stacks_grow_down_to_their_limit() {
+	let mut address_space = AddressSpace::new().expect("Out of memory");
+	let top = USER_VADDR + 0x40_0000;
+	let limit = top - 0x20_0000;
+	let stack = MemoryArea::new(
+		top - 0x1000,
+		top,
+		AreaKind::Stack {
+			limit,
+		},
+		true,
+	);
+	address_space.add_area(stack).unwrap();
+	address_space.activate();
+
+	// too far below the stack pointer
+	assert!(!resolve_demand_fault(limit, true, Some(top - 4)));
+
+	assert!(resolve_demand_fault(limit + 0x10, true, Some(limit + 0x20)));
+	assert_eq!(address_space.area(limit).map(|area| area.start), Some(limit));
+
+	// below the limit
+	assert!(!resolve_demand_fault(limit - 4, true, None));
+}
+
+#[test_case]
+fn break_moves_the_end_of_the_heap() {
+	let mut address_space = AddressSpace::new().expect("Out of memory");
+	assert_eq!(address_space.set_break(USER_VADDR), 0);
+
+	let heap = MemoryArea::new(USER_VADDR, USER_VADDR, AreaKind::Heap, true);
+	address_space.add_area(heap).unwrap();
+	let next = MemoryArea::new(USER_VADDR + 0x4000, USER_VADDR + 0x5000, AreaKind::Anonymous, true);
+	address_space.add_area(next).unwrap();
+
+	assert_eq!(address_space.set_break(0), USER_VADDR);
+	assert_eq!(address_space.set_break(USER_VADDR + 0x1004), USER_VADDR + 0x1004);
+
+	// it would reach the next area
+	assert_eq!(address_space.set_break(USER_VADDR + 0x4001), USER_VADDR + 0x1004);
+
+	write_through_cpu(&address_space, 1);
+	assert!(address_space.translate(USER_VADDR).is_some());
+
+	// the pages above the new break are released
+	assert_eq!(address_space.set_break(USER_VADDR), USER_VADDR);
+	assert!(address_space.translate(USER_VADDR).is_none());
+}
+
+#[test_case]
+fn forks_copy_the_areas() {
+	let mut parent = AddressSpace::new().expect("Out of memory");
+	let area = MemoryArea::new(USER_VADDR, USER_VADDR + 0x1000, AreaKind::Anonymous, true);
+	parent.add_area(area).unwrap();
+
+	let mut child = parent.fork().expect("Out of memory");
+	assert_eq!(child.areas(), [area]);
+
+	// they are independent
+	let heap = MemoryArea::new(USER_VADDR + 0x1000, USER_VADDR + 0x1000, AreaKind::Heap, true);
+	child.add_area(heap).unwrap();
+	assert_eq!(parent.areas(), [area]);
+}
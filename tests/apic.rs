@@ -0,0 +1,56 @@
+//! Boots a kernel and checks that the IRQs go through the APICs (QEMU has them)
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel::{
+	Irq,
+	apic_timer_ticks,
+	irq_controller_name,
+	irq_stats,
+	sleep_ms,
+};
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _entrypoint(magic_number: u32, multiboot_info_ptr: u32) -> ! {
+	kernel::init(magic_number, multiboot_info_ptr);
+
+	test_main();
+
+	unreachable!()
+}
+
+fn timer_irq_count() -> u64 {
+	let mut count = 0;
+	irq_stats(|line| {
+		if line.irq == Irq::Timer {
+			count = line.count;
+		}
+	});
+	count
+}
+
+#[test_case]
+fn io_apic_replaces_the_pics() {
+	assert_eq!(irq_controller_name(), "I/O APIC");
+}
+
+#[test_case]
+fn pit_interrupts_are_routed_through_the_io_apic() {
+	let count = timer_irq_count();
+	sleep_ms(20);
+
+	assert!(timer_irq_count() - count >= 19);
+}
+
+#[test_case]
+fn local_apic_timer_runs() {
+	let ticks = apic_timer_ticks();
+	sleep_ms(50);
+
+	// 100 Hz
+	assert!(apic_timer_ticks() - ticks >= 3);
+}
@@ -0,0 +1,99 @@
+//! Boots a kernel and checks the GDT loaded by `kernel::init`
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::arch::asm;
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _entrypoint(magic_number: u32, multiboot_info_ptr: u32) -> ! {
+	kernel::init(magic_number, multiboot_info_ptr);
+
+	test_main();
+
+	unreachable!()
+}
+
+#[repr(C, packed)]
+struct DescriptorTablePointer {
+	limit: u16,
+	base: u32,
+}
+
+fn sgdt() -> DescriptorTablePointer {
+	let mut ptr = DescriptorTablePointer {
+		limit: 0,
+		base: 0,
+	};
+	unsafe { asm!("sgdt [{}]", in(reg) &mut ptr) };
+	ptr
+}
+
+#[test_case]
+fn gdt_is_loaded() {
+	let ptr = sgdt();
+	let limit = ptr.limit;
+
+	// null + kernel code/data/stack + user code/data/stack + 2 TSS
+	assert!(limit as usize + 1 >= 9 * 8);
+	assert_eq!((limit as usize + 1) % 8, 0);
+}
+
+#[test_case]
+fn first_gdt_entry_is_null() {
+	let ptr = sgdt();
+	let null_entry = unsafe { *(ptr.base as *const u64) };
+
+	assert_eq!(null_entry, 0);
+}
+
+#[test_case]
+fn kernel_segments_are_loaded() {
+	let (cs, ds, ss): (u16, u16, u16);
+	unsafe {
+		asm!("mov {:x}, cs", out(reg) cs);
+		asm!("mov {:x}, ds", out(reg) ds);
+		asm!("mov {:x}, ss", out(reg) ss);
+	}
+
+	assert_eq!(cs, 0x08);
+	assert_eq!(ds, 0x10);
+	assert_eq!(ss, 0x18);
+}
+
+#[test_case]
+fn user_segments_have_privilege_3() {
+	let ptr = sgdt();
+
+	for index in 4..7 {
+		let entry = unsafe { *((ptr.base as *const u64).add(index)) };
+
+		// DPL is in bits 45-46 of the descriptor
+		assert_eq!((entry >> 45) & 0b11, 3);
+	}
+}
+
+#[test_case]
+fn task_register_is_loaded() {
+	let selector: u16;
+	unsafe { asm!("str {:x}", out(reg) selector) };
+
+	assert_eq!(selector, 0x38);
+}
+
+#[test_case]
+fn tss_descriptors_are_present() {
+	let ptr = sgdt();
+
+	for index in [7, 8] {
+		let entry = unsafe { *((ptr.base as *const u64).add(index)) };
+
+		// present bit, system segment, 32-bit TSS (0x9 when available, 0xB when busy)
+		assert_ne!(entry & (1 << 47), 0);
+		assert_eq!(entry & (1 << 44), 0);
+		assert_eq!((entry >> 40) & 0b1101, 0x9);
+	}
+}
@@ -0,0 +1,145 @@
+//! Boots a kernel and checks that the global allocator works
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel::KmemCache;
+use kernel::alloc::boxed::Box;
+use kernel::alloc::rc::Rc;
+use kernel::alloc::string::String;
+use kernel::alloc::vec::Vec;
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _entrypoint(magic_number: u32, multiboot_info_ptr: u32) -> ! {
+	kernel::init(magic_number, multiboot_info_ptr);
+
+	test_main();
+
+	unreachable!()
+}
+
+#[test_case]
+fn box_allocation() {
+	let heap_value = Box::new(42);
+	assert_eq!(*heap_value, 42);
+}
+
+#[test_case]
+fn vec_reallocation_and_growth() {
+	let mut vec = Vec::with_capacity(500);
+	for i in 0..500 {
+		vec.push(i);
+	}
+	assert_eq!(vec.len(), 500);
+	assert_eq!(vec[499], 499);
+}
+
+#[test_case]
+fn string_allocation() {
+	let mut s = String::from("Kernel ");
+	s.push_str("Global Allocator");
+	assert_eq!(s, "Kernel Global Allocator");
+}
+
+#[test_case]
+fn rc_allocation() {
+	let rc = Rc::new(100);
+	let rc_clone = Rc::clone(&rc);
+	assert_eq!(Rc::strong_count(&rc), 2);
+	drop(rc_clone);
+	assert_eq!(Rc::strong_count(&rc), 1);
+}
+
+/// If `dealloc` is broken, this loop will exhaust the kernel heap quickly
+#[test_case]
+fn memory_churn() {
+	for i in 0..10_000 {
+		let x = Box::new(i);
+		assert_eq!(*x, i);
+	}
+}
+
+#[test_case]
+fn many_live_boxes() {
+	let boxes: Vec<Box<u32>> = (0..1000).map(Box::new).collect();
+
+	for (i, b) in boxes.iter().enumerate() {
+		assert_eq!(**b, i as u32);
+	}
+}
+
+/// Freed neighbours are merged, so the heap isn't fragmented once small objects are freed
+#[test_case]
+fn large_allocation_after_fragmentation() {
+	{
+		let boxes: Vec<Box<u64>> = (0..2000).map(Box::new).collect();
+		drop(boxes);
+	}
+
+	let big: Vec<u8> = Vec::with_capacity(100 * 1024);
+	assert_eq!(big.capacity(), 100 * 1024);
+}
+
+/// The heap starts with 128 KiB, and maps more frames on demand
+#[test_case]
+fn allocation_bigger_than_initial_heap() {
+	let mut big: Vec<u32> = Vec::with_capacity(256 * 1024);
+	for i in 0..big.capacity() {
+		big.push(i as u32);
+	}
+
+	assert_eq!(big[256 * 1024 - 1], 256 * 1024 - 1);
+}
+
+#[test_case]
+fn many_allocations_grow_the_heap() {
+	let boxes: Vec<Box<[u8; 1024]>> = (0..512).map(|i| Box::new([i as u8; 1024])).collect();
+
+	for (i, b) in boxes.iter().enumerate() {
+		assert!(b.iter().all(|&byte| byte == i as u8));
+	}
+}
+
+/// Small allocations of different size classes don't overlap
+#[test_case]
+fn mixed_size_classes() {
+	let small: Vec<Box<u8>> = (0..300).map(|i| Box::new(i as u8)).collect();
+	let medium: Vec<Box<[u16; 100]>> = (0..300).map(|i| Box::new([i as u16; 100])).collect();
+
+	for (i, (s, m)) in small.iter().zip(medium.iter()).enumerate() {
+		assert_eq!(**s, i as u8);
+		assert!(m.iter().all(|&x| x == i as u16));
+	}
+}
+
+/// Growing a vector moves it across size classes, then to the linked list
+#[test_case]
+fn reallocation_across_size_classes() {
+	let mut v: Vec<u8> = Vec::new();
+	for i in 0..10_000 {
+		v.push(i as u8);
+	}
+
+	assert!(v.iter().enumerate().all(|(i, &x)| x == i as u8));
+}
+
+#[test_case]
+fn kmem_cache_objects() {
+	static CACHE: KmemCache<[u32; 6]> = KmemCache::new("test");
+
+	let objects: Vec<_> = (0..500).map(|i| CACHE.alloc([i; 6]).unwrap()).collect();
+	for (i, object) in objects.iter().enumerate() {
+		assert_eq!(unsafe { object.as_ref() }, &[i as u32; 6]);
+	}
+
+	for object in objects {
+		unsafe { CACHE.free(object) };
+	}
+
+	let reused = CACHE.alloc([42; 6]).unwrap();
+	assert_eq!(unsafe { reused.as_ref() }, &[42; 6]);
+	unsafe { CACHE.free(reused) };
+}
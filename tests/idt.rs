@@ -0,0 +1,100 @@
+//! Boots a kernel and checks the IDT loaded by `kernel::init`
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::arch::asm;
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _entrypoint(magic_number: u32, multiboot_info_ptr: u32) -> ! {
+	kernel::init(magic_number, multiboot_info_ptr);
+
+	test_main();
+
+	unreachable!()
+}
+
+#[repr(C, packed)]
+struct DescriptorTablePointer {
+	limit: u16,
+	base: u32,
+}
+
+fn sidt() -> DescriptorTablePointer {
+	let mut ptr = DescriptorTablePointer {
+		limit: 0,
+		base: 0,
+	};
+	unsafe { asm!("sidt [{}]", in(reg) &mut ptr) };
+	ptr
+}
+
+/// Returns true if the present bit of the `vector`-th IDT entry is set
+fn is_vector_present(vector: usize) -> bool {
+	let entry = unsafe { *((sidt().base as *const u64).add(vector)) };
+	entry & (1 << 47) != 0
+}
+
+#[test_case]
+fn idt_has_256_entries() {
+	let limit = sidt().limit;
+	assert_eq!(limit as usize + 1, 256 * 8);
+}
+
+#[test_case]
+fn exception_handlers_are_present() {
+	// breakpoint and page fault
+	assert!(is_vector_present(3));
+	assert!(is_vector_present(14));
+}
+
+#[test_case]
+fn every_exception_has_a_handler() {
+	for vector in 0..32 {
+		assert!(is_vector_present(vector), "no handler for exception {vector}");
+	}
+}
+
+#[test_case]
+fn double_fault_is_a_task_gate() {
+	let entry = unsafe { *((sidt().base as *const u64).add(8)) };
+
+	// gate type 0x5, pointing to the double fault TSS selector
+	assert_eq!((entry >> 40) & 0xf, 0x5);
+	assert_eq!((entry >> 16) & 0xffff, 0x40);
+}
+
+#[test_case]
+fn keyboard_handler_is_present() {
+	assert!(is_vector_present(33));
+}
+
+#[test_case]
+fn breakpoint_returns() {
+	// the handler prints the exception and resumes execution
+	unsafe { asm!("int3") };
+}
+
+#[test_case]
+fn registers_survive_an_interrupt() {
+	let (ecx, edx, edi): (u32, u32, u32);
+	unsafe {
+		asm!(
+			"mov ecx, 0x12345678",
+			"mov edx, 0x9abcdef0",
+			"mov edi, 0x0badf00d",
+			"int3",
+			out("ecx") ecx,
+			out("edx") edx,
+			out("edi") edi,
+		)
+	};
+
+	// the entry stub saved and restored them around the handler
+	assert_eq!(ecx, 0x12345678);
+	assert_eq!(edx, 0x9abcdef0);
+	assert_eq!(edi, 0x0badf00d);
+}
@@ -0,0 +1,111 @@
+//! Boots a kernel and checks the generic IRQ layer (handlers are called through software
+//! interrupts on a free line)
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::arch::asm;
+use core::sync::atomic::{
+	AtomicUsize,
+	Ordering,
+};
+
+use kernel::{
+	Irq,
+	IrqError,
+	TrapFrame,
+	free_irq,
+	irq_stats,
+	request_irq,
+};
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _entrypoint(magic_number: u32, multiboot_info_ptr: u32) -> ! {
+	kernel::init(magic_number, multiboot_info_ptr);
+
+	test_main();
+
+	unreachable!()
+}
+
+static FIRST_CALLS: AtomicUsize = AtomicUsize::new(0);
+static SECOND_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn first_handler(_frame: &mut TrapFrame) {
+	FIRST_CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+fn second_handler(_frame: &mut TrapFrame) {
+	SECOND_CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Raises the interrupt of IRQ11 (vector 32 + 11)
+fn raise_irq11() {
+	unsafe { asm!("int 43") };
+}
+
+fn irq11_count() -> u64 {
+	let mut count = 0;
+	irq_stats(|line| {
+		if line.irq == Irq::Free11 {
+			count = line.count;
+		}
+	});
+	count
+}
+
+#[test_case]
+fn keyboard_handler_is_registered() {
+	let mut handlers = [None; 4];
+	irq_stats(|line| {
+		if line.irq == Irq::Keyboard {
+			handlers = line.handlers;
+		}
+	});
+
+	assert_eq!(handlers[0], Some("keyboard"));
+}
+
+#[test_case]
+fn shared_line_calls_every_handler() {
+	request_irq(Irq::Free11, first_handler, "first").unwrap();
+	request_irq(Irq::Free11, second_handler, "second").unwrap();
+
+	let count = irq11_count();
+	raise_irq11();
+
+	assert_eq!(FIRST_CALLS.load(Ordering::Relaxed), 1);
+	assert_eq!(SECOND_CALLS.load(Ordering::Relaxed), 1);
+	assert_eq!(irq11_count(), count + 1);
+
+	// freed handlers aren't called anymore
+	free_irq(Irq::Free11, "first").unwrap();
+	raise_irq11();
+
+	assert_eq!(FIRST_CALLS.load(Ordering::Relaxed), 1);
+	assert_eq!(SECOND_CALLS.load(Ordering::Relaxed), 2);
+
+	free_irq(Irq::Free11, "second").unwrap();
+}
+
+#[test_case]
+fn registration_errors() {
+	request_irq(Irq::Free10, first_handler, "a").unwrap();
+	assert_eq!(
+		request_irq(Irq::Free10, first_handler, "a"),
+		Err(IrqError::NameTaken(Irq::Free10, "a"))
+	);
+
+	request_irq(Irq::Free10, first_handler, "b").unwrap();
+	request_irq(Irq::Free10, first_handler, "c").unwrap();
+	request_irq(Irq::Free10, first_handler, "d").unwrap();
+	assert_eq!(request_irq(Irq::Free10, first_handler, "e"), Err(IrqError::LineFull(Irq::Free10)));
+
+	for name in ["a", "b", "c", "d"] {
+		free_irq(Irq::Free10, name).unwrap();
+	}
+	assert_eq!(free_irq(Irq::Free10, "a"), Err(IrqError::NotRegistered(Irq::Free10, "a")));
+}
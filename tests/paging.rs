@@ -0,0 +1,134 @@
+//! Boots a kernel and checks the physical frame allocator and the page mappings
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel::{
+	MAX_ORDER,
+	PageDirectory,
+	alloc_frames,
+	free_frames,
+	kfree,
+	kmalloc,
+};
+
+/// Unused virtual addresses (far from the kernel, the heap and the recursive page tables)
+const TEST_VADDR: u32 = 0xe000_0000;
+const TEST_ALIAS_VADDR: u32 = 0xe000_1000;
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _entrypoint(magic_number: u32, multiboot_info_ptr: u32) -> ! {
+	kernel::init(magic_number, multiboot_info_ptr);
+
+	test_main();
+
+	unreachable!()
+}
+
+#[test_case]
+fn kernel_runs_in_the_higher_half() {
+	let code = _entrypoint as *const () as usize;
+	let local = 0u32;
+	let stack = &raw const local as usize;
+
+	assert!(code >= 0xc010_0000, "code at {code:#x}");
+	assert!(stack >= 0xc010_0000, "stack at {stack:#x}");
+}
+
+#[test_case]
+fn identity_map_is_removed() {
+	// the first entries of the page directory (cf. recursive mapping at 0xfffff000)
+	let directory = 0xffff_f000 as *const u32;
+
+	for i in 0..768 {
+		let entry = unsafe { directory.add(i).read_volatile() };
+		assert_eq!(entry & 1, 0, "lower half table {i} is present");
+	}
+}
+
+#[test_case]
+fn frames_are_page_aligned_and_distinct() {
+	let first = kmalloc().expect("Out of memory");
+	let second = kmalloc().expect("Out of memory");
+
+	assert_eq!(first % 4096, 0);
+	assert_eq!(second % 4096, 0);
+	assert_ne!(first, second);
+
+	kfree(first);
+	kfree(second);
+}
+
+#[test_case]
+fn frames_are_above_low_memory() {
+	let frame = kmalloc().expect("Out of memory");
+
+	// the first MiB (BIOS, VGA, ...) is reserved
+	assert!(frame >= 0x100000);
+
+	kfree(frame);
+}
+
+#[test_case]
+fn contiguous_frames_are_aligned_on_their_size() {
+	for order in [1, 4, MAX_ORDER] {
+		let block = alloc_frames(order).expect("Out of memory");
+		assert_eq!(block as usize % (4096 << order), 0, "order {order}");
+
+		// single frames never come from an allocated block
+		let frame = kmalloc().expect("Out of memory");
+		assert!(!(block..block + (4096 << order) as u32).contains(&frame));
+
+		kfree(frame);
+		free_frames(block, order);
+	}
+}
+
+#[test_case]
+fn freed_blocks_are_merged_back() {
+	let first = alloc_frames(3).expect("Out of memory");
+	free_frames(first, 3);
+
+	// the block was merged with its buddies and split again the same way
+	assert_eq!(alloc_frames(3), Some(first));
+	free_frames(first, 3);
+}
+
+#[test_case]
+fn mapped_page_is_readable_and_writable() {
+	let frame = kmalloc().expect("Out of memory");
+
+	unsafe { PageDirectory::map_page(TEST_VADDR, frame, false, true) };
+
+	let ptr = TEST_VADDR as *mut u32;
+	for i in 0..1024 {
+		unsafe { ptr.add(i).write_volatile(i as u32 ^ 0xdead_beef) };
+	}
+	for i in 0..1024 {
+		assert_eq!(unsafe { ptr.add(i).read_volatile() }, i as u32 ^ 0xdead_beef);
+	}
+
+	assert_eq!(unsafe { PageDirectory::unmap_page(TEST_VADDR) }, Some(frame));
+	kfree(frame);
+}
+
+#[test_case]
+fn aliased_pages_share_the_same_frame() {
+	let frame = kmalloc().expect("Out of memory");
+
+	unsafe {
+		PageDirectory::map_page(TEST_VADDR, frame, false, true);
+		PageDirectory::map_page(TEST_ALIAS_VADDR, frame, false, true);
+
+		(TEST_VADDR as *mut u32).write_volatile(0x1234_5678);
+		assert_eq!((TEST_ALIAS_VADDR as *const u32).read_volatile(), 0x1234_5678);
+
+		PageDirectory::unmap_page(TEST_VADDR);
+		PageDirectory::unmap_page(TEST_ALIAS_VADDR);
+	}
+
+	kfree(frame);
+}
@@ -0,0 +1,94 @@
+//! Boots a kernel and checks the PIT clock and the software timers
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::sync::atomic::{
+	AtomicUsize,
+	Ordering,
+};
+use core::time::Duration;
+
+use kernel::{
+	add_oneshot_timer,
+	add_periodic_timer,
+	busy_sleep_ms,
+	cancel_timer,
+	frequency,
+	sleep_ms,
+	ticks,
+	uptime,
+};
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _entrypoint(magic_number: u32, multiboot_info_ptr: u32) -> ! {
+	kernel::init(magic_number, multiboot_info_ptr);
+
+	test_main();
+
+	unreachable!()
+}
+
+static ONESHOT_CALLS: AtomicUsize = AtomicUsize::new(0);
+static PERIODIC_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[test_case]
+fn frequency_is_close_to_1_khz() {
+	// the PIT base frequency isn't a multiple of 1000
+	assert!((995..=1005).contains(&frequency()));
+}
+
+#[test_case]
+fn ticks_advance_while_sleeping() {
+	let start_ticks = ticks();
+	let start = uptime();
+
+	sleep_ms(20);
+
+	assert!(uptime() - start >= Duration::from_millis(20));
+	assert!(ticks() - start_ticks >= 19);
+}
+
+#[test_case]
+fn busy_sleep_works_without_interrupts() {
+	let start = uptime();
+
+	// interrupts are disabled, so the clock doesn't advance
+	kernel::without_interrupts(|| busy_sleep_ms(5));
+	assert!(uptime() - start < Duration::from_millis(5));
+
+	busy_sleep_ms(5);
+	assert!(uptime() - start >= Duration::from_millis(4));
+}
+
+#[test_case]
+fn oneshot_timer_runs_once() {
+	add_oneshot_timer(Duration::from_millis(5), || {
+		ONESHOT_CALLS.fetch_add(1, Ordering::Relaxed);
+	})
+	.unwrap();
+
+	sleep_ms(20);
+	assert_eq!(ONESHOT_CALLS.load(Ordering::Relaxed), 1);
+}
+
+#[test_case]
+fn periodic_timer_runs_until_cancelled() {
+	let id = add_periodic_timer(Duration::from_millis(2), || {
+		PERIODIC_CALLS.fetch_add(1, Ordering::Relaxed);
+	})
+	.unwrap();
+
+	sleep_ms(20);
+	assert!(cancel_timer(id));
+
+	let calls = PERIODIC_CALLS.load(Ordering::Relaxed);
+	assert!(calls >= 5, "only {calls} calls");
+
+	sleep_ms(10);
+	assert_eq!(PERIODIC_CALLS.load(Ordering::Relaxed), calls);
+	assert!(!cancel_timer(id));
+}
@@ -0,0 +1,146 @@
+//! Boots a kernel and checks the kernel threads and the scheduler
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::sync::atomic::{
+	AtomicBool,
+	AtomicU32,
+	Ordering,
+};
+
+use kernel::{
+	MAX_TASKS,
+	TaskId,
+	TaskState,
+	block_current,
+	current_task_id,
+	spawn_kernel_thread,
+	tasks,
+	uptime,
+	wake,
+	yield_now,
+};
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _entrypoint(magic_number: u32, multiboot_info_ptr: u32) -> ! {
+	kernel::init(magic_number, multiboot_info_ptr);
+
+	test_main();
+
+	unreachable!()
+}
+
+fn state_of(id: TaskId) -> Option<TaskState> {
+	let mut state = None;
+	tasks(|task| {
+		if task.id == id {
+			state = Some(task.state);
+		}
+	});
+	state
+}
+
+/// Yields until `condition` holds, or panics after a second
+fn wait_for(condition: impl Fn() -> bool) {
+	let deadline = uptime().as_millis() + 1000;
+	while !condition() {
+		assert!(uptime().as_millis() < deadline, "timed out");
+		yield_now();
+	}
+}
+
+#[test_case]
+fn boot_flow_is_the_main_task() {
+	let mut names = [""; 2];
+	tasks(|task| {
+		if task.id.0 < 2 {
+			names[task.id.0 as usize] = task.name;
+		}
+	});
+
+	assert_eq!(names, ["main", "idle"]);
+	assert_eq!(current_task_id(), TaskId(0));
+	assert_eq!(state_of(TaskId(0)), Some(TaskState::Running));
+}
+
+static RAN: AtomicU32 = AtomicU32::new(0);
+
+#[test_case]
+fn spawned_threads_run_and_exit() {
+	RAN.store(0, Ordering::SeqCst);
+
+	let id = spawn_kernel_thread("counter", || {
+		RAN.fetch_add(1, Ordering::SeqCst);
+	})
+	.unwrap();
+	assert_ne!(id, current_task_id());
+
+	wait_for(|| state_of(id) == Some(TaskState::Dead));
+	assert_eq!(RAN.load(Ordering::SeqCst), 1);
+}
+
+static SPINNING: AtomicBool = AtomicBool::new(false);
+static STOP_SPINNING: AtomicBool = AtomicBool::new(false);
+
+#[test_case]
+fn threads_that_never_yield_are_preempted() {
+	spawn_kernel_thread("spinner", || {
+		SPINNING.store(true, Ordering::SeqCst);
+		while !STOP_SPINNING.load(Ordering::SeqCst) {
+			core::hint::spin_loop();
+		}
+	})
+	.unwrap();
+
+	// main doesn't yield either: only the time slices let the spinner start
+	let deadline = uptime().as_millis() + 1000;
+	while !SPINNING.load(Ordering::SeqCst) {
+		assert!(uptime().as_millis() < deadline, "the spinner never ran");
+	}
+
+	STOP_SPINNING.store(true, Ordering::SeqCst);
+}
+
+static WOKEN: AtomicBool = AtomicBool::new(false);
+
+#[test_case]
+fn blocked_threads_wait_for_wake() {
+	let id = spawn_kernel_thread("sleeper", || {
+		block_current();
+		WOKEN.store(true, Ordering::SeqCst);
+	})
+	.unwrap();
+
+	wait_for(|| state_of(id) == Some(TaskState::Blocked));
+	for _ in 0..10 {
+		yield_now();
+	}
+	assert!(!WOKEN.load(Ordering::SeqCst));
+
+	assert!(wake(id));
+	wait_for(|| WOKEN.load(Ordering::SeqCst));
+	wait_for(|| state_of(id) == Some(TaskState::Dead));
+	assert!(!wake(id));
+}
+
+#[test_case]
+fn wake_before_block_isnt_lost() {
+	let id = current_task_id();
+
+	assert!(wake(id));
+	// returns at once
+	block_current();
+	assert_eq!(state_of(id), Some(TaskState::Running));
+}
+
+#[test_case]
+fn dead_task_slots_are_reused() {
+	for _ in 0..MAX_TASKS * 2 {
+		let id = spawn_kernel_thread("short", || {}).unwrap();
+		wait_for(|| state_of(id) == Some(TaskState::Dead));
+	}
+}
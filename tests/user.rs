@@ -0,0 +1,841 @@
+//! Boots a kernel and runs user programs: the `hello` module (cf. tools/user/hello.s), and small
+//! executables built in memory
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{
+	AtomicU32,
+	Ordering,
+};
+
+use kernel::{
+	EXIT_CODE_KILLED,
+	ElfError,
+	INIT_PID,
+	MAX_ARGUMENTS_SIZE,
+	PageDirectory,
+	ProcessState,
+	SA_RESETHAND,
+	SIG_BLOCK,
+	SIG_IGN,
+	SIG_SETMASK,
+	SIG_UNBLOCK,
+	SIGCHLD,
+	SIGCONT,
+	SIGFPE,
+	SIGILL,
+	SIGKILL,
+	SIGSEGV,
+	SIGSTOP,
+	SIGTERM,
+	SIGUSR1,
+	SYS_BRK,
+	SYS_EXIT,
+	SYS_FORK,
+	SYS_GETPID,
+	SYS_GETPPID,
+	SYS_KILL,
+	SYS_SIGACTION,
+	SYS_SIGPROCMASK,
+	SYS_WAITPID,
+	SYS_WRITE,
+	SYS_YIELD,
+	WNOHANG,
+	boot_module,
+	processes,
+	run_user_program,
+	send_signal,
+	signal_bit,
+	spawn_kernel_thread,
+	yield_now,
+};
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _entrypoint(magic_number: u32, multiboot_info_ptr: u32) -> ! {
+	kernel::init(magic_number, multiboot_info_ptr);
+
+	test_main();
+
+	unreachable!()
+}
+
+/// Where [program] maps the code
+const TEXT: u32 = 0x0804_8000;
+
+/// Where [program] maps [DATA_CONTENT], followed by a page of .bss
+const DATA: u32 = 0x0804_9000;
+const DATA_CONTENT: &[u8] = b"hello";
+const BSS: u32 = DATA + 0x1000;
+/// Where the heap of [program] starts (the program break), after the data segment
+const HEAP: u32 = DATA + 0x2000;
+
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+struct Segment<'a> {
+	virtual_address: u32,
+	content: &'a [u8],
+	memory_size: u32,
+	flags: u32,
+}
+
+/// An ELF32 i386 executable with the `segments`, their content after the headers
+fn build_elf(entry: u32, segments: &[Segment]) -> Vec<u8> {
+	let mut elf = vec![0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+	elf.extend(2u16.to_le_bytes()); // ET_EXEC
+	elf.extend(3u16.to_le_bytes()); // EM_386
+	elf.extend(1u32.to_le_bytes());
+	elf.extend(entry.to_le_bytes());
+	elf.extend(52u32.to_le_bytes()); // program headers offset
+	elf.extend(0u32.to_le_bytes());
+	elf.extend(0u32.to_le_bytes());
+	elf.extend(52u16.to_le_bytes());
+	elf.extend(32u16.to_le_bytes());
+	elf.extend((segments.len() as u16).to_le_bytes());
+	elf.extend([0; 6]);
+
+	let mut offset = 52 + 32 * segments.len() as u32;
+	for segment in segments {
+		elf.extend(1u32.to_le_bytes()); // PT_LOAD
+		elf.extend(offset.to_le_bytes());
+		elf.extend(segment.virtual_address.to_le_bytes());
+		elf.extend(segment.virtual_address.to_le_bytes());
+		elf.extend((segment.content.len() as u32).to_le_bytes());
+		elf.extend(segment.memory_size.to_le_bytes());
+		elf.extend(segment.flags.to_le_bytes());
+		elf.extend(0x1000u32.to_le_bytes());
+		offset += segment.content.len() as u32;
+	}
+
+	for segment in segments {
+		elf.extend(segment.content);
+	}
+	elf
+}
+
+/// An executable running `code` (read-only, at [TEXT]), with a writable data segment
+fn program(code: &[u8]) -> Vec<u8> {
+	program_with_data(code, DATA_CONTENT)
+}
+
+/// [program], with `data` at [DATA]
+fn program_with_data(code: &[u8], data: &[u8]) -> Vec<u8> {
+	build_elf(
+		TEXT,
+		&[
+			Segment {
+				virtual_address: TEXT,
+				content: code,
+				memory_size: code.len() as u32,
+				flags: PF_R | PF_X,
+			},
+			Segment {
+				virtual_address: DATA,
+				content: data,
+				memory_size: 0x2000,
+				flags: PF_R | PF_W,
+			},
+		],
+	)
+}
+
+/// `mov <register>, imm32`, with the opcode of the register
+fn mov(opcode: u8, value: u32) -> impl Iterator<Item = u8> {
+	[opcode].into_iter().chain(value.to_le_bytes())
+}
+
+const EAX: u8 = 0xb8;
+const EBX: u8 = 0xbb;
+const ECX: u8 = 0xb9;
+const EDX: u8 = 0xba;
+
+const INT_0X80: [u8; 2] = [0xcd, 0x80];
+
+/// `mov ebx, eax`
+const EBX_FROM_EAX: [u8; 2] = [0x89, 0xc3];
+
+/// Code that calls `exit(ebx)`
+fn exit_with_ebx() -> Vec<u8> {
+	mov(EAX, SYS_EXIT as u32).chain(INT_0X80).collect()
+}
+
+/// Code that calls the `number` system call with `arguments` (ebx, ecx, edx)
+fn syscall(number: usize, arguments: [u32; 3]) -> Vec<u8> {
+	mov(EAX, number as u32)
+		.chain(mov(EBX, arguments[0]))
+		.chain(mov(ECX, arguments[1]))
+		.chain(mov(EDX, arguments[2]))
+		.chain(INT_0X80)
+		.collect()
+}
+
+/// Code that calls the `number` system call with `arguments` (ebx, ecx, edx), then exits with its
+/// result
+fn syscall_then_exit(number: usize, arguments: [u32; 3]) -> Vec<u8> {
+	let mut code = syscall(number, arguments);
+	code.extend(EBX_FROM_EAX);
+	code.extend(exit_with_ebx());
+	code
+}
+
+fn run(code: &[u8]) -> Result<i32, ElfError> {
+	run_user_program(&program(code), &["test"], &[])
+}
+
+#[test_case]
+fn hello_module_runs_with_its_arguments() {
+	let hello = boot_module("hello").expect("GRUB loads the hello module");
+
+	// exits with argc, or 255 if its .bss isn't zeroed
+	assert_eq!(run_user_program(hello, &["hello", "from", "ring 3"], &["TERM=vga"]), Ok(3));
+}
+
+#[test_case]
+fn exit_code_is_returned() {
+	let code: Vec<u8> = mov(EBX, 42).chain(exit_with_ebx()).collect();
+	assert_eq!(run(&code), Ok(42));
+}
+
+#[test_case]
+fn getpid_is_a_new_process_id() {
+	let code = syscall_then_exit(SYS_GETPID, [0; 3]);
+	let first = run(&code).unwrap();
+	let second = run(&code).unwrap();
+
+	// init excepted
+	assert!(first > INIT_PID.0 as i32);
+	assert!(second > first);
+
+	// started by the kernel
+	assert_eq!(run(&syscall_then_exit(SYS_GETPPID, [0; 3])), Ok(0));
+}
+
+#[test_case]
+fn write_prints_user_buffers() {
+	let code = syscall_then_exit(SYS_WRITE, [1, DATA, DATA_CONTENT.len() as u32]);
+	assert_eq!(run(&code), Ok(DATA_CONTENT.len() as i32));
+}
+
+#[test_case]
+fn write_rejects_bad_arguments() {
+	// EFAULT: kernel and unmapped addresses
+	assert_eq!(run(&syscall_then_exit(SYS_WRITE, [1, 0xc010_0000, 4])), Ok(-14));
+	assert_eq!(run(&syscall_then_exit(SYS_WRITE, [1, 0x1000_0000, 4])), Ok(-14));
+
+	// the .bss is only mapped on the first access, but it is part of the program
+	assert_eq!(run(&syscall_then_exit(SYS_WRITE, [1, BSS, 4])), Ok(4));
+
+	// nothing to read, even at NULL
+	assert_eq!(run(&syscall_then_exit(SYS_WRITE, [1, 0, 0])), Ok(0));
+	assert_eq!(run(&syscall_then_exit(SYS_WRITE, [1, 0, 4])), Ok(-14));
+
+	// EBADF
+	assert_eq!(run(&syscall_then_exit(SYS_WRITE, [7, DATA, 4])), Ok(-9));
+}
+
+#[test_case]
+fn unknown_system_calls_return_enosys() {
+	assert_eq!(run(&syscall_then_exit(0, [0; 3])), Ok(-38));
+	assert_eq!(run(&syscall_then_exit(1000, [0; 3])), Ok(-38));
+}
+
+#[test_case]
+fn yield_returns_to_the_program() {
+	assert_eq!(run(&syscall_then_exit(SYS_YIELD, [0; 3])), Ok(0));
+}
+
+#[test_case]
+fn arguments_are_on_the_stack() {
+	// mov ebx, [esp] (argc)
+	let mut code = vec![0x8b, 0x1c, 0x24];
+	code.extend(exit_with_ebx());
+	assert_eq!(run_user_program(&program(&code), &["test", "a", "b", "c"], &[]), Ok(4));
+
+	// mov eax, [esp + 8] (argv[1]), movzx ebx, byte [eax]
+	let mut code = vec![0x8b, 0x44, 0x24, 0x08, 0x0f, 0xb6, 0x18];
+	code.extend(exit_with_ebx());
+	assert_eq!(run_user_program(&program(&code), &["test", "xyz"], &[]), Ok(b'x' as i32));
+
+	// mov eax, [esp + 12] (envp[0], after argv[0] and NULL), movzx ebx, byte [eax]
+	let mut code = vec![0x8b, 0x44, 0x24, 0x0c, 0x0f, 0xb6, 0x18];
+	code.extend(exit_with_ebx());
+	assert_eq!(run_user_program(&program(&code), &["test"], &["HOME=/"]), Ok(b'H' as i32));
+}
+
+#[test_case]
+fn bss_is_zeroed_and_writable() {
+	// mov dword [BSS], 7
+	let mut code = vec![0xc7, 0x05];
+	code.extend(BSS.to_le_bytes());
+	code.extend(7u32.to_le_bytes());
+
+	// mov ebx, [BSS + 4], add ebx, [BSS]
+	code.extend([0x8b, 0x1d]);
+	code.extend((BSS + 4).to_le_bytes());
+	code.extend([0x03, 0x1d]);
+	code.extend(BSS.to_le_bytes());
+	code.extend(exit_with_ebx());
+
+	assert_eq!(run(&code), Ok(7));
+}
+
+#[test_case]
+fn exceptions_only_kill_the_program() {
+	// mov eax, [0]
+	assert_eq!(run(&[0xa1, 0, 0, 0, 0]), Ok(EXIT_CODE_KILLED));
+
+	// cli (privileged)
+	assert_eq!(run(&[0xfa]), Ok(EXIT_CODE_KILLED));
+
+	// int 3 (its gate is kernel only)
+	assert_eq!(run(&[0xcc]), Ok(EXIT_CODE_KILLED));
+
+	// mov dword [TEXT], 0 (read-only segment)
+	let mut code = vec![0xc7, 0x05];
+	code.extend(TEXT.to_le_bytes());
+	code.extend(0u32.to_le_bytes());
+	assert_eq!(run(&code), Ok(EXIT_CODE_KILLED));
+}
+
+/// `mov ebx, [esp]`
+const EBX_FROM_STACK: [u8; 3] = [0x8b, 0x1c, 0x24];
+
+#[test_case]
+fn stack_grows_down() {
+	// sub esp, 1 MiB (far below the initial stack), then mov dword [esp], 5
+	let mut code = vec![0x81, 0xec];
+	code.extend(0x10_0000u32.to_le_bytes());
+	code.extend([0xc7, 0x04, 0x24]);
+	code.extend(5u32.to_le_bytes());
+
+	code.extend(EBX_FROM_STACK);
+	code.extend(exit_with_ebx());
+
+	assert_eq!(run(&code), Ok(5));
+}
+
+#[test_case]
+fn accesses_outside_of_the_areas_kill_the_program() {
+	// mov eax, [esp - 1 MiB] (too far below the stack pointer for the stack to grow)
+	let mut code = vec![0x8b, 0x84, 0x24];
+	code.extend((-0x10_0000i32).to_le_bytes());
+	assert_eq!(run(&code), Ok(EXIT_CODE_KILLED));
+
+	// mov eax, [HEAP] (past the program break)
+	let mut code = vec![0xa1];
+	code.extend(HEAP.to_le_bytes());
+	assert_eq!(run(&code), Ok(EXIT_CODE_KILLED));
+}
+
+#[test_case]
+fn brk_moves_the_program_break() {
+	assert_eq!(run(&syscall_then_exit(SYS_BRK, [0; 3])), Ok(HEAP as i32));
+
+	// below the heap: the break doesn't move
+	assert_eq!(run(&syscall_then_exit(SYS_BRK, [TEXT, 0, 0])), Ok(HEAP as i32));
+
+	// brk(HEAP + 0x3000), then mov dword [eax - 4], 9, and exit with it
+	let mut code: Vec<u8> = mov(EAX, SYS_BRK as u32).chain(mov(EBX, HEAP + 0x3000)).collect();
+	code.extend(INT_0X80);
+	code.extend([0xc7, 0x40, 0xfc]);
+	code.extend(9u32.to_le_bytes());
+	code.extend([0x8b, 0x58, 0xfc]);
+	code.extend(exit_with_ebx());
+
+	assert_eq!(run(&code), Ok(9));
+}
+
+#[test_case]
+fn programs_are_unloaded() {
+	let code: Vec<u8> = mov(EBX, 0).chain(exit_with_ebx()).collect();
+
+	for _ in 0..3 {
+		assert_eq!(run(&code), Ok(0));
+	}
+
+	assert!(unsafe { PageDirectory::translate(TEXT) }.is_none());
+	assert!(unsafe { PageDirectory::translate(DATA) }.is_none());
+}
+
+/// Code that yields 50 times, then exits with the byte at [DATA]
+fn yield_then_exit_with_data() -> Vec<u8> {
+	// mov esi, 50
+	let mut code: Vec<u8> = mov(0xbe, 50).collect();
+
+	// loop: yield, dec esi, jnz loop
+	code.extend(mov(EAX, SYS_YIELD as u32));
+	code.extend(INT_0X80);
+	code.extend([0x4e, 0x75, 0xf6]);
+
+	// movzx ebx, byte [DATA]
+	code.extend([0x0f, 0xb6, 0x1d]);
+	code.extend(DATA.to_le_bytes());
+	code.extend(exit_with_ebx());
+	code
+}
+
+/// Exit code of the program run by [run_in_thread] (or [loop_in_thread]), or [u32::MAX] while it
+/// runs
+static THREAD_EXIT_CODE: AtomicU32 = AtomicU32::new(u32::MAX);
+
+fn run_in_thread() {
+	let image = program_with_data(&yield_then_exit_with_data(), b"T");
+	let exit_code = run_user_program(&image, &["thread"], &[]).expect("the program is valid");
+
+	THREAD_EXIT_CODE.store(exit_code as u32, Ordering::SeqCst);
+}
+
+#[test_case]
+fn programs_run_in_their_own_address_space() {
+	THREAD_EXIT_CODE.store(u32::MAX, Ordering::SeqCst);
+	spawn_kernel_thread("program", run_in_thread).expect("a slot is free");
+
+	// both programs are mapped at the same addresses, and switch at each yield
+	let image = program_with_data(&yield_then_exit_with_data(), b"M");
+	assert_eq!(run_user_program(&image, &["main"], &[]), Ok(b'M' as i32));
+
+	while THREAD_EXIT_CODE.load(Ordering::SeqCst) == u32::MAX {
+		yield_now();
+	}
+	assert_eq!(THREAD_EXIT_CODE.load(Ordering::SeqCst), b'T' as u32);
+}
+
+/// `mov eax, SYS_FORK`, `int 0x80`, then `test eax, eax`, and a jump over the `child` code to the
+/// `parent` code if eax isn't 0
+fn fork_then(child: &[u8], parent: &[u8]) -> Vec<u8> {
+	let mut code: Vec<u8> = mov(EAX, SYS_FORK as u32).chain(INT_0X80).collect();
+	code.extend([0x85, 0xc0, 0x75, child.len() as u8]);
+	code.extend(child);
+	code.extend(parent);
+	code
+}
+
+/// Yields until the process `pid` is reaped
+fn wait_for_process(pid: u32) {
+	let is_alive = || {
+		let mut is_alive = false;
+		processes(|process| is_alive |= process.pid.0 == pid);
+		is_alive
+	};
+
+	while is_alive() {
+		yield_now();
+	}
+}
+
+/// Number of processes, zombies included
+fn process_count() -> usize {
+	let mut count = 0;
+	processes(|_| count += 1);
+	count
+}
+
+/// Code that calls `waitpid(pid, status, options)`
+fn waitpid(pid: i32, status: u32, options: u32) -> Vec<u8> {
+	mov(EAX, SYS_WAITPID as u32)
+		.chain(mov(EBX, pid as u32))
+		.chain(mov(ECX, status))
+		.chain(mov(EDX, options))
+		.chain(INT_0X80)
+		.collect()
+}
+
+#[test_case]
+fn fork_returns_the_child_id() {
+	let child: Vec<u8> = mov(EBX, 0).chain(exit_with_ebx()).collect();
+	let mut parent = EBX_FROM_EAX.to_vec();
+	parent.extend(exit_with_ebx());
+
+	let count = process_count();
+	let child_id = run(&fork_then(&child, &parent)).unwrap();
+	assert!(child_id > INIT_PID.0 as i32);
+
+	// the orphan is reaped when it exits, as init doesn't run
+	wait_for_process(child_id as u32);
+	assert_eq!(process_count(), count);
+}
+
+#[test_case]
+fn waitpid_reaps_the_child() {
+	let child: Vec<u8> = mov(EBX, 7).chain(exit_with_ebx()).collect();
+
+	// waitpid(-1, DATA, 0), then exit with WEXITSTATUS (the second byte of the status)
+	let mut parent = waitpid(-1, DATA, 0);
+	parent.extend([0x0f, 0xb6, 0x1d]);
+	parent.extend((DATA + 1).to_le_bytes());
+	parent.extend(exit_with_ebx());
+
+	let count = process_count();
+	assert_eq!(run(&fork_then(&child, &parent)), Ok(7));
+	assert_eq!(process_count(), count);
+}
+
+#[test_case]
+fn waitpid_returns_the_child_id() {
+	// the child runs after the parent waits
+	let mut child: Vec<u8> = mov(EAX, SYS_YIELD as u32).chain(INT_0X80).collect();
+	child.extend(mov(EBX, 0).chain(exit_with_ebx()));
+
+	// mov esi, eax (the child id), waitpid(-1, NULL, 0), sub eax, esi, then exit with the
+	// difference
+	let mut parent = vec![0x89, 0xc6];
+	parent.extend(waitpid(-1, 0, 0));
+	parent.extend([0x29, 0xf0]);
+	parent.extend(EBX_FROM_EAX);
+	parent.extend(exit_with_ebx());
+
+	assert_eq!(run(&fork_then(&child, &parent)), Ok(0));
+}
+
+#[test_case]
+fn waitpid_fails_without_children() {
+	let run_waitpid = |pid: i32, status: u32, options: u32| {
+		let mut code = waitpid(pid, status, options);
+		code.extend(EBX_FROM_EAX);
+		code.extend(exit_with_ebx());
+		run(&code)
+	};
+
+	// ECHILD
+	assert_eq!(run_waitpid(-1, 0, 0), Ok(-10));
+	assert_eq!(run_waitpid(INIT_PID.0 as i32, 0, WNOHANG), Ok(-10));
+
+	// EINVAL: unknown options, and process groups
+	assert_eq!(run_waitpid(-1, 0, 4), Ok(-22));
+	assert_eq!(run_waitpid(0, 0, 0), Ok(-22));
+
+	// EFAULT: the status can't be written
+	assert_eq!(run_waitpid(-1, TEXT, 0), Ok(-14));
+}
+
+#[test_case]
+fn wnohang_returns_while_the_child_runs() {
+	let child = yield_then_exit_with_data();
+
+	let mut parent = waitpid(-1, 0, WNOHANG);
+	parent.extend(EBX_FROM_EAX);
+	parent.extend(exit_with_ebx());
+
+	let count = process_count();
+	assert_eq!(run(&fork_then(&child, &parent)), Ok(0));
+
+	while process_count() != count {
+		yield_now();
+	}
+}
+
+#[test_case]
+fn forked_processes_copy_on_write() {
+	// mov byte [DATA], 'C', then exit with it
+	let mut child = vec![0xc6, 0x05];
+	child.extend(DATA.to_le_bytes());
+	child.push(b'C');
+	child.extend([0x0f, 0xb6, 0x1d]);
+	child.extend(DATA.to_le_bytes());
+	child.extend(exit_with_ebx());
+
+	// let the child write, then exit with the byte at DATA
+	let parent = yield_then_exit_with_data();
+
+	assert_eq!(run(&fork_then(&child, &parent)), Ok(DATA_CONTENT[0] as i32));
+}
+
+#[test_case]
+fn malformed_executables_are_rejected() {
+	let code: Vec<u8> = mov(EBX, 0).chain(exit_with_ebx()).collect();
+	let valid = program(&code);
+	let run = |elf: &[u8]| run_user_program(elf, &["test"], &[]);
+
+	assert_eq!(run(&valid[..40]), Err(ElfError::Truncated));
+
+	let mut elf = valid.clone();
+	elf[1] = b'X';
+	assert_eq!(run(&elf), Err(ElfError::BadMagic));
+
+	// ELFCLASS64
+	let mut elf = valid.clone();
+	elf[4] = 2;
+	assert_eq!(run(&elf), Err(ElfError::UnsupportedFormat));
+
+	// ET_DYN
+	let mut elf = valid.clone();
+	elf[16] = 3;
+	assert_eq!(run(&elf), Err(ElfError::NotExecutable));
+
+	// EM_X86_64
+	let mut elf = valid.clone();
+	elf[18] = 62;
+	assert_eq!(run(&elf), Err(ElfError::WrongMachine));
+
+	// the second segment content goes past the end of the file
+	assert_eq!(run(&valid[..valid.len() - 1]), Err(ElfError::SegmentOutOfFile(1)));
+
+	let segment = |virtual_address: u32, content: &'static [u8], memory_size: u32| Segment {
+		virtual_address,
+		content,
+		memory_size,
+		flags: PF_R | PF_X,
+	};
+
+	let elf = build_elf(TEXT, &[segment(TEXT, b"abcd", 2)]);
+	assert_eq!(run(&elf), Err(ElfError::SegmentFileSizeTooBig(0)));
+
+	// empty, right after the text page
+	let elf = build_elf(TEXT, &[segment(TEXT, b"abcd", 4), segment(TEXT + 0x1000, b"", 0)]);
+	assert_eq!(run(&elf), Err(ElfError::EmptySegment(1)));
+
+	let elf = build_elf(0xc010_0000, &[segment(0xc010_0000, b"abcd", 4)]);
+	assert_eq!(run(&elf), Err(ElfError::SegmentOutsideUserSpace(0)));
+
+	let elf = build_elf(0x10, &[segment(0x10, b"abcd", 4)]);
+	assert_eq!(run(&elf), Err(ElfError::SegmentOutsideUserSpace(0)));
+
+	let elf = build_elf(TEXT, &[segment(TEXT, b"abcd", 0x1000), segment(TEXT + 0x800, b"", 4)]);
+	assert_eq!(run(&elf), Err(ElfError::OverlappingSegments(0, 1)));
+
+	let elf = build_elf(TEXT + 0x5000, &[segment(TEXT, b"abcd", 4)]);
+	assert_eq!(run(&elf), Err(ElfError::EntryOutsideSegments));
+
+	let elf = build_elf(TEXT, &[]);
+	assert_eq!(run(&elf), Err(ElfError::NoLoadableSegment));
+
+	let long_argument = "a".repeat(MAX_ARGUMENTS_SIZE);
+	assert_eq!(
+		run_user_program(&valid, &["test", &long_argument], &[]),
+		Err(ElfError::ArgumentsTooLong)
+	);
+
+	// nothing stays mapped after an error
+	assert!(unsafe { PageDirectory::translate(TEXT) }.is_none());
+}
+
+/// Code that calls `kill(getpid(), signal)`
+fn kill_self(signal: u32) -> Vec<u8> {
+	let mut code: Vec<u8> = mov(EAX, SYS_GETPID as u32).chain(INT_0X80).collect();
+	code.extend(EBX_FROM_EAX);
+	code.extend(mov(EAX, SYS_KILL as u32).chain(mov(ECX, signal)).chain(INT_0X80));
+	code
+}
+
+/// A `sigaction` structure
+fn sig_action(handler: u32, flags: u32) -> Vec<u8> {
+	[handler, 0, flags, 0].iter().flat_map(|field| field.to_le_bytes()).collect()
+}
+
+/// An executable running `code`, then the `handler` code, with a `sigaction` structure at [DATA]
+/// that calls it (cf. [sigaction_for])
+fn program_with_handler(code: &[u8], handler: &[u8], flags: u32) -> Vec<u8> {
+	let action = sig_action(TEXT + code.len() as u32, flags);
+	program_with_data(&[code, handler].concat(), &action)
+}
+
+/// Code that sets the action at [DATA] for `signal`
+fn sigaction_for(signal: u32) -> Vec<u8> {
+	syscall(SYS_SIGACTION, [signal, DATA, 0])
+}
+
+/// Handler that exits with its argument (the signal)
+fn exit_with_signal() -> Vec<u8> {
+	// mov ebx, [esp + 4]
+	let mut handler = vec![0x8b, 0x5c, 0x24, 0x04];
+	handler.extend(exit_with_ebx());
+	handler
+}
+
+#[test_case]
+fn handlers_run_then_the_program_resumes() {
+	// kill returns 0 once the handler wrote the signal to BSS: exit with the sum
+	let mut code = sigaction_for(SIGUSR1);
+	code.extend(kill_self(SIGUSR1));
+	code.extend([0x8b, 0x1d]);
+	code.extend(BSS.to_le_bytes());
+	code.extend([0x01, 0xc3]);
+	code.extend(exit_with_ebx());
+
+	// mov eax, [esp + 4], mov [BSS], eax, ret (to the sigreturn trampoline)
+	let mut handler = vec![0x8b, 0x44, 0x24, 0x04, 0xa3];
+	handler.extend(BSS.to_le_bytes());
+	handler.push(0xc3);
+
+	let image = program_with_handler(&code, &handler, 0);
+	assert_eq!(run_user_program(&image, &["test"], &[]), Ok(SIGUSR1 as i32));
+
+	// the second signal gets the default action
+	let mut code = sigaction_for(SIGUSR1);
+	code.extend(kill_self(SIGUSR1));
+	code.extend(kill_self(SIGUSR1));
+	code.extend(mov(EBX, 0).chain(exit_with_ebx()));
+
+	let image = program_with_handler(&code, &[0xc3], SA_RESETHAND);
+	assert_eq!(run_user_program(&image, &["test"], &[]), Ok(EXIT_CODE_KILLED));
+}
+
+#[test_case]
+fn default_actions_apply() {
+	let mut code = kill_self(SIGTERM);
+	code.extend(mov(EBX, 0).chain(exit_with_ebx()));
+	assert_eq!(run(&code), Ok(EXIT_CODE_KILLED));
+
+	// ignored
+	let mut code = kill_self(SIGCHLD);
+	code.extend(EBX_FROM_EAX);
+	code.extend(exit_with_ebx());
+	assert_eq!(run(&code), Ok(0));
+
+	let mut code = sigaction_for(SIGTERM);
+	code.extend(kill_self(SIGTERM));
+	code.extend(EBX_FROM_EAX);
+	code.extend(exit_with_ebx());
+	let image = program_with_data(&code, &sig_action(SIG_IGN, 0));
+	assert_eq!(run_user_program(&image, &["test"], &[]), Ok(0));
+}
+
+#[test_case]
+fn waitpid_reports_the_killing_signal() {
+	// waitpid(-1, DATA, 0), then exit with the status
+	let mut parent = waitpid(-1, DATA, 0);
+	parent.extend([0x8b, 0x1d]);
+	parent.extend(DATA.to_le_bytes());
+	parent.extend(exit_with_ebx());
+
+	let mut child = kill_self(SIGTERM);
+	child.extend(mov(EBX, 0).chain(exit_with_ebx()));
+	assert_eq!(run(&fork_then(&child, &parent)), Ok(SIGTERM as i32));
+
+	// mov eax, [0]: the core dump flag is set
+	assert_eq!(run(&fork_then(&[0xa1, 0, 0, 0, 0], &parent)), Ok(SIGSEGV as i32 | 0x80));
+}
+
+#[test_case]
+fn exceptions_raise_signals() {
+	let faults: [(u32, &[u8]); 3] = [
+		// xor ecx, ecx, div ecx
+		(SIGFPE, &[0x31, 0xc9, 0xf7, 0xf1]),
+		// ud2
+		(SIGILL, &[0x0f, 0x0b]),
+		// mov eax, [0]
+		(SIGSEGV, &[0xa1, 0, 0, 0, 0]),
+	];
+
+	for (signal, fault) in faults {
+		assert_eq!(run(fault), Ok(EXIT_CODE_KILLED));
+
+		let mut code = sigaction_for(signal);
+		code.extend(fault);
+		let image = program_with_handler(&code, &exit_with_signal(), 0);
+		assert_eq!(run_user_program(&image, &["test"], &[]), Ok(signal as i32));
+	}
+}
+
+#[test_case]
+fn blocked_signals_stay_pending() {
+	let blocked = signal_bit(SIGUSR1);
+	let run_with_mask = |code: &[u8], mask: u32| {
+		run_user_program(&program_with_data(code, &mask.to_le_bytes()), &["test"], &[])
+	};
+
+	let mut code = syscall(SYS_SIGPROCMASK, [SIG_BLOCK, DATA, 0]);
+	code.extend(kill_self(SIGUSR1));
+	code.extend(mov(EBX, 5).chain(exit_with_ebx()));
+	assert_eq!(run_with_mask(&code, blocked), Ok(5));
+
+	// delivered once unblocked
+	let mut code = syscall(SYS_SIGPROCMASK, [SIG_BLOCK, DATA, 0]);
+	code.extend(kill_self(SIGUSR1));
+	code.extend(syscall(SYS_SIGPROCMASK, [SIG_UNBLOCK, DATA, 0]));
+	code.extend(mov(EBX, 5).chain(exit_with_ebx()));
+	assert_eq!(run_with_mask(&code, blocked), Ok(EXIT_CODE_KILLED));
+
+	// SIGKILL isn't blocked: exit with the mask stored at DATA + 4
+	let mut code = syscall(SYS_SIGPROCMASK, [SIG_SETMASK, DATA, 0]);
+	code.extend(syscall(SYS_SIGPROCMASK, [SIG_BLOCK, 0, DATA + 4]));
+	code.extend([0x8b, 0x1d]);
+	code.extend((DATA + 4).to_le_bytes());
+	code.extend(exit_with_ebx());
+	assert_eq!(run_with_mask(&code, blocked | signal_bit(SIGKILL)), Ok(blocked as i32));
+
+	let mut code = syscall(SYS_SIGPROCMASK, [7, DATA, 0]);
+	code.extend(EBX_FROM_EAX);
+	code.extend(exit_with_ebx());
+	assert_eq!(run_with_mask(&code, blocked), Ok(-22));
+}
+
+#[test_case]
+fn signal_system_calls_check_their_arguments() {
+	// EINVAL: SIGKILL and SIGSTOP keep their action, and 0 isn't a signal
+	assert_eq!(run(&syscall_then_exit(SYS_SIGACTION, [SIGKILL, DATA, 0])), Ok(-22));
+	assert_eq!(run(&syscall_then_exit(SYS_SIGACTION, [SIGSTOP, DATA, 0])), Ok(-22));
+	assert_eq!(run(&syscall_then_exit(SYS_SIGACTION, [0, 0, 0])), Ok(-22));
+	assert_eq!(run(&syscall_then_exit(SYS_SIGACTION, [SIGKILL, 0, DATA])), Ok(0));
+
+	// EFAULT
+	assert_eq!(run(&syscall_then_exit(SYS_SIGACTION, [SIGUSR1, 0, TEXT])), Ok(-14));
+
+	// ESRCH, then EINVAL for process groups and unknown signals
+	assert_eq!(run(&syscall_then_exit(SYS_KILL, [1_000_000, SIGTERM, 0])), Ok(-3));
+	assert_eq!(run(&syscall_then_exit(SYS_KILL, [0, SIGTERM, 0])), Ok(-22));
+
+	let mut code = kill_self(64);
+	code.extend(EBX_FROM_EAX);
+	code.extend(exit_with_ebx());
+	assert_eq!(run(&code), Ok(-22));
+
+	// the signal 0 only checks that the process exists
+	let mut code = kill_self(0);
+	code.extend(EBX_FROM_EAX);
+	code.extend(exit_with_ebx());
+	assert_eq!(run(&code), Ok(0));
+}
+
+/// Runs a program that loops forever, until a signal kills it (cf. [THREAD_EXIT_CODE])
+fn loop_in_thread() {
+	// jmp $
+	let exit_code = run(&[0xeb, 0xfe]).expect("the program is valid");
+
+	THREAD_EXIT_CODE.store(exit_code as u32, Ordering::SeqCst);
+}
+
+#[test_case]
+fn signals_stop_and_continue_processes() {
+	THREAD_EXIT_CODE.store(u32::MAX, Ordering::SeqCst);
+	let task = spawn_kernel_thread("loop", loop_in_thread).expect("a slot is free");
+
+	let process = |task| {
+		let mut found = None;
+		processes(|process| {
+			if process.task == Some(task) {
+				found = Some((process.pid, process.state));
+			}
+		});
+		found
+	};
+	let wait_for_state = |state| {
+		while process(task).map(|(_, current)| current) != Some(state) {
+			yield_now();
+		}
+	};
+
+	wait_for_state(ProcessState::Running);
+	let (pid, _) = process(task).unwrap();
+
+	send_signal(pid, SIGSTOP).unwrap();
+	wait_for_state(ProcessState::Stopped);
+
+	send_signal(pid, SIGCONT).unwrap();
+	wait_for_state(ProcessState::Running);
+
+	send_signal(pid, SIGKILL).unwrap();
+	while THREAD_EXIT_CODE.load(Ordering::SeqCst) == u32::MAX {
+		yield_now();
+	}
+	assert_eq!(THREAD_EXIT_CODE.load(Ordering::SeqCst), EXIT_CODE_KILLED as u32);
+}
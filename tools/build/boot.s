@@ -1,23 +1,88 @@
-section .multiboot
+; The kernel is linked in the higher half (at KERNEL_VIRTUAL_BASE + 1 MiB), but GRUB loads it at
+; 1 MiB (c.f. link.ld). Until paging is enabled, only the .multiboot.* sections can be used, and
+; every other symbol must be converted to its physical address (symbol - KERNEL_VIRTUAL_BASE)
+KERNEL_VIRTUAL_BASE equ 0xC0000000
+
+; index of the first higher half table in the page directory (768)
+KERNEL_DIRECTORY_INDEX equ (KERNEL_VIRTUAL_BASE >> 22)
+
+; number of page tables in the boot page directory (each one maps 4 MiB)
+BOOT_PAGE_TABLES equ 2
+
+; page entry flags (PRESENT | WRITABLE)
+PAGE_FLAGS equ 0x003
+
+section .multiboot.data
 align 4
     dd 0x1BADB002 ; MAGIC
     dd (1 << 0) | (1 << 1) ; FLAGS (ALIGN | MEMINFO)
     dd -(0x1BADB002 + ((1 << 0) | (1 << 1))) ; CHECKSUM
 
 section .bss
-align 16
+align 4096
+; the kernel page directory (c.f. init_virtual_memory implementation)
+global boot_page_directory
+boot_page_directory:
+    resb 4096
+boot_page_tables:
+    resb 4096 * BOOT_PAGE_TABLES
+
+; left unmapped (c.f. init_virtual_memory), so a kernel stack overflow faults instead of
+; silently corrupting the page tables above
+global stack_guard
+stack_guard:
+    resb 4096
+
 stack_bottom:
     resb 16384
 stack_top:
 
-section .text
+section .multiboot.text
 global _start
 extern _entrypoint
 
+; eax (GRUB magic number) and ebx (multiboot_info_ptr) must be kept for _entrypoint
 _start:
+    ; map the first BOOT_PAGE_TABLES * 4 MiB of physical memory in the boot page tables
+    mov edi, boot_page_tables - KERNEL_VIRTUAL_BASE
+    mov edx, PAGE_FLAGS
+    mov ecx, 1024 * BOOT_PAGE_TABLES
+.fill_tables:
+    mov [edi], edx
+    add edx, 4096
+    add edi, 4
+    loop .fill_tables
+
+    ; put the tables both at 0 (identity map, so we keep running once paging is enabled),
+    ; and at KERNEL_VIRTUAL_BASE (higher half)
+    mov edi, boot_page_directory - KERNEL_VIRTUAL_BASE
+    mov edx, boot_page_tables - KERNEL_VIRTUAL_BASE + PAGE_FLAGS
+    xor ecx, ecx
+.fill_directory:
+    mov [edi + ecx * 4], edx
+    mov [edi + ecx * 4 + KERNEL_DIRECTORY_INDEX * 4], edx
+    add edx, 4096
+    inc ecx
+    cmp ecx, BOOT_PAGE_TABLES
+    jne .fill_directory
+
+    ; enable paging (c.f. PageDirectory documentation)
+    mov ecx, boot_page_directory - KERNEL_VIRTUAL_BASE
+    mov cr3, ecx
+    ; with write protection, the kernel also faults on read-only pages (e.g. copy-on-write ones)
+    mov ecx, cr0
+    or ecx, 0x80010000
+    mov cr0, ecx
+
+    ; absolute jump to the higher half (eip is still in the identity map)
+    lea ecx, [higher_half]
+    jmp ecx
+
+section .text
+higher_half:
     mov esp, stack_top
 
-	; multiboot_info_ptr (c.f. _entrypoint implementation)
+	; multiboot_info_ptr, a physical address (c.f. _entrypoint implementation)
 	push ebx
 
 	; GRUB magic number
@@ -28,4 +93,4 @@ _start:
 .hang:
     cli
     hlt
-    jmp .hang
\ No newline at end of file
+    jmp .hang
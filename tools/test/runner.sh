@@ -0,0 +1,53 @@
+#!/bin/sh
+# Cargo runner for the x86 target (cf. .cargo/config.toml)
+#
+# Wraps the kernel ELF given by cargo into a bootable GRUB ISO, then boots it headlessly in QEMU
+# with COM1 on stdio. Test kernels end QEMU through the isa-debug-exit device (cf.
+# src/testing/mod.rs): QEMU exits with 33 when every test passed, and 35 when one failed.
+
+set -eu
+
+KERNEL="$1"
+NAME=$(basename "$KERNEL")
+BUILD_DIR="${BUILD_DIR:-build}"
+ISO_DIR="$BUILD_DIR/tests/$NAME"
+ISO="$BUILD_DIR/tests/$NAME.iso"
+QEMU="${QEMU:-qemu-system-i386}"
+TEST_TIMEOUT="${TEST_TIMEOUT:-300}"
+
+rm -rf "$ISO_DIR"
+mkdir -p "$ISO_DIR/boot/grub"
+cp "$KERNEL" "$ISO_DIR/boot/babyOS"
+
+# the user programs loaded as multiboot modules (cf. tools/build/grub.cfg)
+for PROGRAM in tools/user/*.s; do
+	nasm -f bin "$PROGRAM" -o "$ISO_DIR/boot/$(basename "$PROGRAM" .s)"
+done
+
+# boot the kernel right away, without waiting on the GRUB menu
+{
+	echo "set timeout=0"
+	echo "set default=0"
+	cat tools/build/grub.cfg
+} > "$ISO_DIR/boot/grub/grub.cfg"
+
+grub-file --is-x86-multiboot "$ISO_DIR/boot/babyOS"
+grub-mkrescue -o "$ISO" "$ISO_DIR" 2> /dev/null
+
+set +e
+timeout "$TEST_TIMEOUT" "$QEMU" \
+	-cdrom "$ISO" \
+	-m 512M \
+	-serial stdio \
+	-display none \
+	-no-reboot \
+	-device isa-debug-exit,iobase=0xf4,iosize=0x04
+STATUS=$?
+set -e
+
+case $STATUS in
+	33) exit 0 ;;
+	35) exit 1 ;;
+	124) echo "$NAME: timed out after ${TEST_TIMEOUT}s" >&2; exit 1 ;;
+	*) echo "$NAME: QEMU exited with status $STATUS (triple fault?)" >&2; exit 1 ;;
+esac
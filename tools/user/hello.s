@@ -0,0 +1,99 @@
+; A minimal user program, loaded by GRUB as a multiboot module (cf. tools/build/grub.cfg)
+;
+; nasm writes the whole ELF executable itself (`nasm -f bin`), so no linker is needed: a single
+; PT_LOAD segment holds the headers, the code and the data, followed by the .bss.
+;
+; Prints its arguments, one per line, then exits with argc
+bits 32
+
+SYS_EXIT  equ 1
+SYS_WRITE equ 4
+STDOUT    equ 1
+
+LOAD_ADDRESS equ 0x08048000
+
+org LOAD_ADDRESS
+
+elf_header:
+    db 0x7f, "ELF", 1, 1, 1, 0      ; ELFCLASS32, little endian, version 1
+    times 8 db 0
+    dw 2                            ; ET_EXEC
+    dw 3                            ; EM_386
+    dd 1                            ; version
+    dd _start                       ; entry point
+    dd program_header - $$          ; program headers offset
+    dd 0                            ; section headers offset
+    dd 0                            ; flags
+    dw ELF_HEADER_SIZE
+    dw PROGRAM_HEADER_SIZE
+    dw 1                            ; program header count
+    dw 0, 0, 0                      ; no section headers
+ELF_HEADER_SIZE equ $ - elf_header
+
+program_header:
+    dd 1                            ; PT_LOAD
+    dd 0                            ; file offset
+    dd $$                           ; virtual address
+    dd $$                           ; physical address
+    dd file_end - $$                ; size in the file
+    dd bss_end - $$                 ; size in memory (with the .bss)
+    dd 7                            ; PF_R | PF_W | PF_X
+    dd 0x1000                       ; alignment
+PROGRAM_HEADER_SIZE equ $ - program_header
+
+_start:
+    ; the stack holds argc, then argv
+    mov esi, [esp]
+    lea edi, [esp + 4]
+
+    ; the .bss must be zeroed by the loader
+    cmp dword [bss_canary], 0
+    jne .exit_failure
+
+.next_argument:
+    mov ecx, [edi]
+    test ecx, ecx
+    jz .exit
+
+    ; strlen
+    xor edx, edx
+.length:
+    cmp byte [ecx + edx], 0
+    je .print
+    inc edx
+    jmp .length
+
+.print:
+    mov eax, SYS_WRITE
+    mov ebx, STDOUT
+    int 0x80
+
+    mov eax, SYS_WRITE
+    mov ebx, STDOUT
+    mov ecx, newline
+    mov edx, 1
+    int 0x80
+
+    add edi, 4
+    jmp .next_argument
+
+.exit:
+    mov eax, SYS_EXIT
+    mov ebx, esi
+    int 0x80
+
+.exit_failure:
+    mov eax, SYS_EXIT
+    mov ebx, 255
+    int 0x80
+
+newline:
+    db 10
+
+file_end:
+
+section .bss
+bss_canary:
+    resd 1
+    resb 4096
+bss_end:
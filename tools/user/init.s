@@ -0,0 +1,72 @@
+; The first user process (PID 1), run by the kernel after its initialization (cf. src/process)
+;
+; Built like hello.s: a single PT_LOAD segment holds the headers and the code.
+;
+; Reaps the orphans the kernel gives it, forever: the kernel panics if init exits
+bits 32
+
+SYS_WRITE       equ 4
+SYS_WAITPID     equ 7
+SYS_SCHED_YIELD equ 158
+STDOUT          equ 1
+ECHILD          equ 10
+
+LOAD_ADDRESS equ 0x08048000
+
+org LOAD_ADDRESS
+
+elf_header:
+    db 0x7f, "ELF", 1, 1, 1, 0      ; ELFCLASS32, little endian, version 1
+    times 8 db 0
+    dw 2                            ; ET_EXEC
+    dw 3                            ; EM_386
+    dd 1                            ; version
+    dd _start                       ; entry point
+    dd program_header - $$          ; program headers offset
+    dd 0                            ; section headers offset
+    dd 0                            ; flags
+    dw ELF_HEADER_SIZE
+    dw PROGRAM_HEADER_SIZE
+    dw 1                            ; program header count
+    dw 0, 0, 0                      ; no section headers
+ELF_HEADER_SIZE equ $ - elf_header
+
+program_header:
+    dd 1                            ; PT_LOAD
+    dd 0                            ; file offset
+    dd $$                           ; virtual address
+    dd $$                           ; physical address
+    dd file_end - $$                ; size in the file
+    dd file_end - $$                ; size in memory
+    dd 5                            ; PF_R | PF_X
+    dd 0x1000                       ; alignment
+PROGRAM_HEADER_SIZE equ $ - program_header
+
+_start:
+    mov eax, SYS_WRITE
+    mov ebx, STDOUT
+    mov ecx, message
+    mov edx, MESSAGE_LENGTH
+    int 0x80
+
+.reap:
+    ; waitpid(-1, NULL, 0): blocks until an orphan exits
+    mov eax, SYS_WAITPID
+    mov ebx, -1
+    xor ecx, ecx
+    xor edx, edx
+    int 0x80
+
+    cmp eax, -ECHILD
+    jne .reap
+
+    ; no orphan yet
+    mov eax, SYS_SCHED_YIELD
+    int 0x80
+    jmp .reap
+
+message:
+    db "init: running", 10
+MESSAGE_LENGTH equ $ - message
+
+file_end: